@@ -1,16 +1,19 @@
 #![no_std]
 #![no_main]
 
+use kendryte_hal::delay::DelayNs;
 use kendryte_hal::gpio::{Output, PinState, StatefulOutputPin};
 use kendryte_hal::iomux::pad::Strength;
+use kendryte_rt::arch::timer;
 use kendryte_rt::{Clocks, Peripherals, entry};
 use panic_halt as _;
 
 #[entry]
-fn main(p: Peripherals, _c: Clocks) -> ! {
+fn main(p: Peripherals, c: Clocks) -> ! {
+    let mut delay = timer::delay(c);
     let mut led = Output::new(p.gpio0, p.iomux.io19, PinState::High, Strength::_7);
     loop {
         led.toggle().ok();
-        riscv::asm::delay(10_000_000);
+        delay.delay_ms(500);
     }
 }
@@ -1,14 +1,17 @@
 #![no_std]
 #![no_main]
 
+use kendryte_hal::delay::DelayNs;
 use kendryte_hal::gpio::{Input, Output, OutputPin, PinState};
 use kendryte_hal::iomux::ops::Pull;
 use kendryte_hal::iomux::pad::Strength;
+use kendryte_rt::arch::timer;
 use kendryte_rt::{Clocks, Peripherals, entry};
 use panic_halt as _;
 
 #[entry]
-fn main(p: Peripherals, _c: Clocks) -> ! {
+fn main(p: Peripherals, c: Clocks) -> ! {
+    let mut delay = timer::delay(c);
     let mut led = Output::new(&p.gpio0, p.iomux.io19, PinState::High, Strength::_7);
     let mut button = Input::new(&p.gpio0, p.iomux.io20, Pull::Down);
     loop {
@@ -16,6 +19,6 @@ fn main(p: Peripherals, _c: Clocks) -> ! {
             PinState::High => led.set_high().ok(),
             PinState::Low => led.set_low().ok(),
         };
-        riscv::asm::delay(100_000);
+        delay.delay_ms(1);
     }
 }
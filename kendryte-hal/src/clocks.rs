@@ -5,7 +5,14 @@ pub struct Clocks;
 
 impl Clocks {
     pub fn uart_sclk<const N: usize>(&self) -> Hertz {
-        assert!(N <= 4, "N must be less than or equal to 4");
+        const { assert!(N < crate::uart::COUNT, "the chip has UART0 to UART4 only") };
         50_000_000.Hz()
     }
+
+    /// Returns the frequency of the machine timer read through the `time` CSR.
+    ///
+    /// The timer clock is fixed, so it does not follow CPU frequency changes.
+    pub fn timer(&self) -> Hertz {
+        27_000_000.Hz()
+    }
 }
@@ -0,0 +1,253 @@
+//! Peripheral ownership shared with Linux on the other core.
+//!
+//! In an AMP setup Linux runs on the big core and the bare-metal firmware
+//! on the little core, and both see every peripheral. Linux publishes the
+//! peripherals it drives in a [`Descriptor`] in memory reserved for it in
+//! its device tree; the firmware loads it once at start, and from then on
+//! the HAL constructors of those peripherals fail, or panic if they cannot
+//! fail otherwise, instead of silently reprogramming a device Linux is using:
+//!
+//! ```ignore
+//! // Reserved in the device tree at the same address on both sides.
+//! const DESCRIPTOR: usize = 0x0FFF_F000;
+//! let owned = unsafe { coexist::load(DESCRIPTOR as *const Descriptor) }?;
+//! // Fails with `Error::OwnedByLinux` if Linux drives UART3.
+//! let uart3 = BlockingUart::new(p.uart3, tx, rx, config, clocks)?;
+//! // Panics if Linux drives GPIO0, so check first:
+//! if coexist::claim(Resource::Gpio(0)).is_ok() {
+//!     let led = Output::new(p.gpio0, p.iomux.io19, PinState::High, Strength::_7);
+//! }
+//! ```
+//!
+//! Before a descriptor is loaded the firmware owns everything, so
+//! single-OS systems need not know about this module.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// First word of a valid [`Descriptor`], `"KCOX"` in memory order.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"KCOX");
+/// Layout version of [`Descriptor`].
+pub const VERSION: u32 = 1;
+
+/// Peripheral that one side can own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Resource {
+    /// UART0 to UART4.
+    Uart(u8),
+    /// GPIO0 and GPIO1.
+    Gpio(u8),
+    /// I2C0 to I2C4.
+    I2c(u8),
+    /// SPI0 to SPI2.
+    Spi(u8),
+    Pwm,
+    Lsadc,
+}
+
+impl Resource {
+    /// Bit of the resource in a [`Descriptor`], or `None` for an instance
+    /// the chip does not have.
+    pub const fn bit(self) -> Option<u32> {
+        let (first, count, index) = match self {
+            Resource::Uart(n) => (0, 5, n),
+            Resource::Gpio(n) => (5, 2, n),
+            Resource::I2c(n) => (8, 5, n),
+            Resource::Spi(n) => (13, 3, n),
+            Resource::Pwm => (16, 1, 0),
+            Resource::Lsadc => (17, 1, 0),
+        };
+        if index < count {
+            Some(first + index as u32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Set of resources.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Owned(u32);
+
+impl Owned {
+    /// No resources.
+    pub const NONE: Self = Self(0);
+
+    /// Returns the set with `resource` added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chip has no such instance.
+    #[inline]
+    pub const fn with(self, resource: Resource) -> Self {
+        match resource.bit() {
+            Some(bit) => Self(self.0 | (1 << bit)),
+            None => panic!("no such peripheral instance"),
+        }
+    }
+
+    /// Checks whether `resource` is in the set.
+    #[inline]
+    pub const fn contains(self, resource: Resource) -> bool {
+        match resource.bit() {
+            Some(bit) => self.0 & (1 << bit) != 0,
+            None => false,
+        }
+    }
+
+    /// Returns the set as stored in a [`Descriptor`].
+    #[inline]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// Ownership record Linux writes to reserved memory.
+///
+/// The set is stored with its complement, so a record torn by a concurrent
+/// write or left over from a previous layout is rejected.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Descriptor {
+    /// [`MAGIC`].
+    pub magic: u32,
+    /// [`VERSION`].
+    pub version: u32,
+    /// Bits of the resources Linux owns, see [`Resource::bit`].
+    pub linux: u32,
+    /// Complement of `linux`.
+    pub check: u32,
+}
+
+/// Reason a [`Descriptor`] was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InvalidDescriptor {
+    /// The memory does not hold a descriptor.
+    Magic,
+    /// The descriptor has a layout this HAL does not know.
+    Version(u32),
+    /// The set does not match its complement.
+    Corrupt,
+}
+
+/// A constructor was asked for a resource Linux owns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OwnedByLinux(pub Resource);
+
+impl Descriptor {
+    /// Creates the descriptor of Linux owning `linux`.
+    #[inline]
+    pub const fn new(linux: Owned) -> Self {
+        Self {
+            magic: MAGIC,
+            version: VERSION,
+            linux: linux.0,
+            check: !linux.0,
+        }
+    }
+
+    /// Validates the descriptor and returns the resources Linux owns.
+    pub fn owned(&self) -> Result<Owned, InvalidDescriptor> {
+        if self.magic != MAGIC {
+            return Err(InvalidDescriptor::Magic);
+        }
+        if self.version != VERSION {
+            return Err(InvalidDescriptor::Version(self.version));
+        }
+        if self.linux != !self.check {
+            return Err(InvalidDescriptor::Corrupt);
+        }
+        Ok(Owned(self.linux))
+    }
+}
+
+/// Resources owned by Linux, as checked by the constructors.
+static LINUX: AtomicU32 = AtomicU32::new(0);
+
+/// Reads the descriptor at `descriptor` and hands its resources to Linux.
+///
+/// An invalid descriptor changes nothing, leaving the resources of any
+/// earlier load with Linux.
+///
+/// # Safety
+///
+/// `descriptor` must be valid for reads and suitably aligned.
+pub unsafe fn load(descriptor: *const Descriptor) -> Result<Owned, InvalidDescriptor> {
+    // Written by the other core, so never cached in a register.
+    let owned = unsafe { descriptor.read_volatile() }.owned()?;
+    install(owned);
+    Ok(owned)
+}
+
+/// Hands `linux` to Linux, without a descriptor in memory.
+#[inline]
+pub fn install(linux: Owned) {
+    LINUX.store(linux.0, Ordering::Release);
+}
+
+/// Returns the resources Linux owns.
+#[inline]
+pub fn linux_owned() -> Owned {
+    Owned(LINUX.load(Ordering::Acquire))
+}
+
+/// Checks that the firmware may drive `resource`.
+#[inline]
+pub fn claim(resource: Resource) -> Result<(), OwnedByLinux> {
+    match linux_owned().contains(resource) {
+        true => Err(OwnedByLinux(resource)),
+        false => Ok(()),
+    }
+}
+
+/// Panics if Linux owns `resource`; called by the infallible constructors.
+#[track_caller]
+pub(crate) fn assert_claimable(resource: Resource) {
+    if let Err(OwnedByLinux(resource)) = claim(resource) {
+        panic!("{resource:?} is owned by Linux");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptor() {
+        let linux = Owned::NONE
+            .with(Resource::Uart(0))
+            .with(Resource::I2c(4))
+            .with(Resource::Lsadc);
+        assert!(linux.contains(Resource::I2c(4)));
+        assert!(!linux.contains(Resource::Uart(3)));
+        assert!(!linux.contains(Resource::Uart(9)));
+
+        let mut descriptor = Descriptor::new(linux);
+        let owned = unsafe { load(&descriptor) }.unwrap();
+        assert_eq!(owned, linux);
+        assert_eq!(
+            claim(Resource::Uart(0)),
+            Err(OwnedByLinux(Resource::Uart(0)))
+        );
+        assert_eq!(claim(Resource::Uart(3)), Ok(()));
+
+        descriptor.linux |= 1 << 3;
+        assert_eq!(descriptor.owned(), Err(InvalidDescriptor::Corrupt));
+        descriptor.version = 2;
+        assert_eq!(descriptor.owned(), Err(InvalidDescriptor::Version(2)));
+        assert_eq!(
+            unsafe {
+                load(&Descriptor {
+                    magic: 0,
+                    ..descriptor
+                })
+            },
+            Err(InvalidDescriptor::Magic)
+        );
+        assert_eq!(linux_owned(), linux);
+        install(Owned::NONE);
+    }
+}
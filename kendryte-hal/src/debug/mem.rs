@@ -0,0 +1,331 @@
+//! Checked memory and register access for debugging.
+//!
+//! Reading an address nothing decodes hangs the bus, so every access here is
+//! first checked against a [`MemoryMap`]. Peripheral regions are only
+//! accessed as aligned 32-bit words, the width their registers decode, and
+//! regions not marked writable are only read.
+
+use crate::memory_map;
+use core::fmt;
+
+/// Kind of a region of the address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegionKind {
+    /// RAM, readable at any width.
+    Memory,
+    /// Peripheral registers, accessed as aligned words.
+    Peripheral,
+}
+
+/// A region of the address space safe to access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Region {
+    pub name: &'static str,
+    pub start: usize,
+    pub len: usize,
+    pub kind: RegionKind,
+    /// Whether the region can be written, not just read.
+    pub writable: bool,
+}
+
+impl Region {
+    /// Checks whether `len` bytes at `address` lie in the region.
+    #[inline]
+    pub const fn contains(&self, address: usize, len: usize) -> bool {
+        address >= self.start
+            && address - self.start <= self.len
+            && len <= self.len - (address - self.start)
+    }
+}
+
+/// Regions of the K230 this crate has drivers for, plus RAM.
+///
+/// The firmware lives in this RAM, so it is only readable.
+pub const K230_REGIONS: &[Region] = &[
+    Region {
+        name: "DDR",
+        start: memory_map::DDR_BASE,
+        len: memory_map::DDR_LEN,
+        kind: RegionKind::Memory,
+        writable: false,
+    },
+    Region {
+        name: "SRAM",
+        start: memory_map::SRAM_BASE,
+        len: memory_map::SRAM_LEN,
+        kind: RegionKind::Memory,
+        writable: false,
+    },
+    Region {
+        name: "IOMUX",
+        start: memory_map::IOMUX,
+        len: memory_map::PERIPHERAL_LEN,
+        kind: RegionKind::Peripheral,
+        writable: true,
+    },
+    Region {
+        name: "UART",
+        start: memory_map::UART0,
+        len: memory_map::UART4 + memory_map::PERIPHERAL_LEN - memory_map::UART0,
+        kind: RegionKind::Peripheral,
+        writable: true,
+    },
+    Region {
+        name: "GPIO",
+        start: memory_map::GPIO0,
+        len: memory_map::GPIO1 + memory_map::PERIPHERAL_LEN - memory_map::GPIO0,
+        kind: RegionKind::Peripheral,
+        writable: true,
+    },
+];
+
+/// Errors in accessing memory through a [`MemoryMap`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessError {
+    /// No region holds the whole access.
+    Unmapped(usize),
+    /// A peripheral access is not an aligned word.
+    Misaligned(usize),
+    /// A write to a region that is not writable.
+    ReadOnly(usize),
+}
+
+/// Set of regions accesses are checked against.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryMap<'a> {
+    regions: &'a [Region],
+}
+
+impl<'a> MemoryMap<'a> {
+    /// Creates a map of `regions`.
+    ///
+    /// # Safety
+    ///
+    /// For as long as the map is used, every region must be safe to read as
+    /// its kind, and every writable region safe to write: it must not hold
+    /// memory Rust code uses, such as the stack, statics or the heap, and
+    /// writing its registers must not break a driver's invariants.
+    #[inline]
+    pub const unsafe fn new(regions: &'a [Region]) -> Self {
+        Self { regions }
+    }
+
+    /// Returns the regions.
+    #[inline]
+    pub fn regions(&self) -> &'a [Region] {
+        self.regions
+    }
+
+    /// Returns the region holding `len` bytes at `address`.
+    pub fn check(&self, address: usize, len: usize) -> Result<&'a Region, AccessError> {
+        let region = self
+            .regions
+            .iter()
+            .find(|region| region.contains(address, len))
+            .ok_or(AccessError::Unmapped(address))?;
+        if region.kind == RegionKind::Peripheral && (address % 4 != 0 || len % 4 != 0) {
+            return Err(AccessError::Misaligned(address));
+        }
+        Ok(region)
+    }
+
+    /// Reads the word at `address`.
+    pub fn read_u32(&self, address: usize) -> Result<u32, AccessError> {
+        self.check(address, 4)?;
+        if address % 4 != 0 {
+            return Err(AccessError::Misaligned(address));
+        }
+        // Checked against the map above.
+        Ok(unsafe { (address as *const u32).read_volatile() })
+    }
+
+    /// Writes the word at `address`.
+    pub fn write_u32(&self, address: usize, value: u32) -> Result<(), AccessError> {
+        if !self.check(address, 4)?.writable {
+            return Err(AccessError::ReadOnly(address));
+        }
+        if address % 4 != 0 {
+            return Err(AccessError::Misaligned(address));
+        }
+        // Checked against the map above.
+        unsafe { (address as *mut u32).write_volatile(value) };
+        Ok(())
+    }
+
+    /// Copies the bytes at `address` into `buf`, by words in peripheral regions.
+    pub fn read(&self, address: usize, buf: &mut [u8]) -> Result<(), AccessError> {
+        match self.check(address, buf.len())?.kind {
+            RegionKind::Memory => {
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    // Checked against the map above.
+                    *byte = unsafe { ((address + i) as *const u8).read_volatile() };
+                }
+            }
+            RegionKind::Peripheral => {
+                for (i, word) in buf.chunks_exact_mut(4).enumerate() {
+                    let value = unsafe { ((address + i * 4) as *const u32).read_volatile() };
+                    word.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a hexdump of `len` bytes at `address` to `out`.
+    pub fn dump(&self, out: &mut dyn fmt::Write, address: usize, len: usize) -> fmt::Result {
+        let mut buf = [0; 16];
+        for offset in (0..len).step_by(16) {
+            let line = &mut buf[..(len - offset).min(16)];
+            match self.read(address + offset, line) {
+                Ok(()) => hexdump(out, address + offset, line)?,
+                Err(e) => return writeln!(out, "{:#010x}: {e:?}", address + offset),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MemoryMap<'static> {
+    /// Creates a map of [`K230_REGIONS`].
+    ///
+    /// # Safety
+    ///
+    /// The peripheral regions are writable, and reading the receive buffer or
+    /// line status register of a UART takes a character or line error from
+    /// its driver. The caller must not access the registers of a peripheral
+    /// through the map while a driver uses it, see [`MemoryMap::new`].
+    #[inline]
+    pub const unsafe fn k230() -> Self {
+        unsafe { Self::new(K230_REGIONS) }
+    }
+}
+
+/// Writes `data` as hex and ASCII, 16 bytes a line, labelled from `base`.
+pub fn hexdump(out: &mut dyn fmt::Write, base: usize, data: &[u8]) -> fmt::Result {
+    for (i, line) in data.chunks(16).enumerate() {
+        write!(out, "{:#010x}:", base + i * 16)?;
+        for byte in line {
+            write!(out, " {byte:02x}")?;
+        }
+        for _ in line.len()..16 {
+            out.write_str("   ")?;
+        }
+        out.write_str("  ")?;
+        for &byte in line {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            out.write_char(c)?;
+        }
+        out.write_char('\n')?;
+    }
+    Ok(())
+}
+
+/// Copy of `N` consecutive registers, for comparing a block before and after a change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Snapshot<const N: usize> {
+    base: usize,
+    words: [u32; N],
+}
+
+impl<const N: usize> Snapshot<N> {
+    /// Reads `N` words starting at `base`.
+    pub fn capture(map: &MemoryMap<'_>, base: usize) -> Result<Self, AccessError> {
+        map.check(base, N * 4)?;
+        if base % 4 != 0 {
+            return Err(AccessError::Misaligned(base));
+        }
+        let mut words = [0; N];
+        for (i, word) in words.iter_mut().enumerate() {
+            // Checked against the map above.
+            *word = unsafe { ((base + i * 4) as *const u32).read_volatile() };
+        }
+        Ok(Self { base, words })
+    }
+
+    /// Returns the address of the first register.
+    #[inline]
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the captured words.
+    #[inline]
+    pub fn words(&self) -> &[u32; N] {
+        &self.words
+    }
+
+    /// Returns the address, old and new value of each register that differs in `later`.
+    pub fn diff<'s>(&'s self, later: &'s Self) -> impl Iterator<Item = (usize, u32, u32)> + 's {
+        self.words
+            .iter()
+            .zip(&later.words)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(i, (&old, &new))| (self.base + i * 4, old, new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Text;
+
+    #[test]
+    fn checks_accesses() {
+        // Every access below is rejected before it reaches the bus.
+        let map = unsafe { MemoryMap::k230() };
+        assert_eq!(map.check(0x9140_0000, 4).unwrap().name, "UART");
+        assert_eq!(
+            map.check(0x9140_4FFC, 8),
+            Err(AccessError::Unmapped(0x9140_4FFC))
+        );
+        assert_eq!(
+            map.check(0x9140_0002, 4),
+            Err(AccessError::Misaligned(0x9140_0002))
+        );
+        assert_eq!(map.check(0x8030_0001, 3).unwrap().name, "SRAM");
+        assert_eq!(
+            map.write_u32(0x8030_0000, 0),
+            Err(AccessError::ReadOnly(0x8030_0000))
+        );
+        assert_eq!(
+            map.read_u32(0x9000_0000),
+            Err(AccessError::Unmapped(0x9000_0000))
+        );
+    }
+
+    #[test]
+    fn dumps_and_diffs() {
+        let mut block = [0x6c6c_6548u32, 0x0a21_6f, 0, 0];
+        let base = block.as_mut_ptr() as usize;
+        let regions = [Region {
+            name: "TEST",
+            start: base,
+            len: 16,
+            kind: RegionKind::Peripheral,
+            writable: true,
+        }];
+        // Only accessed through the map until it is dropped.
+        let map = unsafe { MemoryMap::new(&regions) };
+
+        let before = Snapshot::<4>::capture(&map, base).unwrap();
+        map.write_u32(base + 8, 7).unwrap();
+        let after = Snapshot::<4>::capture(&map, base).unwrap();
+        let mut diff = before.diff(&after);
+        assert_eq!(diff.next(), Some((base + 8, 0, 7)));
+        assert_eq!(diff.next(), None);
+        assert_eq!(block[2], 7);
+
+        let mut text = Text::<256>::new();
+        map.dump(&mut text, base, 8).unwrap();
+        assert!(text.as_str().contains(": 48 65 6c 6c 6f 21 0a 00 "));
+        assert!(text.as_str().ends_with("  Hello!..\n"));
+    }
+}
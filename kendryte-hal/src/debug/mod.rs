@@ -0,0 +1,4 @@
+//! Debugging aids for bring-up without a debugger.
+
+pub mod mem;
+pub mod report;
@@ -0,0 +1,89 @@
+//! Usage report of pads and peripheral clocks.
+//!
+//! Lists every pad with its input or output enabled, the function it is
+//! set to and the signal that function carries, then the peripheral clocks,
+//! to find out why a pin stays dead:
+//!
+//! ```text
+//! io38 func 1 out   pull none uart0.sout
+//! io39 func 1 in    pull up   uart0.sin
+//! uart0_sclk 50000000 Hz
+//! ```
+
+use crate::clocks::Clocks;
+use crate::iomux::map::{self, PadFunction};
+use crate::iomux::ops::{PadOps, Pull};
+use crate::iomux::{self, FlexPad};
+use core::fmt;
+
+/// Returns the direction of `pad` as shown in reports.
+pub(crate) fn direction(pad: &impl PadOps) -> &'static str {
+    match (pad.is_input_enabled(), pad.is_output_enabled()) {
+        (true, true) => "inout",
+        (true, false) => "in",
+        (false, true) => "out",
+        (false, false) => "off",
+    }
+}
+
+/// Returns the pull of `pad` as shown in reports.
+pub(crate) fn pull(pad: &impl PadOps) -> &'static str {
+    match pad.pull() {
+        Some(Pull::Up) => "up",
+        Some(Pull::Down) => "down",
+        Some(Pull::None) => "none",
+        None => "both",
+    }
+}
+
+/// Writes the pads in use, naming their signals from `tables`.
+pub fn pads(
+    out: &mut dyn fmt::Write,
+    iomux: &'static iomux::RegisterBlock,
+    tables: &[&[PadFunction]],
+) -> fmt::Result {
+    for (n, pad) in iomux.pads.iter().enumerate() {
+        let pad = FlexPad::new(pad);
+        if !pad.is_input_enabled() && !pad.is_output_enabled() {
+            continue;
+        }
+        let function = pad.function_select().value();
+        write!(
+            out,
+            "io{n:<2} func {function} {:<5} pull {:<4} ",
+            direction(&pad),
+            pull(&pad)
+        )?;
+        match map::lookup(tables, n as u8, function) {
+            Some(signal) => writeln!(out, "{signal}")?,
+            None => writeln!(out, "-")?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes the peripheral clock frequencies.
+pub fn clocks(out: &mut dyn fmt::Write, clocks: &Clocks) -> fmt::Result {
+    let uart = [
+        clocks.uart_sclk::<0>(),
+        clocks.uart_sclk::<1>(),
+        clocks.uart_sclk::<2>(),
+        clocks.uart_sclk::<3>(),
+        clocks.uart_sclk::<4>(),
+    ];
+    for (i, hz) in uart.iter().enumerate() {
+        writeln!(out, "uart{i}_sclk {} Hz", hz.0)?;
+    }
+    Ok(())
+}
+
+/// Writes the pad report followed by the clock report.
+pub fn report(
+    out: &mut dyn fmt::Write,
+    iomux: &'static iomux::RegisterBlock,
+    tables: &[&[PadFunction]],
+    clocks: &Clocks,
+) -> fmt::Result {
+    pads(out, iomux, tables)?;
+    self::clocks(out, clocks)
+}
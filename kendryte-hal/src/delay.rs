@@ -0,0 +1,123 @@
+//! Delays measured on a hardware timer.
+//!
+//! [`Delay`] waits on a [`Monotonic`] counter rather than counting CPU
+//! cycles, so a delay keeps its length when the CPU clock is scaled. On the
+//! K230 the runtime's `time` CSR counter runs from the fixed 27 MHz timer
+//! clock, [`Clocks::timer`](crate::clocks::Clocks::timer):
+//!
+//! ```ignore
+//! let mut delay = kendryte_rt::arch::timer::delay(c);
+//! delay.delay_ms(500);
+//!
+//! // A fixed-rate loop that does not drift with the work done in it.
+//! let mut next = delay.now();
+//! loop {
+//!     next += Duration::from_millis(10);
+//!     sample();
+//!     delay.delay_until(next);
+//! }
+//! ```
+
+use crate::time::{Duration, Instant};
+use crate::timeout::Monotonic;
+pub use embedded_hal::delay::DelayNs;
+
+/// Busy-wait delay on a hardware timer.
+pub struct Delay<M> {
+    timer: M,
+}
+
+impl<M: Monotonic> Delay<M> {
+    /// Creates a delay measured on `timer`.
+    #[inline]
+    pub fn new(timer: M) -> Self {
+        Self { timer }
+    }
+
+    /// Returns the current time.
+    #[inline]
+    pub fn now(&self) -> Instant {
+        Instant::now(&self.timer)
+    }
+
+    /// Returns the time since `instant`, zero if it lies in the future.
+    #[inline]
+    pub fn elapsed(&self, instant: Instant) -> Duration {
+        self.now() - instant
+    }
+
+    /// Waits until the timer reaches `instant`; returns at once if it already has.
+    pub fn delay_until(&mut self, instant: Instant) {
+        // Compared in ticks, so the loop does no division.
+        let end = instant.to_ticks(self.timer.frequency().0);
+        while self.timer.now() < end {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Releases the timer.
+    #[inline]
+    pub fn free(self) -> M {
+        self.timer
+    }
+}
+
+impl<M: Monotonic> DelayNs for Delay<M> {
+    fn delay_ns(&mut self, ns: u32) {
+        // The first reading may be late in its tick, so wait for one more.
+        let hz = self.timer.frequency().0;
+        let start = Instant::from_ticks(self.timer.now().saturating_add(1), hz);
+        self.delay_until(start + Duration::from_nanos(ns as u64));
+    }
+}
+
+/// Implements the `embedded-hal` 0.2 delay traits for `$t` through [`DelayNs`].
+#[cfg(feature = "eh02")]
+macro_rules! impl_eh02_delay {
+    ($($t:ty),+) => {
+        $(
+            impl<M: Monotonic> embedded_hal_02::blocking::delay::DelayMs<$t> for Delay<M> {
+                #[inline]
+                fn delay_ms(&mut self, ms: $t) {
+                    DelayNs::delay_ms(self, ms.into());
+                }
+            }
+
+            impl<M: Monotonic> embedded_hal_02::blocking::delay::DelayUs<$t> for Delay<M> {
+                #[inline]
+                fn delay_us(&mut self, us: $t) {
+                    DelayNs::delay_us(self, us.into());
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "eh02")]
+impl_eh02_delay!(u8, u16, u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Ticks;
+
+    #[test]
+    fn waits_on_timer() {
+        let mut delay = Delay::new(Ticks::new(1_000_000));
+        delay.delay_us(100);
+        let now = delay.free().count();
+        assert!((101..=104).contains(&now), "{now}");
+
+        // Partial ticks round up.
+        let mut delay = Delay::new(Ticks::new(1_000_000));
+        delay.delay_until(Instant::from_nanos(1_500));
+        assert_eq!(delay.free().count(), 3);
+
+        let mut delay = Delay::new(Ticks::new(1_000_000));
+        let start = delay.now();
+        delay.delay_until(start + Duration::from_millis(1));
+        assert!(delay.elapsed(start) >= Duration::from_millis(1));
+        delay.delay_until(Instant::ZERO);
+        assert_eq!(delay.elapsed(Instant::from_nanos(u64::MAX)), Duration::ZERO);
+    }
+}
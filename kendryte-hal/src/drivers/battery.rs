@@ -0,0 +1,335 @@
+//! Battery fuel gauges on I2C.
+//!
+//! A [`FuelGauge`] reports a [`Reading`] of state of charge and cell voltage.
+//! [`Battery`] polls one and queues a [`BatteryEvent`] on a [`Channel`] when
+//! the charge level or state changes, or the level falls below a threshold:
+//!
+//! ```ignore
+//! static BATTERY: Channel<BatteryEvent, 4> = Channel::new();
+//!
+//! let mut battery = Battery::new(Max17048::new(i2c), 10);
+//! // Every few seconds, with the charger status pin if the board has one:
+//! battery.poll(Some(chrg.is_low()?), &BATTERY)?;
+//! // In the UI task:
+//! match BATTERY.receive().await {
+//!     BatteryEvent::Changed(reading) => show(reading.percent, reading.charge),
+//!     BatteryEvent::Low(_) => warn(),
+//! }
+//! ```
+
+use crate::sync::Channel;
+use embedded_hal::i2c::I2c;
+
+/// Address of the MAX17048 and MAX17049.
+pub const MAX17048_ADDRESS: u8 = 0x36;
+/// Address of the CW2015.
+pub const CW2015_ADDRESS: u8 = 0x62;
+
+/// Whether the battery is charging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChargeState {
+    /// The gauge cannot tell, and no charger status was given.
+    Unknown,
+    Charging,
+    Discharging,
+    /// Neither charging nor discharging noticeably.
+    Idle,
+    /// Charged to 100 percent.
+    Full,
+}
+
+/// State of the battery.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reading {
+    /// State of charge in percent, at most 100.
+    pub percent: u8,
+    /// Cell voltage in millivolts.
+    pub millivolts: u16,
+    pub charge: ChargeState,
+}
+
+/// Change reported by [`Battery::poll`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BatteryEvent {
+    /// The percentage or charge state changed.
+    Changed(Reading),
+    /// The percentage fell below the low threshold while discharging.
+    Low(Reading),
+}
+
+/// Fuel gauge measuring a single cell.
+pub trait FuelGauge {
+    type Error;
+
+    /// Reads the state of the battery.
+    fn read(&mut self) -> Result<Reading, Self::Error>;
+}
+
+/// Maxim MAX17048 and MAX17049 ModelGauge.
+pub struct Max17048<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> Max17048<I2C> {
+    const VCELL: u8 = 0x02;
+    const SOC: u8 = 0x04;
+    const VERSION: u8 = 0x08;
+    const CRATE: u8 = 0x16;
+    /// Rate of charge below which the battery counts as idle, in 0.208 %/h.
+    const IDLE_RATE: i16 = 5;
+
+    /// Creates a driver for the gauge at [`MAX17048_ADDRESS`].
+    #[inline]
+    pub const fn new(i2c: I2C) -> Self {
+        Self { i2c }
+    }
+
+    /// Reads the production version, `0x001x` for the MAX17048.
+    pub fn version(&mut self) -> Result<u16, I2C::Error> {
+        self.read_reg(Self::VERSION)
+    }
+
+    /// Releases the I2C bus.
+    #[inline]
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u16, I2C::Error> {
+        let mut value = [0; 2];
+        self.i2c.write_read(MAX17048_ADDRESS, &[reg], &mut value)?;
+        Ok(u16::from_be_bytes(value))
+    }
+}
+
+impl<I2C: I2c> FuelGauge for Max17048<I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self) -> Result<Reading, Self::Error> {
+        // 78.125 uV per bit.
+        let millivolts = (self.read_reg(Self::VCELL)? as u32 * 5 / 64) as u16;
+        let percent = ((self.read_reg(Self::SOC)? >> 8) as u8).min(100);
+        let rate = self.read_reg(Self::CRATE)? as i16;
+        let charge = match rate {
+            _ if percent == 100 && rate < Self::IDLE_RATE => ChargeState::Full,
+            rate if rate >= Self::IDLE_RATE => ChargeState::Charging,
+            rate if rate <= -Self::IDLE_RATE => ChargeState::Discharging,
+            _ => ChargeState::Idle,
+        };
+        Ok(Reading {
+            percent,
+            millivolts,
+            charge,
+        })
+    }
+}
+
+/// CellWise CW2015.
+///
+/// The gauge cannot tell whether the battery is charging, so its readings
+/// have [`ChargeState::Unknown`] unless [`Battery::poll`] is given the
+/// charger status.
+pub struct Cw2015<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> Cw2015<I2C> {
+    const VCELL: u8 = 0x02;
+    const SOC: u8 = 0x04;
+    const CONFIG: u8 = 0x08;
+    const MODE: u8 = 0x0A;
+    const BATINFO: u8 = 0x10;
+    /// Set in `CONFIG` to make the gauge use a newly written profile.
+    const UFG: u8 = 0x02;
+
+    /// Creates a driver for the gauge at [`CW2015_ADDRESS`].
+    #[inline]
+    pub const fn new(i2c: I2C) -> Self {
+        Self { i2c }
+    }
+
+    /// Wakes the gauge from the sleep mode it powers up in.
+    pub fn wake(&mut self) -> Result<(), I2C::Error> {
+        self.i2c.write(CW2015_ADDRESS, &[Self::MODE, 0x00])
+    }
+
+    /// Writes the 64-byte battery profile from the cell vendor.
+    ///
+    /// The gauge is accurate only with the profile of its cell; it is kept
+    /// until the gauge loses power.
+    pub fn load_profile(&mut self, profile: &[u8; 64]) -> Result<(), I2C::Error> {
+        let mut write = [0; 65];
+        write[0] = Self::BATINFO;
+        write[1..].copy_from_slice(profile);
+        self.i2c.write(CW2015_ADDRESS, &write)?;
+        let mut config = [0];
+        self.i2c
+            .write_read(CW2015_ADDRESS, &[Self::CONFIG], &mut config)?;
+        self.i2c
+            .write(CW2015_ADDRESS, &[Self::CONFIG, config[0] | Self::UFG])
+    }
+
+    /// Releases the I2C bus.
+    #[inline]
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: I2c> FuelGauge for Cw2015<I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self) -> Result<Reading, Self::Error> {
+        let mut vcell = [0; 2];
+        self.i2c
+            .write_read(CW2015_ADDRESS, &[Self::VCELL], &mut vcell)?;
+        let mut soc = [0];
+        self.i2c
+            .write_read(CW2015_ADDRESS, &[Self::SOC], &mut soc)?;
+        // 14 bits of 305 uV.
+        let raw = u16::from_be_bytes(vcell) & 0x3FFF;
+        Ok(Reading {
+            percent: soc[0].min(100),
+            millivolts: (raw as u32 * 305 / 1000) as u16,
+            charge: ChargeState::Unknown,
+        })
+    }
+}
+
+/// Fuel gauge feeding an event queue.
+pub struct Battery<G> {
+    gauge: G,
+    last: Option<Reading>,
+    low_percent: u8,
+    low: bool,
+}
+
+impl<G: FuelGauge> Battery<G> {
+    /// Wraps a gauge, reporting [`BatteryEvent::Low`] below `low_percent`.
+    #[inline]
+    pub const fn new(gauge: G, low_percent: u8) -> Self {
+        Self {
+            gauge,
+            last: None,
+            low_percent,
+            low: false,
+        }
+    }
+
+    /// Reads the gauge and queues the events of the change since the last
+    /// poll, returning the reading.
+    ///
+    /// `charging` is the charger status, if the board wires it to a pin; it
+    /// takes precedence over the gauge's estimate. Events that do not fit in
+    /// the queue are dropped.
+    pub fn poll<const N: usize>(
+        &mut self,
+        charging: Option<bool>,
+        queue: &Channel<BatteryEvent, N>,
+    ) -> Result<Reading, G::Error> {
+        let mut reading = self.gauge.read()?;
+        reading.charge = match charging {
+            Some(true) => ChargeState::Charging,
+            Some(false) if reading.percent == 100 => ChargeState::Full,
+            Some(false) => ChargeState::Discharging,
+            None => reading.charge,
+        };
+        let changed = self
+            .last
+            .is_none_or(|last| (last.percent, last.charge) != (reading.percent, reading.charge));
+        if changed {
+            queue.try_send(BatteryEvent::Changed(reading)).ok();
+        }
+        let low = reading.percent < self.low_percent && reading.charge != ChargeState::Charging;
+        if low && !self.low {
+            queue.try_send(BatteryEvent::Low(reading)).ok();
+        }
+        self.low = low;
+        self.last = Some(reading);
+        Ok(reading)
+    }
+
+    /// Returns the last reading.
+    #[inline]
+    pub fn last(&self) -> Option<Reading> {
+        self.last
+    }
+
+    /// Releases the gauge.
+    #[inline]
+    pub fn free(self) -> G {
+        self.gauge
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+
+    /// MAX17048 with 16-bit registers.
+    struct Gauge {
+        regs: [u16; 0x20],
+    }
+
+    impl ErrorType for Gauge {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Gauge {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            match operations {
+                [Operation::Write([reg]), Operation::Read(buf)] if address == MAX17048_ADDRESS => {
+                    buf.copy_from_slice(&self.regs[*reg as usize].to_be_bytes());
+                    Ok(())
+                }
+                _ => Err(ErrorKind::Other),
+            }
+        }
+    }
+
+    #[test]
+    fn events() {
+        let mut regs = [0; 0x20];
+        // 3.9 V, 11.5 %, discharging at 2 %/h.
+        regs[0x02] = 49920;
+        regs[0x04] = 0x0B80;
+        regs[0x16] = -10i16 as u16;
+        let queue: Channel<BatteryEvent, 4> = Channel::new();
+        let mut battery = Battery::new(Max17048::new(Gauge { regs }), 10);
+        let first = battery.poll(None, &queue).unwrap();
+        assert_eq!(
+            first,
+            Reading {
+                percent: 11,
+                millivolts: 3900,
+                charge: ChargeState::Discharging
+            }
+        );
+        assert_eq!(queue.try_receive(), Some(BatteryEvent::Changed(first)));
+
+        // Same level: nothing to report.
+        battery.poll(None, &queue).unwrap();
+        assert_eq!(queue.try_receive(), None);
+
+        let mut gauge = battery.free();
+        gauge.i2c.regs[0x04] = 0x0900;
+        let mut battery = Battery::new(gauge, 10);
+        let low = battery.poll(None, &queue).unwrap();
+        assert_eq!(queue.try_receive(), Some(BatteryEvent::Changed(low)));
+        assert_eq!(queue.try_receive(), Some(BatteryEvent::Low(low)));
+
+        // The charger status overrides the gauge's rate.
+        let charging = battery.poll(Some(true), &queue).unwrap();
+        assert_eq!(charging.charge, ChargeState::Charging);
+        assert_eq!(queue.try_receive(), Some(BatteryEvent::Changed(charging)));
+        assert_eq!(queue.try_receive(), None);
+    }
+}
@@ -0,0 +1,314 @@
+//! 16-bit I2C GPIO expanders.
+//!
+//! PCA9555 and MCP23017 add sixteen pins on the I2C bus. Each
+//! [`ExpanderPin`] implements the `embedded-hal` digital traits, so it can
+//! replace a native pin in any driver that takes one:
+//!
+//! ```ignore
+//! let expander = Expander::new(i2c, EXPANDER_ADDRESS, Model::Pca9555)?;
+//! let mut reset = expander.pin(3);
+//! reset.set_as_output(PinState::High)?;
+//! let int = expander.pin(8);
+//! let mut touch = Gt911::new(touch_i2c, int, GT911_ADDRESS);
+//! ```
+//!
+//! Pins 0 to 7 are port 0 (port A on the MCP23017) and 8 to 15 port 1. The
+//! output latch and directions are cached, so setting an output is a
+//! single register pair write. The MCP23017 is used in its reset register
+//! layout, `IOCON.BANK` clear.
+
+use core::cell::RefCell;
+use embedded_hal::digital::{self, ErrorType, InputPin, OutputPin, PinState, StatefulOutputPin};
+use embedded_hal::i2c::I2c;
+
+/// Address of an expander with A2 to A0 low; the pins add 0 to 7.
+pub const EXPANDER_ADDRESS: u8 = 0x20;
+/// Number of pins of an expander.
+pub const PINS: u8 = 16;
+
+/// Expander chip, which sets the register map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Model {
+    Pca9555,
+    Mcp23017,
+}
+
+impl Model {
+    /// Register of port 0 input levels; port 1 follows.
+    const fn input(self) -> u8 {
+        match self {
+            Model::Pca9555 => 0x00,
+            Model::Mcp23017 => 0x12,
+        }
+    }
+
+    /// Register of the port 0 output latch.
+    const fn output(self) -> u8 {
+        match self {
+            Model::Pca9555 => 0x02,
+            Model::Mcp23017 => 0x14,
+        }
+    }
+
+    /// Register of port 0 directions, a set bit for an input.
+    const fn direction(self) -> u8 {
+        match self {
+            Model::Pca9555 => 0x06,
+            Model::Mcp23017 => 0x00,
+        }
+    }
+}
+
+/// Errors of expander pins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The I2C transfer failed.
+    I2c(E),
+}
+
+impl<E: core::fmt::Debug> digital::Error for Error<E> {
+    #[inline]
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+struct State<I2C> {
+    i2c: I2C,
+    output: u16,
+    /// Direction of each pin, a set bit for an input.
+    direction: u16,
+}
+
+/// GPIO expander on an I2C bus.
+pub struct Expander<I2C> {
+    state: RefCell<State<I2C>>,
+    address: u8,
+    model: Model,
+}
+
+impl<I2C: I2c> Expander<I2C> {
+    /// Creates a driver for the expander at 7-bit `address`, reading its
+    /// output latch and directions.
+    pub fn new(mut i2c: I2C, address: u8, model: Model) -> Result<Self, Error<I2C::Error>> {
+        let output = read_pair(&mut i2c, address, model.output())?;
+        let direction = read_pair(&mut i2c, address, model.direction())?;
+        Ok(Self {
+            state: RefCell::new(State {
+                i2c,
+                output,
+                direction,
+            }),
+            address,
+            model,
+        })
+    }
+
+    /// Returns pin `pin`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pin` is not below [`PINS`].
+    #[inline]
+    pub fn pin(&self, pin: u8) -> ExpanderPin<'_, I2C> {
+        assert!(pin < PINS, "expander pin out of range");
+        ExpanderPin {
+            expander: self,
+            mask: 1 << pin,
+        }
+    }
+
+    /// Reads the levels of all pins, pin 0 in bit 0.
+    pub fn read(&self) -> Result<u16, Error<I2C::Error>> {
+        let mut state = self.state.borrow_mut();
+        read_pair(&mut state.i2c, self.address, self.model.input())
+    }
+
+    /// Sets the output latch of the pins in `mask` to the bits of `levels`.
+    pub fn write(&self, mask: u16, levels: u16) -> Result<(), Error<I2C::Error>> {
+        let mut state = self.state.borrow_mut();
+        let output = (state.output & !mask) | (levels & mask);
+        write_pair(&mut state.i2c, self.address, self.model.output(), output)?;
+        state.output = output;
+        Ok(())
+    }
+
+    /// Makes the pins in `mask` inputs if `input`, outputs otherwise.
+    pub fn set_direction(&self, mask: u16, input: bool) -> Result<(), Error<I2C::Error>> {
+        let mut state = self.state.borrow_mut();
+        let direction = if input {
+            state.direction | mask
+        } else {
+            state.direction & !mask
+        };
+        write_pair(
+            &mut state.i2c,
+            self.address,
+            self.model.direction(),
+            direction,
+        )?;
+        state.direction = direction;
+        Ok(())
+    }
+
+    /// Releases the I2C bus.
+    #[inline]
+    pub fn free(self) -> I2C {
+        self.state.into_inner().i2c
+    }
+}
+
+fn read_pair<I2C: I2c>(i2c: &mut I2C, address: u8, reg: u8) -> Result<u16, Error<I2C::Error>> {
+    let mut value = [0; 2];
+    i2c.write_read(address, &[reg], &mut value)
+        .map_err(Error::I2c)?;
+    Ok(u16::from_le_bytes(value))
+}
+
+fn write_pair<I2C: I2c>(
+    i2c: &mut I2C,
+    address: u8,
+    reg: u8,
+    value: u16,
+) -> Result<(), Error<I2C::Error>> {
+    let [port0, port1] = value.to_le_bytes();
+    i2c.write(address, &[reg, port0, port1]).map_err(Error::I2c)
+}
+
+/// Pin of an [`Expander`].
+pub struct ExpanderPin<'a, I2C> {
+    expander: &'a Expander<I2C>,
+    mask: u16,
+}
+
+impl<I2C: I2c> ExpanderPin<'_, I2C> {
+    /// Makes the pin an input.
+    #[inline]
+    pub fn set_as_input(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.expander.set_direction(self.mask, true)
+    }
+
+    /// Makes the pin an output, driving `state` from the start.
+    pub fn set_as_output(&mut self, state: PinState) -> Result<(), Error<I2C::Error>> {
+        self.set_state(state)?;
+        self.expander.set_direction(self.mask, false)
+    }
+}
+
+impl<I2C: I2c> ErrorType for ExpanderPin<'_, I2C> {
+    type Error = Error<I2C::Error>;
+}
+
+impl<I2C: I2c> InputPin for ExpanderPin<'_, I2C> {
+    #[inline]
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.expander.read()? & self.mask != 0)
+    }
+
+    #[inline]
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+impl<I2C: I2c> OutputPin for ExpanderPin<'_, I2C> {
+    #[inline]
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.expander.write(self.mask, 0)
+    }
+
+    #[inline]
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.expander.write(self.mask, u16::MAX)
+    }
+}
+
+impl<I2C: I2c> StatefulOutputPin for ExpanderPin<'_, I2C> {
+    #[inline]
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.expander.state.borrow().output & self.mask != 0)
+    }
+
+    #[inline]
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_set_high().map(|high| !high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource, Operation};
+
+    /// MCP23017 whose input pins read back the levels in `pins`.
+    struct Mcp23017 {
+        regs: [u8; 0x16],
+        pins: u16,
+    }
+
+    impl embedded_hal::i2c::ErrorType for Mcp23017 {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Mcp23017 {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            if address != EXPANDER_ADDRESS {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            let [input_a, input_b] = self.pins.to_le_bytes();
+            self.regs[0x12] = input_a;
+            self.regs[0x13] = input_b;
+            let mut pointer = 0;
+            for operation in operations {
+                match operation {
+                    Operation::Write([reg, data @ ..]) => {
+                        pointer = *reg as usize;
+                        for byte in data {
+                            self.regs[pointer] = *byte;
+                            pointer += 1;
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        for byte in buf.iter_mut() {
+                            *byte = self.regs[pointer];
+                            pointer += 1;
+                        }
+                    }
+                    _ => return Err(ErrorKind::Other),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pins() {
+        let mut regs = [0; 0x16];
+        // All inputs at reset.
+        regs[0x00] = 0xFF;
+        regs[0x01] = 0xFF;
+        let chip = Mcp23017 { regs, pins: 0x0100 };
+        let expander = Expander::new(chip, EXPANDER_ADDRESS, Model::Mcp23017).unwrap();
+        let mut led = expander.pin(3);
+        let mut button = expander.pin(8);
+        led.set_as_output(PinState::High).unwrap();
+        assert_eq!(led.is_set_high(), Ok(true));
+        assert_eq!(button.is_high(), Ok(true));
+        led.set_low().unwrap();
+        expander.pin(15).set_as_output(PinState::High).unwrap();
+
+        let chip = expander.free();
+        assert_eq!(chip.regs[0x00..0x02], [0xF7, 0x7F]);
+        assert_eq!(chip.regs[0x14..0x16], [0x00, 0x80]);
+        assert!(matches!(
+            Expander::new(chip, 0x21, Model::Pca9555),
+            Err(Error::I2c(ErrorKind::NoAcknowledge(_)))
+        ));
+    }
+}
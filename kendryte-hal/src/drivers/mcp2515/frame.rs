@@ -0,0 +1,156 @@
+use super::register::{EXIDE, RTR, SRR};
+use embedded_can::{ExtendedId, Id, StandardId};
+
+/// A classic CAN 2.0 frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CanFrame {
+    id: Id,
+    remote: bool,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+impl CanFrame {
+    /// Encodes the frame as the `SIDH, SIDL, EID8, EID0, DLC, D0..D7` buffer layout.
+    pub(crate) fn to_buffer(&self) -> [u8; 13] {
+        let mut buffer = [0; 13];
+        buffer[..4].copy_from_slice(&encode_id(self.id));
+        buffer[4] = self.dlc | if self.remote { RTR } else { 0 };
+        buffer[5..].copy_from_slice(&self.data);
+        buffer
+    }
+
+    /// Decodes a frame from a receive buffer.
+    pub(crate) fn from_buffer(buffer: &[u8; 13]) -> Self {
+        let id = decode_id(&[buffer[0], buffer[1], buffer[2], buffer[3]]);
+        let remote = match id {
+            Id::Standard(_) => buffer[1] & SRR != 0,
+            Id::Extended(_) => buffer[4] & RTR != 0,
+        };
+        let dlc = (buffer[4] & 0x0F).min(8);
+        let mut data = [0; 8];
+        if !remote {
+            data[..dlc as usize].copy_from_slice(&buffer[5..5 + dlc as usize]);
+        }
+        Self {
+            id,
+            remote,
+            dlc,
+            data,
+        }
+    }
+}
+
+impl embedded_can::Frame for CanFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        let mut buffer = [0; 8];
+        buffer[..data.len()].copy_from_slice(data);
+        Some(Self {
+            id: id.into(),
+            remote: false,
+            dlc: data.len() as u8,
+            data: buffer,
+        })
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        Some(Self {
+            id: id.into(),
+            remote: true,
+            dlc: dlc as u8,
+            data: [0; 8],
+        })
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.remote
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn dlc(&self) -> usize {
+        self.dlc as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        match self.remote {
+            true => &[],
+            false => &self.data[..self.dlc as usize],
+        }
+    }
+}
+
+/// Encodes an identifier as the `SIDH, SIDL, EID8, EID0` register layout.
+pub(crate) fn encode_id(id: Id) -> [u8; 4] {
+    match id {
+        Id::Standard(id) => {
+            let raw = id.as_raw();
+            [(raw >> 3) as u8, ((raw & 0x07) << 5) as u8, 0, 0]
+        }
+        Id::Extended(id) => {
+            let raw = id.as_raw();
+            [
+                (raw >> 21) as u8,
+                (((raw >> 18) & 0x07) << 5) as u8 | EXIDE | ((raw >> 16) & 0x03) as u8,
+                (raw >> 8) as u8,
+                raw as u8,
+            ]
+        }
+    }
+}
+
+/// Decodes an identifier from the `SIDH, SIDL, EID8, EID0` register layout.
+pub(crate) fn decode_id(raw: &[u8; 4]) -> Id {
+    let sid = ((raw[0] as u32) << 3) | ((raw[1] as u32) >> 5);
+    if raw[1] & EXIDE != 0 {
+        let eid = (((raw[1] & 0x03) as u32) << 16) | ((raw[2] as u32) << 8) | raw[3] as u32;
+        // Always within range: 11 + 18 bits.
+        Id::Extended(ExtendedId::new((sid << 18) | eid).unwrap())
+    } else {
+        // Always within range: 11 bits.
+        Id::Standard(StandardId::new(sid as u16).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_can::Frame;
+
+    #[test]
+    fn id_round_trip() {
+        let standard = Id::Standard(StandardId::new(0x5A5).unwrap());
+        assert_eq!(encode_id(standard), [0xB4, 0xA0, 0x00, 0x00]);
+        assert_eq!(decode_id(&encode_id(standard)), standard);
+
+        let extended = Id::Extended(ExtendedId::new(0x1234_5678).unwrap());
+        assert_eq!(encode_id(extended), [0x91, 0xA8, 0x56, 0x78]);
+        assert_eq!(decode_id(&encode_id(extended)), extended);
+    }
+
+    #[test]
+    fn frame_buffer_round_trip() {
+        let id = ExtendedId::new(0x18DA_F110).unwrap();
+        let frame = CanFrame::new(id, &[1, 2, 3]).unwrap();
+        let decoded = CanFrame::from_buffer(&frame.to_buffer());
+        assert_eq!(decoded, frame);
+        assert_eq!(decoded.data(), &[1, 2, 3]);
+
+        let remote = CanFrame::new_remote(id, 4).unwrap();
+        let decoded = CanFrame::from_buffer(&remote.to_buffer());
+        assert!(decoded.is_remote_frame());
+        assert_eq!(decoded.dlc(), 4);
+    }
+}
@@ -0,0 +1,462 @@
+//! Microchip MCP2515 stand-alone CAN controller over SPI.
+//!
+//! The K230 has no CAN controller of its own; this driver talks to an MCP2515
+//! through any [`SpiDevice`] and implements the `embedded-can` traits on top of it.
+//!
+//! Received frames are drained from the two hardware buffers into a software
+//! queue by [`Mcp2515::on_interrupt`], which is meant to be called from the
+//! handler of the GPIO wired to the chip's `INT` pin. When no interrupt is used,
+//! [`embedded_can::nb::Can::receive`] polls the chip itself.
+mod frame;
+mod register;
+
+pub use frame::CanFrame;
+
+use embedded_can::{ErrorKind, Id};
+use embedded_hal::spi::{Operation, SpiDevice};
+use embedded_hal_nb::nb;
+use register::*;
+
+/// Number of `CANSTAT` reads before a mode change is considered failed.
+const MODE_CHANGE_ATTEMPTS: usize = 1000;
+
+/// Operation mode of the controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Normal bus operation.
+    Normal = 0b000,
+    /// Low power sleep mode.
+    Sleep = 0b001,
+    /// Internal loopback, frames are not sent on the bus.
+    Loopback = 0b010,
+    /// Receive only, no acknowledge or error frames are sent.
+    ListenOnly = 0b011,
+    /// Configuration mode, required to change bit timing and filters.
+    Configuration = 0b100,
+}
+
+/// CAN bit timing, in time quanta.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitTiming {
+    /// Baud rate prescaler, 1 to 64.
+    pub prescaler: u8,
+    /// Synchronization jump width, 1 to 4.
+    pub sjw: u8,
+    /// Propagation segment, 1 to 8.
+    pub prop_seg: u8,
+    /// Phase segment 1, 1 to 8.
+    pub phase_seg1: u8,
+    /// Phase segment 2, 2 to 8.
+    pub phase_seg2: u8,
+}
+
+impl BitTiming {
+    /// Derives a bit timing for `bitrate` from the controller oscillator frequency.
+    ///
+    /// Picks the largest number of time quanta per bit that divides the
+    /// oscillator exactly and places the sample point at about 75%.
+    /// Returns `None` if no exact timing exists.
+    pub fn new(oscillator_hz: u32, bitrate: u32) -> Option<Self> {
+        (8..=25).rev().find_map(|quanta: u32| {
+            let divisor = 2 * bitrate.checked_mul(quanta)?;
+            if divisor == 0 || oscillator_hz % divisor != 0 {
+                return None;
+            }
+            let prescaler = oscillator_hz / divisor;
+            if !(1..=64).contains(&prescaler) {
+                return None;
+            }
+            // Quanta before the sample point, excluding the synchronization segment.
+            let before = quanta * 3 / 4 - 1;
+            let phase_seg2 = quanta - 1 - before;
+            let prop_seg = before / 2;
+            let phase_seg1 = before - prop_seg;
+            if phase_seg1 > 8 || prop_seg > 8 || !(2..=8).contains(&phase_seg2) {
+                return None;
+            }
+            Some(Self {
+                prescaler: prescaler as u8,
+                sjw: 1,
+                prop_seg: prop_seg as u8,
+                phase_seg1: phase_seg1 as u8,
+                phase_seg2: phase_seg2 as u8,
+            })
+        })
+    }
+
+    /// Encodes the timing as the `CNF3, CNF2, CNF1` register values.
+    fn registers(&self) -> [u8; 3] {
+        let cnf1 = ((self.sjw - 1) << 6) | (self.prescaler - 1);
+        // BTLMODE: phase segment 2 is taken from CNF3.
+        let cnf2 = 0x80 | ((self.phase_seg1 - 1) << 3) | (self.prop_seg - 1);
+        let cnf3 = self.phase_seg2 - 1;
+        [cnf3, cnf2, cnf1]
+    }
+}
+
+/// Acceptance masks and filters of the two receive buffers.
+///
+/// A frame is accepted by a buffer when, for each bit set in its mask,
+/// the identifier matches one of the buffer's filters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Filters {
+    /// Mask applied to the filters of receive buffer 0.
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub rxb0_mask: Id,
+    /// Filters 0 and 1, feeding receive buffer 0.
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub rxb0: [Id; 2],
+    /// Mask applied to the filters of receive buffer 1.
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub rxb1_mask: Id,
+    /// Filters 2 to 5, feeding receive buffer 1.
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub rxb1: [Id; 4],
+}
+
+/// Configuration struct for the MCP2515.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// CAN bit timing.
+    pub timing: BitTiming,
+    /// Operation mode entered after configuration.
+    pub mode: Mode,
+    /// Acceptance filters, or `None` to receive every frame.
+    pub filters: Option<Filters>,
+    /// Whether frames for a full receive buffer 0 roll over into buffer 1.
+    pub rollover: bool,
+}
+
+impl Config {
+    /// Creates a new Config with the given bit timing.
+    ///
+    /// Default settings are:
+    /// - Normal mode.
+    /// - No filtering.
+    /// - Rollover enabled.
+    pub const fn new(timing: BitTiming) -> Self {
+        Self {
+            timing,
+            mode: Mode::Normal,
+            filters: None,
+            rollover: true,
+        }
+    }
+
+    /// Sets the operation mode entered after configuration.
+    pub const fn set_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the acceptance filters.
+    pub const fn set_filters(mut self, filters: Option<Filters>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Sets whether receive buffer 0 rolls over into buffer 1.
+    pub const fn set_rollover(mut self, rollover: bool) -> Self {
+        self.rollover = rollover;
+        self
+    }
+}
+
+/// Indicate different error conditions that may occur during CAN communication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The SPI transfer failed.
+    Spi(E),
+    /// A frame was lost because a receive buffer or the software queue was full.
+    Overrun,
+    /// The controller entered the error-passive state.
+    ErrorPassive,
+    /// The controller entered the bus-off state.
+    BusOff,
+    /// The controller did not enter the requested mode.
+    ModeChange,
+}
+
+impl<E: core::fmt::Debug> embedded_can::Error for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Fixed capacity queue of received frames.
+struct Queue<const N: usize> {
+    frames: [Option<CanFrame>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Queue<N> {
+    const fn new() -> Self {
+        Self {
+            frames: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends a frame, returning `false` if the queue is full.
+    fn push(&mut self, frame: CanFrame) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.frames[(self.head + self.len) % N] = Some(frame);
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<CanFrame> {
+        if self.len == 0 {
+            return None;
+        }
+        let frame = self.frames[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        frame
+    }
+}
+
+/// MCP2515 driver with a receive queue of `N` frames.
+pub struct Mcp2515<SPI: SpiDevice, const N: usize = 8> {
+    spi: SPI,
+    queue: Queue<N>,
+    mode: Mode,
+    error: Option<Error<SPI::Error>>,
+}
+
+impl<SPI: SpiDevice, const N: usize> Mcp2515<SPI, N> {
+    /// Resets and configures the controller.
+    pub fn new(spi: SPI, config: Config) -> Result<Self, Error<SPI::Error>> {
+        let mut mcp = Self {
+            spi,
+            queue: Queue::new(),
+            mode: Mode::Configuration,
+            error: None,
+        };
+        mcp.spi.write(&[INSTRUCTION_RESET]).map_err(Error::Spi)?;
+        mcp.wait_mode(Mode::Configuration)?;
+
+        mcp.write_registers(CNF3, &config.timing.registers())?;
+        mcp.write_filters(config.filters)?;
+        let bukt = if config.rollover { BUKT } else { 0 };
+        mcp.modify_register(RXB0CTRL, BUKT, bukt)?;
+        mcp.write_registers(CANINTE, &[RX0IF | RX1IF | ERRIF])?;
+        mcp.set_mode(config.mode)?;
+        Ok(mcp)
+    }
+
+    /// Requests an operation mode and waits until the controller enters it.
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), Error<SPI::Error>> {
+        self.modify_register(CANCTRL, OPMODE_MASK, (mode as u8) << 5)?;
+        self.wait_mode(mode)
+    }
+
+    /// Returns the current operation mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Replaces the acceptance filters, or receives every frame for `None`.
+    ///
+    /// The controller passes through configuration mode and returns to its current mode.
+    pub fn set_filters(&mut self, filters: Option<Filters>) -> Result<(), Error<SPI::Error>> {
+        let mode = self.mode;
+        self.set_mode(Mode::Configuration)?;
+        self.write_filters(filters)?;
+        self.set_mode(mode)
+    }
+
+    /// Services the controller interrupt.
+    ///
+    /// Moves received frames into the software queue and latches error
+    /// conditions, which are reported by the next receive call.
+    pub fn on_interrupt(&mut self) -> Result<(), Error<SPI::Error>> {
+        let flags = self.read_register(CANINTF)?;
+        for (flag, instruction) in [(RX0IF, 0x00), (RX1IF, 0x04)] {
+            if flags & flag != 0 {
+                // Reading through this instruction clears the buffer's interrupt flag.
+                let mut buffer = [0; 13];
+                self.spi
+                    .transaction(&mut [
+                        Operation::Write(&[INSTRUCTION_READ_RX_BUFFER | instruction]),
+                        Operation::Read(&mut buffer),
+                    ])
+                    .map_err(Error::Spi)?;
+                if !self.queue.push(CanFrame::from_buffer(&buffer)) {
+                    self.error = Some(Error::Overrun);
+                }
+            }
+        }
+        if flags & (ERRIF | MERRF) != 0 {
+            let eflg = self.read_register(EFLG)?;
+            if eflg & TXBO != 0 {
+                self.error = Some(Error::BusOff);
+            } else if eflg & (TXEP | RXEP) != 0 {
+                self.error = Some(Error::ErrorPassive);
+            } else if eflg & (RX0OVR | RX1OVR) != 0 {
+                self.error = Some(Error::Overrun);
+            }
+            self.modify_register(EFLG, RX0OVR | RX1OVR, 0)?;
+            self.modify_register(CANINTF, ERRIF | MERRF, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Releases the SPI device.
+    pub fn free(self) -> SPI {
+        self.spi
+    }
+
+    fn wait_mode(&mut self, mode: Mode) -> Result<(), Error<SPI::Error>> {
+        for _ in 0..MODE_CHANGE_ATTEMPTS {
+            if self.read_register(CANSTAT)? & OPMODE_MASK == (mode as u8) << 5 {
+                self.mode = mode;
+                return Ok(());
+            }
+        }
+        Err(Error::ModeChange)
+    }
+
+    fn write_filters(&mut self, filters: Option<Filters>) -> Result<(), Error<SPI::Error>> {
+        let Some(filters) = filters else {
+            // Receive any message, ignoring masks and filters.
+            self.modify_register(RXB0CTRL, RXM_MASK, RXM_MASK)?;
+            return self.modify_register(RXB1CTRL, RXM_MASK, RXM_MASK);
+        };
+        self.write_registers(RXM0SIDH, &frame::encode_id(filters.rxb0_mask))?;
+        self.write_registers(RXM1SIDH, &frame::encode_id(filters.rxb1_mask))?;
+        let ids = filters.rxb0.into_iter().chain(filters.rxb1);
+        for (address, id) in RXFSIDH.into_iter().zip(ids) {
+            self.write_registers(address, &frame::encode_id(id))?;
+        }
+        self.modify_register(RXB0CTRL, RXM_MASK, 0)?;
+        self.modify_register(RXB1CTRL, RXM_MASK, 0)
+    }
+
+    fn read_register(&mut self, address: u8) -> Result<u8, Error<SPI::Error>> {
+        let mut value = [0];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[INSTRUCTION_READ, address]),
+                Operation::Read(&mut value),
+            ])
+            .map_err(Error::Spi)?;
+        Ok(value[0])
+    }
+
+    fn write_registers(&mut self, address: u8, values: &[u8]) -> Result<(), Error<SPI::Error>> {
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[INSTRUCTION_WRITE, address]),
+                Operation::Write(values),
+            ])
+            .map_err(Error::Spi)
+    }
+
+    fn modify_register(
+        &mut self,
+        address: u8,
+        mask: u8,
+        value: u8,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.spi
+            .write(&[INSTRUCTION_BIT_MODIFY, address, mask, value])
+            .map_err(Error::Spi)
+    }
+}
+
+impl<SPI: SpiDevice, const N: usize> embedded_can::nb::Can for Mcp2515<SPI, N> {
+    type Frame = CanFrame;
+    type Error = Error<SPI::Error>;
+
+    /// Loads the frame into a free transmit buffer and requests its transmission.
+    ///
+    /// Pending frames are never replaced, so this returns `WouldBlock` while
+    /// all three transmit buffers are in use.
+    fn transmit(&mut self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, Self::Error> {
+        let mut status = [0];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[INSTRUCTION_READ_STATUS]),
+                Operation::Read(&mut status),
+            ])
+            .map_err(Error::Spi)?;
+        // TXREQ bits of TXB0, TXB1 and TXB2.
+        let Some(n) = (0..3).find(|n| status[0] & (1 << (2 + 2 * n)) == 0) else {
+            return Err(nb::Error::WouldBlock);
+        };
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[INSTRUCTION_LOAD_TX_BUFFER | (n << 1)]),
+                Operation::Write(&frame.to_buffer()),
+            ])
+            .map_err(Error::Spi)?;
+        self.spi
+            .write(&[INSTRUCTION_RTS | (1 << n)])
+            .map_err(Error::Spi)?;
+        Ok(None)
+    }
+
+    fn receive(&mut self) -> nb::Result<CanFrame, Self::Error> {
+        if let Some(error) = self.error.take() {
+            return Err(nb::Error::Other(error));
+        }
+        if let Some(frame) = self.queue.pop() {
+            return Ok(frame);
+        }
+        self.on_interrupt()?;
+        self.queue.pop().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<SPI: SpiDevice, const N: usize> embedded_can::blocking::Can for Mcp2515<SPI, N> {
+    type Frame = CanFrame;
+    type Error = Error<SPI::Error>;
+
+    fn transmit(&mut self, frame: &CanFrame) -> Result<(), Self::Error> {
+        nb::block!(embedded_can::nb::Can::transmit(self, frame)).map(|_| ())
+    }
+
+    fn receive(&mut self) -> Result<CanFrame, Self::Error> {
+        nb::block!(embedded_can::nb::Can::receive(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_timing() {
+        // 500 kbit/s from a 16 MHz crystal: 16 quanta, prescaler 1.
+        let timing = BitTiming::new(16_000_000, 500_000).unwrap();
+        assert_eq!(timing.prescaler, 1);
+        assert_eq!(
+            1 + timing.prop_seg + timing.phase_seg1 + timing.phase_seg2,
+            16
+        );
+        assert_eq!(timing.registers(), [0x03, 0xAC, 0x00]);
+
+        // 125 kbit/s from a 16 MHz crystal.
+        let timing = BitTiming::new(16_000_000, 125_000).unwrap();
+        let quanta = 1 + timing.prop_seg + timing.phase_seg1 + timing.phase_seg2;
+        assert_eq!(
+            16_000_000 / (2 * timing.prescaler as u32 * quanta as u32),
+            125_000
+        );
+
+        assert_eq!(BitTiming::new(8_000_000, 1_000_000), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! SPI instructions and register addresses of the MCP2515.
+
+/// Resets internal registers to the default state and enters configuration mode.
+pub const INSTRUCTION_RESET: u8 = 0xC0;
+/// Reads data from the register beginning at the selected address.
+pub const INSTRUCTION_READ: u8 = 0x03;
+/// Writes data to the register beginning at the selected address.
+pub const INSTRUCTION_WRITE: u8 = 0x02;
+/// Sets or clears individual bits in a register.
+pub const INSTRUCTION_BIT_MODIFY: u8 = 0x05;
+/// Quick polling command reading receive and transmit status bits.
+pub const INSTRUCTION_READ_STATUS: u8 = 0xA0;
+/// Reads a receive buffer starting at its SIDH register; `| 0x04` selects RXB1.
+pub const INSTRUCTION_READ_RX_BUFFER: u8 = 0x90;
+/// Loads a transmit buffer starting at its SIDH register; `| (n << 1)` selects TXBn.
+pub const INSTRUCTION_LOAD_TX_BUFFER: u8 = 0x40;
+/// Requests to send a transmit buffer; `| (1 << n)` selects TXBn.
+pub const INSTRUCTION_RTS: u8 = 0x80;
+
+/// CAN status register.
+pub const CANSTAT: u8 = 0x0E;
+/// CAN control register.
+pub const CANCTRL: u8 = 0x0F;
+/// Configuration register 3 (phase segment 2).
+pub const CNF3: u8 = 0x28;
+/// Configuration register 2 (propagation and phase segment 1).
+pub const CNF2: u8 = 0x29;
+/// Configuration register 1 (baud rate prescaler and jump width).
+pub const CNF1: u8 = 0x2A;
+/// Interrupt enable register.
+pub const CANINTE: u8 = 0x2B;
+/// Interrupt flag register.
+pub const CANINTF: u8 = 0x2C;
+/// Error flag register.
+pub const EFLG: u8 = 0x2D;
+/// Receive buffer 0 control register.
+pub const RXB0CTRL: u8 = 0x60;
+/// Receive buffer 1 control register.
+pub const RXB1CTRL: u8 = 0x70;
+/// Receive mask 0 (filters 0 and 1) standard identifier high register.
+pub const RXM0SIDH: u8 = 0x20;
+/// Receive mask 1 (filters 2 to 5) standard identifier high register.
+pub const RXM1SIDH: u8 = 0x24;
+/// Standard identifier high registers of receive filters 0 to 5.
+pub const RXFSIDH: [u8; 6] = [0x00, 0x04, 0x08, 0x10, 0x14, 0x18];
+
+/// Operation mode request field of `CANCTRL` and operation mode field of `CANSTAT`.
+pub const OPMODE_MASK: u8 = 0b1110_0000;
+/// Receive buffer operating mode field of `RXBnCTRL`.
+pub const RXM_MASK: u8 = 0b0110_0000;
+/// Rollover enable bit of `RXB0CTRL`.
+pub const BUKT: u8 = 1 << 2;
+
+/// Receive buffer 0 full interrupt.
+pub const RX0IF: u8 = 1 << 0;
+/// Receive buffer 1 full interrupt.
+pub const RX1IF: u8 = 1 << 1;
+/// Error interrupt (multiple sources in `EFLG`).
+pub const ERRIF: u8 = 1 << 5;
+/// Message error interrupt.
+pub const MERRF: u8 = 1 << 7;
+
+/// Receive buffer 1 overflow flag.
+pub const RX1OVR: u8 = 1 << 7;
+/// Receive buffer 0 overflow flag.
+pub const RX0OVR: u8 = 1 << 6;
+/// Bus-off error flag.
+pub const TXBO: u8 = 1 << 5;
+/// Transmit error-passive flag.
+pub const TXEP: u8 = 1 << 4;
+/// Receive error-passive flag.
+pub const RXEP: u8 = 1 << 3;
+
+/// Extended identifier enable bit of `SIDL`.
+pub const EXIDE: u8 = 1 << 3;
+/// Standard frame remote transmit request bit of received `SIDL`.
+pub const SRR: u8 = 1 << 4;
+/// Remote transmission request bit of `DLC`.
+pub const RTR: u8 = 1 << 6;
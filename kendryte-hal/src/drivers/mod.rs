@@ -0,0 +1,17 @@
+//! Drivers for external devices attached to the SoC peripherals.
+pub mod battery;
+pub mod expander;
+pub mod mcp2515;
+pub mod sccb;
+pub mod spi_display;
+pub mod tca9548;
+pub mod touch;
+
+/// Sharing of one I2C bus between several drivers, from `embedded-hal-bus`.
+///
+/// Sensor drivers take any [`embedded_hal::i2c::I2c`], so a bus wrapped in a
+/// [`RefCellDevice`](shared_i2c::RefCellDevice) can be handed to a breakout
+/// driver and the camera [`sccb::Sccb`] at once, all in one execution
+/// context. The runtime provides no `critical-section` implementation, so
+/// the bus cannot be shared with interrupt handlers or the other hart.
+pub use embedded_hal_bus::i2c as shared_i2c;
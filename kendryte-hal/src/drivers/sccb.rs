@@ -0,0 +1,257 @@
+//! Camera sensor configuration over SCCB.
+//!
+//! SCCB is the I2C-like bus of OmniVision-style image sensors. It differs
+//! from I2C in three ways this helper hides: registers often have 16-bit
+//! addresses, a read is a write of the register address followed by a
+//! separate read transaction rather than a repeated start, and the ninth
+//! bit of a write is "don't care", so some sensors never acknowledge data.
+//!
+//! Sensor init sequences are register tables with delays in between:
+//!
+//! ```ignore
+//! const INIT: &[Entry] = &[
+//!     Entry::write(0x3008, 0x82), // software reset
+//!     Entry::delay_ms(5),
+//!     Entry::write(0x3008, 0x02),
+//! ];
+//!
+//! let mut sensor = Sccb::new(i2c, 0x3C, AddressWidth::Sixteen);
+//! sensor.check_id(0x300A, 0x5640)?;
+//! sensor.write_table(INIT, &mut delay)?;
+//! ```
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c, NoAcknowledgeSource};
+
+/// Width of the sensor's register addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressWidth {
+    Eight,
+    Sixteen,
+}
+
+/// Step of a register table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Entry {
+    /// Write `value` to register `reg`.
+    Write { reg: u16, value: u8 },
+    /// Wait this many milliseconds.
+    Delay(u32),
+}
+
+impl Entry {
+    /// Writes `value` to register `reg`.
+    #[inline]
+    pub const fn write(reg: u16, value: u8) -> Self {
+        Entry::Write { reg, value }
+    }
+
+    /// Waits `ms` milliseconds.
+    #[inline]
+    pub const fn delay_ms(ms: u32) -> Self {
+        Entry::Delay(ms)
+    }
+}
+
+/// Errors of SCCB transfers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The I2C transfer failed.
+    I2c(E),
+    /// The sensor reported another chip ID.
+    UnexpectedId(u16),
+    /// A table write failed at the entry with this index.
+    Table(usize, E),
+}
+
+/// Sensor on an SCCB bus, driven through an I2C controller.
+pub struct Sccb<I2C> {
+    i2c: I2C,
+    address: u8,
+    width: AddressWidth,
+    tolerate_nack: bool,
+}
+
+impl<I2C: I2c> Sccb<I2C> {
+    /// Creates a helper for the sensor at 7-bit `address`.
+    ///
+    /// Missing acknowledges of written data are tolerated; see
+    /// [`set_nack_tolerance`](Self::set_nack_tolerance).
+    #[inline]
+    pub fn new(i2c: I2C, address: u8, width: AddressWidth) -> Self {
+        Self {
+            i2c,
+            address,
+            width,
+            tolerate_nack: true,
+        }
+    }
+
+    /// Sets whether a write whose data is not acknowledged counts as done.
+    ///
+    /// The address byte must always be acknowledged, so an absent sensor is
+    /// still reported.
+    #[inline]
+    pub fn set_nack_tolerance(&mut self, tolerate: bool) {
+        self.tolerate_nack = tolerate;
+    }
+
+    /// Writes `value` to register `reg`.
+    pub fn write_reg(&mut self, reg: u16, value: u8) -> Result<(), Error<I2C::Error>> {
+        let [high, low] = reg.to_be_bytes();
+        let result = match self.width {
+            AddressWidth::Eight => self.i2c.write(self.address, &[low, value]),
+            AddressWidth::Sixteen => self.i2c.write(self.address, &[high, low, value]),
+        };
+        match result {
+            Err(e)
+                if self.tolerate_nack
+                    && e.kind() == ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) =>
+            {
+                Ok(())
+            }
+            result => result.map_err(Error::I2c),
+        }
+    }
+
+    /// Reads register `reg`, as an address write followed by a separate read.
+    pub fn read_reg(&mut self, reg: u16) -> Result<u8, Error<I2C::Error>> {
+        let [high, low] = reg.to_be_bytes();
+        let address: &[u8] = match self.width {
+            AddressWidth::Eight => &[low],
+            AddressWidth::Sixteen => &[high, low],
+        };
+        let mut value = [0];
+        self.i2c.write(self.address, address).map_err(Error::I2c)?;
+        self.i2c
+            .read(self.address, &mut value)
+            .map_err(Error::I2c)?;
+        Ok(value[0])
+    }
+
+    /// Reads the big-endian 16-bit value of registers `reg` and `reg + 1`.
+    pub fn read_reg16(&mut self, reg: u16) -> Result<u16, Error<I2C::Error>> {
+        let high = self.read_reg(reg)?;
+        let low = self.read_reg(reg.wrapping_add(1))?;
+        Ok(u16::from_be_bytes([high, low]))
+    }
+
+    /// Replaces the bits of register `reg` selected by `mask` with those of `value`.
+    pub fn modify_reg(&mut self, reg: u16, mask: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        let current = self.read_reg(reg)?;
+        self.write_reg(reg, (current & !mask) | (value & mask))
+    }
+
+    /// Checks the 16-bit chip ID at `reg` against `expected`.
+    pub fn check_id(&mut self, reg: u16, expected: u16) -> Result<(), Error<I2C::Error>> {
+        match self.read_reg16(reg)? {
+            id if id == expected => Ok(()),
+            id => Err(Error::UnexpectedId(id)),
+        }
+    }
+
+    /// Runs a register table, stopping at the first failed write.
+    pub fn write_table(
+        &mut self,
+        table: &[Entry],
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<I2C::Error>> {
+        for (index, entry) in table.iter().enumerate() {
+            match *entry {
+                Entry::Write { reg, value } => match self.write_reg(reg, value) {
+                    Err(Error::I2c(e)) => return Err(Error::Table(index, e)),
+                    result => result?,
+                },
+                Entry::Delay(ms) => delay.delay_ms(ms),
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases the I2C bus.
+    #[inline]
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NoDelay;
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    /// Sensor with 16-bit register addresses that never acknowledges data.
+    struct Sensor {
+        regs: [u8; 8],
+        pointer: usize,
+        writes: usize,
+    }
+
+    impl ErrorType for Sensor {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Sensor {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            if address != 0x3C {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Write([high, low, data @ ..]) => {
+                        self.pointer = u16::from_be_bytes([*high, *low]) as usize & 7;
+                        if let [value] = data {
+                            self.regs[self.pointer] = *value;
+                            self.writes += 1;
+                            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data));
+                        }
+                    }
+                    Operation::Read(buf) => buf[0] = self.regs[self.pointer],
+                    _ => return Err(ErrorKind::Other),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tables_and_ids() {
+        let sensor = Sensor {
+            regs: [0x56, 0x40, 0, 0, 0, 0, 0, 0],
+            pointer: 0,
+            writes: 0,
+        };
+        let mut sccb = Sccb::new(sensor, 0x3C, AddressWidth::Sixteen);
+        assert_eq!(sccb.check_id(0x3000, 0x5640), Ok(()));
+        assert_eq!(
+            sccb.check_id(0x3001, 0x5640),
+            Err(Error::UnexpectedId(0x4000))
+        );
+
+        let mut delay = NoDelay::new();
+        let table = [
+            Entry::write(0x3002, 0x82),
+            Entry::delay_ms(5),
+            Entry::write(0x3003, 7),
+        ];
+        sccb.write_table(&table, &mut delay).unwrap();
+        sccb.modify_reg(0x3002, 0x0F, 0x01).unwrap();
+        assert_eq!(sccb.read_reg(0x3002), Ok(0x81));
+        assert_eq!(delay.waited_ns(), 5_000_000);
+
+        sccb.set_nack_tolerance(false);
+        assert!(matches!(
+            sccb.write_table(&table, &mut delay),
+            Err(Error::Table(0, ErrorKind::NoAcknowledge(_)))
+        ));
+        assert_eq!(sccb.free().writes, 4);
+    }
+}
@@ -0,0 +1,481 @@
+//! ST7789 and ILI9341 class SPI LCDs.
+//!
+//! Drawing goes to an RGB565 framebuffer in RAM and only marks the touched
+//! area dirty; [`SpiDisplay::flush`] then sends the bounding rectangle of
+//! everything drawn since the last flush, one row per SPI transaction. With
+//! a DMA-backed [`SpiDevice`] the rows go out without the CPU copying them.
+//!
+//! With the `embedded-graphics` feature the display is an
+//! `embedded_graphics_core::draw_target::DrawTarget`:
+//!
+//! ```ignore
+//! let mut buffer = [0u16; 240 * 240];
+//! let mut display = SpiDisplay::new(spi, dc, Config::st7789(240, 240), &mut buffer, &mut delay)?;
+//! Circle::new(Point::new(60, 60), 80)
+//!     .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+//!     .draw(&mut display)?;
+//! display.flush()?;
+//! ```
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+
+/// Widest panel supported, the long side of the ILI9341.
+pub const MAX_WIDTH: u16 = 320;
+
+const SWRESET: u8 = 0x01;
+const SLPOUT: u8 = 0x11;
+const NORON: u8 = 0x13;
+const INVON: u8 = 0x21;
+const DISPON: u8 = 0x29;
+const CASET: u8 = 0x2A;
+const RASET: u8 = 0x2B;
+const RAMWR: u8 = 0x2C;
+const MADCTL: u8 = 0x36;
+const COLMOD: u8 = 0x3A;
+
+/// Display controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Controller {
+    St7789,
+    Ili9341,
+}
+
+/// Orientation of the picture, clockwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// Returns the row/column exchange and mirror bits of `MADCTL`.
+    const fn madctl(self) -> u8 {
+        match self {
+            Rotation::Deg0 => 0x00,
+            Rotation::Deg90 => 0x60,
+            Rotation::Deg180 => 0xC0,
+            Rotation::Deg270 => 0xA0,
+        }
+    }
+}
+
+/// Panel configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub controller: Controller,
+    /// Visible width in the chosen rotation.
+    pub width: u16,
+    /// Visible height in the chosen rotation.
+    pub height: u16,
+    /// Column of the controller memory the visible area starts at.
+    pub x_offset: u16,
+    /// Row of the controller memory the visible area starts at.
+    pub y_offset: u16,
+    pub rotation: Rotation,
+    /// Inverts colors, needed by most IPS panels.
+    pub invert: bool,
+    /// Panel wired with blue and red swapped.
+    pub bgr: bool,
+}
+
+impl Config {
+    /// Configuration of an ST7789 IPS panel.
+    pub const fn st7789(width: u16, height: u16) -> Self {
+        Self {
+            controller: Controller::St7789,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            rotation: Rotation::Deg0,
+            invert: true,
+            bgr: false,
+        }
+    }
+
+    /// Configuration of an ILI9341 TFT panel.
+    pub const fn ili9341(width: u16, height: u16) -> Self {
+        Self {
+            controller: Controller::Ili9341,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            rotation: Rotation::Deg0,
+            invert: false,
+            bgr: true,
+        }
+    }
+}
+
+/// Area of the display, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    /// Creates a rectangle.
+    #[inline]
+    pub const fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the smallest rectangle covering both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Returns the part of the rectangle inside a `width` by `height` area.
+    pub fn clip(&self, width: u16, height: u16) -> Option<Rect> {
+        let right = self.x.saturating_add(self.width).min(width);
+        let bottom = self.y.saturating_add(self.height).min(height);
+        (right > self.x && bottom > self.y)
+            .then(|| Rect::new(self.x, self.y, right - self.x, bottom - self.y))
+    }
+}
+
+/// Errors of display operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<S, P> {
+    /// The SPI transfer failed.
+    Spi(S),
+    /// Driving the data/command pin failed.
+    Pin(P),
+    /// The framebuffer does not hold `width * height` pixels, or the panel is wider than [`MAX_WIDTH`].
+    InvalidSize,
+}
+
+/// SPI LCD with a framebuffer and dirty-rectangle tracking.
+pub struct SpiDisplay<'a, SPI, DC> {
+    spi: SPI,
+    dc: DC,
+    config: Config,
+    buffer: &'a mut [u16],
+    dirty: Option<Rect>,
+}
+
+impl<'a, SPI: SpiDevice, DC: OutputPin> SpiDisplay<'a, SPI, DC> {
+    /// Resets and configures the controller, with the whole screen dirty.
+    ///
+    /// `buffer` holds `width * height` RGB565 pixels.
+    pub fn new(
+        spi: SPI,
+        dc: DC,
+        config: Config,
+        buffer: &'a mut [u16],
+        delay: &mut impl DelayNs,
+    ) -> Result<Self, Error<SPI::Error, DC::Error>> {
+        if config.width > MAX_WIDTH
+            || buffer.len() != config.width as usize * config.height as usize
+        {
+            return Err(Error::InvalidSize);
+        }
+        let mut display = Self {
+            spi,
+            dc,
+            config,
+            buffer,
+            dirty: None,
+        };
+        display.command(SWRESET, &[])?;
+        delay.delay_ms(150);
+        display.command(SLPOUT, &[])?;
+        delay.delay_ms(120);
+        // 16 bits per pixel.
+        display.command(COLMOD, &[0x55])?;
+        let bgr = if config.bgr { 0x08 } else { 0 };
+        display.command(MADCTL, &[config.rotation.madctl() | bgr])?;
+        if config.invert {
+            display.command(INVON, &[])?;
+        }
+        display.command(NORON, &[])?;
+        display.command(DISPON, &[])?;
+        display.dirty = Some(Rect::new(0, 0, config.width, config.height));
+        Ok(display)
+    }
+
+    /// Returns the visible width.
+    #[inline]
+    pub fn width(&self) -> u16 {
+        self.config.width
+    }
+
+    /// Returns the visible height.
+    #[inline]
+    pub fn height(&self) -> u16 {
+        self.config.height
+    }
+
+    /// Sets a pixel; pixels outside the screen are ignored.
+    pub fn set_pixel(&mut self, x: u16, y: u16, color: u16) {
+        if x < self.config.width && y < self.config.height {
+            self.buffer[y as usize * self.config.width as usize + x as usize] = color;
+            self.mark_dirty(Rect::new(x, y, 1, 1));
+        }
+    }
+
+    /// Fills the part of `rect` on the screen.
+    pub fn fill_rect(&mut self, rect: Rect, color: u16) {
+        let Some(rect) = rect.clip(self.config.width, self.config.height) else {
+            return;
+        };
+        let stride = self.config.width as usize;
+        for row in rect.y..rect.y + rect.height {
+            let start = row as usize * stride + rect.x as usize;
+            self.buffer[start..start + rect.width as usize].fill(color);
+        }
+        self.mark_dirty(rect);
+    }
+
+    /// Fills the whole screen.
+    pub fn clear(&mut self, color: u16) {
+        self.fill_rect(
+            Rect::new(0, 0, self.config.width, self.config.height),
+            color,
+        );
+    }
+
+    /// Returns the framebuffer, row by row.
+    #[inline]
+    pub fn buffer(&self) -> &[u16] {
+        self.buffer
+    }
+
+    /// Marks `rect` as changed, after drawing into the framebuffer directly.
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(&rect),
+            None => rect,
+        });
+    }
+
+    /// Returns the area drawn since the last flush.
+    #[inline]
+    pub fn dirty(&self) -> Option<Rect> {
+        self.dirty
+    }
+
+    /// Sends the dirty area to the controller.
+    pub fn flush(&mut self) -> Result<(), Error<SPI::Error, DC::Error>> {
+        let Some(rect) = self.dirty.take() else {
+            return Ok(());
+        };
+        let [x0, x1] = [rect.x, rect.x + rect.width - 1].map(|x| x + self.config.x_offset);
+        let [y0, y1] = [rect.y, rect.y + rect.height - 1].map(|y| y + self.config.y_offset);
+        self.command(
+            CASET,
+            &[(x0 >> 8) as u8, x0 as u8, (x1 >> 8) as u8, x1 as u8],
+        )?;
+        self.command(
+            RASET,
+            &[(y0 >> 8) as u8, y0 as u8, (y1 >> 8) as u8, y1 as u8],
+        )?;
+        self.command(RAMWR, &[])?;
+
+        self.dc.set_high().map_err(Error::Pin)?;
+        let stride = self.config.width as usize;
+        let mut row = [0; 2 * MAX_WIDTH as usize];
+        let row = &mut row[..2 * rect.width as usize];
+        for y in rect.y..rect.y + rect.height {
+            let start = y as usize * stride + rect.x as usize;
+            let pixels = &self.buffer[start..start + rect.width as usize];
+            for (bytes, pixel) in row.chunks_exact_mut(2).zip(pixels) {
+                bytes.copy_from_slice(&pixel.to_be_bytes());
+            }
+            self.spi.write(row).map_err(Error::Spi)?;
+        }
+        Ok(())
+    }
+
+    /// Releases the SPI device and data/command pin.
+    pub fn free(self) -> (SPI, DC) {
+        (self.spi, self.dc)
+    }
+
+    fn command(&mut self, command: u8, params: &[u8]) -> Result<(), Error<SPI::Error, DC::Error>> {
+        self.dc.set_low().map_err(Error::Pin)?;
+        self.spi.write(&[command]).map_err(Error::Spi)?;
+        if !params.is_empty() {
+            self.dc.set_high().map_err(Error::Pin)?;
+            self.spi.write(params).map_err(Error::Spi)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-graphics")]
+mod graphics {
+    use super::{Rect, SpiDisplay};
+    use core::convert::Infallible;
+    use embedded_graphics_core::pixelcolor::Rgb565;
+    use embedded_graphics_core::pixelcolor::raw::{RawData, RawU16};
+    use embedded_graphics_core::prelude::*;
+    use embedded_graphics_core::primitives::Rectangle;
+    use embedded_hal::digital::OutputPin;
+    use embedded_hal::spi::SpiDevice;
+
+    fn raw(color: Rgb565) -> u16 {
+        RawU16::from(color).into_inner()
+    }
+
+    impl<SPI: SpiDevice, DC: OutputPin> OriginDimensions for SpiDisplay<'_, SPI, DC> {
+        fn size(&self) -> Size {
+            Size::new(self.width() as u32, self.height() as u32)
+        }
+    }
+
+    /// Draws into the framebuffer; call [`SpiDisplay::flush`] to show it.
+    impl<SPI: SpiDevice, DC: OutputPin> DrawTarget for SpiDisplay<'_, SPI, DC> {
+        type Color = Rgb565;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+        where
+            I: IntoIterator<Item = Pixel<Rgb565>>,
+        {
+            for Pixel(point, color) in pixels {
+                if let (Ok(x), Ok(y)) = (u16::try_from(point.x), u16::try_from(point.y)) {
+                    self.set_pixel(x, y, raw(color));
+                }
+            }
+            Ok(())
+        }
+
+        fn fill_solid(&mut self, area: &Rectangle, color: Rgb565) -> Result<(), Infallible> {
+            let area = area.intersection(&self.bounding_box());
+            if let Some(bottom_right) = area.bottom_right() {
+                let rect = Rect::new(
+                    area.top_left.x as u16,
+                    area.top_left.y as u16,
+                    (bottom_right.x - area.top_left.x + 1) as u16,
+                    (bottom_right.y - area.top_left.y + 1) as u16,
+                );
+                self.fill_rect(rect, raw(color));
+            }
+            Ok(())
+        }
+
+        fn clear(&mut self, color: Rgb565) -> Result<(), Infallible> {
+            SpiDisplay::clear(self, raw(color));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NoDelay;
+    use core::convert::Infallible;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use embedded_hal::spi::{ErrorType, Operation};
+
+    static DC: AtomicBool = AtomicBool::new(false);
+
+    /// Records the last command and the data bytes sent after it.
+    struct Bus {
+        command: u8,
+        data: [u8; 64],
+        len: usize,
+    }
+
+    impl ErrorType for Bus {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for Bus {
+        fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            for operation in operations {
+                if let Operation::Write(bytes) = operation {
+                    if DC.load(Ordering::Relaxed) {
+                        self.data[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+                        self.len += bytes.len();
+                    } else {
+                        self.command = bytes[0];
+                        self.len = 0;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct Dc;
+
+    impl embedded_hal::digital::ErrorType for Dc {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Dc {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            DC.store(false, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            DC.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flushes_dirty_rectangle() {
+        let bus = || Bus {
+            command: 0,
+            data: [0; 64],
+            len: 0,
+        };
+        let config = Config::st7789(8, 4);
+        assert!(matches!(
+            SpiDisplay::new(bus(), Dc, config, &mut [0; 3], &mut NoDelay::new()),
+            Err(Error::InvalidSize)
+        ));
+
+        let mut buffer = [0; 8 * 4];
+        let mut display =
+            SpiDisplay::new(bus(), Dc, config, &mut buffer, &mut NoDelay::new()).unwrap();
+        assert_eq!(display.dirty(), Some(Rect::new(0, 0, 8, 4)));
+        display.flush().unwrap();
+        assert_eq!(display.dirty(), None);
+
+        display.set_pixel(2, 1, 0x1234);
+        display.fill_rect(Rect::new(3, 2, 10, 1), 0xABCD);
+        display.set_pixel(9, 9, 0xFFFF);
+        assert_eq!(display.dirty(), Some(Rect::new(2, 1, 6, 2)));
+        display.flush().unwrap();
+
+        // Two rows of six pixels, big-endian, after the memory write command.
+        let (bus, _) = display.free();
+        assert_eq!(bus.command, RAMWR);
+        assert_eq!(bus.len, 24);
+        assert_eq!(bus.data[..2], [0x12, 0x34]);
+        assert_eq!(bus.data[14..16], [0xAB, 0xCD]);
+        assert_eq!(bus.data[22..24], [0xAB, 0xCD]);
+        assert_eq!(
+            Rect::new(5, 5, 10, 10).clip(8, 8),
+            Some(Rect::new(5, 5, 3, 3))
+        );
+    }
+}
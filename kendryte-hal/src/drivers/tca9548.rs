@@ -0,0 +1,181 @@
+//! TCA9548-style I2C multiplexers.
+//!
+//! A multiplexer sits on one bus and connects it to one of eight downstream
+//! channels, so several sensors with the same address can be attached.
+//! Each [`MuxChannel`] is an [`I2c`] bus of its own that selects its channel
+//! before every transaction, and can be handed to any driver:
+//!
+//! ```ignore
+//! let mux = Tca9548::new(i2c, TCA9548_ADDRESS);
+//! let mut left = Sccb::new(mux.channel(0), 0x3C, AddressWidth::Sixteen);
+//! let mut right = Sccb::new(mux.channel(1), 0x3C, AddressWidth::Sixteen);
+//! ```
+//!
+//! The channels share the bus through a [`RefCell`], so they must stay in
+//! one execution context. To keep other devices on the upstream bus as
+//! well, give the multiplexer one device of a shared bus from
+//! [`shared_i2c`](super::shared_i2c).
+
+use core::cell::RefCell;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+
+/// Address of a TCA9548 with A2 to A0 low; the pins add 0 to 7.
+pub const TCA9548_ADDRESS: u8 = 0x70;
+/// Number of downstream channels.
+pub const CHANNELS: u8 = 8;
+
+struct Bus<I2C> {
+    i2c: I2C,
+    /// Channel mask last written, if known.
+    selected: Option<u8>,
+}
+
+/// I2C multiplexer with eight channels.
+pub struct Tca9548<I2C> {
+    bus: RefCell<Bus<I2C>>,
+    address: u8,
+}
+
+impl<I2C: I2c> Tca9548<I2C> {
+    /// Creates a driver for the multiplexer at 7-bit `address`.
+    #[inline]
+    pub const fn new(i2c: I2C, address: u8) -> Self {
+        Self {
+            bus: RefCell::new(Bus {
+                i2c,
+                selected: None,
+            }),
+            address,
+        }
+    }
+
+    /// Returns the bus behind downstream channel `channel`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not below [`CHANNELS`].
+    #[inline]
+    pub fn channel(&self, channel: u8) -> MuxChannel<'_, I2C> {
+        assert!(channel < CHANNELS, "multiplexer channel out of range");
+        MuxChannel {
+            mux: self,
+            mask: 1 << channel,
+        }
+    }
+
+    /// Disconnects all downstream channels.
+    pub fn disconnect(&self) -> Result<(), I2C::Error> {
+        self.select(&mut self.bus.borrow_mut(), 0)
+    }
+
+    /// Releases the upstream bus.
+    #[inline]
+    pub fn free(self) -> I2C {
+        self.bus.into_inner().i2c
+    }
+
+    fn select(&self, bus: &mut Bus<I2C>, mask: u8) -> Result<(), I2C::Error> {
+        if bus.selected == Some(mask) {
+            return Ok(());
+        }
+        // A failed write may have changed the selection or not.
+        bus.selected = None;
+        bus.i2c.write(self.address, &[mask])?;
+        bus.selected = Some(mask);
+        Ok(())
+    }
+}
+
+/// Downstream channel of a [`Tca9548`].
+pub struct MuxChannel<'a, I2C> {
+    mux: &'a Tca9548<I2C>,
+    mask: u8,
+}
+
+impl<I2C: I2c> ErrorType for MuxChannel<'_, I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for MuxChannel<'_, I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut bus = self.mux.bus.borrow_mut();
+        self.mux.select(&mut bus, self.mask)?;
+        bus.i2c.transaction(address, operations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+
+    /// Multiplexer with one single-register device at 0x40 on each channel.
+    struct Bus {
+        mask: u8,
+        regs: [u8; 8],
+        selects: usize,
+    }
+
+    impl ErrorType for Bus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Bus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            let channel = match (address, self.mask) {
+                (TCA9548_ADDRESS, _) => {
+                    if let [Operation::Write([mask])] = operations {
+                        self.mask = *mask;
+                        self.selects += 1;
+                        return Ok(());
+                    }
+                    return Err(ErrorKind::Other);
+                }
+                (0x40, mask) if mask.count_ones() == 1 => mask.trailing_zeros() as usize,
+                _ => return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            };
+            for operation in operations {
+                match operation {
+                    Operation::Write([value]) => self.regs[channel] = *value,
+                    Operation::Read([value]) => *value = self.regs[channel],
+                    _ => return Err(ErrorKind::Other),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn channels() {
+        let bus = Bus {
+            mask: 0,
+            regs: [0; 8],
+            selects: 0,
+        };
+        let mux = Tca9548::new(bus, TCA9548_ADDRESS);
+        let (mut left, mut right) = (mux.channel(0), mux.channel(5));
+        left.write(0x40, &[1]).unwrap();
+        left.write(0x40, &[2]).unwrap();
+        right.write(0x40, &[3]).unwrap();
+        let mut value = [0];
+        left.read(0x40, &mut value).unwrap();
+        assert_eq!(value, [2]);
+
+        mux.disconnect().unwrap();
+        assert_eq!(
+            right.read(0x41, &mut value),
+            Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        );
+        let bus = mux.free();
+        assert_eq!(bus.regs, [2, 0, 0, 0, 0, 3, 0, 0]);
+        assert_eq!(bus.selects, 5);
+    }
+}
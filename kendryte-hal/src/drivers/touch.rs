@@ -0,0 +1,432 @@
+//! Capacitive touch controllers on I2C.
+//!
+//! A [`TouchController`] reports the contacts of the latest frame;
+//! [`TouchScreen`] compares consecutive frames and turns them into
+//! [`TouchEvent`]s on a [`Channel`], so a UI task can wait on the queue while
+//! the interrupt line of the controller drives polling:
+//!
+//! ```ignore
+//! static TOUCH: Channel<TouchEvent, 16> = Channel::new();
+//!
+//! let mut screen = TouchScreen::new(Gt911::new(i2c, int, GT911_ADDRESS));
+//! // From the GPIO interrupt handler, or a periodic task:
+//! screen.poll(&TOUCH)?;
+//! // In the UI task:
+//! match TOUCH.receive().await {
+//!     TouchEvent::Down(point) => ...,
+//!     ...
+//! }
+//! ```
+//!
+//! Both drivers leave the interrupt line to the caller's GPIO setup and only
+//! sample it: while it is inactive the controller has no new frame and the
+//! bus is left alone.
+
+use crate::sync::Channel;
+use embedded_hal::digital::InputPin;
+use embedded_hal::i2c::I2c;
+
+/// Most contacts a frame can hold.
+pub const MAX_POINTS: usize = 5;
+
+/// Address of a GT911 strapped with INT low at reset.
+pub const GT911_ADDRESS: u8 = 0x5D;
+/// Address of a GT911 strapped with INT high at reset.
+pub const GT911_ADDRESS_ALT: u8 = 0x14;
+/// Address of FT5x06 family controllers.
+pub const FT5X06_ADDRESS: u8 = 0x38;
+
+/// Contact on the panel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchPoint {
+    /// Identifier kept by the controller while the contact lasts.
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+    /// Contact area or pressure, in controller units; zero if not reported.
+    pub size: u16,
+}
+
+/// Change of one contact between frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TouchEvent {
+    /// A new contact.
+    Down(TouchPoint),
+    /// A contact moved.
+    Move(TouchPoint),
+    /// A contact ended, at its last position.
+    Up(TouchPoint),
+}
+
+/// Errors of touch controller drivers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<I, P> {
+    /// The I2C transfer failed.
+    I2c(I),
+    /// Sampling the interrupt line failed.
+    Pin(P),
+}
+
+/// Touch controller reporting frames of contacts.
+pub trait TouchController {
+    type Error;
+
+    /// Reads the contacts of a new frame into `points`, returning how many there are.
+    ///
+    /// Returns `None` if the controller has no frame since the last read.
+    fn read(&mut self, points: &mut [TouchPoint; MAX_POINTS])
+    -> Result<Option<usize>, Self::Error>;
+}
+
+/// Goodix GT911.
+pub struct Gt911<I2C, INT> {
+    i2c: I2C,
+    int: INT,
+    address: u8,
+}
+
+impl<I2C: I2c, INT: InputPin> Gt911<I2C, INT> {
+    const PRODUCT_ID: u16 = 0x8140;
+    const STATUS: u16 = 0x814E;
+    const POINTS: u16 = 0x814F;
+
+    /// Creates a driver for the controller at `address`, with its active-low interrupt line `int`.
+    #[inline]
+    pub fn new(i2c: I2C, int: INT, address: u8) -> Self {
+        Self { i2c, int, address }
+    }
+
+    /// Reads the four-character product ID, `b"911\0"` for a GT911.
+    pub fn product_id(&mut self) -> Result<[u8; 4], Error<I2C::Error, INT::Error>> {
+        let mut id = [0; 4];
+        self.read_regs(Self::PRODUCT_ID, &mut id)?;
+        Ok(id)
+    }
+
+    /// Releases the I2C bus and interrupt line.
+    #[inline]
+    pub fn free(self) -> (I2C, INT) {
+        (self.i2c, self.int)
+    }
+
+    fn read_regs(&mut self, reg: u16, buf: &mut [u8]) -> Result<(), Error<I2C::Error, INT::Error>> {
+        self.i2c
+            .write_read(self.address, &reg.to_be_bytes(), buf)
+            .map_err(Error::I2c)
+    }
+}
+
+impl<I2C: I2c, INT: InputPin> TouchController for Gt911<I2C, INT> {
+    type Error = Error<I2C::Error, INT::Error>;
+
+    fn read(
+        &mut self,
+        points: &mut [TouchPoint; MAX_POINTS],
+    ) -> Result<Option<usize>, Self::Error> {
+        if self.int.is_high().map_err(Error::Pin)? {
+            return Ok(None);
+        }
+        let mut status = [0];
+        self.read_regs(Self::STATUS, &mut status)?;
+        // Bit 7 is set once the frame is complete.
+        if status[0] & 0x80 == 0 {
+            return Ok(None);
+        }
+        let count = ((status[0] & 0x0F) as usize).min(MAX_POINTS);
+        let mut raw = [0; 8 * MAX_POINTS];
+        self.read_regs(Self::POINTS, &mut raw[..8 * count])?;
+        for (point, raw) in points.iter_mut().zip(raw.chunks_exact(8)).take(count) {
+            *point = TouchPoint {
+                id: raw[0],
+                x: u16::from_le_bytes([raw[1], raw[2]]),
+                y: u16::from_le_bytes([raw[3], raw[4]]),
+                size: u16::from_le_bytes([raw[5], raw[6]]),
+            };
+        }
+        // Hands the buffer back to the controller for the next frame.
+        let [high, low] = Self::STATUS.to_be_bytes();
+        self.i2c
+            .write(self.address, &[high, low, 0])
+            .map_err(Error::I2c)?;
+        Ok(Some(count))
+    }
+}
+
+/// FocalTech FT5x06 family (FT5206, FT5306, FT5406, FT6206 and similar).
+pub struct Ft5x06<I2C, INT> {
+    i2c: I2C,
+    int: INT,
+    address: u8,
+}
+
+impl<I2C: I2c, INT: InputPin> Ft5x06<I2C, INT> {
+    const TD_STATUS: u8 = 0x02;
+    const CHIP_ID: u8 = 0xA3;
+
+    /// Creates a driver for the controller at `address`, with its active-low interrupt line `int`.
+    #[inline]
+    pub fn new(i2c: I2C, int: INT, address: u8) -> Self {
+        Self { i2c, int, address }
+    }
+
+    /// Reads the chip ID register.
+    pub fn chip_id(&mut self) -> Result<u8, Error<I2C::Error, INT::Error>> {
+        let mut id = [0];
+        self.i2c
+            .write_read(self.address, &[Self::CHIP_ID], &mut id)
+            .map_err(Error::I2c)?;
+        Ok(id[0])
+    }
+
+    /// Releases the I2C bus and interrupt line.
+    #[inline]
+    pub fn free(self) -> (I2C, INT) {
+        (self.i2c, self.int)
+    }
+}
+
+impl<I2C: I2c, INT: InputPin> TouchController for Ft5x06<I2C, INT> {
+    type Error = Error<I2C::Error, INT::Error>;
+
+    fn read(
+        &mut self,
+        points: &mut [TouchPoint; MAX_POINTS],
+    ) -> Result<Option<usize>, Self::Error> {
+        if self.int.is_high().map_err(Error::Pin)? {
+            return Ok(None);
+        }
+        // Status followed by six bytes per contact, read in one transfer.
+        let mut raw = [0; 1 + 6 * MAX_POINTS];
+        self.i2c
+            .write_read(self.address, &[Self::TD_STATUS], &mut raw)
+            .map_err(Error::I2c)?;
+        let count = ((raw[0] & 0x0F) as usize).min(MAX_POINTS);
+        for (point, raw) in points.iter_mut().zip(raw[1..].chunks_exact(6)).take(count) {
+            *point = TouchPoint {
+                id: raw[2] >> 4,
+                x: u16::from_be_bytes([raw[0] & 0x0F, raw[1]]),
+                y: u16::from_be_bytes([raw[2] & 0x0F, raw[3]]),
+                size: raw[4] as u16,
+            };
+        }
+        Ok(Some(count))
+    }
+}
+
+/// Contacts of the previous frame, to derive events from the next one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tracker {
+    points: [TouchPoint; MAX_POINTS],
+    count: usize,
+}
+
+impl Tracker {
+    /// Creates a tracker with no contacts.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            points: [TouchPoint {
+                id: 0,
+                x: 0,
+                y: 0,
+                size: 0,
+            }; MAX_POINTS],
+            count: 0,
+        }
+    }
+
+    /// Returns the contacts of the last frame.
+    #[inline]
+    pub fn points(&self) -> &[TouchPoint] {
+        &self.points[..self.count]
+    }
+
+    /// Compares a new frame with the last, passing ended contacts, then new
+    /// and moved ones, to `emit`.
+    pub fn update(&mut self, points: &[TouchPoint], mut emit: impl FnMut(TouchEvent)) {
+        let points = &points[..points.len().min(MAX_POINTS)];
+        for old in self.points() {
+            if !points.iter().any(|new| new.id == old.id) {
+                emit(TouchEvent::Up(*old));
+            }
+        }
+        for new in points {
+            match self.points().iter().find(|old| old.id == new.id) {
+                None => emit(TouchEvent::Down(*new)),
+                Some(old) if (old.x, old.y) != (new.x, new.y) => emit(TouchEvent::Move(*new)),
+                Some(_) => {}
+            }
+        }
+        self.points[..points.len()].copy_from_slice(points);
+        self.count = points.len();
+    }
+}
+
+/// Touch controller feeding an event queue.
+pub struct TouchScreen<C> {
+    controller: C,
+    tracker: Tracker,
+}
+
+impl<C: TouchController> TouchScreen<C> {
+    /// Wraps a controller, starting with no contacts.
+    #[inline]
+    pub const fn new(controller: C) -> Self {
+        Self {
+            controller,
+            tracker: Tracker::new(),
+        }
+    }
+
+    /// Reads a frame if there is one and queues its events, returning how many were queued.
+    ///
+    /// Events that do not fit in the queue are dropped; the next frame is
+    /// still compared against this one.
+    pub fn poll<const N: usize>(
+        &mut self,
+        queue: &Channel<TouchEvent, N>,
+    ) -> Result<usize, C::Error> {
+        let mut points = [TouchPoint::default(); MAX_POINTS];
+        let Some(count) = self.controller.read(&mut points)? else {
+            return Ok(0);
+        };
+        let mut queued = 0;
+        self.tracker.update(&points[..count], |event| {
+            if queue.try_send(event).is_ok() {
+                queued += 1;
+            }
+        });
+        Ok(queued)
+    }
+
+    /// Returns the contacts of the last frame.
+    #[inline]
+    pub fn points(&self) -> &[TouchPoint] {
+        self.tracker.points()
+    }
+
+    /// Releases the controller.
+    #[inline]
+    pub fn free(self) -> C {
+        self.controller
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+
+    /// FT5x06 reporting one frame of registers from `TD_STATUS` on.
+    struct Panel {
+        regs: [u8; 1 + 6 * MAX_POINTS],
+    }
+
+    impl ErrorType for Panel {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Panel {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            match operations {
+                [Operation::Write([0x02]), Operation::Read(buf)] if address == FT5X06_ADDRESS => {
+                    buf.copy_from_slice(&self.regs);
+                    Ok(())
+                }
+                _ => Err(ErrorKind::Other),
+            }
+        }
+    }
+
+    struct Int(bool);
+
+    impl embedded_hal::digital::ErrorType for Int {
+        type Error = Infallible;
+    }
+
+    impl InputPin for Int {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0)
+        }
+    }
+
+    fn point(id: u8, x: u16, y: u16) -> TouchPoint {
+        TouchPoint { id, x, y, size: 0 }
+    }
+
+    #[test]
+    fn reads_ft5x06_frames() {
+        let mut regs = [0; 1 + 6 * MAX_POINTS];
+        regs[..13].copy_from_slice(&[
+            2, // two contacts
+            0x01, 0x20, 0x00, 0x40, 9, 0, // id 0 at (0x120, 0x40)
+            0x00, 0x10, 0x13, 0x02, 4, 0, // id 1 at (0x10, 0x302)
+        ]);
+        let mut ft = Ft5x06::new(Panel { regs }, Int(true), FT5X06_ADDRESS);
+        let mut points = [TouchPoint::default(); MAX_POINTS];
+        assert_eq!(ft.read(&mut points), Ok(None));
+
+        let (panel, _) = ft.free();
+        let mut ft = Ft5x06::new(panel, Int(false), FT5X06_ADDRESS);
+        assert_eq!(ft.read(&mut points), Ok(Some(2)));
+        assert_eq!(
+            points[0],
+            TouchPoint {
+                id: 0,
+                x: 0x120,
+                y: 0x40,
+                size: 9
+            }
+        );
+        assert_eq!((points[1].id, points[1].x, points[1].y), (1, 0x10, 0x302));
+    }
+
+    #[test]
+    fn tracks_contacts() {
+        let mut tracker = Tracker::new();
+        let mut events = [None; 4];
+        let mut record = |frame: &[TouchPoint], tracker: &mut Tracker| {
+            events = [None; 4];
+            let mut n = 0;
+            tracker.update(frame, |event| {
+                events[n] = Some(event);
+                n += 1;
+            });
+            events
+        };
+        assert_eq!(
+            record(&[point(0, 10, 10)], &mut tracker),
+            [Some(TouchEvent::Down(point(0, 10, 10))), None, None, None]
+        );
+        assert_eq!(
+            record(&[point(0, 10, 10), point(1, 50, 50)], &mut tracker),
+            [Some(TouchEvent::Down(point(1, 50, 50))), None, None, None]
+        );
+        assert_eq!(
+            record(&[point(1, 55, 50)], &mut tracker),
+            [
+                Some(TouchEvent::Up(point(0, 10, 10))),
+                Some(TouchEvent::Move(point(1, 55, 50))),
+                None,
+                None
+            ]
+        );
+        assert_eq!(
+            record(&[], &mut tracker),
+            [Some(TouchEvent::Up(point(1, 55, 50))), None, None, None]
+        );
+        assert!(tracker.points().is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+//! Fixed-point audio utilities.
+//!
+//! Sample-rate conversion, channel mixing and volume ramps on interleaved
+//! 16-bit PCM, the format of I2S DMA buffers. Everything is integer
+//! arithmetic, so a 16 kHz voice path and 48 kHz playback can share one
+//! codec without floating point in the audio loop.
+
+use embedded_time::rate::Hertz;
+
+/// Largest number of interleaved channels a [`Resampler`] handles.
+pub const MAX_CHANNELS: usize = 8;
+
+/// Linear gain in Q16 fixed point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Gain(pub u32);
+
+/// Q16 gain of each whole decibel of attenuation within a 20 dB decade.
+const DB_STEPS: [u32; 20] = [
+    65536, 58409, 52057, 46396, 41350, 36854, 32846, 29274, 26090, 23253, 20724, 18471, 16462,
+    14672, 13076, 11654, 10387, 9257, 8250, 7353,
+];
+
+/// Q16 gain of each tenth of a decibel of attenuation.
+const TENTH_DB_STEPS: [u32; 10] = [
+    65536, 64786, 64044, 63311, 62586, 61870, 61162, 60462, 59770, 59085,
+];
+
+impl Gain {
+    /// Silence.
+    pub const MUTE: Gain = Gain(0);
+    /// Samples pass unchanged.
+    pub const UNITY: Gain = Gain(1 << 16);
+    /// Largest gain, +24 dB.
+    pub const MAX: Gain = Gain(1_038_857);
+
+    /// Converts a level in tenths of a decibel into a gain.
+    ///
+    /// Levels above +24 dB are clamped, levels below -100 dB mute.
+    pub const fn from_db_tenths(tenths: i32) -> Gain {
+        if tenths >= 240 {
+            return Gain::MAX;
+        }
+        if tenths < -1000 {
+            return Gain::MUTE;
+        }
+        // Attenuate from +40 dB so the lookup only ever attenuates.
+        let attenuation = (400 - tenths) as u32;
+        let db = attenuation / 10;
+        let mut gain = (DB_STEPS[(db % 20) as usize] as u64
+            * TENTH_DB_STEPS[(attenuation % 10) as usize] as u64)
+            >> 16;
+        // +40 dB is a gain of 100.
+        gain *= 100;
+        let mut decades = db / 20;
+        while decades > 0 {
+            gain /= 10;
+            decades -= 1;
+        }
+        Gain(gain as u32)
+    }
+
+    /// Scales one sample, saturating.
+    #[inline]
+    pub const fn apply(self, sample: i16) -> i16 {
+        saturate((sample as i64 * self.0 as i64) >> 16)
+    }
+}
+
+impl Default for Gain {
+    fn default() -> Self {
+        Gain::UNITY
+    }
+}
+
+#[inline]
+const fn saturate(sample: i64) -> i16 {
+    if sample > i16::MAX as i64 {
+        i16::MAX
+    } else if sample < i16::MIN as i64 {
+        i16::MIN
+    } else {
+        sample as i16
+    }
+}
+
+/// Adds `src` scaled by `gain` onto `dst`, saturating.
+pub fn mix(dst: &mut [i16], src: &[i16], gain: Gain) {
+    for (d, &s) in dst.iter_mut().zip(src) {
+        *d = saturate(*d as i64 + ((s as i64 * gain.0 as i64) >> 16));
+    }
+}
+
+/// Averages interleaved stereo frames into mono, returning the samples written.
+pub fn stereo_to_mono(input: &[i16], output: &mut [i16]) -> usize {
+    let mut n = 0;
+    for (frame, out) in input.chunks_exact(2).zip(output.iter_mut()) {
+        *out = ((frame[0] as i32 + frame[1] as i32) >> 1) as i16;
+        n += 1;
+    }
+    n
+}
+
+/// Duplicates mono samples into interleaved stereo frames, returning the samples written.
+pub fn mono_to_stereo(input: &[i16], output: &mut [i16]) -> usize {
+    let mut n = 0;
+    for (&sample, frame) in input.iter().zip(output.chunks_exact_mut(2)) {
+        frame[0] = sample;
+        frame[1] = sample;
+        n += 2;
+    }
+    n
+}
+
+/// Streaming sample-rate converter with linear interpolation.
+///
+/// Interpolation has no anti-aliasing filter; when reducing the rate, low
+/// pass the input first if it has content above the output Nyquist rate.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    channels: usize,
+    /// Input frames per output frame, Q32.
+    step: u64,
+    /// Position of the next output frame, Q32, counted from `prev`.
+    phase: u64,
+    /// Last frame of the previous input buffer.
+    prev: [i16; MAX_CHANNELS],
+}
+
+impl Resampler {
+    /// Creates a converter from rate `from` to rate `to` for `channels` interleaved channels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a rate is zero or `channels` is not between 1 and [`MAX_CHANNELS`].
+    pub fn new(from: Hertz, to: Hertz, channels: usize) -> Self {
+        assert!(from.0 != 0 && to.0 != 0, "sample rates must be non-zero");
+        assert!(
+            (1..=MAX_CHANNELS).contains(&channels),
+            "unsupported channel count"
+        );
+        Self {
+            channels,
+            step: ((from.0 as u64) << 32) / to.0 as u64,
+            phase: 0,
+            prev: [0; MAX_CHANNELS],
+        }
+    }
+
+    /// Returns an upper bound of the samples produced from `input_samples` samples.
+    #[inline]
+    pub fn output_len(&self, input_samples: usize) -> usize {
+        let frames = (((input_samples / self.channels) as u64) << 32).div_ceil(self.step) + 1;
+        frames as usize * self.channels
+    }
+
+    /// Converts `input` into `output` and returns the samples consumed and produced.
+    ///
+    /// Input left unconsumed because `output` is full is to be passed again.
+    pub fn process(&mut self, input: &[i16], output: &mut [i16]) -> (usize, usize) {
+        let channels = self.channels;
+        let frames_in = input.len() / channels;
+        let frames_out = output.len() / channels;
+        let mut produced = 0;
+        while produced < frames_out {
+            let index = (self.phase >> 32) as usize;
+            if index >= frames_in {
+                break;
+            }
+            let frac = ((self.phase >> 17) & 0x7FFF) as i32;
+            for c in 0..channels {
+                let a = match index {
+                    0 => self.prev[c],
+                    _ => input[(index - 1) * channels + c],
+                } as i32;
+                let b = input[index * channels + c] as i32;
+                output[produced * channels + c] = (a + (((b - a) * frac) >> 15)) as i16;
+            }
+            produced += 1;
+            self.phase += self.step;
+        }
+
+        let consumed = ((self.phase >> 32) as usize).min(frames_in);
+        if consumed > 0 {
+            let last = (consumed - 1) * channels;
+            self.prev[..channels].copy_from_slice(&input[last..last + channels]);
+            self.phase -= (consumed as u64) << 32;
+        }
+        (consumed * channels, produced * channels)
+    }
+
+    /// Forgets the stream history.
+    pub fn reset(&mut self) {
+        self.phase = 0;
+        self.prev = [0; MAX_CHANNELS];
+    }
+}
+
+/// Volume changing smoothly from one gain to another, avoiding clicks.
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp {
+    current: i64,
+    target: Gain,
+    step: i64,
+    remaining: u32,
+}
+
+impl Ramp {
+    /// Creates a ramp holding `gain`.
+    pub const fn new(gain: Gain) -> Self {
+        Self {
+            current: gain.0 as i64,
+            target: gain,
+            step: 0,
+            remaining: 0,
+        }
+    }
+
+    /// Moves to `target` over `frames` frames.
+    pub fn set_target(&mut self, target: Gain, frames: u32) {
+        self.target = target;
+        match frames {
+            0 => {
+                self.current = target.0 as i64;
+                self.remaining = 0;
+            }
+            _ => {
+                self.step = (target.0 as i64 - self.current) / frames as i64;
+                self.remaining = frames;
+            }
+        }
+    }
+
+    /// Returns the gain of the next frame.
+    #[inline]
+    pub fn gain(&self) -> Gain {
+        Gain(self.current as u32)
+    }
+
+    /// Checks whether the target has been reached.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Applies the ramp to interleaved frames of `channels` samples.
+    pub fn process(&mut self, samples: &mut [i16], channels: usize) {
+        for frame in samples.chunks_mut(channels) {
+            let gain = self.gain();
+            for sample in frame {
+                *sample = gain.apply(*sample);
+            }
+            if self.remaining > 0 {
+                self.remaining -= 1;
+                self.current = match self.remaining {
+                    0 => self.target.0 as i64,
+                    _ => self.current + self.step,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn db_to_gain() {
+        assert_eq!(Gain::from_db_tenths(0), Gain::UNITY);
+        assert_eq!(Gain::from_db_tenths(-60).0, 32846);
+        assert_eq!(Gain::from_db_tenths(200).0, 10 << 16);
+        assert_eq!(Gain::from_db_tenths(-200).0, 6553);
+        assert_eq!(Gain::from_db_tenths(-1001), Gain::MUTE);
+        assert_eq!(Gain::from_db_tenths(500), Gain::MAX);
+        assert_eq!(Gain::UNITY.apply(-1234), -1234);
+        assert_eq!(Gain::MAX.apply(i16::MIN), i16::MIN);
+    }
+
+    #[test]
+    fn mixing() {
+        let mut dst = [30000, -100, 0];
+        mix(&mut dst, &[10000, 100, 200], Gain::UNITY);
+        assert_eq!(dst, [i16::MAX, 0, 200]);
+
+        let mut mono = [0; 2];
+        assert_eq!(stereo_to_mono(&[100, 300, -2, -4], &mut mono), 2);
+        assert_eq!(mono, [200, -3]);
+        let mut stereo = [0; 4];
+        assert_eq!(mono_to_stereo(&mono, &mut stereo), 4);
+        assert_eq!(stereo, [200, 200, -3, -3]);
+    }
+
+    #[test]
+    fn resample_rates() {
+        let input = [1000i16; 160];
+        let mut up = Resampler::new(Hertz::new(16_000), Hertz::new(48_000), 1);
+        let mut output = [0; 600];
+        assert!(up.output_len(input.len()) <= output.len());
+        let (consumed, produced) = up.process(&input, &mut output);
+        assert_eq!(consumed, 160);
+        assert!((480..=481).contains(&produced));
+        // Past the first interpolated frame, a constant stays constant.
+        assert!(output[4..produced].iter().all(|&s| s == 1000));
+
+        let mut down = Resampler::new(Hertz::new(48_000), Hertz::new(16_000), 2);
+        let input = [500i16; 960];
+        let mut output = [0; 160];
+        let (consumed, produced) = down.process(&input, &mut output);
+        assert_eq!(produced, 160);
+        assert!(consumed < input.len());
+        let (rest, produced) = down.process(&input[consumed..], &mut output);
+        assert_eq!(consumed + rest, 960);
+        assert_eq!(produced, 160);
+        assert!(output.iter().all(|&s| s == 500));
+    }
+
+    #[test]
+    fn ramp_reaches_target() {
+        let mut ramp = Ramp::new(Gain::UNITY);
+        ramp.set_target(Gain::MUTE, 4);
+        let mut samples = [1000i16; 12];
+        ramp.process(&mut samples, 2);
+        assert_eq!(samples[0], 1000);
+        assert!(samples[2] < 1000 && samples[2] > samples[4]);
+        assert!(ramp.is_done());
+        assert_eq!(&samples[8..], &[0; 4]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Crate-level error type.
+//!
+//! Driver errors convert into [`Error`] with `?`. Errors of the underlying
+//! bus keep their embedded-hal error kind, so generic code can match on the
+//! kind without knowing the concrete driver.
+
+use crate::coexist::{OwnedByLinux, Resource};
+use crate::drivers::mcp2515;
+#[cfg(feature = "fs")]
+use crate::fs;
+use crate::iomux::ops::VoltageMismatch;
+use crate::kvstore;
+use crate::proto::modbus::{self, Exception};
+use crate::secure_storage;
+use crate::security::InvalidLength;
+use crate::uart::{ConfigError, UartError};
+use embedded_can::Error as _;
+use embedded_hal::spi::Error as _;
+use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+
+/// Indicate the errors of every driver in the crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// UART line error.
+    Uart(UartError),
+    /// The UART configuration cannot be applied.
+    UartConfig(ConfigError),
+    /// Linux owns the peripheral, see [`coexist`](crate::coexist).
+    OwnedByLinux(Resource),
+    /// A pad is not configured for the IO voltage its function requires.
+    Voltage(VoltageMismatch),
+    /// SPI bus error.
+    Spi(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] embedded_hal::spi::ErrorKind),
+    /// Serial port error.
+    Serial(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] embedded_hal_nb::serial::ErrorKind),
+    /// CAN controller error.
+    Can(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] embedded_can::ErrorKind),
+    /// Flash access error.
+    Flash(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] NorFlashErrorKind),
+    /// A remote device did not answer in time.
+    Timeout,
+    /// A remote device answered with a MODBUS exception.
+    Exception(Exception),
+    /// Received or stored data is corrupt or failed authentication.
+    InvalidData,
+    /// An argument is out of range.
+    InvalidArgument,
+    /// The data does not fit.
+    TooLarge,
+    /// The requested item does not exist.
+    NotFound,
+    /// Stored data is older than the anti-rollback counter allows.
+    Rollback,
+    /// Any other error.
+    Other,
+}
+
+impl From<UartError> for Error {
+    fn from(error: UartError) -> Self {
+        Error::Uart(error)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Self {
+        Error::UartConfig(error)
+    }
+}
+
+impl From<OwnedByLinux> for Error {
+    fn from(OwnedByLinux(resource): OwnedByLinux) -> Self {
+        Error::OwnedByLinux(resource)
+    }
+}
+
+impl From<VoltageMismatch> for Error {
+    fn from(error: VoltageMismatch) -> Self {
+        Error::Voltage(error)
+    }
+}
+
+impl From<InvalidLength> for Error {
+    fn from(_: InvalidLength) -> Self {
+        Error::InvalidArgument
+    }
+}
+
+impl<E: embedded_hal::spi::Error> From<mcp2515::Error<E>> for Error {
+    fn from(error: mcp2515::Error<E>) -> Self {
+        match error {
+            mcp2515::Error::Spi(e) => Error::Spi(e.kind()),
+            e => Error::Can(e.kind()),
+        }
+    }
+}
+
+impl<E: embedded_hal_nb::serial::Error> From<modbus::Error<E>> for Error {
+    fn from(error: modbus::Error<E>) -> Self {
+        match error {
+            modbus::Error::Serial(e) => Error::Serial(e.kind()),
+            modbus::Error::Timeout => Error::Timeout,
+            modbus::Error::Crc | modbus::Error::InvalidFrame => Error::InvalidData,
+            modbus::Error::TooLarge => Error::TooLarge,
+            modbus::Error::Exception(exception) => Error::Exception(exception),
+        }
+    }
+}
+
+impl<F: NorFlashError, C> From<secure_storage::Error<F, C>> for Error {
+    fn from(error: secure_storage::Error<F, C>) -> Self {
+        match error {
+            secure_storage::Error::Flash(e) => Error::Flash(e.kind()),
+            secure_storage::Error::Counter(_) => Error::Other,
+            secure_storage::Error::InvalidPartition | secure_storage::Error::InvalidSlot => {
+                Error::InvalidArgument
+            }
+            secure_storage::Error::TooLarge => Error::TooLarge,
+            secure_storage::Error::NotFound => Error::NotFound,
+            secure_storage::Error::Authentication => Error::InvalidData,
+            secure_storage::Error::Rollback => Error::Rollback,
+        }
+    }
+}
+
+#[cfg(feature = "fs")]
+impl From<fs::Error> for Error {
+    fn from(error: fs::Error) -> Self {
+        match error {
+            fs::Error::NotFound => Error::NotFound,
+            fs::Error::NoSpace => Error::TooLarge,
+            fs::Error::InvalidArgument => Error::InvalidArgument,
+            fs::Error::Corrupt => Error::InvalidData,
+            _ => Error::Other,
+        }
+    }
+}
+
+impl<F: NorFlashError> From<kvstore::Error<F>> for Error {
+    fn from(error: kvstore::Error<F>) -> Self {
+        match error {
+            kvstore::Error::Flash(e) => Error::Flash(e.kind()),
+            kvstore::Error::InvalidPartition | kvstore::Error::InvalidKey => Error::InvalidArgument,
+            kvstore::Error::TooLarge | kvstore::Error::Full => Error::TooLarge,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bus_error_kinds_preserved() {
+        let error: Error = mcp2515::Error::Spi(embedded_hal::spi::ErrorKind::Overrun).into();
+        assert_eq!(error, Error::Spi(embedded_hal::spi::ErrorKind::Overrun));
+        let error: Error = mcp2515::Error::<embedded_hal::spi::ErrorKind>::Overrun.into();
+        assert_eq!(error, Error::Can(embedded_can::ErrorKind::Overrun));
+        let error: Error = modbus::Error::Serial(UartError::Framing).into();
+        assert_eq!(
+            error,
+            Error::Serial(embedded_hal_nb::serial::ErrorKind::FrameFormat)
+        );
+    }
+}
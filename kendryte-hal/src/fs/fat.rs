@@ -0,0 +1,170 @@
+//! FAT volumes through `embedded-sdmmc`.
+
+use super::{Entry, Error, FileSystem};
+use core::fmt::Debug;
+use embedded_sdmmc::{
+    BlockDevice, Mode, RawDirectory, RawFile, RawVolume, ShortFileName, TimeSource, VolumeIdx,
+    VolumeManager,
+};
+
+/// The first FAT volume of a block device.
+///
+/// No file or directory stays open between calls, so the default handle
+/// limits of the volume manager are enough.
+pub struct Fat<D: BlockDevice, T: TimeSource> {
+    manager: VolumeManager<D, T>,
+    volume: RawVolume,
+}
+
+type SdResult<T, D> = Result<T, embedded_sdmmc::Error<<D as BlockDevice>::Error>>;
+
+impl<D: BlockDevice, T: TimeSource> Fat<D, T> {
+    /// Mounts the FAT volume in the first partition of `device`; `clock`
+    /// stamps the files written.
+    pub fn mount(device: D, clock: T) -> Result<Self, Error> {
+        let mut manager = VolumeManager::new(device, clock);
+        let volume = manager.open_raw_volume(VolumeIdx(0))?;
+        Ok(Self { manager, volume })
+    }
+
+    /// Unmounts the volume and returns the block device and the clock.
+    pub fn unmount(mut self) -> Result<(D, T), Error> {
+        self.manager.close_volume(self.volume)?;
+        Ok(self.manager.free())
+    }
+
+    /// Opens directory `path`.
+    fn open_dir(&mut self, path: &str) -> Result<RawDirectory, Error> {
+        let mut dir = self.manager.open_root_dir(self.volume)?;
+        for name in super::components(path) {
+            let next = self.manager.open_dir(dir, name);
+            self.manager.close_dir(dir)?;
+            dir = next?;
+        }
+        Ok(dir)
+    }
+
+    /// Runs `f` on file `path` opened in `mode`, and closes it again.
+    fn with_file<R>(
+        &mut self,
+        path: &str,
+        mode: Mode,
+        f: impl FnOnce(&mut VolumeManager<D, T>, RawFile) -> SdResult<R, D>,
+    ) -> Result<R, Error> {
+        let (dir, name) = super::split(path);
+        let dir = self.open_dir(dir)?;
+        let result = self
+            .manager
+            .open_file_in_dir(dir, name, mode)
+            .and_then(|file| {
+                let result = f(&mut self.manager, file);
+                let closed = self.manager.close_file(file);
+                result.and_then(|value| closed.map(|()| value))
+            });
+        self.manager.close_dir(dir)?;
+        Ok(result?)
+    }
+
+    /// Runs `f` on the directory holding `path` with the name in it.
+    fn in_parent(
+        &mut self,
+        path: &str,
+        f: impl FnOnce(&mut VolumeManager<D, T>, RawDirectory, &str) -> SdResult<(), D>,
+    ) -> Result<(), Error> {
+        let (dir, name) = super::split(path);
+        let dir = self.open_dir(dir)?;
+        let result = f(&mut self.manager, dir, name);
+        self.manager.close_dir(dir)?;
+        Ok(result?)
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> FileSystem for Fat<D, T> {
+    fn read(&mut self, path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        self.with_file(path, Mode::ReadOnly, |manager, file| {
+            if offset >= manager.file_length(file)? {
+                return Ok(0);
+            }
+            manager.file_seek_from_start(file, offset)?;
+            let mut read = 0;
+            while read < buf.len() && !manager.file_eof(file)? {
+                read += manager.read(file, &mut buf[read..])?;
+            }
+            Ok(read)
+        })
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        self.with_file(path, Mode::ReadWriteCreateOrTruncate, |manager, file| {
+            manager.write(file, data)
+        })
+    }
+
+    fn append(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        self.with_file(path, Mode::ReadWriteCreateOrAppend, |manager, file| {
+            manager.write(file, data)
+        })
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), Error> {
+        self.in_parent(path, |manager, dir, name| {
+            manager.delete_file_in_dir(dir, name)
+        })
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<(), Error> {
+        self.in_parent(path, |manager, dir, name| {
+            manager.make_dir_in_dir(dir, name)
+        })
+    }
+
+    fn read_dir(&mut self, path: &str, f: &mut dyn FnMut(Entry<'_>)) -> Result<(), Error> {
+        let dir = self.open_dir(path)?;
+        let result = self.manager.iterate_dir(dir, |entry| {
+            let mut name = [0; 12];
+            let name = short_name(&entry.name, &mut name);
+            if entry.attributes.is_volume() || name == "." || name == ".." {
+                return;
+            }
+            f(Entry {
+                name,
+                is_dir: entry.attributes.is_directory(),
+                len: entry.size,
+            });
+        });
+        self.manager.close_dir(dir)?;
+        Ok(result?)
+    }
+}
+
+/// Formats an 8.3 name as `BASE.EXT` into `buf`.
+fn short_name<'a>(name: &ShortFileName, buf: &'a mut [u8; 12]) -> &'a str {
+    let (base, extension) = (name.base_name(), name.extension());
+    let mut len = base.len();
+    buf[..len].copy_from_slice(base);
+    if !extension.is_empty() {
+        buf[len] = b'.';
+        buf[len + 1..len + 1 + extension.len()].copy_from_slice(extension);
+        len += 1 + extension.len();
+    }
+    // Names outside ASCII are listed as empty.
+    core::str::from_utf8(&buf[..len]).unwrap_or_default()
+}
+
+impl<E: Debug> From<embedded_sdmmc::Error<E>> for Error {
+    fn from(error: embedded_sdmmc::Error<E>) -> Self {
+        use embedded_sdmmc::Error as Sd;
+        match error {
+            Sd::NotFound => Error::NotFound,
+            Sd::FileAlreadyExists | Sd::DirAlreadyExists => Error::AlreadyExists,
+            Sd::OpenedFileAsDir => Error::NotADirectory,
+            Sd::OpenedDirAsFile | Sd::DeleteDirAsFile => Error::IsADirectory,
+            Sd::NotEnoughSpace | Sd::DiskFull => Error::NoSpace,
+            Sd::FilenameError(_) | Sd::InvalidOffset => Error::InvalidArgument,
+            Sd::FormatError(_) | Sd::NoSuchVolume | Sd::BadCluster | Sd::UnterminatedFatChain => {
+                Error::Corrupt
+            }
+            _ => Error::Io,
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! littlefs in a flash partition through `littlefs2`.
+
+use super::{Entry, Error, FileSystem};
+use embedded_storage::nor_flash::NorFlash;
+use littlefs2::consts::{PATH_MAX, U1, U256};
+use littlefs2::fs::Filesystem;
+use littlefs2::io::{self, Read, Seek, SeekFrom, Write};
+use littlefs2::path::PathBuf;
+
+pub use littlefs2::fs::Allocation;
+
+/// Size of the read, program and file caches.
+const CACHE_SIZE: usize = 256;
+
+/// Erase cycles of a block before littlefs moves its metadata elsewhere.
+const BLOCK_CYCLES: isize = 500;
+
+/// Partition of `BLOCKS` erase sectors of a [`NorFlash`], as littlefs storage.
+pub struct Storage<F, const BLOCKS: usize> {
+    flash: F,
+    offset: u32,
+}
+
+impl<F: NorFlash, const BLOCKS: usize> Storage<F, BLOCKS> {
+    /// Uses `BLOCKS` erase sectors of `flash` starting at `offset`.
+    ///
+    /// Fails with [`Error::InvalidArgument`] if the partition is not aligned
+    /// to erase sectors or does not fit, or if the flash geometry does not
+    /// fit the 256-byte caches.
+    pub fn new(flash: F, offset: u32) -> Result<Self, Error> {
+        let len = (BLOCKS * F::ERASE_SIZE) as u64;
+        if BLOCKS < 2
+            || offset as usize % F::ERASE_SIZE != 0
+            || offset as u64 + len > flash.capacity() as u64
+            || CACHE_SIZE % F::READ_SIZE != 0
+            || CACHE_SIZE % F::WRITE_SIZE != 0
+            || F::ERASE_SIZE % CACHE_SIZE != 0
+        {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Self { flash, offset })
+    }
+
+    /// Returns the flash.
+    pub fn free(self) -> F {
+        self.flash
+    }
+}
+
+impl<F: NorFlash, const BLOCKS: usize> littlefs2::driver::Storage for Storage<F, BLOCKS> {
+    const READ_SIZE: usize = F::READ_SIZE;
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const BLOCK_SIZE: usize = F::ERASE_SIZE;
+    const BLOCK_COUNT: usize = BLOCKS;
+    const BLOCK_CYCLES: isize = BLOCK_CYCLES;
+    type CACHE_SIZE = U256;
+    type LOOKAHEAD_SIZE = U1;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let offset = self.offset + off as u32;
+        self.flash.read(offset, buf).map_err(|_| io::Error::Io)?;
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> io::Result<usize> {
+        let offset = self.offset + off as u32;
+        self.flash.write(offset, data).map_err(|_| io::Error::Io)?;
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> io::Result<usize> {
+        let offset = self.offset + off as u32;
+        let end = offset + len as u32;
+        self.flash.erase(offset, end).map_err(|_| io::Error::Io)?;
+        Ok(len)
+    }
+}
+
+/// littlefs mounted over a [`Storage`].
+///
+/// ```ignore
+/// let mut storage = fs::Storage::<_, 64>::new(flash, 0x0010_0000)?;
+/// let mut alloc = fs::Allocation::new();
+/// let mut fs = fs::LittleFs::mount_or_format(&mut alloc, &mut storage)?;
+/// fs.write("boot_count", &count.to_le_bytes())?;
+/// ```
+pub struct LittleFs<'a, F: NorFlash, const BLOCKS: usize> {
+    fs: Filesystem<'a, Storage<F, BLOCKS>>,
+}
+
+impl<'a, F: NorFlash, const BLOCKS: usize> LittleFs<'a, F, BLOCKS> {
+    /// Mounts the littlefs in `storage`, formatting it first if it holds none.
+    pub fn mount_or_format(
+        alloc: &'a mut Allocation<Storage<F, BLOCKS>>,
+        storage: &'a mut Storage<F, BLOCKS>,
+    ) -> Result<Self, Error> {
+        if !Filesystem::is_mountable(storage) {
+            Filesystem::format(storage)?;
+        }
+        let fs = Filesystem::mount(alloc, storage)?;
+        Ok(Self { fs })
+    }
+}
+
+impl<F: NorFlash, const BLOCKS: usize> FileSystem for LittleFs<'_, F, BLOCKS> {
+    fn read(&mut self, path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        let path = path_buf(path)?;
+        let read = self.fs.open_file_and_then(&path, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut read = 0;
+            while read < buf.len() {
+                match file.read(&mut buf[read..])? {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            Ok(read)
+        })?;
+        Ok(read)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let path = path_buf(path)?;
+        self.fs
+            .create_file_and_then(&path, |file| file.write(data).map(drop))?;
+        Ok(())
+    }
+
+    fn append(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let path = path_buf(path)?;
+        self.fs.open_file_with_options_and_then(
+            |options| options.write(true).create(true).append(true),
+            &path,
+            |file| file.write(data).map(drop),
+        )?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let path = path_buf(path)?;
+        self.fs.remove(&path)?;
+        Ok(())
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<(), Error> {
+        let path = path_buf(path)?;
+        self.fs.create_dir(&path)?;
+        Ok(())
+    }
+
+    fn read_dir(&mut self, path: &str, f: &mut dyn FnMut(Entry<'_>)) -> Result<(), Error> {
+        let path = path_buf(path)?;
+        self.fs.read_dir_and_then(&path, |dir| {
+            for entry in dir {
+                let entry = entry?;
+                let name = entry.file_name().as_str();
+                if name == "." || name == ".." {
+                    continue;
+                }
+                f(Entry {
+                    name,
+                    is_dir: entry.file_type().is_dir(),
+                    len: entry.metadata().len() as u32,
+                });
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+/// Converts `path` to a littlefs path from the root.
+fn path_buf(path: &str) -> Result<PathBuf, Error> {
+    let path = path.trim_matches('/');
+    if path.len() >= PATH_MAX || path.contains('\0') {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(match path {
+        "" => PathBuf::from("/"),
+        path => PathBuf::from(path),
+    })
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        match error {
+            io::Error::NoSuchEntry => Error::NotFound,
+            io::Error::EntryAlreadyExisted => Error::AlreadyExists,
+            io::Error::PathNotDir => Error::NotADirectory,
+            io::Error::PathIsDir => Error::IsADirectory,
+            io::Error::DirNotEmpty => Error::DirectoryNotEmpty,
+            io::Error::NoSpace | io::Error::FileTooBig => Error::NoSpace,
+            io::Error::Invalid | io::Error::FilenameTooLong => Error::InvalidArgument,
+            io::Error::Corruption => Error::Corrupt,
+            _ => Error::Io,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RamFlash;
+
+    type Flash = RamFlash<4096, 16>;
+
+    #[test]
+    fn rejects_bad_partitions() {
+        assert!(Storage::<_, 16>::new(Flash::new(), 0).is_ok());
+        assert_eq!(
+            Storage::<_, 16>::new(Flash::new(), 4096).err(),
+            Some(Error::InvalidArgument)
+        );
+        assert_eq!(
+            Storage::<_, 8>::new(Flash::new(), 100).err(),
+            Some(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn files_survive_remount() {
+        let mut storage = Storage::<_, 16>::new(Flash::new(), 0).unwrap();
+        let mut buf = [0; 32];
+        {
+            let mut alloc = Allocation::new();
+            let mut fs = LittleFs::mount_or_format(&mut alloc, &mut storage).unwrap();
+            fs.create_dir("logs").unwrap();
+            assert_eq!(fs.create_dir("logs"), Err(Error::AlreadyExists));
+            fs.write("logs/boot.txt", b"hello").unwrap();
+            fs.append("logs/boot.txt", b", world").unwrap();
+            assert_eq!(fs.read("logs/boot.txt", 0, &mut buf), Ok(12));
+            assert_eq!(&buf[..12], b"hello, world");
+            assert_eq!(fs.read("logs/boot.txt", 7, &mut buf), Ok(5));
+            assert_eq!(&buf[..5], b"world");
+            assert_eq!(fs.read("missing", 0, &mut buf), Err(Error::NotFound));
+        }
+
+        let mut storage = Storage::<_, 16>::new(storage.free(), 0).unwrap();
+        let mut alloc = Allocation::new();
+        let mut fs = LittleFs::mount_or_format(&mut alloc, &mut storage).unwrap();
+        assert_eq!(fs.read("logs/boot.txt", 0, &mut buf), Ok(12));
+        assert_eq!(&buf[..12], b"hello, world");
+        let mut entries = 0;
+        fs.read_dir("logs", &mut |entry| {
+            let file = Entry {
+                name: "boot.txt",
+                is_dir: false,
+                len: 12,
+            };
+            assert_eq!(entry, file);
+            entries += 1;
+        })
+        .unwrap();
+        assert_eq!(entries, 1);
+        fs.write("logs/boot.txt", b"new").unwrap();
+        assert_eq!(fs.read("logs/boot.txt", 0, &mut buf), Ok(3));
+        fs.remove("logs/boot.txt").unwrap();
+        assert_eq!(fs.read("logs/boot.txt", 0, &mut buf), Err(Error::NotFound));
+    }
+}
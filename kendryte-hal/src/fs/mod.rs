@@ -0,0 +1,115 @@
+//! Files on an SD card or in a flash partition.
+//!
+//! [`Fat`] mounts the first FAT volume of any `embedded_sdmmc::BlockDevice`,
+//! and [`LittleFs`] mounts littlefs over any
+//! [`NorFlash`](embedded_storage::nor_flash::NorFlash) partition. Both implement [`FileSystem`], so code storing files does not
+//! care which one it is given:
+//!
+//! ```ignore
+//! fn record(fs: &mut impl FileSystem, jpeg: &[u8]) -> Result<(), fs::Error> {
+//!     match fs.create_dir("camera") {
+//!         Ok(()) | Err(fs::Error::AlreadyExists) => {}
+//!         Err(e) => return Err(e),
+//!     }
+//!     fs.append("camera/frames.jpg", jpeg)
+//! }
+//! ```
+//!
+//! Paths are relative to the root of the volume, with `/` between
+//! components; `""` names the root. FAT volumes take 8.3 names only.
+//!
+//! Requires feature `fs`.
+
+mod fat;
+mod littlefs;
+
+pub use fat::Fat;
+pub use littlefs::{Allocation, LittleFs, Storage};
+
+/// Indicate different error conditions that may occur when accessing files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The path names no file or directory.
+    NotFound,
+    /// A file or directory of that name already exists.
+    AlreadyExists,
+    /// A directory is expected but the path names a file.
+    NotADirectory,
+    /// A file is expected but the path names a directory.
+    IsADirectory,
+    /// The directory to remove is not empty.
+    DirectoryNotEmpty,
+    /// The volume is full.
+    NoSpace,
+    /// The path, offset or partition is not valid for the volume.
+    InvalidArgument,
+    /// The volume is not formatted or its metadata is corrupt.
+    Corrupt,
+    /// The storage device failed.
+    Io,
+}
+
+/// An entry of a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry<'a> {
+    /// Name of the entry in its directory.
+    pub name: &'a str,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// Length of a file in bytes, zero for a directory.
+    pub len: u32,
+}
+
+/// Operations common to the mounted file systems.
+pub trait FileSystem {
+    /// Reads file `path` from byte `offset` into `buf`, returning the number
+    /// of bytes read, less than `buf.len()` at the end of the file.
+    fn read(&mut self, path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Creates file `path`, or truncates it, and writes `data` to it.
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// Appends `data` to file `path`, creating the file if needed.
+    fn append(&mut self, path: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// Removes file `path`.
+    fn remove(&mut self, path: &str) -> Result<(), Error>;
+
+    /// Creates directory `path`, whose parent must exist.
+    fn create_dir(&mut self, path: &str) -> Result<(), Error>;
+
+    /// Calls `f` with every entry of directory `path`, except `.` and `..`.
+    fn read_dir(&mut self, path: &str, f: &mut dyn FnMut(Entry<'_>)) -> Result<(), Error>;
+}
+
+/// Splits `path` into its directory and the name in it.
+fn split(path: &str) -> (&str, &str) {
+    let path = path.trim_matches('/');
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+/// Returns the components of `path`, skipping empty ones.
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            split("camera/2024/frames.jpg"),
+            ("camera/2024", "frames.jpg")
+        );
+        assert_eq!(split("/log.txt"), ("", "log.txt"));
+        assert_eq!(split("camera/"), ("", "camera"));
+        let mut names = components("/camera//2024/");
+        assert_eq!(names.next(), Some("camera"));
+        assert_eq!(names.next(), Some("2024"));
+        assert_eq!(names.next(), None);
+        assert_eq!(components("").next(), None);
+    }
+}
@@ -0,0 +1,201 @@
+//! Interrupt driven future primitives shared by async drivers.
+//!
+//! An interrupt handler wakes an [`AtomicWaker`] or a [`WaitQueue`] after
+//! masking the interrupt source; the driver future checks the peripheral
+//! status, re-enables the interrupt and returns `Pending` until the
+//! condition holds. Nothing here depends on a particular executor.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::task::{Context, Poll};
+
+pub use atomic_waker::AtomicWaker;
+
+/// Future completing when `check` returns `Some`, re-polled when `waker` is woken.
+///
+/// `check` runs after the waker is registered, so a wake between checking
+/// and returning `Pending` is never lost. It is also the place to re-enable
+/// the interrupt when the condition does not hold yet.
+pub struct IrqFuture<'a, F> {
+    waker: &'a AtomicWaker,
+    check: F,
+}
+
+impl<'a, T, F: FnMut() -> Option<T>> IrqFuture<'a, F> {
+    /// Creates a future waiting on `waker` until `check` returns `Some`.
+    #[inline]
+    pub fn new(waker: &'a AtomicWaker, check: F) -> Self {
+        Self { waker, check }
+    }
+}
+
+impl<T, F: FnMut() -> Option<T> + Unpin> Future for IrqFuture<'_, F> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        self.waker.register(cx.waker());
+        match (self.check)() {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Set of up to `N` tasks waiting on one interrupt, with `N` at most 32.
+///
+/// Unlike a single [`AtomicWaker`], several tasks can wait at once, for
+/// example on different pins of one GPIO port.
+pub struct WaitQueue<const N: usize> {
+    wakers: [AtomicWaker; N],
+    used: AtomicU32,
+}
+
+impl<const N: usize> WaitQueue<N> {
+    const CHECK: () = assert!(N <= 32, "a WaitQueue holds at most 32 waiters");
+
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        let () = Self::CHECK;
+        Self {
+            wakers: [const { AtomicWaker::new() }; N],
+            used: AtomicU32::new(0),
+        }
+    }
+
+    /// Wakes every waiting task; call from the interrupt handler.
+    pub fn wake_all(&self) {
+        let used = self.used.load(Ordering::Acquire);
+        for (i, waker) in self.wakers.iter().enumerate() {
+            if used & (1 << i) != 0 {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Returns a future completing when `check` returns `Some`, re-polled on [`wake_all`](Self::wake_all).
+    #[inline]
+    pub fn wait<T, F: FnMut() -> Option<T>>(&self, check: F) -> Wait<'_, N, F> {
+        Wait {
+            queue: self,
+            slot: None,
+            check,
+        }
+    }
+
+    /// Claims a free slot.
+    fn claim(&self) -> Option<usize> {
+        let mut used = self.used.load(Ordering::Relaxed);
+        loop {
+            let slot = (!used).trailing_zeros() as usize;
+            if slot >= N {
+                return None;
+            }
+            match self.used.compare_exchange_weak(
+                used,
+                used | 1 << slot,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(slot),
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    fn release(&self, slot: usize) {
+        self.used.fetch_and(!(1 << slot), Ordering::AcqRel);
+    }
+}
+
+impl<const N: usize> Default for WaitQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by [`WaitQueue::wait`].
+pub struct Wait<'a, const N: usize, F> {
+    queue: &'a WaitQueue<N>,
+    slot: Option<usize>,
+    check: F,
+}
+
+impl<const N: usize, T, F: FnMut() -> Option<T> + Unpin> Future for Wait<'_, N, F> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if self.slot.is_none() {
+            self.slot = self.queue.claim();
+        }
+        match self.slot {
+            Some(slot) => self.queue.wakers[slot].register(cx.waker()),
+            // Every slot is taken: fall back to polling.
+            None => cx.waker().wake_by_ref(),
+        }
+        match (self.check)() {
+            Some(value) => {
+                if let Some(slot) = self.slot.take() {
+                    self.queue.release(slot);
+                }
+                Poll::Ready(value)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<const N: usize, F> Drop for Wait<'_, N, F> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            self.queue.release(slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::pin::pin;
+    use core::task::Waker;
+
+    #[test]
+    fn irq_future() {
+        let waker = AtomicWaker::new();
+        let ready = Cell::new(false);
+        let mut future = pin!(IrqFuture::new(&waker, || ready.get().then_some(7)));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        ready.set(true);
+        waker.wake();
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(7));
+    }
+
+    #[test]
+    fn wait_queue_slots() {
+        let queue = WaitQueue::<2>::new();
+        let ready = Cell::new(false);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut a = pin!(queue.wait(|| ready.get().then_some(())));
+        let mut b = pin!(queue.wait(|| ready.get().then_some(())));
+        assert_eq!(a.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(b.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(queue.used.load(Ordering::Relaxed), 0b11);
+
+        {
+            // No slot left, still completes by polling.
+            let mut c = pin!(queue.wait(|| ready.get().then_some(())));
+            assert_eq!(c.as_mut().poll(&mut cx), Poll::Pending);
+        }
+
+        ready.set(true);
+        queue.wake_all();
+        assert_eq!(a.as_mut().poll(&mut cx), Poll::Ready(()));
+        assert_eq!(queue.used.load(Ordering::Relaxed), 0b10);
+        assert_eq!(b.as_mut().poll(&mut cx), Poll::Ready(()));
+        assert_eq!(queue.used.load(Ordering::Relaxed), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::coexist::{self, Resource};
 use crate::gpio::pad::{IntoGpio, Port};
 use crate::gpio::{Direction, Output, RegisterBlock};
 use crate::instance::Numbered;
@@ -19,6 +20,11 @@ pub struct Input<'i, 'p> {
 
 impl<'i, 'p> Input<'i, 'p> {
     /// Creates a new Input instance for a specific pad and GPIO port.
+    ///
+    /// # Panics
+    ///
+    /// Panics if Linux owns the GPIO port, see [`coexist`](crate::coexist).
+    #[track_caller]
     pub fn new<const N: usize, P>(
         instance: impl Numbered<'i, N, R = RegisterBlock>,
         pad: P,
@@ -27,6 +33,7 @@ impl<'i, 'p> Input<'i, 'p> {
     where
         P: PadOps + IntoGpio<'p, N>,
     {
+        coexist::assert_claimable(Resource::Gpio(N as u8));
         let mut pad = pad.into_gpio();
         pad.set_pull(pull);
         let port = <P as IntoGpio<N>>::PORT;
@@ -54,7 +61,7 @@ impl<'i, 'p> Input<'i, 'p> {
     }
 
     /// Reads the current state of the input pin.
-    pub fn pin_state(&mut self) -> PinState {
+    pub fn pin_state(&self) -> PinState {
         match self.port {
             Port::A => self
                 .inner
@@ -164,3 +171,16 @@ impl<'i, 'p> InputPin for Input<'i, 'p> {
         }
     }
 }
+
+#[cfg(feature = "eh02")]
+impl<'i, 'p> embedded_hal_02::digital::v2::InputPin for Input<'i, 'p> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.pin_state() == PinState::High)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.pin_state() == PinState::Low)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::coexist::{self, Resource};
 use crate::gpio::pad::{IntoGpio, Port};
 use crate::gpio::{Direction, Input, RegisterBlock};
 use crate::instance::Numbered;
@@ -20,6 +21,11 @@ pub struct Output<'i, 'p> {
 
 impl<'i, 'p> Output<'i, 'p> {
     /// Creates a new Output instance for a specific pad and GPIO port.
+    ///
+    /// # Panics
+    ///
+    /// Panics if Linux owns the GPIO port, see [`coexist`](crate::coexist).
+    #[track_caller]
     pub fn new<const N: usize, P>(
         instance: impl Numbered<'i, N, R = RegisterBlock>,
         pad: P,
@@ -29,6 +35,7 @@ impl<'i, 'p> Output<'i, 'p> {
     where
         P: PadOps + IntoGpio<'p, N>,
     {
+        coexist::assert_claimable(Resource::Gpio(N as u8));
         let mut pad = pad.into_gpio();
         pad.set_drive_strength(drive_strength);
         let port = <P as IntoGpio<N>>::PORT;
@@ -64,7 +71,7 @@ impl<'i, 'p> Output<'i, 'p> {
     }
 
     /// Reads the current output state of the pin.
-    pub fn pin_state(&mut self) -> PinState {
+    pub fn pin_state(&self) -> PinState {
         match self.port {
             Port::A => self.inner.swporta_dr.read().pin_state(self.pin_num).into(),
             Port::B => self.inner.swportb_dr.read().pin_state(self.pin_num).into(),
@@ -158,3 +165,30 @@ impl<'i, 'p> StatefulOutputPin for Output<'i, 'p> {
         Ok(self.pin_state() == PinState::Low)
     }
 }
+
+#[cfg(feature = "eh02")]
+impl<'i, 'p> embedded_hal_02::digital::v2::OutputPin for Output<'i, 'p> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        OutputPin::set_low(self)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        OutputPin::set_high(self)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 'p> embedded_hal_02::digital::v2::StatefulOutputPin for Output<'i, 'p> {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.pin_state() == PinState::High)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.pin_state() == PinState::Low)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 'p> embedded_hal_02::digital::v2::toggleable::Default for Output<'i, 'p> {}
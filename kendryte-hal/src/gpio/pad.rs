@@ -1,5 +1,7 @@
 use crate::iomux::FlexPad;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Port {
     A,
     B,
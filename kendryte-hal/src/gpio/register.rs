@@ -99,6 +99,7 @@ pub struct Dr {
 /// Defines the direction of a GPIO pin.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Direction {
     /// Pin is configured as input.
     Input = 0b0,
@@ -119,6 +120,7 @@ pub struct Ddr {
 /// Control mode for each GPIO pin.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ControlMode {
     /// Controlled by software.
     SoftWare = 0b0,
@@ -160,6 +162,7 @@ pub struct IntMask {
 /// Type of interrupt trigger for GPIO pins.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TriggerType {
     /// Level-triggered interrupt.
     Level = 0b0,
@@ -181,6 +184,7 @@ pub struct IntTypeLevel {
 /// Polarity configuration for GPIO interrupts.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Polarity {
     /// Triggers on low signal.
     /// Triggered on low level or falling edge.
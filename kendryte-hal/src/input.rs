@@ -0,0 +1,317 @@
+//! Buttons and rotary encoders.
+//!
+//! [`Button`] debounces a pin sampled from a periodic timer interrupt and
+//! recognises clicks, double clicks and long presses; [`Encoder`] decodes a
+//! quadrature rotary encoder from its two pins, sampled on every edge from
+//! the GPIO interrupt. Both report [`InputEvent`]s onto a [`Channel`] shared
+//! with the UI task:
+//!
+//! ```ignore
+//! static INPUT: Channel<InputEvent, 16> = Channel::new();
+//!
+//! // 1 ms timer tick: 20 ms debounce, 600 ms long press, 300 ms double click.
+//! let mut ok = Button::new(ButtonConfig::new(20, 600, 300));
+//! ok.poll(0, button.pin_state() == PinState::Low, &INPUT);
+//!
+//! // GPIO interrupt on both edges of either encoder pin.
+//! knob.poll(1, a.pin_state() == PinState::High, b.pin_state() == PinState::High, &INPUT);
+//! ```
+
+use crate::sync::Channel;
+
+/// Gesture of a button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ButtonEvent {
+    /// The button went down.
+    Down,
+    /// The button went up.
+    Up,
+    /// A short press not followed by another within the double click window.
+    Click,
+    /// A second short press within the double click window.
+    DoubleClick,
+    /// The button has been held for the long press time; no click follows.
+    LongPress,
+}
+
+/// Event of an input device, tagged with an identifier chosen by the application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InputEvent {
+    Button {
+        id: u8,
+        event: ButtonEvent,
+    },
+    /// Detents turned, positive when A leads B.
+    Rotary {
+        id: u8,
+        delta: i8,
+    },
+}
+
+/// Timing of a button, in timer ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ButtonConfig {
+    /// Ticks a new level must hold before it counts.
+    pub debounce: u16,
+    /// Ticks held down before a long press.
+    pub long_press: u16,
+    /// Ticks after a click within which a second press makes a double click.
+    pub double_click: u16,
+}
+
+impl ButtonConfig {
+    /// Creates a button timing.
+    #[inline]
+    pub const fn new(debounce: u16, long_press: u16, double_click: u16) -> Self {
+        Self {
+            debounce,
+            long_press,
+            double_click,
+        }
+    }
+}
+
+/// Debounced button with click detection.
+#[derive(Clone, Copy, Debug)]
+pub struct Button {
+    config: ButtonConfig,
+    /// Debounced level.
+    pressed: bool,
+    /// Ticks the raw level has differed from the debounced one.
+    bounce: u16,
+    /// Ticks since the last debounced change.
+    held: u16,
+    long: bool,
+    /// A click waits to see whether a second press follows.
+    pending: bool,
+    second: bool,
+}
+
+impl Button {
+    /// Creates a released button.
+    pub const fn new(config: ButtonConfig) -> Self {
+        Self {
+            config,
+            pressed: false,
+            bounce: 0,
+            held: 0,
+            long: false,
+            pending: false,
+            second: false,
+        }
+    }
+
+    /// Returns whether the debounced button is down.
+    #[inline]
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Advances one tick with the raw level of the button, passing events to `emit`.
+    pub fn on_tick(&mut self, pressed: bool, mut emit: impl FnMut(ButtonEvent)) {
+        self.held = self.held.saturating_add(1);
+        if pressed == self.pressed {
+            self.bounce = 0;
+        } else {
+            self.bounce += 1;
+            if self.bounce >= self.config.debounce {
+                self.bounce = 0;
+                self.held = 0;
+                self.pressed = pressed;
+                if pressed {
+                    self.down(&mut emit);
+                } else {
+                    self.up(&mut emit);
+                }
+                return;
+            }
+        }
+        if self.pressed && !self.long && self.held >= self.config.long_press {
+            self.long = true;
+            self.pending = false;
+            emit(ButtonEvent::LongPress);
+        }
+        if !self.pressed && self.pending && self.held >= self.config.double_click {
+            self.pending = false;
+            emit(ButtonEvent::Click);
+        }
+    }
+
+    /// Advances one tick and queues the events as `id`, returning how many were queued.
+    ///
+    /// Events that do not fit in the queue are dropped.
+    pub fn poll<const N: usize>(
+        &mut self,
+        id: u8,
+        pressed: bool,
+        queue: &Channel<InputEvent, N>,
+    ) -> usize {
+        let mut queued = 0;
+        self.on_tick(pressed, |event| {
+            if queue.try_send(InputEvent::Button { id, event }).is_ok() {
+                queued += 1;
+            }
+        });
+        queued
+    }
+
+    fn down(&mut self, emit: &mut impl FnMut(ButtonEvent)) {
+        self.long = false;
+        self.second = self.pending;
+        self.pending = false;
+        emit(ButtonEvent::Down);
+    }
+
+    fn up(&mut self, emit: &mut impl FnMut(ButtonEvent)) {
+        emit(ButtonEvent::Up);
+        if self.long {
+            return;
+        }
+        if self.second {
+            self.second = false;
+            emit(ButtonEvent::DoubleClick);
+        } else {
+            self.pending = true;
+        }
+    }
+}
+
+/// Steps between two levels of the A and B pins, indexed by old and new levels.
+const QUADRATURE: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Quadrature rotary encoder decoder.
+#[derive(Clone, Copy, Debug)]
+pub struct Encoder {
+    state: u8,
+    steps: i8,
+    steps_per_detent: i8,
+}
+
+impl Encoder {
+    /// Creates a decoder, with the encoder at rest with pins `a` and `b`.
+    ///
+    /// Most mechanical encoders go through all four states, `steps_per_detent`
+    /// 4, between detents; some stop every second state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `steps_per_detent` is zero.
+    pub const fn new(a: bool, b: bool, steps_per_detent: i8) -> Self {
+        assert!(steps_per_detent > 0, "steps per detent must be non-zero");
+        Self {
+            state: ((a as u8) << 1) | b as u8,
+            steps: 0,
+            steps_per_detent,
+        }
+    }
+
+    /// Takes new pin levels, returning the detents turned, positive when A leads B.
+    ///
+    /// Transitions skipping a state, which bouncing contacts produce, count
+    /// as no movement.
+    pub fn update(&mut self, a: bool, b: bool) -> i8 {
+        let state = ((a as u8) << 1) | b as u8;
+        self.steps += QUADRATURE[((self.state << 2) | state) as usize];
+        self.state = state;
+        let detents = self.steps / self.steps_per_detent;
+        self.steps %= self.steps_per_detent;
+        detents
+    }
+
+    /// Takes new pin levels and queues any movement as `id`, returning whether it was queued.
+    pub fn poll<const N: usize>(
+        &mut self,
+        id: u8,
+        a: bool,
+        b: bool,
+        queue: &Channel<InputEvent, N>,
+    ) -> bool {
+        match self.update(a, b) {
+            0 => false,
+            delta => queue.try_send(InputEvent::Rotary { id, delta }).is_ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(button: &mut Button, level: bool, ticks: u16, events: &mut [Option<ButtonEvent>]) {
+        for _ in 0..ticks {
+            button.on_tick(level, |event| {
+                let slot = events.iter_mut().find(|slot| slot.is_none()).unwrap();
+                *slot = Some(event);
+            });
+        }
+    }
+
+    #[test]
+    fn button_gestures() {
+        use ButtonEvent::*;
+        let mut button = Button::new(ButtonConfig::new(3, 50, 20));
+        let mut events = [None; 8];
+
+        // Bounces shorter than the debounce time are ignored.
+        run(&mut button, true, 2, &mut events);
+        run(&mut button, false, 1, &mut events);
+        assert_eq!(events, [None; 8]);
+
+        // Click, reported once the double click window closes.
+        run(&mut button, true, 10, &mut events);
+        run(&mut button, false, 25, &mut events);
+        assert_eq!(events[..4], [Some(Down), Some(Up), Some(Click), None]);
+
+        let mut events = [None; 8];
+        run(&mut button, true, 5, &mut events);
+        run(&mut button, false, 5, &mut events);
+        run(&mut button, true, 5, &mut events);
+        run(&mut button, false, 30, &mut events);
+        assert_eq!(
+            events[..6],
+            [
+                Some(Down),
+                Some(Up),
+                Some(Down),
+                Some(Up),
+                Some(DoubleClick),
+                None
+            ]
+        );
+
+        let mut events = [None; 8];
+        run(&mut button, true, 60, &mut events);
+        run(&mut button, false, 30, &mut events);
+        assert_eq!(events[..4], [Some(Down), Some(LongPress), Some(Up), None]);
+        assert!(!button.is_pressed());
+    }
+
+    #[test]
+    fn encoder_detents() {
+        let mut encoder = Encoder::new(false, false, 4);
+        // One clockwise detent, A leading B: 00 -> 10 -> 11 -> 01 -> 00.
+        let cw = [(true, false), (true, true), (false, true), (false, false)];
+        let deltas = cw.map(|(a, b)| encoder.update(a, b));
+        assert_eq!(deltas, [0, 0, 0, 1]);
+        // A bounce back and forth cancels out.
+        assert_eq!(encoder.update(true, false), 0);
+        assert_eq!(encoder.update(false, false), 0);
+        // Counter-clockwise, reported on the fourth step.
+        let ccw = [(false, true), (true, true), (true, false), (false, false)];
+        let deltas = ccw.map(|(a, b)| encoder.update(a, b));
+        assert_eq!(deltas, [0, 0, 0, -1]);
+
+        let queue: Channel<InputEvent, 4> = Channel::new();
+        for (a, b) in cw {
+            encoder.poll(7, a, b, &queue);
+        }
+        assert_eq!(
+            queue.try_receive(),
+            Some(InputEvent::Rotary { id: 7, delta: 1 })
+        );
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! Tables of the peripheral signals each pad can carry.
+//!
+//! A SoC runtime lists every pad and function select it supports as
+//! [`PadFunction`]s, so the configured function of a pad can be named when
+//! debugging, and a board's pin map can be checked at compile time with
+//! [`board_pins!`](crate::board_pins):
+//!
+//! ```ignore
+//! use kendryte_hal::gpio::pad::Port;
+//! use kendryte_hal::iomux::map::{Signal, UartLine};
+//!
+//! kendryte_hal::board_pins! {
+//!     tables: kendryte_rt::soc::k230::PAD_FUNCTIONS;
+//!     CONSOLE_TX: 38 => Signal::uart(0, UartLine::Sout),
+//!     CONSOLE_RX: 39 => Signal::uart(0, UartLine::Sin),
+//!     LED: 52 => Signal::gpio(1, Port::A, 20),
+//! }
+//! ```
+//!
+//! Two pins on one pad, or a signal the pad cannot carry, fail the build
+//! with an error naming the pin.
+
+use crate::gpio::pad::Port;
+use core::fmt;
+
+/// Line of a UART.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UartLine {
+    Sout,
+    Sin,
+    Rts,
+    Cts,
+    De,
+    Re,
+}
+
+/// Peripheral signal routed to a pad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Signal {
+    /// Pin of a GPIO controller port.
+    Gpio { gpio: u8, port: Port, pin: u8 },
+    /// Line of a UART.
+    Uart { uart: u8, line: UartLine },
+}
+
+impl Signal {
+    /// Pin `pin` of port `port` of GPIO controller `gpio`.
+    #[inline]
+    pub const fn gpio(gpio: u8, port: Port, pin: u8) -> Self {
+        Signal::Gpio { gpio, port, pin }
+    }
+
+    /// Line `line` of UART `uart`.
+    #[inline]
+    pub const fn uart(uart: u8, line: UartLine) -> Self {
+        Signal::Uart { uart, line }
+    }
+
+    /// Compares two signals, usable in constants.
+    pub const fn same(self, other: Signal) -> bool {
+        match (self, other) {
+            (
+                Signal::Gpio { gpio, port, pin },
+                Signal::Gpio {
+                    gpio: other_gpio,
+                    port: other_port,
+                    pin: other_pin,
+                },
+            ) => {
+                gpio == other_gpio
+                    && pin == other_pin
+                    && matches!((port, other_port), (Port::A, Port::A) | (Port::B, Port::B))
+            }
+            (
+                Signal::Uart { uart, line },
+                Signal::Uart {
+                    uart: other_uart,
+                    line: other_line,
+                },
+            ) => uart == other_uart && line as u8 == other_line as u8,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Signal::Gpio { gpio, port, pin } => {
+                let port = match port {
+                    Port::A => 'a',
+                    Port::B => 'b',
+                };
+                write!(f, "gpio{gpio}.p{port}{pin}")
+            }
+            Signal::Uart { uart, line } => {
+                let line = match line {
+                    UartLine::Sout => "sout",
+                    UartLine::Sin => "sin",
+                    UartLine::Rts => "rts",
+                    UartLine::Cts => "cts",
+                    UartLine::De => "de",
+                    UartLine::Re => "re",
+                };
+                write!(f, "uart{uart}.{line}")
+            }
+        }
+    }
+}
+
+/// A signal a pad carries at one function select.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PadFunction {
+    pub pad: u8,
+    pub function: u8,
+    pub signal: Signal,
+}
+
+/// Returns the signal of `pad` at function select `function`, if the tables list it.
+pub fn lookup(tables: &[&[PadFunction]], pad: u8, function: u8) -> Option<Signal> {
+    tables
+        .iter()
+        .flat_map(|table| table.iter())
+        .find(|entry| entry.pad == pad && entry.function == function)
+        .map(|entry| entry.signal)
+}
+
+/// A named signal of a board on a pad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinAssignment {
+    pub name: &'static str,
+    pub pad: u8,
+    pub signal: Signal,
+}
+
+/// Fault in a board's pin map, with the index of the offending pin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PinError {
+    /// The pin's pad is already taken by an earlier pin.
+    SharedPad(usize),
+    /// The tables list no function of the pad carrying the pin's signal.
+    Unroutable(usize),
+}
+
+/// Checks that no two pins share a pad and that each pad can carry its signal.
+pub const fn check_pins(pins: &[PinAssignment], tables: &[&[PadFunction]]) -> Result<(), PinError> {
+    let mut i = 0;
+    while i < pins.len() {
+        let mut j = 0;
+        while j < i {
+            if pins[j].pad == pins[i].pad {
+                return Err(PinError::SharedPad(i));
+            }
+            j += 1;
+        }
+        if function_of(tables, pins[i].pad, pins[i].signal).is_none() {
+            return Err(PinError::Unroutable(i));
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Returns the function select routing `signal` to `pad`, usable in constants.
+pub const fn function_of(tables: &[&[PadFunction]], pad: u8, signal: Signal) -> Option<u8> {
+    let mut t = 0;
+    while t < tables.len() {
+        let mut e = 0;
+        while e < tables[t].len() {
+            let entry = tables[t][e];
+            if entry.pad == pad && entry.signal.same(signal) {
+                return Some(entry.function);
+            }
+            e += 1;
+        }
+        t += 1;
+    }
+    None
+}
+
+/// Declares a board's pins as pad number constants, checked against pad
+/// function tables at compile time.
+///
+/// Also defines `BOARD_PINS`, the list of [`PinAssignment`]s.
+#[macro_export]
+macro_rules! board_pins {
+    (
+        tables: $tables:expr;
+        $($(#[$attr:meta])* $name:ident: $pad:literal => $signal:expr),+ $(,)?
+    ) => {
+        $($(#[$attr])* pub const $name: u8 = $pad;)+
+
+        pub const BOARD_PINS: &[$crate::iomux::map::PinAssignment] = &[
+            $($crate::iomux::map::PinAssignment {
+                name: stringify!($name),
+                pad: $pad,
+                signal: $signal,
+            }),+
+        ];
+
+        const _: () = {
+            const SHARED: &[&str] = &[$(concat!(
+                "board pin `", stringify!($name), "` is on pad ", stringify!($pad),
+                ", already taken by another pin"
+            )),+];
+            const UNROUTABLE: &[&str] = &[$(concat!(
+                "board pin `", stringify!($name), "`: pad ", stringify!($pad),
+                " cannot carry this signal"
+            )),+];
+            match $crate::iomux::map::check_pins(BOARD_PINS, $tables) {
+                Ok(()) => {}
+                Err($crate::iomux::map::PinError::SharedPad(i)) => panic!("{}", SHARED[i]),
+                Err($crate::iomux::map::PinError::Unroutable(i)) => panic!("{}", UNROUTABLE[i]),
+            }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Text;
+    use core::fmt::Write;
+
+    #[test]
+    fn looks_up_and_names_signals() {
+        let uart = [PadFunction {
+            pad: 38,
+            function: 1,
+            signal: Signal::Uart {
+                uart: 0,
+                line: UartLine::Sout,
+            },
+        }];
+        let gpio = [PadFunction {
+            pad: 38,
+            function: 0,
+            signal: Signal::Gpio {
+                gpio: 1,
+                port: Port::A,
+                pin: 6,
+            },
+        }];
+        let tables: [&[PadFunction]; 2] = [&uart, &gpio];
+        assert_eq!(lookup(&tables, 38, 1), Some(uart[0].signal));
+        assert_eq!(lookup(&tables, 38, 2), None);
+
+        let mut text = Text::<32>::new();
+        write!(text, "{} {}", uart[0].signal, gpio[0].signal).unwrap();
+        assert_eq!(text.as_str(), "uart0.sout gpio1.pa6");
+    }
+
+    #[test]
+    fn checks_pin_maps() {
+        const TABLE: &[PadFunction] = &[
+            PadFunction {
+                pad: 38,
+                function: 1,
+                signal: Signal::uart(0, UartLine::Sout),
+            },
+            PadFunction {
+                pad: 39,
+                function: 1,
+                signal: Signal::uart(0, UartLine::Sin),
+            },
+            PadFunction {
+                pad: 39,
+                function: 0,
+                signal: Signal::gpio(1, Port::A, 7),
+            },
+        ];
+        let pin = |name, pad, signal| PinAssignment { name, pad, signal };
+        let tx = pin("TX", 38, Signal::uart(0, UartLine::Sout));
+        let rx = pin("RX", 39, Signal::uart(0, UartLine::Sin));
+        let led = pin("LED", 39, Signal::gpio(1, Port::A, 7));
+        assert_eq!(check_pins(&[tx, rx], &[TABLE]), Ok(()));
+        assert_eq!(
+            check_pins(&[tx, rx, led], &[TABLE]),
+            Err(PinError::SharedPad(2))
+        );
+        let wrong = pin("TX", 38, Signal::uart(0, UartLine::Sin));
+        assert_eq!(check_pins(&[wrong], &[TABLE]), Err(PinError::Unroutable(0)));
+        assert_eq!(function_of(&[TABLE], 39, led.signal), Some(0));
+    }
+}
@@ -1,11 +1,106 @@
+pub mod map;
 pub mod ops;
 pub mod pad;
 mod register;
 
 use crate::iomux::ops::PadOps;
+use crate::iomux::pad::IoVoltage;
 use core::marker::PhantomData;
+use core::ops::Range;
 pub use register::*;
 
+/// IO banks, groups of pads sharing one IO supply rail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IoBank {
+    /// IO0 to IO1.
+    Bank0,
+    /// IO2 to IO13.
+    Bank1,
+    /// IO14 to IO25.
+    Bank2,
+    /// IO26 to IO37.
+    Bank3,
+    /// IO38 to IO49.
+    Bank4,
+    /// IO50 to IO61.
+    Bank5,
+    /// IO62 to IO63.
+    Bank6,
+}
+
+impl IoBank {
+    /// All IO banks.
+    pub const ALL: [IoBank; 7] = [
+        IoBank::Bank0,
+        IoBank::Bank1,
+        IoBank::Bank2,
+        IoBank::Bank3,
+        IoBank::Bank4,
+        IoBank::Bank5,
+        IoBank::Bank6,
+    ];
+
+    /// Returns the bank pad `pad` belongs to.
+    ///
+    /// Being `const`, this can check pin assignments at compile time:
+    ///
+    /// ```
+    /// # use kendryte_hal::iomux::IoBank;
+    /// const _: () = assert!(matches!(IoBank::of(38), IoBank::Bank4));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pad` is not below 64.
+    pub const fn of(pad: usize) -> IoBank {
+        match pad {
+            0..=1 => IoBank::Bank0,
+            2..=13 => IoBank::Bank1,
+            14..=25 => IoBank::Bank2,
+            26..=37 => IoBank::Bank3,
+            38..=49 => IoBank::Bank4,
+            50..=61 => IoBank::Bank5,
+            62..=63 => IoBank::Bank6,
+            _ => panic!("pad number out of range"),
+        }
+    }
+
+    /// Returns the pads of the bank.
+    pub const fn pads(self) -> Range<usize> {
+        match self {
+            IoBank::Bank0 => 0..2,
+            IoBank::Bank1 => 2..14,
+            IoBank::Bank2 => 14..26,
+            IoBank::Bank3 => 26..38,
+            IoBank::Bank4 => 38..50,
+            IoBank::Bank5 => 50..62,
+            IoBank::Bank6 => 62..64,
+        }
+    }
+}
+
+impl RegisterBlock {
+    /// Set the IO voltage of every pad in `bank`.
+    /// The voltage must match the supply the board connects to the bank.
+    pub fn set_bank_voltage(&self, bank: IoBank, io_voltage: IoVoltage) {
+        for pad in &self.pads[bank.pads()] {
+            unsafe { pad.pad.modify(|r| r.with_io_voltage(io_voltage)) };
+        }
+    }
+
+    /// Returns the IO voltage of the pads in `bank`,
+    /// or `None` if they are not all configured alike.
+    pub fn bank_voltage(&self, bank: IoBank) -> Option<IoVoltage> {
+        let mut pads = self.pads[bank.pads()]
+            .iter()
+            .map(|p| p.pad.read().io_voltage());
+        let first = pads.next()?;
+        pads.all(|v| v == first).then_some(first)
+    }
+}
+
 pub struct FlexPad<'p> {
     inner: &'static pad::RegisterBlock,
     _marker: PhantomData<&'p ()>,
@@ -29,3 +124,17 @@ impl<'p> FlexPad<'p> {
 pub trait IntoFlexPad<'p> {
     fn into_flex_pad(self) -> FlexPad<'p>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banks_cover_all_pads() {
+        for pad in 0..64 {
+            assert!(IoBank::of(pad).pads().contains(&pad));
+        }
+        let total: usize = IoBank::ALL.iter().map(|bank| bank.pads().len()).sum();
+        assert_eq!(total, 64);
+    }
+}
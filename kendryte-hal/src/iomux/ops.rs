@@ -1,16 +1,28 @@
 use super::pad;
-use crate::iomux::pad::{SlewRate, Strength};
+use crate::iomux::pad::{IoVoltage, PadPreset, SlewRate, Strength};
 use arbitrary_int::{u1, u3};
 use volatile_register::RW;
 
 /// Pull-up/down configuration for a pad.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Pull {
     None,
     Up,
     Down,
 }
 
+/// Error returned when a pad is not configured for the voltage a function needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VoltageMismatch {
+    /// Voltage the function requires.
+    pub required: IoVoltage,
+    /// Voltage the pad is configured for.
+    pub configured: IoVoltage,
+}
+
 /// PadOps trait provides methods to operate and configure IO pads.
 pub trait PadOps {
     /// Returns a reference to the underlying pad register.
@@ -95,6 +107,18 @@ pub trait PadOps {
         self
     }
 
+    /// Apply the drive strength, slew rate and Schmitt trigger of a preset.
+    fn apply_preset(&self, preset: PadPreset) -> &Self {
+        unsafe {
+            self.inner().pad.modify(|r| {
+                r.with_drive_strength(preset.drive_strength)
+                    .with_slew_rate(preset.slew_rate)
+                    .with_schmitt_trigger_enable(preset.schmitt_trigger)
+            });
+        }
+        self
+    }
+
     /// Set the function select value for the pad.
     fn set_function_select(&self, function_select: u3) -> &Self {
         unsafe {
@@ -105,6 +129,34 @@ pub trait PadOps {
         self
     }
 
+    /// Set the IO voltage of the pad.
+    /// This must match the supply of the pad's bank, see [`IoBank`](super::IoBank).
+    fn set_io_voltage(&self, io_voltage: IoVoltage) -> &Self {
+        unsafe {
+            self.inner().pad.modify(|r| r.with_io_voltage(io_voltage));
+        }
+        self
+    }
+
+    /// Get the current IO voltage setting of the pad.
+    fn io_voltage(&self) -> IoVoltage {
+        self.inner().pad.read().io_voltage()
+    }
+
+    /// Check that the pad is configured for the voltage a function requires,
+    /// for functions only specified at one IO voltage.
+    fn check_io_voltage(&self, required: IoVoltage) -> Result<(), VoltageMismatch> {
+        let configured = self.io_voltage();
+        if configured == required {
+            Ok(())
+        } else {
+            Err(VoltageMismatch {
+                required,
+                configured,
+            })
+        }
+    }
+
     /// Get the current slew rate setting of the pad.
     fn slew_rate(&self) -> SlewRate {
         self.inner().pad.read().slew_rate()
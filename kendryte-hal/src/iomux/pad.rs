@@ -12,6 +12,8 @@ pub struct RegisterBlock {
 /// Fast means a faster transition, while Slow means a slower transition.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SlewRate {
     /// Fast transition speed.
     Fast = 0b0,
@@ -23,6 +25,8 @@ pub enum SlewRate {
 /// The value ranges from 0 (weakest) to 15 (strongest).
 #[bitenum(u4, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Strength {
     _0 = 0b0000,
     _1 = 0b0001,
@@ -42,6 +46,21 @@ pub enum Strength {
     _15 = 0b1111,
 }
 
+/// IO supply voltage of a pad, which must match the supply of its bank.
+///
+/// The same strength code drives less current at 1.8V, so presets pick higher
+/// codes on 1.8V banks.
+#[bitenum(u1, exhaustive = true)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IoVoltage {
+    /// 3.3V IO supply.
+    V3_3 = 0b0,
+    /// 1.8V IO supply.
+    V1_8 = 0b1,
+}
+
 /// Pad represents the configuration of a single IO pad.
 /// Each field controls a specific aspect of the pad's behavior.
 #[bitfield(u32)]
@@ -58,6 +77,10 @@ pub struct Pad {
     #[bit(10, rw)]
     pub slew_rate: SlewRate,
 
+    /// IO voltage select, matches the pad input and output stages to the bank supply.
+    #[bit(9, rw)]
+    pub io_voltage: IoVoltage,
+
     /// Input enable, allows the pad to receive input.
     #[bit(8, rw)]
     pub input_enable: bool,
@@ -82,3 +105,128 @@ pub struct Pad {
     #[bit(0, rw)]
     pub schmitt_trigger_enable: bool,
 }
+
+/// Electrical pad settings suited to an interface.
+///
+/// Presets are starting points with margin for short board traces; check
+/// signal integrity on long or heavily loaded lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PadPreset {
+    /// Output drive strength.
+    pub drive_strength: Strength,
+    /// Output slew rate.
+    pub slew_rate: SlewRate,
+    /// Whether the input Schmitt trigger is enabled.
+    pub schmitt_trigger: bool,
+}
+
+impl Strength {
+    /// Strength for general purpose IO such as LEDs and chip selects.
+    pub const fn for_gpio(voltage: IoVoltage) -> Strength {
+        match voltage {
+            IoVoltage::V1_8 => Strength::_7,
+            IoVoltage::V3_3 => Strength::_5,
+        }
+    }
+
+    /// Strength for UART lines up to 3 Mbaud.
+    pub const fn for_uart(voltage: IoVoltage) -> Strength {
+        match voltage {
+            IoVoltage::V1_8 => Strength::_7,
+            IoVoltage::V3_3 => Strength::_5,
+        }
+    }
+
+    /// Strength for open-drain I2C lines up to 400 kHz.
+    pub const fn for_i2c_400k(voltage: IoVoltage) -> Strength {
+        match voltage {
+            IoVoltage::V1_8 => Strength::_6,
+            IoVoltage::V3_3 => Strength::_4,
+        }
+    }
+
+    /// Strength for SPI clock and data lines up to 50 MHz.
+    pub const fn for_spi_50mhz(voltage: IoVoltage) -> Strength {
+        match voltage {
+            IoVoltage::V1_8 => Strength::_11,
+            IoVoltage::V3_3 => Strength::_8,
+        }
+    }
+
+    /// Strength for SDIO clock, command and data lines up to 50 MHz.
+    pub const fn for_sdio_50mhz(voltage: IoVoltage) -> Strength {
+        match voltage {
+            IoVoltage::V1_8 => Strength::_13,
+            IoVoltage::V3_3 => Strength::_10,
+        }
+    }
+}
+
+/// Preset for general purpose IO.
+pub const fn preset_gpio(voltage: IoVoltage) -> PadPreset {
+    PadPreset {
+        drive_strength: Strength::for_gpio(voltage),
+        slew_rate: SlewRate::Slow,
+        schmitt_trigger: true,
+    }
+}
+
+/// Preset for UART lines up to 3 Mbaud.
+pub const fn preset_uart(voltage: IoVoltage) -> PadPreset {
+    PadPreset {
+        drive_strength: Strength::for_uart(voltage),
+        slew_rate: SlewRate::Fast,
+        schmitt_trigger: true,
+    }
+}
+
+/// Preset for I2C lines up to 400 kHz.
+/// The slow edge limits ringing and crosstalk on the open-drain bus.
+pub const fn preset_i2c_400k(voltage: IoVoltage) -> PadPreset {
+    PadPreset {
+        drive_strength: Strength::for_i2c_400k(voltage),
+        slew_rate: SlewRate::Slow,
+        schmitt_trigger: true,
+    }
+}
+
+/// Preset for SPI lines up to 50 MHz.
+pub const fn preset_spi_50mhz(voltage: IoVoltage) -> PadPreset {
+    PadPreset {
+        drive_strength: Strength::for_spi_50mhz(voltage),
+        slew_rate: SlewRate::Fast,
+        schmitt_trigger: false,
+    }
+}
+
+/// Preset for SDIO lines up to 50 MHz.
+pub const fn preset_sdio_50mhz(voltage: IoVoltage) -> PadPreset {
+    PadPreset {
+        drive_strength: Strength::for_sdio_50mhz(voltage),
+        slew_rate: SlewRate::Fast,
+        schmitt_trigger: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_stronger_at_low_voltage() {
+        let presets = [
+            preset_gpio,
+            preset_uart,
+            preset_i2c_400k,
+            preset_spi_50mhz,
+            preset_sdio_50mhz,
+        ];
+        for preset in presets {
+            let low = preset(IoVoltage::V1_8).drive_strength.raw_value();
+            let high = preset(IoVoltage::V3_3).drive_strength.raw_value();
+            assert!(low > high);
+        }
+    }
+}
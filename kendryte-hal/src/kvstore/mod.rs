@@ -0,0 +1,609 @@
+//! Persistent key-value store in a flash partition.
+//!
+//! Entries are appended as records to a log spread over the erase sectors of
+//! a [`NorFlash`] partition; the last record for a key wins. When the active
+//! sector fills up the log moves on to the next sector, the live entries of
+//! the oldest sector are copied forward and that sector is erased, so erases
+//! rotate over the whole partition.
+//!
+//! One sector is always kept erased, and every record carries a CRC, so a
+//! power failure at any point loses at most the record being written:
+//! a torn record fails its CRC and is skipped, and an interrupted sector
+//! rotation is completed when the store is next opened.
+//!
+//! On-flash layout of a sector:
+//!
+//! | Offset | Length | Field |
+//! |---|---|---|
+//! | 0 | 4 | Magic `KVS1` |
+//! | 4 | 4 | Sequence number, little endian |
+//! | 8 | ... | Records, each aligned to the flash write size |
+//!
+//! and of a record:
+//!
+//! | Offset | Length | Field |
+//! |---|---|---|
+//! | 0 | 4 | CRC-32 of the following fields, little endian |
+//! | 4 | 1 | Key length |
+//! | 5 | 1 | Kind, value or removal |
+//! | 6 | 2 | Value length, little endian |
+//! | 8 | k | Key |
+//! | 8 + k | v | Value |
+use embedded_storage::nor_flash::NorFlash;
+
+/// Largest key length.
+pub const MAX_KEY_LEN: usize = 32;
+
+/// Largest value length.
+pub const MAX_VALUE_LEN: usize = 256;
+
+/// Magic number at the start of every sector in use.
+pub const MAGIC: [u8; 4] = *b"KVS1";
+
+/// Length of the sector header, before write alignment.
+const SECTOR_HEADER_LEN: usize = 8;
+
+/// Length of the record header.
+const RECORD_HEADER_LEN: usize = 8;
+
+/// Largest write size supported.
+const MAX_WRITE_SIZE: usize = 16;
+
+/// Largest on-flash size of a record, including write alignment padding.
+const MAX_RECORD_LEN: usize =
+    (RECORD_HEADER_LEN + MAX_KEY_LEN + MAX_VALUE_LEN).next_multiple_of(MAX_WRITE_SIZE);
+
+/// Record holding a value.
+const KIND_VALUE: u8 = 0x01;
+
+/// Record marking a key as removed.
+const KIND_REMOVED: u8 = 0x02;
+
+/// Indicate different error conditions that may occur when accessing the store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The flash access failed.
+    Flash(E),
+    /// The partition is not aligned to erase sectors, has fewer than two,
+    /// or the flash geometry is not supported.
+    InvalidPartition,
+    /// The key is empty or longer than [`MAX_KEY_LEN`].
+    InvalidKey,
+    /// The value is longer than [`MAX_VALUE_LEN`], or than the destination buffer.
+    TooLarge,
+    /// The live entries no longer fit in the partition.
+    Full,
+}
+
+type KvResult<T, F> = Result<T, Error<<F as embedded_storage::nor_flash::ErrorType>::Error>>;
+
+/// Location and shape of a valid record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Record {
+    sector: u32,
+    offset: u32,
+    kind: u8,
+    key_len: usize,
+    value_len: usize,
+}
+
+/// Outcome of reading the record at an offset.
+enum Scan {
+    /// Erased flash, the end of the sector's log.
+    End,
+    /// The header is unreadable; nothing after it can be trusted.
+    Corrupt,
+    /// A torn record, skipped.
+    Invalid { next: u32 },
+    /// A valid record, read into the buffer.
+    Valid { record: Record, next: u32 },
+}
+
+/// Key-value store over a flash partition.
+pub struct KvStore<F> {
+    flash: F,
+    offset: u32,
+    sectors: u32,
+    active: u32,
+    sequence: u32,
+    write_offset: u32,
+}
+
+impl<F: NorFlash> KvStore<F> {
+    /// Opens the store in `len` bytes of `flash` starting at `offset`,
+    /// formatting the partition if it holds no store.
+    ///
+    /// The partition needs at least two erase sectors.
+    pub fn new(flash: F, offset: u32, len: u32) -> KvResult<Self, F> {
+        let erase_size = F::ERASE_SIZE as u32;
+        if offset % erase_size != 0
+            || len % erase_size != 0
+            || len / erase_size < 2
+            || F::WRITE_SIZE > MAX_WRITE_SIZE
+            || F::WRITE_SIZE % F::READ_SIZE != 0
+            || RECORD_HEADER_LEN % F::READ_SIZE != 0
+            || F::ERASE_SIZE < Self::header_len() as usize + MAX_RECORD_LEN
+        {
+            return Err(Error::InvalidPartition);
+        }
+        let mut store = Self {
+            flash,
+            offset,
+            sectors: len / erase_size,
+            active: 0,
+            sequence: 0,
+            write_offset: 0,
+        };
+        store.mount()?;
+        Ok(store)
+    }
+
+    /// Reads the value of `key` into `buf` and returns its length,
+    /// or `None` if the key is not set.
+    pub fn get(&mut self, key: &[u8], buf: &mut [u8]) -> KvResult<Option<usize>, F> {
+        Self::check_key(key)?;
+        let mut record_buf = [0; MAX_RECORD_LEN];
+        let Some(record) = self.find(key, &mut record_buf)? else {
+            return Ok(None);
+        };
+        if record.kind == KIND_REMOVED {
+            return Ok(None);
+        }
+        if record.value_len > buf.len() {
+            return Err(Error::TooLarge);
+        }
+        let value = RECORD_HEADER_LEN + record.key_len;
+        buf[..record.value_len].copy_from_slice(&record_buf[value..value + record.value_len]);
+        Ok(Some(record.value_len))
+    }
+
+    /// Sets `key` to `value`. Writing the value already stored is skipped.
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> KvResult<(), F> {
+        Self::check_key(key)?;
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::TooLarge);
+        }
+        let mut record_buf = [0; MAX_RECORD_LEN];
+        if let Some(record) = self.find(key, &mut record_buf)? {
+            let start = RECORD_HEADER_LEN + record.key_len;
+            if record.kind == KIND_VALUE && &record_buf[start..start + record.value_len] == value {
+                return Ok(());
+            }
+        }
+        self.append(KIND_VALUE, key, value)
+    }
+
+    /// Removes `key`. Removing a key that is not set does nothing.
+    pub fn remove(&mut self, key: &[u8]) -> KvResult<(), F> {
+        Self::check_key(key)?;
+        let mut record_buf = [0; MAX_RECORD_LEN];
+        match self.find(key, &mut record_buf)? {
+            Some(record) if record.kind == KIND_VALUE => self.append(KIND_REMOVED, key, &[]),
+            _ => Ok(()),
+        }
+    }
+
+    /// Erases every entry.
+    pub fn format(&mut self) -> KvResult<(), F> {
+        let end = self.offset + self.sectors * F::ERASE_SIZE as u32;
+        self.flash.erase(self.offset, end).map_err(Error::Flash)?;
+        self.start_sector(0, 0)
+    }
+
+    /// Releases the flash.
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    fn check_key(key: &[u8]) -> KvResult<(), F> {
+        match key.len() {
+            1..=MAX_KEY_LEN => Ok(()),
+            _ => Err(Error::InvalidKey),
+        }
+    }
+
+    /// Length of the sector header, aligned to the write size.
+    fn header_len() -> u32 {
+        SECTOR_HEADER_LEN.next_multiple_of(F::WRITE_SIZE) as u32
+    }
+
+    fn sector_start(&self, sector: u32) -> u32 {
+        self.offset + sector * F::ERASE_SIZE as u32
+    }
+
+    /// Finds the active sector, and completes a rotation cut short by power loss.
+    fn mount(&mut self) -> KvResult<(), F> {
+        let mut newest = None;
+        for sector in 0..self.sectors {
+            if let Some(sequence) = self.sector_sequence(sector)? {
+                if newest.is_none_or(|(_, newest)| sequence > newest) {
+                    newest = Some((sector, sequence));
+                }
+            }
+        }
+        let Some((active, sequence)) = newest else {
+            return self.format();
+        };
+        self.active = active;
+        self.sequence = sequence;
+
+        let mut buf = [0; MAX_RECORD_LEN];
+        let mut offset = Self::header_len();
+        self.write_offset = loop {
+            match self.read_record(active, offset, &mut buf)? {
+                Scan::End => break offset,
+                Scan::Corrupt => break F::ERASE_SIZE as u32,
+                Scan::Invalid { next } | Scan::Valid { next, .. } => offset = next,
+            }
+        };
+
+        // The sector after the active one is always left erased.
+        let spare = (active + 1) % self.sectors;
+        if self.sector_sequence(spare)?.is_some() {
+            self.collect(spare)?;
+        }
+        if !self.is_erased(spare)? {
+            self.erase_sector(spare)?;
+        }
+        Ok(())
+    }
+
+    /// Appends a record to the active sector, rotating to the next sector if it is full.
+    ///
+    /// Each rotation compacts one sector, so space freed anywhere in the log
+    /// is found after at most one rotation per sector in use.
+    fn append(&mut self, kind: u8, key: &[u8], value: &[u8]) -> KvResult<(), F> {
+        let len = (RECORD_HEADER_LEN + key.len() + value.len()).next_multiple_of(F::WRITE_SIZE);
+        let mut rotations = 0;
+        while self.write_offset as usize + len > F::ERASE_SIZE {
+            if rotations == self.sectors - 1 {
+                return Err(Error::Full);
+            }
+            self.rotate()?;
+            rotations += 1;
+        }
+
+        let mut buf = [0xFF; MAX_RECORD_LEN];
+        buf[4] = key.len() as u8;
+        buf[5] = kind;
+        buf[6..8].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        buf[8..8 + key.len()].copy_from_slice(key);
+        buf[8 + key.len()..8 + key.len() + value.len()].copy_from_slice(value);
+        let crc = crc32(&buf[4..8 + key.len() + value.len()]);
+        buf[0..4].copy_from_slice(&crc.to_le_bytes());
+
+        let start = self.sector_start(self.active) + self.write_offset;
+        self.write_offset += len as u32;
+        self.flash.write(start, &buf[..len]).map_err(Error::Flash)
+    }
+
+    /// Moves the log to the spare sector, then copies the live entries of the
+    /// oldest sector forward and erases it to become the new spare.
+    ///
+    /// If the entries do not fit, the log stays where it was and the spare
+    /// is erased again.
+    fn rotate(&mut self) -> KvResult<(), F> {
+        let (active, sequence, write_offset) = (self.active, self.sequence, self.write_offset);
+        let next = (active + 1) % self.sectors;
+        self.start_sector(next, sequence.wrapping_add(1))?;
+        let oldest = (next + 1) % self.sectors;
+        if self.sector_sequence(oldest)?.is_some() {
+            if let Err(e) = self.collect(oldest) {
+                if matches!(e, Error::Full) {
+                    self.erase_sector(next)?;
+                    self.active = active;
+                    self.sequence = sequence;
+                    self.write_offset = write_offset;
+                }
+                return Err(e);
+            }
+        }
+        self.erase_sector(oldest)
+    }
+
+    /// Copies the entries whose latest record lives in `sector` to the active sector.
+    fn collect(&mut self, sector: u32) -> KvResult<(), F> {
+        let mut buf = [0; MAX_RECORD_LEN];
+        let mut latest = [0; MAX_RECORD_LEN];
+        let mut offset = Self::header_len();
+        loop {
+            let record = match self.read_record(sector, offset, &mut buf)? {
+                Scan::End | Scan::Corrupt => return Ok(()),
+                Scan::Invalid { next } => {
+                    offset = next;
+                    continue;
+                }
+                Scan::Valid { record, next } => {
+                    offset = next;
+                    record
+                }
+            };
+            // Removal records are dropped: nothing older survives the erase.
+            if record.kind != KIND_VALUE {
+                continue;
+            }
+            let key = &buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + record.key_len];
+            let mut key_copy = [0; MAX_KEY_LEN];
+            key_copy[..key.len()].copy_from_slice(key);
+            let key = &key_copy[..record.key_len];
+            if self.find(key, &mut latest)? != Some(record) {
+                continue;
+            }
+            let value = &latest[RECORD_HEADER_LEN + record.key_len..][..record.value_len];
+            let mut value_copy = [0; MAX_VALUE_LEN];
+            value_copy[..value.len()].copy_from_slice(value);
+
+            let len = (RECORD_HEADER_LEN + record.key_len + record.value_len)
+                .next_multiple_of(F::WRITE_SIZE);
+            if self.write_offset as usize + len > F::ERASE_SIZE {
+                return Err(Error::Full);
+            }
+            self.append(KIND_VALUE, key, &value_copy[..record.value_len])?;
+        }
+    }
+
+    /// Finds the latest valid record of `key`, leaving it in `buf`.
+    fn find(&mut self, key: &[u8], buf: &mut [u8; MAX_RECORD_LEN]) -> KvResult<Option<Record>, F> {
+        let mut found = None;
+        // Oldest to newest, so later records override earlier ones.
+        for i in 1..=self.sectors {
+            let sector = (self.active + i) % self.sectors;
+            if self.sector_sequence(sector)?.is_none() {
+                continue;
+            }
+            let mut offset = Self::header_len();
+            loop {
+                match self.read_record(sector, offset, buf)? {
+                    Scan::End | Scan::Corrupt => break,
+                    Scan::Invalid { next } => offset = next,
+                    Scan::Valid { record, next } => {
+                        if &buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + record.key_len] == key {
+                            found = Some(record);
+                        }
+                        offset = next;
+                    }
+                }
+            }
+        }
+        // Leave the winning record in the buffer.
+        if let Some(record) = found {
+            self.read_record(record.sector, record.offset, buf)?;
+        }
+        Ok(found)
+    }
+
+    /// Reads the record at `offset` of `sector` into `buf`.
+    fn read_record(
+        &mut self,
+        sector: u32,
+        offset: u32,
+        buf: &mut [u8; MAX_RECORD_LEN],
+    ) -> KvResult<Scan, F> {
+        if offset as usize + RECORD_HEADER_LEN > F::ERASE_SIZE {
+            return Ok(Scan::End);
+        }
+        let start = self.sector_start(sector) + offset;
+        let header = &mut buf[..RECORD_HEADER_LEN];
+        self.flash.read(start, header).map_err(Error::Flash)?;
+        if header.iter().all(|&b| b == 0xFF) {
+            return Ok(Scan::End);
+        }
+        let key_len = header[4] as usize;
+        let kind = header[5];
+        let value_len = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let len = (RECORD_HEADER_LEN + key_len + value_len).next_multiple_of(F::WRITE_SIZE);
+        if key_len == 0
+            || key_len > MAX_KEY_LEN
+            || value_len > MAX_VALUE_LEN
+            || offset as usize + len > F::ERASE_SIZE
+        {
+            return Ok(Scan::Corrupt);
+        }
+
+        self.flash
+            .read(start, &mut buf[..len])
+            .map_err(Error::Flash)?;
+        let next = offset + len as u32;
+        let crc = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        if crc != crc32(&buf[4..RECORD_HEADER_LEN + key_len + value_len])
+            || !matches!(kind, KIND_VALUE | KIND_REMOVED)
+        {
+            return Ok(Scan::Invalid { next });
+        }
+        Ok(Scan::Valid {
+            record: Record {
+                sector,
+                offset,
+                kind,
+                key_len,
+                value_len,
+            },
+            next,
+        })
+    }
+
+    /// Returns the sequence number of `sector`, or `None` if it is not in use.
+    fn sector_sequence(&mut self, sector: u32) -> KvResult<Option<u32>, F> {
+        let mut header = [0; SECTOR_HEADER_LEN];
+        let start = self.sector_start(sector);
+        self.flash.read(start, &mut header).map_err(Error::Flash)?;
+        if header[0..4] != MAGIC {
+            return Ok(None);
+        }
+        Ok(Some(u32::from_le_bytes([
+            header[4], header[5], header[6], header[7],
+        ])))
+    }
+
+    /// Writes the header of `sector`, which must be erased, and makes it the active sector.
+    fn start_sector(&mut self, sector: u32, sequence: u32) -> KvResult<(), F> {
+        let mut header = [0xFF; SECTOR_HEADER_LEN.next_multiple_of(MAX_WRITE_SIZE)];
+        header[0..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        let start = self.sector_start(sector);
+        self.flash
+            .write(start, &header[..Self::header_len() as usize])
+            .map_err(Error::Flash)?;
+        self.active = sector;
+        self.sequence = sequence;
+        self.write_offset = Self::header_len();
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, sector: u32) -> KvResult<(), F> {
+        let start = self.sector_start(sector);
+        self.flash
+            .erase(start, start + F::ERASE_SIZE as u32)
+            .map_err(Error::Flash)
+    }
+
+    fn is_erased(&mut self, sector: u32) -> KvResult<bool, F> {
+        let mut chunk = [0; 64];
+        let start = self.sector_start(sector);
+        for offset in (0..F::ERASE_SIZE).step_by(chunk.len()) {
+            let chunk = &mut chunk[..(F::ERASE_SIZE - offset).min(64)];
+            self.flash
+                .read(start + offset as u32, chunk)
+                .map_err(Error::Flash)?;
+            if chunk.iter().any(|&b| b != 0xFF) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RamFlash;
+
+    const SECTOR: usize = 1024;
+
+    type Flash = RamFlash<SECTOR, 3>;
+
+    fn store() -> KvStore<Flash> {
+        KvStore::new(Flash::new(), 0, 3 * SECTOR as u32).unwrap()
+    }
+
+    fn get(store: &mut KvStore<Flash>, key: &[u8]) -> Option<[u8; 4]> {
+        let mut buf = [0; 4];
+        let len = store.get(key, &mut buf).unwrap()?;
+        assert_eq!(len, 4);
+        Some(buf)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn set_get_remove() {
+        let mut store = store();
+        assert_eq!(get(&mut store, b"boot"), None);
+        store.set(b"boot", &1u32.to_le_bytes()).unwrap();
+        store.set(b"gain", &[1, 2, 3, 4]).unwrap();
+        store.set(b"boot", &2u32.to_le_bytes()).unwrap();
+        assert_eq!(get(&mut store, b"boot"), Some(2u32.to_le_bytes()));
+        store.remove(b"boot").unwrap();
+        assert_eq!(get(&mut store, b"boot"), None);
+        assert_eq!(get(&mut store, b"gain"), Some([1, 2, 3, 4]));
+
+        let mut store = KvStore::new(store.free(), 0, 3 * SECTOR as u32).unwrap();
+        assert_eq!(get(&mut store, b"boot"), None);
+        assert_eq!(get(&mut store, b"gain"), Some([1, 2, 3, 4]));
+
+        let mut small = [0; 2];
+        assert_eq!(store.get(b"gain", &mut small), Err(Error::TooLarge));
+        assert_eq!(store.set(b"", &[]), Err(Error::InvalidKey));
+    }
+
+    #[test]
+    fn rotation_keeps_live_entries() {
+        let mut store = store();
+        store.set(b"serial", &[9, 9, 9, 9]).unwrap();
+        for i in 0..1000u32 {
+            store.set(b"boot", &i.to_le_bytes()).unwrap();
+        }
+        assert_eq!(get(&mut store, b"boot"), Some(999u32.to_le_bytes()));
+        assert_eq!(get(&mut store, b"serial"), Some([9, 9, 9, 9]));
+        assert!(store.sequence > 3);
+
+        let mut store = KvStore::new(store.free(), 0, 3 * SECTOR as u32).unwrap();
+        assert_eq!(get(&mut store, b"boot"), Some(999u32.to_le_bytes()));
+        assert_eq!(get(&mut store, b"serial"), Some([9, 9, 9, 9]));
+    }
+
+    #[test]
+    fn torn_write_is_skipped() {
+        let mut store = store();
+        store.set(b"boot", &1u32.to_le_bytes()).unwrap();
+        let start = store.sector_start(store.active) + store.write_offset;
+        store.set(b"boot", &2u32.to_le_bytes()).unwrap();
+
+        // Corrupt the value of the last record, as a power loss mid-write would.
+        let mut flash = store.free();
+        flash.bytes_mut()[start as usize + 12] = 0;
+        let mut store = KvStore::new(flash, 0, 3 * SECTOR as u32).unwrap();
+        assert_eq!(get(&mut store, b"boot"), Some(1u32.to_le_bytes()));
+        store.set(b"boot", &3u32.to_le_bytes()).unwrap();
+        assert_eq!(get(&mut store, b"boot"), Some(3u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn full_store_recovers_after_remove() {
+        let mut store = store();
+        let value = [7; 200];
+        let mut keys = 0u8;
+        loop {
+            match store.set(&[b'k', keys], &value) {
+                Ok(()) => keys += 1,
+                Err(Error::Full) => break,
+                Err(e) => panic!("{e:?}"),
+            }
+        }
+        assert!(keys > 1);
+        store.remove(&[b'k', 0]).unwrap();
+        store.set(&[b'k', keys], &value).unwrap();
+
+        let mut store = KvStore::new(store.free(), 0, 3 * SECTOR as u32).unwrap();
+        let mut buf = [0; MAX_VALUE_LEN];
+        assert_eq!(store.get(&[b'k', 0], &mut buf), Ok(None));
+        for key in 1..=keys {
+            assert_eq!(store.get(&[b'k', key], &mut buf), Ok(Some(200)));
+            assert_eq!(buf[..200], value);
+        }
+    }
+
+    #[test]
+    fn interrupted_rotation_is_completed() {
+        let mut store = store();
+        store.set(b"serial", &[9, 9, 9, 9]).unwrap();
+        // Start the next sector without collecting the oldest one.
+        let next = (store.active + 1) % store.sectors;
+        store.start_sector(next, store.sequence + 1).unwrap();
+        store
+            .start_sector((next + 1) % store.sectors, store.sequence + 1)
+            .unwrap();
+
+        let mut store = KvStore::new(store.free(), 0, 3 * SECTOR as u32).unwrap();
+        assert_eq!(get(&mut store, b"serial"), Some([9, 9, 9, 9]));
+        let spare = (store.active + 1) % store.sectors;
+        assert!(store.is_erased(spare).unwrap());
+    }
+}
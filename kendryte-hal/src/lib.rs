@@ -1,12 +1,47 @@
 //! SoC peripheral support for Cannan Kendryte chips.
 #![no_std]
 #![allow(unused)]
+
+#[cfg(all(feature = "chip-k230", feature = "chip-k230d"))]
+compile_error!("features `chip-k230` and `chip-k230d` select different chips; enable one");
+#[cfg(not(any(feature = "chip-k230", feature = "chip-k230d")))]
+compile_error!("select the chip with feature `chip-k230` or `chip-k230d`");
+
 pub mod clocks;
+pub mod coexist;
+pub mod debug;
+pub mod delay;
+pub mod drivers;
+pub mod dsp;
+pub mod error;
+#[cfg(feature = "fs")]
+pub mod fs;
+pub mod futures;
 pub mod gpio;
 pub mod i2c;
+pub mod input;
 pub mod instance;
 pub mod iomux;
+pub mod kvstore;
 pub mod lsadc;
+pub mod memory_map;
+pub mod pool;
+pub mod proto;
 pub mod pwm;
+pub mod secure_storage;
+pub mod security;
+pub mod selftest;
+pub mod shell;
+pub mod softpwm;
 pub mod spi;
+pub mod sync;
+pub mod tensor;
+#[cfg(test)]
+mod test_support;
+pub mod time;
+pub mod timeout;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod uart;
+
+pub use error::Error;
@@ -0,0 +1,31 @@
+//! Physical address map of the SoC.
+//!
+//! These are the canonical base addresses of memories and peripherals; the
+//! runtime's peripheral structs and linker script and the debug memory map
+//! are built from them rather than repeating the numbers.
+//!
+//! The chip is selected with exactly one of the `chip-k230` and `chip-k230d`
+//! features; neither is enabled by default. The K230D places the same
+//! peripherals at the same addresses but has 128 MiB of in-package DDR
+//! instead of up to 512 MiB; memory size is the only difference between the
+//! chips, so no peripheral instance or pad is left out on either.
+
+// Shared with the runtime's build script, which lays out the linker script
+// from the same numbers.
+pub use kendryte_memories::{DDR_BASE, DDR_LEN, SRAM_BASE, SRAM_LEN};
+
+/// Core-local interruptor, starting with the software interrupt register of each hart.
+pub const CLINT: usize = 0xF_0400_0000;
+
+/// Length of the register block of each peripheral below.
+pub const PERIPHERAL_LEN: usize = 0x1000;
+
+/// Pad function and electrical configuration.
+pub const IOMUX: usize = 0x9110_5000;
+pub const UART0: usize = 0x9140_0000;
+pub const UART1: usize = 0x9140_1000;
+pub const UART2: usize = 0x9140_2000;
+pub const UART3: usize = 0x9140_3000;
+pub const UART4: usize = 0x9140_4000;
+pub const GPIO0: usize = 0x9140_B000;
+pub const GPIO1: usize = 0x9140_C000;
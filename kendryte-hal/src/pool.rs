@@ -0,0 +1,237 @@
+//! Lock-free pools of fixed-size buffers.
+//!
+//! A [`Pool`] is a static array of `N` buffers of `SIZE` bytes, each with a
+//! reference count, so frames move through a pipeline without a general
+//! purpose allocator. Taking a buffer claims a free slot with a
+//! compare-and-swap and never blocks, which makes it usable from interrupt
+//! handlers:
+//!
+//! ```ignore
+//! static FRAMES: Pool<{ 640 * 480 }, 4> = Pool::new();
+//!
+//! let mut frame = FRAMES.take().ok_or(Error::Busy)?;
+//! capture(&mut frame);
+//! // Fan out to several consumers; the slot is freed when the last drops.
+//! let frame = frame.share();
+//! encoder.push(frame.clone());
+//! display.push(frame);
+//! ```
+//!
+//! Buffers are aligned to 64 bytes, a cache line, for DMA.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[repr(C, align(64))]
+struct Slot<const SIZE: usize>(UnsafeCell<[u8; SIZE]>);
+
+/// Pool of `N` buffers of `SIZE` bytes.
+pub struct Pool<const SIZE: usize, const N: usize> {
+    slots: [Slot<SIZE>; N],
+    /// Number of handles to each buffer; zero when free.
+    refs: [AtomicUsize; N],
+}
+
+// A buffer is only reached through the handle that claimed it, or through
+// shared handles that only read it.
+unsafe impl<const SIZE: usize, const N: usize> Sync for Pool<SIZE, N> {}
+
+impl<const SIZE: usize, const N: usize> Pool<SIZE, N> {
+    /// Creates a pool with every buffer free and zeroed.
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { Slot(UnsafeCell::new([0; SIZE])) }; N],
+            refs: [const { AtomicUsize::new(0) }; N],
+        }
+    }
+
+    /// Takes a free buffer, or returns `None` if all are in use.
+    ///
+    /// The buffer keeps the contents of its last use.
+    pub fn take(&self) -> Option<Buffer<'_, SIZE, N>> {
+        (0..N)
+            .find(|&index| {
+                self.refs[index]
+                    .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .map(|index| Buffer { pool: self, index })
+    }
+
+    /// Returns the number of buffers.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of free buffers.
+    pub fn available(&self) -> usize {
+        self.refs
+            .iter()
+            .filter(|refs| refs.load(Ordering::Relaxed) == 0)
+            .count()
+    }
+
+    #[inline]
+    fn release(&self, index: usize) {
+        self.refs[index].fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<const SIZE: usize, const N: usize> Default for Pool<SIZE, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exclusive handle to a buffer of a [`Pool`], freed on drop.
+pub struct Buffer<'a, const SIZE: usize, const N: usize> {
+    pool: &'a Pool<SIZE, N>,
+    index: usize,
+}
+
+impl<'a, const SIZE: usize, const N: usize> Buffer<'a, SIZE, N> {
+    /// Makes the buffer read-only, so it can be handed to several consumers.
+    pub fn share(self) -> Shared<'a, SIZE, N> {
+        let this = core::mem::ManuallyDrop::new(self);
+        Shared {
+            pool: this.pool,
+            index: this.index,
+        }
+    }
+
+    /// Returns the index of the buffer in its pool.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<const SIZE: usize, const N: usize> Deref for Buffer<'_, SIZE, N> {
+    type Target = [u8; SIZE];
+
+    #[inline]
+    fn deref(&self) -> &[u8; SIZE] {
+        // Claimed by this handle alone.
+        unsafe { &*self.pool.slots[self.index].0.get() }
+    }
+}
+
+impl<const SIZE: usize, const N: usize> DerefMut for Buffer<'_, SIZE, N> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8; SIZE] {
+        // Claimed by this handle alone.
+        unsafe { &mut *self.pool.slots[self.index].0.get() }
+    }
+}
+
+impl<const SIZE: usize, const N: usize> Drop for Buffer<'_, SIZE, N> {
+    fn drop(&mut self) {
+        self.pool.release(self.index);
+    }
+}
+
+/// Read-only, reference counted handle to a buffer of a [`Pool`].
+///
+/// Cloning adds a reference; the buffer is freed when the last one drops.
+pub struct Shared<'a, const SIZE: usize, const N: usize> {
+    pool: &'a Pool<SIZE, N>,
+    index: usize,
+}
+
+impl<'a, const SIZE: usize, const N: usize> Shared<'a, SIZE, N> {
+    /// Returns the number of handles to the buffer.
+    pub fn ref_count(&self) -> usize {
+        self.pool.refs[self.index].load(Ordering::Relaxed)
+    }
+
+    /// Turns the handle back into an exclusive one if it is the last.
+    pub fn try_unique(self) -> Result<Buffer<'a, SIZE, N>, Self> {
+        match self.pool.refs[self.index].load(Ordering::Acquire) {
+            1 => {
+                let this = core::mem::ManuallyDrop::new(self);
+                Ok(Buffer {
+                    pool: this.pool,
+                    index: this.index,
+                })
+            }
+            _ => Err(self),
+        }
+    }
+
+    /// Returns the index of the buffer in its pool.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<const SIZE: usize, const N: usize> Clone for Shared<'_, SIZE, N> {
+    fn clone(&self) -> Self {
+        self.pool.refs[self.index].fetch_add(1, Ordering::Relaxed);
+        Self {
+            pool: self.pool,
+            index: self.index,
+        }
+    }
+}
+
+impl<const SIZE: usize, const N: usize> Deref for Shared<'_, SIZE, N> {
+    type Target = [u8; SIZE];
+
+    #[inline]
+    fn deref(&self) -> &[u8; SIZE] {
+        // Only read while shared.
+        unsafe { &*self.pool.slots[self.index].0.get() }
+    }
+}
+
+impl<const SIZE: usize, const N: usize> Drop for Shared<'_, SIZE, N> {
+    fn drop(&mut self) {
+        self.pool.release(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_and_frees() {
+        let pool: Pool<16, 2> = Pool::new();
+        let mut a = pool.take().unwrap();
+        let b = pool.take().unwrap();
+        assert!(pool.take().is_none());
+        assert_eq!(pool.available(), 0);
+        assert_eq!(a.as_ptr() as usize % 64, 0);
+
+        a[0] = 7;
+        let index = a.index();
+        drop(a);
+        let a = pool.take().unwrap();
+        assert_eq!((a.index(), a[0]), (index, 7));
+        drop((a, b));
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    fn shares_until_last_drop() {
+        let pool: Pool<4, 1> = Pool::new();
+        let mut buffer = pool.take().unwrap();
+        buffer.copy_from_slice(b"abcd");
+        let first = buffer.share();
+        let second = first.clone();
+        assert_eq!(second.ref_count(), 2);
+        assert_eq!(&*second, b"abcd");
+
+        let Err(first) = first.try_unique() else {
+            panic!("buffer still shared");
+        };
+        drop(second);
+        assert!(pool.take().is_none());
+        let buffer = first.try_unique().ok().unwrap();
+        drop(buffer);
+        assert_eq!(pool.available(), 1);
+    }
+}
@@ -0,0 +1,383 @@
+//! JTAG probe bit-banged over GPIO.
+//!
+//! Lets a board drive the JTAG port of an attached chip, as production test
+//! fixtures do to identify and program a target MCU. The probe walks the
+//! TAP state machine itself and rests in Run-Test/Idle between scans:
+//!
+//! ```ignore
+//! let mut jtag = Jtag::new(tck, tms, tdi, tdo, delay, 1_000_000)?;
+//! jtag.reset()?;
+//! assert_eq!(jtag.idcode()? & 0x0FFF_FFFF, EXPECTED_IDCODE);
+//! jtag.shift_ir(&[BYPASS], 5)?;
+//! ```
+//!
+//! Scan data is sent and received least significant bit first, starting
+//! with the lowest byte.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+
+/// State of the TAP controller, as defined by IEEE 1149.1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TapState {
+    TestLogicReset,
+    RunTestIdle,
+    SelectDrScan,
+    CaptureDr,
+    ShiftDr,
+    Exit1Dr,
+    PauseDr,
+    Exit2Dr,
+    UpdateDr,
+    SelectIrScan,
+    CaptureIr,
+    ShiftIr,
+    Exit1Ir,
+    PauseIr,
+    Exit2Ir,
+    UpdateIr,
+}
+
+impl TapState {
+    /// Returns the state after a TCK rising edge with TMS at `tms`.
+    pub const fn next(self, tms: bool) -> TapState {
+        use TapState::*;
+        match (self, tms) {
+            (TestLogicReset, false) => RunTestIdle,
+            (TestLogicReset, true) => TestLogicReset,
+            (RunTestIdle, false) => RunTestIdle,
+            (RunTestIdle, true) => SelectDrScan,
+            (SelectDrScan, false) => CaptureDr,
+            (SelectDrScan, true) => SelectIrScan,
+            (CaptureDr, false) | (ShiftDr, false) | (Exit2Dr, false) => ShiftDr,
+            (CaptureDr, true) | (ShiftDr, true) => Exit1Dr,
+            (Exit1Dr, false) | (PauseDr, false) => PauseDr,
+            (Exit1Dr, true) | (Exit2Dr, true) => UpdateDr,
+            (PauseDr, true) => Exit2Dr,
+            (UpdateDr, false) | (UpdateIr, false) => RunTestIdle,
+            (UpdateDr, true) | (UpdateIr, true) => SelectDrScan,
+            (SelectIrScan, false) => CaptureIr,
+            (SelectIrScan, true) => TestLogicReset,
+            (CaptureIr, false) | (ShiftIr, false) | (Exit2Ir, false) => ShiftIr,
+            (CaptureIr, true) | (ShiftIr, true) => Exit1Ir,
+            (Exit1Ir, false) | (PauseIr, false) => PauseIr,
+            (Exit1Ir, true) | (Exit2Ir, true) => UpdateIr,
+            (PauseIr, true) => Exit2Ir,
+        }
+    }
+}
+
+/// Errors of JTAG operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Driving or sampling a pin failed.
+    Pin(E),
+    /// The buffers hold fewer bits than requested.
+    BufferTooShort,
+}
+
+impl<E> From<E> for Error<E> {
+    #[inline]
+    fn from(e: E) -> Self {
+        Error::Pin(e)
+    }
+}
+
+/// Bit-banged JTAG probe.
+pub struct Jtag<TCK, TMS, TDI, TDO, D> {
+    tck: TCK,
+    tms: TMS,
+    tdi: TDI,
+    tdo: TDO,
+    delay: D,
+    half_period_ns: u32,
+    state: TapState,
+}
+
+impl<TCK, TMS, TDI, TDO, D, E> Jtag<TCK, TMS, TDI, TDO, D>
+where
+    TCK: OutputPin<Error = E>,
+    TMS: OutputPin<Error = E>,
+    TDI: OutputPin<Error = E>,
+    TDO: InputPin<Error = E>,
+    D: DelayNs,
+{
+    /// Creates a probe clocking TCK at up to `frequency` hertz, and resets the target's TAP.
+    pub fn new(
+        tck: TCK,
+        tms: TMS,
+        tdi: TDI,
+        tdo: TDO,
+        delay: D,
+        frequency: u32,
+    ) -> Result<Self, Error<E>> {
+        let mut jtag = Self {
+            tck,
+            tms,
+            tdi,
+            tdo,
+            delay,
+            half_period_ns: 0,
+            state: TapState::TestLogicReset,
+        };
+        jtag.set_frequency(frequency);
+        jtag.tck.set_low()?;
+        jtag.reset()?;
+        Ok(jtag)
+    }
+
+    /// Sets the highest TCK frequency, in hertz; GPIO speed limits it further.
+    #[inline]
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.half_period_ns = 500_000_000u32.div_ceil(frequency.max(1));
+    }
+
+    /// Returns the state the TAP is in.
+    #[inline]
+    pub fn state(&self) -> TapState {
+        self.state
+    }
+
+    /// Resets the TAP through Test-Logic-Reset and stops in Run-Test/Idle.
+    ///
+    /// Five clocks with TMS high reach Test-Logic-Reset from any state, so
+    /// this also recovers a TAP in an unknown state.
+    pub fn reset(&mut self) -> Result<(), Error<E>> {
+        for _ in 0..5 {
+            self.clock(true, false)?;
+        }
+        self.clock(false, false)?;
+        Ok(())
+    }
+
+    /// Clocks `cycles` times in Run-Test/Idle, as some targets need between operations.
+    pub fn run_idle(&mut self, cycles: u32) -> Result<(), Error<E>> {
+        for _ in 0..cycles {
+            self.clock(false, false)?;
+        }
+        Ok(())
+    }
+
+    /// Shifts `bits` bits of `data` into the instruction register.
+    pub fn shift_ir(&mut self, data: &[u8], bits: usize) -> Result<(), Error<E>> {
+        check_len(data, None, bits)?;
+        // Select-DR-Scan, Select-IR-Scan, Capture-IR.
+        self.walk(&[true, true, false])?;
+        self.shift(data, None, bits)
+    }
+
+    /// Shifts `bits` bits of `data` through the data register, capturing its previous content into `read`.
+    pub fn shift_dr(&mut self, data: &[u8], read: &mut [u8], bits: usize) -> Result<(), Error<E>> {
+        check_len(data, Some(read), bits)?;
+        // Select-DR-Scan, Capture-DR.
+        self.walk(&[true, false])?;
+        self.shift(data, Some(read), bits)
+    }
+
+    /// Reads the 32-bit IDCODE the TAP selects after a reset.
+    ///
+    /// Resets the TAP first. A TAP without IDCODE selects its one-bit
+    /// bypass register instead, so bit 0 of the result is then 0.
+    pub fn idcode(&mut self) -> Result<u32, Error<E>> {
+        self.reset()?;
+        let mut id = [0; 4];
+        self.shift_dr(&[0; 4], &mut id, 32)?;
+        Ok(u32::from_le_bytes(id))
+    }
+
+    /// Releases the pins and the delay.
+    #[inline]
+    pub fn free(self) -> (TCK, TMS, TDI, TDO, D) {
+        (self.tck, self.tms, self.tdi, self.tdo, self.delay)
+    }
+
+    /// Shifts from Capture-DR or Capture-IR and returns to Run-Test/Idle.
+    fn shift(
+        &mut self,
+        data: &[u8],
+        mut read: Option<&mut [u8]>,
+        bits: usize,
+    ) -> Result<(), Error<E>> {
+        if let Some(read) = read.as_deref_mut() {
+            read[..bits.div_ceil(8)].fill(0);
+        }
+        if bits == 0 {
+            // Exit1, Update, Run-Test/Idle.
+            return self.walk(&[true, true, false]);
+        }
+        // Into Shift-DR or Shift-IR; the last bit moves on to Exit1.
+        self.clock(false, false)?;
+        for bit in 0..bits {
+            let last = bit + 1 == bits;
+            let tdo = self.clock(last, (data[bit / 8] >> (bit % 8)) & 1 != 0)?;
+            if let Some(read) = read.as_deref_mut() {
+                read[bit / 8] |= (tdo as u8) << (bit % 8);
+            }
+        }
+        // Update, Run-Test/Idle.
+        self.walk(&[true, false])
+    }
+
+    fn walk(&mut self, tms: &[bool]) -> Result<(), Error<E>> {
+        for &tms in tms {
+            self.clock(tms, false)?;
+        }
+        Ok(())
+    }
+
+    /// Runs one TCK cycle, returning TDO as sampled before the rising edge.
+    fn clock(&mut self, tms: bool, tdi: bool) -> Result<bool, Error<E>> {
+        if tms {
+            self.tms.set_high()?;
+        } else {
+            self.tms.set_low()?;
+        }
+        if tdi {
+            self.tdi.set_high()?;
+        } else {
+            self.tdi.set_low()?;
+        }
+        self.delay.delay_ns(self.half_period_ns);
+        let tdo = self.tdo.is_high()?;
+        self.tck.set_high()?;
+        self.state = self.state.next(tms);
+        self.delay.delay_ns(self.half_period_ns);
+        self.tck.set_low()?;
+        Ok(tdo)
+    }
+}
+
+fn check_len<E>(data: &[u8], read: Option<&[u8]>, bits: usize) -> Result<(), Error<E>> {
+    let bytes = bits.div_ceil(8);
+    if data.len() < bytes || read.is_some_and(|read| read.len() < bytes) {
+        return Err(Error::BufferTooShort);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NoDelay;
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+
+    const IDCODE: u32 = 0x1234_5677;
+
+    /// TAP with a 4-bit instruction register, IDCODE and BYPASS.
+    struct Target {
+        state: TapState,
+        tms: bool,
+        tdi: bool,
+        ir: u8,
+        shift: u64,
+        len: u32,
+    }
+
+    impl Target {
+        fn rising_edge(&mut self) {
+            match self.state {
+                TapState::TestLogicReset => self.ir = 0b0001,
+                TapState::CaptureIr => (self.shift, self.len) = (0b0001, 4),
+                TapState::CaptureDr if self.ir == 0b0001 => {
+                    (self.shift, self.len) = (IDCODE as u64, 32)
+                }
+                TapState::CaptureDr => (self.shift, self.len) = (0, 1),
+                TapState::ShiftIr | TapState::ShiftDr => {
+                    self.shift = (self.shift >> 1) | ((self.tdi as u64) << (self.len - 1));
+                }
+                TapState::UpdateIr => self.ir = self.shift as u8,
+                _ => {}
+            }
+            self.state = self.state.next(self.tms);
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Line {
+        Tck,
+        Tms,
+        Tdi,
+        Tdo,
+    }
+
+    struct Pin<'a>(&'a RefCell<Target>, Line);
+
+    impl embedded_hal::digital::ErrorType for Pin<'_> {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin<'_> {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            let mut target = self.0.borrow_mut();
+            match self.1 {
+                Line::Tms => target.tms = false,
+                Line::Tdi => target.tdi = false,
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            let mut target = self.0.borrow_mut();
+            match self.1 {
+                Line::Tck => target.rising_edge(),
+                Line::Tms => target.tms = true,
+                Line::Tdi => target.tdi = true,
+                Line::Tdo => {}
+            }
+            Ok(())
+        }
+    }
+
+    impl InputPin for Pin<'_> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            let target = self.0.borrow();
+            let shifting = matches!(target.state, TapState::ShiftDr | TapState::ShiftIr);
+            Ok(shifting && target.shift & 1 != 0)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    #[test]
+    fn scans_target() {
+        let target = RefCell::new(Target {
+            state: TapState::ShiftIr,
+            tms: false,
+            tdi: false,
+            ir: 0,
+            shift: 0,
+            len: 1,
+        });
+        let pin = |line| Pin(&target, line);
+        let mut jtag = Jtag::new(
+            pin(Line::Tck),
+            pin(Line::Tms),
+            pin(Line::Tdi),
+            pin(Line::Tdo),
+            NoDelay::new(),
+            10_000_000,
+        )
+        .unwrap();
+        assert_eq!(jtag.state(), TapState::RunTestIdle);
+        assert_eq!(jtag.idcode(), Ok(IDCODE));
+        assert_eq!(target.borrow().state, TapState::RunTestIdle);
+
+        // Through BYPASS, data comes back one bit late.
+        jtag.shift_ir(&[0b1111], 4).unwrap();
+        assert_eq!(target.borrow().ir, 0b1111);
+        let mut read = [0; 1];
+        jtag.shift_dr(&[0b1011_0101], &mut read, 8).unwrap();
+        assert_eq!(read, [0b0110_1010]);
+        assert_eq!(
+            jtag.shift_dr(&[0; 1], &mut read, 16),
+            Err(Error::BufferTooShort)
+        );
+        assert_eq!(jtag.state(), TapState::RunTestIdle);
+    }
+}
@@ -0,0 +1,3 @@
+//! Protocol layers built on top of the peripheral drivers.
+pub mod jtag;
+pub mod modbus;
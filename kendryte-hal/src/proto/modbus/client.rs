@@ -0,0 +1,365 @@
+use super::crc::{append_crc, check_crc};
+use super::{
+    BROADCAST_ADDRESS, Error, Exception, MAX_ADU_LEN, Timing, function, read_frame, write_frame,
+};
+use embedded_hal::delay::DelayNs;
+use embedded_hal_nb::serial::{Read, Write};
+use embedded_time::rate::Baud;
+
+/// Default time to wait for the first character of a response.
+const DEFAULT_RESPONSE_TIMEOUT_NS: u32 = 100_000_000;
+
+/// A MODBUS RTU client issuing requests to servers on the line.
+pub struct Client {
+    timing: Timing,
+    response_timeout_ns: u32,
+}
+
+impl Client {
+    /// Creates a new client for the given line baud rate, or returns `None`
+    /// if the baud rate is not usable, see [`Timing::new`].
+    ///
+    /// The response timeout defaults to 100 ms.
+    pub fn new(baud: Baud) -> Option<Self> {
+        Some(Self {
+            timing: Timing::new(baud)?,
+            response_timeout_ns: DEFAULT_RESPONSE_TIMEOUT_NS,
+        })
+    }
+
+    /// Sets the time to wait for the first character of a response, in nanoseconds.
+    pub fn set_response_timeout_ns(mut self, response_timeout_ns: u32) -> Self {
+        self.response_timeout_ns = response_timeout_ns;
+        self
+    }
+
+    /// Reads consecutive coils starting at `address` into `values`.
+    pub fn read_coils<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        values: &mut [bool],
+    ) -> Result<(), Error<S::Error>> {
+        self.read_bits(serial, delay, server, function::READ_COILS, address, values)
+    }
+
+    /// Reads consecutive discrete inputs starting at `address` into `values`.
+    pub fn read_discrete_inputs<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        values: &mut [bool],
+    ) -> Result<(), Error<S::Error>> {
+        let code = function::READ_DISCRETE_INPUTS;
+        self.read_bits(serial, delay, server, code, address, values)
+    }
+
+    /// Reads consecutive holding registers starting at `address` into `values`.
+    pub fn read_holding_registers<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        values: &mut [u16],
+    ) -> Result<(), Error<S::Error>> {
+        let code = function::READ_HOLDING_REGISTERS;
+        self.read_registers(serial, delay, server, code, address, values)
+    }
+
+    /// Reads consecutive input registers starting at `address` into `values`.
+    pub fn read_input_registers<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        values: &mut [u16],
+    ) -> Result<(), Error<S::Error>> {
+        let code = function::READ_INPUT_REGISTERS;
+        self.read_registers(serial, delay, server, code, address, values)
+    }
+
+    /// Writes a single coil.
+    pub fn write_single_coil<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        value: bool,
+    ) -> Result<(), Error<S::Error>> {
+        let value: u16 = match value {
+            true => 0xFF00,
+            false => 0x0000,
+        };
+        self.write_single(
+            serial,
+            delay,
+            server,
+            function::WRITE_SINGLE_COIL,
+            address,
+            value,
+        )
+    }
+
+    /// Writes a single holding register.
+    pub fn write_single_register<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), Error<S::Error>> {
+        let code = function::WRITE_SINGLE_REGISTER;
+        self.write_single(serial, delay, server, code, address, value)
+    }
+
+    /// Writes consecutive coils starting at `address`.
+    pub fn write_multiple_coils<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        values: &[bool],
+    ) -> Result<(), Error<S::Error>> {
+        if values.is_empty() || values.len() > 1968 {
+            return Err(Error::TooLarge);
+        }
+        let bytes = values.len().div_ceil(8);
+        let mut pdu = [0; MAX_ADU_LEN - 3];
+        pdu[0] = function::WRITE_MULTIPLE_COILS;
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu[5] = bytes as u8;
+        for (i, value) in values.iter().enumerate() {
+            pdu[6 + i / 8] |= (*value as u8) << (i % 8);
+        }
+        self.write_multiple(serial, delay, server, &pdu[..6 + bytes])
+    }
+
+    /// Writes consecutive holding registers starting at `address`.
+    pub fn write_multiple_registers<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<(), Error<S::Error>> {
+        if values.is_empty() || values.len() > 123 {
+            return Err(Error::TooLarge);
+        }
+        let bytes = values.len() * 2;
+        let mut pdu = [0; MAX_ADU_LEN - 3];
+        pdu[0] = function::WRITE_MULTIPLE_REGISTERS;
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu[5] = bytes as u8;
+        for (i, value) in values.iter().enumerate() {
+            pdu[6 + i * 2..8 + i * 2].copy_from_slice(&value.to_be_bytes());
+        }
+        self.write_multiple(serial, delay, server, &pdu[..6 + bytes])
+    }
+
+    /// Issues a bit read request and unpacks the response.
+    fn read_bits<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        code: u8,
+        address: u16,
+        values: &mut [bool],
+    ) -> Result<(), Error<S::Error>> {
+        if values.is_empty() || values.len() > 2000 {
+            return Err(Error::TooLarge);
+        }
+        let mut pdu = [code, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        let mut response = [0; MAX_ADU_LEN];
+        let data = self.transact(serial, delay, server, &pdu, &mut response)?;
+        let bytes = values.len().div_ceil(8);
+        if data.len() != 1 + bytes || data[0] as usize != bytes {
+            return Err(Error::InvalidFrame);
+        }
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = data[1 + i / 8] >> (i % 8) & 1 != 0;
+        }
+        Ok(())
+    }
+
+    /// Issues a register read request and unpacks the response.
+    fn read_registers<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        code: u8,
+        address: u16,
+        values: &mut [u16],
+    ) -> Result<(), Error<S::Error>> {
+        if values.is_empty() || values.len() > 125 {
+            return Err(Error::TooLarge);
+        }
+        let mut pdu = [code, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        let mut response = [0; MAX_ADU_LEN];
+        let data = self.transact(serial, delay, server, &pdu, &mut response)?;
+        if data.len() != 1 + values.len() * 2 || data[0] as usize != values.len() * 2 {
+            return Err(Error::InvalidFrame);
+        }
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = u16::from_be_bytes([data[1 + i * 2], data[2 + i * 2]]);
+        }
+        Ok(())
+    }
+
+    /// Issues a single coil or register write, which the server echoes back.
+    fn write_single<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        code: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), Error<S::Error>> {
+        let mut pdu = [code, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&value.to_be_bytes());
+        let mut response = [0; MAX_ADU_LEN];
+        let data = self.transact(serial, delay, server, &pdu, &mut response)?;
+        match server == BROADCAST_ADDRESS || data == &pdu[1..] {
+            true => Ok(()),
+            false => Err(Error::InvalidFrame),
+        }
+    }
+
+    /// Issues a multiple coil or register write, which the server acknowledges
+    /// with the starting address and quantity.
+    fn write_multiple<S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        pdu: &[u8],
+    ) -> Result<(), Error<S::Error>> {
+        let mut response = [0; MAX_ADU_LEN];
+        let data = self.transact(serial, delay, server, pdu, &mut response)?;
+        match server == BROADCAST_ADDRESS || data == &pdu[1..5] {
+            true => Ok(()),
+            false => Err(Error::InvalidFrame),
+        }
+    }
+
+    /// Sends a request PDU and returns the data of the response PDU.
+    ///
+    /// Broadcast requests are not answered, so an empty slice is returned for them.
+    fn transact<'b, S: Read + Write, D: DelayNs>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        server: u8,
+        pdu: &[u8],
+        response: &'b mut [u8; MAX_ADU_LEN],
+    ) -> Result<&'b [u8], Error<S::Error>> {
+        if pdu.len() > MAX_ADU_LEN - 3 {
+            return Err(Error::TooLarge);
+        }
+        let mut request = [0; MAX_ADU_LEN];
+        request[0] = server;
+        request[1..1 + pdu.len()].copy_from_slice(pdu);
+        let len = append_crc(&mut request, 1 + pdu.len());
+
+        delay.delay_ns(self.timing.frame_gap_ns);
+        write_frame(serial, &request[..len])?;
+        if server == BROADCAST_ADDRESS {
+            return Ok(&[]);
+        }
+
+        let timeout_ns = Some(self.response_timeout_ns);
+        let len = read_frame(serial, delay, self.timing, response, timeout_ns)?;
+        let response = &response[..len];
+        if len < 4 {
+            return Err(Error::InvalidFrame);
+        }
+        if !check_crc(response) {
+            return Err(Error::Crc);
+        }
+        if response[0] != server {
+            return Err(Error::InvalidFrame);
+        }
+        match response[1] {
+            code if code == pdu[0] => Ok(&response[2..len - 2]),
+            // Address, function code, exception code and CRC.
+            code if code == pdu[0] | 0x80 && len == 5 => {
+                Err(Error::Exception(Exception::from(response[2])))
+            }
+            _ => Err(Error::InvalidFrame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NoDelay;
+    use core::convert::Infallible;
+    use embedded_hal_nb::nb;
+
+    /// Serial port taking any request and replaying a response.
+    struct Line<'a>(&'a [u8]);
+
+    impl embedded_hal_nb::serial::ErrorType for Line<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Line<'_> {
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            let (&next, rest) = self.0.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.0 = rest;
+            Ok(next)
+        }
+    }
+
+    impl Write for Line<'_> {
+        fn write(&mut self, _: u8) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn exception_needs_the_whole_frame() {
+        let mut client = Client::new(Baud::new(9600)).unwrap();
+        let mut values = [0; 1];
+        let mut frame = [0x01, 0x83, 0x02, 0, 0];
+
+        let len = append_crc(&mut frame, 3);
+        let mut line = Line(&frame[..len]);
+        assert_eq!(
+            client.read_holding_registers(&mut line, &mut NoDelay::new(), 0x01, 0, &mut values),
+            Err(Error::Exception(Exception::IllegalDataAddress))
+        );
+
+        // Cut after the function code, the CRC would be read as the exception code.
+        let len = append_crc(&mut frame, 2);
+        let mut line = Line(&frame[..len]);
+        assert_eq!(
+            client.read_holding_registers(&mut line, &mut NoDelay::new(), 0x01, 0, &mut values),
+            Err(Error::InvalidFrame)
+        );
+    }
+}
@@ -0,0 +1,55 @@
+/// Computes the MODBUS RTU CRC-16 of a byte slice.
+///
+/// The CRC uses the reflected polynomial 0xA001 with an initial value of 0xFFFF.
+/// It is transmitted low byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ 0xA001,
+            };
+        }
+    }
+    crc
+}
+
+/// Checks that a frame ends with the CRC of its preceding bytes.
+pub fn check_crc(frame: &[u8]) -> bool {
+    match frame.len() {
+        0..=2 => false,
+        len => {
+            let (data, crc) = frame.split_at(len - 2);
+            crc16(data).to_le_bytes() == [crc[0], crc[1]]
+        }
+    }
+}
+
+/// Appends the CRC of `frame[..len]` to the frame and returns the new frame length.
+pub(crate) fn append_crc(frame: &mut [u8], len: usize) -> usize {
+    let crc = crc16(&frame[..len]);
+    frame[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+    len + 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_known_frames() {
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]), 0x0A84);
+        assert_eq!(crc16(&[0x11, 0x03, 0x00, 0x6B, 0x00, 0x03]), 0x8776);
+    }
+
+    #[test]
+    fn check_crc_frames() {
+        assert!(check_crc(&[0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87]));
+        assert!(!check_crc(&[
+            0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x87, 0x76
+        ]));
+        assert!(!check_crc(&[0x76, 0x87]));
+    }
+}
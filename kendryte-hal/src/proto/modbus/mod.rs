@@ -0,0 +1,243 @@
+//! MODBUS RTU protocol layer.
+//!
+//! Frames are exchanged over any `embedded_hal_nb` serial port, such as the blocking UART driver.
+//! Frame boundaries are detected from the 3.5 character silent interval (t3.5),
+//! and frames with a gap of more than 1.5 characters (t1.5) inside are
+//! discarded; both are measured with an `embedded_hal` delay provider.
+mod client;
+mod crc;
+mod server;
+
+pub use client::Client;
+pub use crc::{check_crc, crc16};
+pub use server::{RegisterMap, Server};
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::{Read, Write};
+use embedded_time::rate::Baud;
+
+/// Maximum size of an RTU frame, including address and CRC.
+pub const MAX_ADU_LEN: usize = 256;
+
+/// Address used by a client to send a request to all servers.
+pub const BROADCAST_ADDRESS: u8 = 0;
+
+/// Function codes supported by the client and server.
+pub mod function {
+    pub const READ_COILS: u8 = 0x01;
+    pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+    pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+    pub const READ_INPUT_REGISTERS: u8 = 0x04;
+    pub const WRITE_SINGLE_COIL: u8 = 0x05;
+    pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+    pub const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+    pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+}
+
+/// Exception codes returned by a server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Exception {
+    /// The function code is not supported by the server.
+    IllegalFunction,
+    /// The data address is not available in the server.
+    IllegalDataAddress,
+    /// A value in the request is not allowed.
+    IllegalDataValue,
+    /// An unrecoverable error occurred while performing the action.
+    ServerDeviceFailure,
+    /// Any other exception code.
+    Other(u8),
+}
+
+impl Exception {
+    /// Returns the exception code sent on the wire.
+    pub fn code(self) -> u8 {
+        match self {
+            Exception::IllegalFunction => 0x01,
+            Exception::IllegalDataAddress => 0x02,
+            Exception::IllegalDataValue => 0x03,
+            Exception::ServerDeviceFailure => 0x04,
+            Exception::Other(code) => code,
+        }
+    }
+}
+
+impl From<u8> for Exception {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => Exception::IllegalFunction,
+            0x02 => Exception::IllegalDataAddress,
+            0x03 => Exception::IllegalDataValue,
+            0x04 => Exception::ServerDeviceFailure,
+            code => Exception::Other(code),
+        }
+    }
+}
+
+/// Errors that may occur during a MODBUS transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The underlying serial port reported an error.
+    Serial(E),
+    /// No response was received in time.
+    Timeout,
+    /// A frame with a wrong CRC was received.
+    Crc,
+    /// The frame is malformed or does not match the request.
+    InvalidFrame,
+    /// The request does not fit in a single frame.
+    TooLarge,
+    /// The server answered with an exception.
+    Exception(Exception),
+}
+
+/// Timing of an RTU line, derived from its baud rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timing {
+    /// Time to transmit one 11-bit character, in nanoseconds.
+    pub char_ns: u32,
+    /// Longest silence allowed between two characters of a frame (t1.5), in nanoseconds.
+    pub char_gap_ns: u32,
+    /// Silent interval that separates two frames (t3.5), in nanoseconds.
+    pub frame_gap_ns: u32,
+}
+
+impl Timing {
+    /// Computes the line timing for a baud rate, or returns `None` if the
+    /// baud rate is zero or too low for the timing to fit in nanoseconds.
+    ///
+    /// Above 19200 baud the specification fixes t1.5 at 750 µs and t3.5 at 1.75 ms.
+    pub fn new(baud: Baud) -> Option<Self> {
+        let char_ns = 11_000_000_000_u64.checked_div(baud.0 as u64)?;
+        let (char_gap_ns, frame_gap_ns) = match baud.0 {
+            0..=19_200 => (char_ns * 3 / 2, char_ns * 7 / 2),
+            _ => (750_000, 1_750_000),
+        };
+        Some(Self {
+            char_ns: u32::try_from(char_ns).ok()?,
+            char_gap_ns: u32::try_from(char_gap_ns).ok()?,
+            frame_gap_ns: u32::try_from(frame_gap_ns).ok()?,
+        })
+    }
+}
+
+/// Writes a complete frame and waits until it has been transmitted.
+pub(crate) fn write_frame<S: Write>(serial: &mut S, frame: &[u8]) -> Result<(), Error<S::Error>> {
+    for byte in frame {
+        nb::block!(serial.write(*byte)).map_err(Error::Serial)?;
+    }
+    nb::block!(serial.flush()).map_err(Error::Serial)
+}
+
+/// Receives a frame into `buf` and returns its length.
+///
+/// The frame ends once the line has been silent for t3.5. A frame with a
+/// silence longer than t1.5 between two characters is incomplete, and is
+/// read to its end and rejected.
+/// When `timeout_ns` is set, gives up if the first character does not arrive in time.
+pub(crate) fn read_frame<S: Read, D: DelayNs>(
+    serial: &mut S,
+    delay: &mut D,
+    timing: Timing,
+    buf: &mut [u8; MAX_ADU_LEN],
+    timeout_ns: Option<u32>,
+) -> Result<usize, Error<S::Error>> {
+    let step_ns = (timing.char_ns / 2).max(1);
+    let mut len = 0_usize;
+    let mut idle_ns = 0_u32;
+    let mut overflow = false;
+    let mut broken = false;
+    loop {
+        match serial.read() {
+            Ok(byte) => {
+                if len > 0 && idle_ns > timing.char_gap_ns {
+                    broken = true;
+                }
+                match buf.get_mut(len) {
+                    Some(slot) => {
+                        *slot = byte;
+                        len += 1;
+                    }
+                    None => overflow = true,
+                }
+                idle_ns = 0;
+            }
+            Err(nb::Error::WouldBlock) => {
+                if len == 0 {
+                    if timeout_ns.is_some_and(|timeout_ns| idle_ns >= timeout_ns) {
+                        return Err(Error::Timeout);
+                    }
+                } else if idle_ns >= timing.frame_gap_ns {
+                    break;
+                }
+                delay.delay_ns(step_ns);
+                idle_ns = idle_ns.saturating_add(step_ns);
+            }
+            Err(nb::Error::Other(e)) => return Err(Error::Serial(e)),
+        }
+    }
+    match overflow || broken {
+        true => Err(Error::InvalidFrame),
+        false => Ok(len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NoDelay;
+    use core::convert::Infallible;
+
+    /// Serial port replaying bytes, `None` being a poll with nothing received.
+    struct Line<'a>(&'a [Option<u8>]);
+
+    impl embedded_hal_nb::serial::ErrorType for Line<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Line<'_> {
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            let (&next, rest) = self.0.split_first().unwrap_or((&None, &[]));
+            self.0 = rest;
+            next.ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    #[test]
+    fn timing() {
+        let timing = Timing::new(Baud::new(9600)).unwrap();
+        assert_eq!(timing.char_ns, 1_145_833);
+        assert_eq!(timing.char_gap_ns, 1_718_749);
+        assert_eq!(timing.frame_gap_ns, 4_010_415);
+        let timing = Timing::new(Baud::new(115_200)).unwrap();
+        assert_eq!(
+            (timing.char_gap_ns, timing.frame_gap_ns),
+            (750_000, 1_750_000)
+        );
+        assert_eq!(Timing::new(Baud::new(0)), None);
+        assert_eq!(Timing::new(Baud::new(1)), None);
+    }
+
+    #[test]
+    fn frame_with_a_gap_is_rejected() {
+        let timing = Timing::new(Baud::new(9600)).unwrap();
+        let mut buf = [0; MAX_ADU_LEN];
+        // Each empty poll waits half a character.
+        let frame = [Some(1), None, None, None, Some(2), None, None, None, None];
+        let mut line = Line(&frame);
+        assert_eq!(
+            read_frame(&mut line, &mut NoDelay::new(), timing, &mut buf, None),
+            Ok(2)
+        );
+        let frame = [Some(1), None, None, None, None, Some(2), None, None];
+        let mut line = Line(&frame);
+        assert_eq!(
+            read_frame(&mut line, &mut NoDelay::new(), timing, &mut buf, None),
+            Err(Error::InvalidFrame)
+        );
+    }
+}
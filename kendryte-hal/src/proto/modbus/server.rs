@@ -0,0 +1,295 @@
+use super::crc::{append_crc, check_crc};
+use super::{
+    BROADCAST_ADDRESS, Error, Exception, MAX_ADU_LEN, Timing, function, read_frame, write_frame,
+};
+use embedded_hal::delay::DelayNs;
+use embedded_hal_nb::serial::{Read, Write};
+use embedded_time::rate::Baud;
+
+/// Data model exposed by a MODBUS server.
+///
+/// Every access defaults to [`Exception::IllegalDataAddress`],
+/// so implementations only need to provide the tables they support.
+pub trait RegisterMap {
+    /// Reads a single coil.
+    fn read_coil(&mut self, address: u16) -> Result<bool, Exception> {
+        let _ = address;
+        Err(Exception::IllegalDataAddress)
+    }
+
+    /// Reads a single discrete input.
+    fn read_discrete_input(&mut self, address: u16) -> Result<bool, Exception> {
+        let _ = address;
+        Err(Exception::IllegalDataAddress)
+    }
+
+    /// Reads a single holding register.
+    fn read_holding_register(&mut self, address: u16) -> Result<u16, Exception> {
+        let _ = address;
+        Err(Exception::IllegalDataAddress)
+    }
+
+    /// Reads a single input register.
+    fn read_input_register(&mut self, address: u16) -> Result<u16, Exception> {
+        let _ = address;
+        Err(Exception::IllegalDataAddress)
+    }
+
+    /// Writes a single coil.
+    fn write_coil(&mut self, address: u16, value: bool) -> Result<(), Exception> {
+        let _ = (address, value);
+        Err(Exception::IllegalDataAddress)
+    }
+
+    /// Writes a single holding register.
+    fn write_holding_register(&mut self, address: u16, value: u16) -> Result<(), Exception> {
+        let _ = (address, value);
+        Err(Exception::IllegalDataAddress)
+    }
+}
+
+/// A MODBUS RTU server answering requests for one address.
+pub struct Server {
+    address: u8,
+    timing: Timing,
+}
+
+impl Server {
+    /// Creates a new server for the given address and line baud rate, or
+    /// returns `None` if the baud rate is not usable, see [`Timing::new`].
+    pub fn new(address: u8, baud: Baud) -> Option<Self> {
+        Some(Self {
+            address,
+            timing: Timing::new(baud)?,
+        })
+    }
+
+    /// Waits for one request on the serial port and answers it.
+    ///
+    /// Frames with a bad CRC or for other addresses are ignored, as required by the specification.
+    pub fn poll<S, D, M>(
+        &mut self,
+        serial: &mut S,
+        delay: &mut D,
+        map: &mut M,
+    ) -> Result<(), Error<S::Error>>
+    where
+        S: Read + Write,
+        D: DelayNs,
+        M: RegisterMap,
+    {
+        let mut request = [0; MAX_ADU_LEN];
+        let len = read_frame(serial, delay, self.timing, &mut request, None)?;
+        let mut response = [0; MAX_ADU_LEN];
+        match self.process(&request[..len], &mut response, map) {
+            0 => Ok(()),
+            len => write_frame(serial, &response[..len]),
+        }
+    }
+
+    /// Processes a request frame and builds the response frame.
+    ///
+    /// Returns the response length, or 0 when no response must be sent.
+    pub fn process<M: RegisterMap>(
+        &self,
+        request: &[u8],
+        response: &mut [u8; MAX_ADU_LEN],
+        map: &mut M,
+    ) -> usize {
+        if request.len() < 4 || !check_crc(request) {
+            return 0;
+        }
+        let address = request[0];
+        if address != self.address && address != BROADCAST_ADDRESS {
+            return 0;
+        }
+        let pdu = &request[1..request.len() - 2];
+        let len = match handle_pdu(pdu, &mut response[1..MAX_ADU_LEN - 2], map) {
+            Ok(len) => len,
+            Err(exception) => {
+                response[1] = pdu[0] | 0x80;
+                response[2] = exception.code();
+                2
+            }
+        };
+        if address == BROADCAST_ADDRESS {
+            return 0;
+        }
+        response[0] = self.address;
+        append_crc(response, 1 + len)
+    }
+}
+
+/// Reads a big-endian 16-bit value at `offset`.
+fn be_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+/// Reads the starting address and quantity shared by most requests.
+fn address_and_count(data: &[u8], max: u16) -> Result<(u16, u16), Exception> {
+    if data.len() < 4 {
+        return Err(Exception::IllegalDataValue);
+    }
+    let (address, count) = (be_u16(data, 0), be_u16(data, 2));
+    if count == 0 || count > max {
+        return Err(Exception::IllegalDataValue);
+    }
+    if address.checked_add(count - 1).is_none() {
+        return Err(Exception::IllegalDataAddress);
+    }
+    Ok((address, count))
+}
+
+/// Executes a request PDU and writes the response PDU into `out`.
+fn handle_pdu<M: RegisterMap>(pdu: &[u8], out: &mut [u8], map: &mut M) -> Result<usize, Exception> {
+    let (&code, data) = pdu.split_first().ok_or(Exception::IllegalFunction)?;
+    out[0] = code;
+    match code {
+        function::READ_COILS | function::READ_DISCRETE_INPUTS => {
+            let (address, count) = address_and_count(data, 2000)?;
+            let bytes = count.div_ceil(8) as usize;
+            out[1] = bytes as u8;
+            out[2..2 + bytes].fill(0);
+            for i in 0..count {
+                let bit = match code {
+                    function::READ_COILS => map.read_coil(address + i)?,
+                    _ => map.read_discrete_input(address + i)?,
+                };
+                out[2 + i as usize / 8] |= (bit as u8) << (i % 8);
+            }
+            Ok(2 + bytes)
+        }
+        function::READ_HOLDING_REGISTERS | function::READ_INPUT_REGISTERS => {
+            let (address, count) = address_and_count(data, 125)?;
+            out[1] = (count * 2) as u8;
+            for i in 0..count {
+                let value = match code {
+                    function::READ_HOLDING_REGISTERS => map.read_holding_register(address + i)?,
+                    _ => map.read_input_register(address + i)?,
+                };
+                let offset = 2 + i as usize * 2;
+                out[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+            }
+            Ok(2 + count as usize * 2)
+        }
+        function::WRITE_SINGLE_COIL => {
+            if data.len() != 4 {
+                return Err(Exception::IllegalDataValue);
+            }
+            let value = match be_u16(data, 2) {
+                0xFF00 => true,
+                0x0000 => false,
+                _ => return Err(Exception::IllegalDataValue),
+            };
+            map.write_coil(be_u16(data, 0), value)?;
+            out[1..5].copy_from_slice(data);
+            Ok(5)
+        }
+        function::WRITE_SINGLE_REGISTER => {
+            if data.len() != 4 {
+                return Err(Exception::IllegalDataValue);
+            }
+            map.write_holding_register(be_u16(data, 0), be_u16(data, 2))?;
+            out[1..5].copy_from_slice(data);
+            Ok(5)
+        }
+        function::WRITE_MULTIPLE_COILS => {
+            let (address, count) = address_and_count(data, 1968)?;
+            let bytes = count.div_ceil(8) as usize;
+            if data.len() != 5 + bytes || data[4] as usize != bytes {
+                return Err(Exception::IllegalDataValue);
+            }
+            for i in 0..count {
+                let bit = data[5 + i as usize / 8] >> (i % 8) & 1;
+                map.write_coil(address + i, bit != 0)?;
+            }
+            out[1..5].copy_from_slice(&data[..4]);
+            Ok(5)
+        }
+        function::WRITE_MULTIPLE_REGISTERS => {
+            let (address, count) = address_and_count(data, 123)?;
+            let bytes = count as usize * 2;
+            if data.len() != 5 + bytes || data[4] as usize != bytes {
+                return Err(Exception::IllegalDataValue);
+            }
+            for i in 0..count {
+                let value = be_u16(data, 5 + i as usize * 2);
+                map.write_holding_register(address + i, value)?;
+            }
+            out[1..5].copy_from_slice(&data[..4]);
+            Ok(5)
+        }
+        _ => Err(Exception::IllegalFunction),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::modbus::crc16;
+
+    struct Registers([u16; 4]);
+
+    impl RegisterMap for Registers {
+        fn read_holding_register(&mut self, address: u16) -> Result<u16, Exception> {
+            self.0
+                .get(address as usize)
+                .copied()
+                .ok_or(Exception::IllegalDataAddress)
+        }
+
+        fn write_holding_register(&mut self, address: u16, value: u16) -> Result<(), Exception> {
+            let register = self
+                .0
+                .get_mut(address as usize)
+                .ok_or(Exception::IllegalDataAddress)?;
+            *register = value;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_holding_registers() {
+        let server = Server::new(0x01, Baud::new(9600)).unwrap();
+        let mut map = Registers([0x1234, 0, 0, 0]);
+        let mut response = [0; MAX_ADU_LEN];
+        let len = server.process(
+            &[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A],
+            &mut response,
+            &mut map,
+        );
+        assert_eq!(
+            &response[..len],
+            &[0x01, 0x03, 0x02, 0x12, 0x34, 0xB5, 0x33]
+        );
+    }
+
+    #[test]
+    fn exception_and_ignored_frames() {
+        let server = Server::new(0x01, Baud::new(9600)).unwrap();
+        let mut map = Registers([0; 4]);
+        let mut response = [0; MAX_ADU_LEN];
+
+        // Coils are not part of the register map.
+        let mut request = [0x01, 0x01, 0x00, 0x00, 0x00, 0x01, 0, 0];
+        let crc = crc16(&request[..6]).to_le_bytes();
+        request[6..].copy_from_slice(&crc);
+        let len = server.process(&request, &mut response, &mut map);
+        assert_eq!(&response[1..3], &[0x81, 0x02]);
+        assert!(check_crc(&response[..len]));
+
+        // Other addresses and corrupted frames get no response.
+        request[0] = 0x02;
+        let crc = crc16(&request[..6]).to_le_bytes();
+        request[6..].copy_from_slice(&crc);
+        assert_eq!(server.process(&request, &mut response, &mut map), 0);
+        assert_eq!(
+            server.process(
+                &[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x0A, 0x84],
+                &mut response,
+                &mut map
+            ),
+            0
+        );
+    }
+}
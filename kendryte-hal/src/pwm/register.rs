@@ -7,6 +7,7 @@ use volatile_register::{RO, RW};
 /// Defines the behavior of the interrupt pending bits.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum StickyMode {
     /// Interrupt pending bits are cleared automatically when the condition is no longer true.
     AutoClear = 0b0,
@@ -17,6 +18,7 @@ pub enum StickyMode {
 /// Generic enable/disable enum for single-bit flags.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Enable {
     /// The feature is disabled.
     Disabled = 0b0,
@@ -27,6 +29,7 @@ pub enum Enable {
 /// Defines the alignment of the PWM output.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Alignment {
     /// The PWM output is left-aligned.
     Left = 0b0,
@@ -37,6 +40,7 @@ pub enum Alignment {
 /// Represents the state of an interrupt pending flag.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum InterruptPending {
     /// No interrupt is pending.
     NotPending = 0b0,
@@ -0,0 +1,243 @@
+use super::MonotonicCounter;
+
+/// Errors of the [`MonotonicCounter`] implementations of this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CounterError<E> {
+    /// The backing store access failed.
+    Backend(E),
+    /// The counter cannot reach the requested value.
+    Exhausted,
+    /// No copy of the counter passed its integrity check.
+    Corrupt,
+}
+
+/// Bank of one-time programmable bits, in 32-bit words.
+///
+/// Implemented by the OTP driver over a region reserved for one counter.
+pub trait FuseBank {
+    /// Fuse access error.
+    type Error: core::fmt::Debug;
+
+    /// Number of words in the bank.
+    fn words(&self) -> usize;
+
+    /// Reads word `index`; burnt bits read as one.
+    fn read_word(&mut self, index: usize) -> Result<u32, Self::Error>;
+
+    /// Burns the bits set in `mask` of word `index`.
+    fn burn(&mut self, index: usize, mask: u32) -> Result<(), Self::Error>;
+}
+
+/// Counter stored as the number of burnt fuses of a [`FuseBank`].
+///
+/// Each increment burns one more bit, lowest first, so the counter can
+/// reach `32 * words` and no fuse is burnt twice. Fuses only go from zero
+/// to one, so neither an attacker nor an interrupted burn can lower it;
+/// a partly burnt word still counts every bit that took.
+pub struct FuseCounter<B> {
+    bank: B,
+}
+
+impl<B: FuseBank> FuseCounter<B> {
+    /// Uses the whole of `bank` for one counter.
+    #[inline]
+    pub const fn new(bank: B) -> Self {
+        Self { bank }
+    }
+
+    /// Returns the highest value the counter can reach.
+    #[inline]
+    pub fn capacity(&self) -> u32 {
+        (self.bank.words() * 32) as u32
+    }
+
+    /// Releases the fuse bank.
+    #[inline]
+    pub fn free(self) -> B {
+        self.bank
+    }
+}
+
+impl<B: FuseBank> MonotonicCounter for FuseCounter<B> {
+    type Error = CounterError<B::Error>;
+
+    fn read(&mut self) -> Result<u32, Self::Error> {
+        let mut value = 0;
+        for index in 0..self.bank.words() {
+            let word = self.bank.read_word(index).map_err(CounterError::Backend)?;
+            value += word.count_ones();
+        }
+        Ok(value)
+    }
+
+    fn advance_to(&mut self, value: u32) -> Result<(), Self::Error> {
+        if value > self.capacity() {
+            return Err(CounterError::Exhausted);
+        }
+        let mut missing = value.saturating_sub(self.read()?);
+        for index in 0..self.bank.words() {
+            if missing == 0 {
+                break;
+            }
+            let word = self.bank.read_word(index).map_err(CounterError::Backend)?;
+            let mut mask = 0;
+            let mut free = !word;
+            while free != 0 && missing > 0 {
+                let bit = free & free.wrapping_neg();
+                mask |= bit;
+                free &= !bit;
+                missing -= 1;
+            }
+            if mask != 0 {
+                self.bank.burn(index, mask).map_err(CounterError::Backend)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pair of battery-backed registers, such as the RTC backup registers.
+pub trait BackupRegisters {
+    /// Register access error.
+    type Error: core::fmt::Debug;
+
+    /// Reads register `index`, 0 to 3.
+    fn read(&mut self, index: usize) -> Result<u32, Self::Error>;
+
+    /// Writes register `index`, 0 to 3.
+    fn write(&mut self, index: usize, value: u32) -> Result<(), Self::Error>;
+}
+
+/// Counter kept in four battery-backed registers.
+///
+/// The value is stored twice, each copy next to its complement, and the
+/// copies are written one after the other so a power loss leaves at
+/// least one of them whole. Registers have no write wear, but lose their
+/// contents with the battery: a counter read back as
+/// [`CounterError::Corrupt`] must be treated as tampered with, and
+/// registers all zero read as a counter of zero, so use this only where
+/// losing the battery is acceptable, or alongside a [`FuseCounter`].
+pub struct BackupCounter<R> {
+    regs: R,
+}
+
+impl<R: BackupRegisters> BackupCounter<R> {
+    /// Uses registers 0 to 3 of `regs`.
+    #[inline]
+    pub const fn new(regs: R) -> Self {
+        Self { regs }
+    }
+
+    /// Releases the registers.
+    #[inline]
+    pub fn free(self) -> R {
+        self.regs
+    }
+
+    fn copy(&mut self, copy: usize) -> Result<Option<u32>, R::Error> {
+        let value = self.regs.read(2 * copy)?;
+        let check = self.regs.read(2 * copy + 1)?;
+        Ok(match (value, check) {
+            (0, 0) => Some(0),
+            (value, check) if value == !check => Some(value),
+            _ => None,
+        })
+    }
+}
+
+impl<R: BackupRegisters> MonotonicCounter for BackupCounter<R> {
+    type Error = CounterError<R::Error>;
+
+    fn read(&mut self) -> Result<u32, Self::Error> {
+        let first = self.copy(0).map_err(CounterError::Backend)?;
+        let second = self.copy(1).map_err(CounterError::Backend)?;
+        // A copy torn by a power loss is the one being advanced.
+        first.max(second).ok_or(CounterError::Corrupt)
+    }
+
+    fn advance_to(&mut self, value: u32) -> Result<(), Self::Error> {
+        if value <= self.read()? {
+            return Ok(());
+        }
+        for copy in 0..2 {
+            self.regs
+                .write(2 * copy, value)
+                .map_err(CounterError::Backend)?;
+            self.regs
+                .write(2 * copy + 1, !value)
+                .map_err(CounterError::Backend)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fuses([u32; 2]);
+
+    impl FuseBank for Fuses {
+        type Error = ();
+
+        fn words(&self) -> usize {
+            self.0.len()
+        }
+
+        fn read_word(&mut self, index: usize) -> Result<u32, ()> {
+            Ok(self.0[index])
+        }
+
+        fn burn(&mut self, index: usize, mask: u32) -> Result<(), ()> {
+            self.0[index] |= mask;
+            Ok(())
+        }
+    }
+
+    struct Registers([u32; 4]);
+
+    impl BackupRegisters for Registers {
+        type Error = ();
+
+        fn read(&mut self, index: usize) -> Result<u32, ()> {
+            Ok(self.0[index])
+        }
+
+        fn write(&mut self, index: usize, value: u32) -> Result<(), ()> {
+            self.0[index] = value;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fuse_counter() {
+        // A burn that only partly took earlier still counts.
+        let mut counter = FuseCounter::new(Fuses([0b101, 0]));
+        assert_eq!(counter.read(), Ok(2));
+        counter.advance_to(34).unwrap();
+        counter.advance_to(5).unwrap();
+        assert_eq!(counter.read(), Ok(34));
+        assert_eq!(counter.advance_to(65), Err(CounterError::Exhausted));
+        assert_eq!(counter.free().0, [u32::MAX, 0b11]);
+    }
+
+    #[test]
+    fn backup_counter() {
+        let mut counter = BackupCounter::new(Registers([0; 4]));
+        assert_eq!(counter.read(), Ok(0));
+        counter.advance_to(7).unwrap();
+        assert_eq!(counter.read(), Ok(7));
+
+        // Torn while writing the first copy of 9: the second still holds 7.
+        let mut regs = counter.free();
+        regs.0[0] = 9;
+        let mut counter = BackupCounter::new(regs);
+        assert_eq!(counter.read(), Ok(7));
+
+        let mut regs = counter.free();
+        regs.0[2] = 1;
+        let mut counter = BackupCounter::new(regs);
+        assert_eq!(counter.read(), Err(CounterError::Corrupt));
+    }
+}
@@ -0,0 +1,464 @@
+//! Sealed storage of small secrets in a flash partition.
+//!
+//! Each blob (WiFi credentials, device certificates, keys) is encrypted and
+//! authenticated with an [`Aead`] cipher keyed from a device-unique secret,
+//! and stored in its own pair of erase sectors of a [`NorFlash`] partition.
+//!
+//! A new blob is written to the sector of the pair not holding the current
+//! one, with the next sequence number, and the old sector is only erased
+//! afterwards; the magic number is written last. A power failure during
+//! [`store`](SecureStorage::store) therefore leaves either the old or the new
+//! blob, and the one with the higher sequence number is read.
+//!
+//! Blobs carry a version which is checked against a [`MonotonicCounter`],
+//! typically backed by OTP fuses, so an attacker cannot restore an older
+//! blob once the counter has been advanced past it. [`FuseCounter`] and
+//! [`BackupCounter`] implement it over OTP words and battery-backed
+//! registers; both can also hold an anti-rollback epoch or boot count.
+//!
+//! On-flash layout of a blob, with the header authenticated as associated data:
+//!
+//! | Offset | Length | Field |
+//! |---|---|---|
+//! | 0 | 4 | Magic `KSB2` |
+//! | 4 | 4 | Sequence number, little endian |
+//! | 8 | 4 | Version, little endian |
+//! | 12 | 2 | Slot, little endian |
+//! | 14 | 2 | Data length, little endian |
+//! | 16 | 12 | Nonce |
+//! | 28 | n | Ciphertext |
+//! | 28 + n | 16 | Tag |
+mod counter;
+
+pub use counter::{BackupCounter, BackupRegisters, CounterError, FuseBank, FuseCounter};
+
+use crate::security::util::zeroize;
+use crate::security::{DIGEST_LEN, Hasher, hkdf};
+use embedded_storage::nor_flash::NorFlash;
+
+/// Length in bytes of an AEAD nonce.
+pub const NONCE_LEN: usize = 12;
+
+/// Length in bytes of an AEAD tag.
+pub const TAG_LEN: usize = 16;
+
+/// Length in bytes of the blob header.
+pub const HEADER_LEN: usize = 28;
+
+/// Largest data length a blob can hold.
+pub const MAX_DATA_LEN: usize = 1024;
+
+/// Largest on-flash size of a blob, including write alignment padding.
+const MAX_BLOB_LEN: usize = 1088;
+
+/// Magic number at the start of every blob.
+pub const MAGIC: [u8; 4] = *b"KSB2";
+
+/// Authenticated encryption with associated data, such as AES-GCM or SM4-GCM.
+///
+/// Implemented by the crypto engine driver or a software cipher, keyed with
+/// a key obtained from [`derive_storage_key`].
+pub trait Aead {
+    /// Encrypts `buffer` in place and returns the authentication tag.
+    fn encrypt_in_place(
+        &mut self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> [u8; TAG_LEN];
+
+    /// Checks `tag` and decrypts `buffer` in place.
+    ///
+    /// Returns `false`, leaving the contents of `buffer` unspecified, if the tag does not match.
+    fn decrypt_in_place(
+        &mut self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; TAG_LEN],
+    ) -> bool;
+}
+
+/// A counter that can only move forward, such as a bank of OTP fuses.
+pub trait MonotonicCounter {
+    /// Counter access error.
+    type Error: core::fmt::Debug;
+
+    /// Reads the current counter value.
+    fn read(&mut self) -> Result<u32, Self::Error>;
+
+    /// Advances the counter to `value`; values at or below the current one are ignored.
+    fn advance_to(&mut self, value: u32) -> Result<(), Self::Error>;
+}
+
+/// Derives the storage key from a device-unique secret (PUF response or OTP key).
+///
+/// `label` separates keys for different purposes derived from the same secret.
+pub fn derive_storage_key<H: Hasher>(device_secret: &[u8], label: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut key = [0; DIGEST_LEN];
+    // A single digest is always within the HKDF output limit.
+    hkdf::<H>(b"kendryte-secure-storage", device_secret, label, &mut key).unwrap();
+    key
+}
+
+/// Indicate different error conditions that may occur when accessing sealed storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<F, C> {
+    /// The flash access failed.
+    Flash(F),
+    /// The rollback counter access failed.
+    Counter(C),
+    /// The partition is not aligned to erase sectors, or sectors are too small for a blob.
+    InvalidPartition,
+    /// The slot is outside of the partition.
+    InvalidSlot,
+    /// The data is too large for a blob, or for the destination buffer.
+    TooLarge,
+    /// The slot does not hold a blob.
+    NotFound,
+    /// The blob failed authentication.
+    Authentication,
+    /// The blob version is older than the rollback counter.
+    Rollback,
+}
+
+type StorageResult<T, F, C> = Result<
+    T,
+    Error<<F as embedded_storage::nor_flash::ErrorType>::Error, <C as MonotonicCounter>::Error>,
+>;
+
+/// Sealed blob storage over a flash partition.
+pub struct SecureStorage<F, A, C> {
+    flash: F,
+    aead: A,
+    counter: C,
+    offset: u32,
+    slots: u16,
+}
+
+impl<F: NorFlash, A: Aead, C: MonotonicCounter> SecureStorage<F, A, C> {
+    /// Creates a storage over `len` bytes of `flash` starting at `offset`.
+    ///
+    /// Every two erase sectors of the partition hold one slot.
+    pub fn new(flash: F, aead: A, counter: C, offset: u32, len: u32) -> StorageResult<Self, F, C> {
+        let erase_size = F::ERASE_SIZE as u32;
+        if offset % erase_size != 0
+            || len % erase_size != 0
+            || F::ERASE_SIZE < MAX_BLOB_LEN
+            || MAX_BLOB_LEN % F::WRITE_SIZE != 0
+        {
+            return Err(Error::InvalidPartition);
+        }
+        let slots = (len / erase_size / 2).min(u16::MAX as u32) as u16;
+        Ok(Self {
+            flash,
+            aead,
+            counter,
+            offset,
+            slots,
+        })
+    }
+
+    /// Returns the number of slots in the partition.
+    pub fn slots(&self) -> u16 {
+        self.slots
+    }
+
+    /// Seals `data` into `slot` with the given version, replacing any previous blob.
+    ///
+    /// `nonce` must never repeat under the same key; take it from a random source.
+    /// Fails with [`Error::Rollback`] if `version` is below the rollback counter.
+    pub fn store(
+        &mut self,
+        slot: u16,
+        version: u32,
+        nonce: [u8; NONCE_LEN],
+        data: &[u8],
+    ) -> StorageResult<(), F, C> {
+        let start = self.slot_offset(slot)?;
+        if data.len() > MAX_DATA_LEN {
+            return Err(Error::TooLarge);
+        }
+        if version < self.counter.read().map_err(Error::Counter)? {
+            return Err(Error::Rollback);
+        }
+        let other = start + F::ERASE_SIZE as u32;
+        let (target, old, sequence) = match self.newest(slot)? {
+            None => (start, None, 0),
+            Some((sector, sequence)) if sector == start => (other, Some(start), sequence + 1),
+            Some((_, sequence)) => (start, Some(other), sequence + 1),
+        };
+
+        let mut blob = [0xFF; MAX_BLOB_LEN];
+        blob[0..4].copy_from_slice(&MAGIC);
+        blob[4..8].copy_from_slice(&sequence.to_le_bytes());
+        blob[8..12].copy_from_slice(&version.to_le_bytes());
+        blob[12..14].copy_from_slice(&slot.to_le_bytes());
+        blob[14..16].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        blob[16..HEADER_LEN].copy_from_slice(&nonce);
+        let (header, body) = blob.split_at_mut(HEADER_LEN);
+        body[..data.len()].copy_from_slice(data);
+        let tag = self
+            .aead
+            .encrypt_in_place(&nonce, header, &mut body[..data.len()]);
+        body[data.len()..data.len() + TAG_LEN].copy_from_slice(&tag);
+
+        let len = (HEADER_LEN + data.len() + TAG_LEN).next_multiple_of(F::WRITE_SIZE);
+        self.erase(target)?;
+        // The first write unit, holding the magic number, goes last, so a
+        // sector only looks in use once its blob is complete.
+        let (first, rest) = blob[..len].split_at(F::WRITE_SIZE);
+        if !rest.is_empty() {
+            let offset = target + F::WRITE_SIZE as u32;
+            self.flash.write(offset, rest).map_err(Error::Flash)?;
+        }
+        self.flash.write(target, first).map_err(Error::Flash)?;
+        match old {
+            Some(old) => self.erase(old),
+            None => Ok(()),
+        }
+    }
+
+    /// Unseals the blob in `slot` into `buf` and returns its data length.
+    ///
+    /// Fails with [`Error::Rollback`] if the blob version is below the rollback counter.
+    pub fn load(&mut self, slot: u16, buf: &mut [u8]) -> StorageResult<usize, F, C> {
+        let (version, len) = self.load_with_version(slot, buf)?;
+        if version < self.counter.read().map_err(Error::Counter)? {
+            zeroize(&mut buf[..len]);
+            return Err(Error::Rollback);
+        }
+        Ok(len)
+    }
+
+    /// Returns the version of the blob in `slot`, after checking its authenticity.
+    pub fn version(&mut self, slot: u16) -> StorageResult<u32, F, C> {
+        let mut buf = [0; MAX_DATA_LEN];
+        let (version, len) = self.load_with_version(slot, &mut buf)?;
+        zeroize(&mut buf[..len]);
+        Ok(version)
+    }
+
+    /// Erases the blob in `slot`.
+    pub fn remove(&mut self, slot: u16) -> StorageResult<(), F, C> {
+        let start = self.slot_offset(slot)?;
+        let end = start + 2 * F::ERASE_SIZE as u32;
+        self.flash.erase(start, end).map_err(Error::Flash)
+    }
+
+    /// Advances the rollback counter, invalidating blobs with older versions.
+    pub fn advance_rollback_counter(&mut self, version: u32) -> StorageResult<(), F, C> {
+        self.counter.advance_to(version).map_err(Error::Counter)
+    }
+
+    /// Releases the flash, cipher and counter.
+    pub fn free(self) -> (F, A, C) {
+        (self.flash, self.aead, self.counter)
+    }
+
+    fn slot_offset(&self, slot: u16) -> StorageResult<u32, F, C> {
+        match slot < self.slots {
+            true => Ok(self.offset + slot as u32 * 2 * F::ERASE_SIZE as u32),
+            false => Err(Error::InvalidSlot),
+        }
+    }
+
+    /// Returns the sector of `slot` holding its newest blob, and the
+    /// sequence number of that blob, or `None` if the slot is empty.
+    fn newest(&mut self, slot: u16) -> StorageResult<Option<(u32, u32)>, F, C> {
+        let start = self.slot_offset(slot)?;
+        let mut newest = None;
+        for sector in [start, start + F::ERASE_SIZE as u32] {
+            let mut header = [0; 8];
+            self.flash.read(sector, &mut header).map_err(Error::Flash)?;
+            if header[0..4] != MAGIC {
+                continue;
+            }
+            let sequence = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            if newest.is_none_or(|(_, newest)| sequence > newest) {
+                newest = Some((sector, sequence));
+            }
+        }
+        Ok(newest)
+    }
+
+    fn erase(&mut self, sector: u32) -> StorageResult<(), F, C> {
+        self.flash
+            .erase(sector, sector + F::ERASE_SIZE as u32)
+            .map_err(Error::Flash)
+    }
+
+    fn load_with_version(
+        &mut self,
+        slot: u16,
+        buf: &mut [u8],
+    ) -> StorageResult<(u32, usize), F, C> {
+        let (sector, _) = self.newest(slot)?.ok_or(Error::NotFound)?;
+        let mut blob = [0; MAX_BLOB_LEN];
+        self.flash.read(sector, &mut blob).map_err(Error::Flash)?;
+        let version = u32::from_le_bytes([blob[8], blob[9], blob[10], blob[11]]);
+        let stored_slot = u16::from_le_bytes([blob[12], blob[13]]);
+        let len = u16::from_le_bytes([blob[14], blob[15]]) as usize;
+        // A blob copied into another slot fails here rather than being accepted.
+        if stored_slot != slot || len > MAX_DATA_LEN {
+            return Err(Error::Authentication);
+        }
+        if len > buf.len() {
+            return Err(Error::TooLarge);
+        }
+
+        let (header, body) = blob.split_at_mut(HEADER_LEN);
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&header[16..HEADER_LEN]);
+        let mut tag = [0; TAG_LEN];
+        tag.copy_from_slice(&body[len..len + TAG_LEN]);
+        let buf = &mut buf[..len];
+        buf.copy_from_slice(&body[..len]);
+        if !self.aead.decrypt_in_place(&nonce, header, buf, &tag) {
+            zeroize(buf);
+            return Err(Error::Authentication);
+        }
+        Ok((version, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::{Hmac, Sha256};
+    use crate::test_support::RamFlash;
+
+    const SECTOR: usize = 4096;
+
+    type Flash = RamFlash<SECTOR, 4>;
+
+    /// Stand-in cipher: SHA-256 keystream and truncated HMAC-SHA256 tag.
+    struct TestAead;
+
+    impl TestAead {
+        fn keystream(nonce: &[u8; NONCE_LEN], buffer: &mut [u8]) {
+            let pad = Sha256::digest(nonce);
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte ^= pad[i % DIGEST_LEN];
+            }
+        }
+
+        fn tag(nonce: &[u8; NONCE_LEN], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+            let mut mac = Hmac::<Sha256>::new(b"test key");
+            mac.update(nonce);
+            mac.update(aad);
+            mac.update(ciphertext);
+            let mut tag = [0; TAG_LEN];
+            tag.copy_from_slice(&mac.finalize()[..TAG_LEN]);
+            tag
+        }
+    }
+
+    impl Aead for TestAead {
+        fn encrypt_in_place(
+            &mut self,
+            nonce: &[u8; NONCE_LEN],
+            aad: &[u8],
+            buffer: &mut [u8],
+        ) -> [u8; TAG_LEN] {
+            Self::keystream(nonce, buffer);
+            Self::tag(nonce, aad, buffer)
+        }
+
+        fn decrypt_in_place(
+            &mut self,
+            nonce: &[u8; NONCE_LEN],
+            aad: &[u8],
+            buffer: &mut [u8],
+            tag: &[u8; TAG_LEN],
+        ) -> bool {
+            if Self::tag(nonce, aad, buffer) != *tag {
+                return false;
+            }
+            Self::keystream(nonce, buffer);
+            true
+        }
+    }
+
+    struct Counter(u32);
+
+    impl MonotonicCounter for Counter {
+        type Error = ();
+
+        fn read(&mut self) -> Result<u32, ()> {
+            Ok(self.0)
+        }
+
+        fn advance_to(&mut self, value: u32) -> Result<(), ()> {
+            self.0 = self.0.max(value);
+            Ok(())
+        }
+    }
+
+    fn storage() -> SecureStorage<Flash, TestAead, Counter> {
+        let flash = Flash::new();
+        SecureStorage::new(flash, TestAead, Counter(0), 0, 4 * SECTOR as u32).unwrap()
+    }
+
+    #[test]
+    fn store_and_load() {
+        let mut storage = storage();
+        assert_eq!(storage.slots(), 2);
+        storage.store(1, 3, [7; NONCE_LEN], b"ssid:psk").unwrap();
+
+        let mut buf = [0; 32];
+        let len = storage.load(1, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ssid:psk");
+        assert_eq!(storage.version(1), Ok(3));
+        assert_eq!(storage.load(0, &mut buf), Err(Error::NotFound));
+        assert_eq!(storage.load(2, &mut buf), Err(Error::InvalidSlot));
+        assert_eq!(storage.load(1, &mut [0; 4]), Err(Error::TooLarge));
+
+        storage.remove(1).unwrap();
+        assert_eq!(storage.load(1, &mut buf), Err(Error::NotFound));
+    }
+
+    #[test]
+    fn tampering_and_rollback() {
+        let mut storage = storage();
+        storage.store(0, 1, [1; NONCE_LEN], b"certificate").unwrap();
+
+        let (mut flash, aead, counter) = storage.free();
+        flash.bytes_mut()[HEADER_LEN] ^= 0x01;
+        let mut storage = SecureStorage::new(flash, aead, counter, 0, 4 * SECTOR as u32).unwrap();
+        let mut buf = [0; 32];
+        assert_eq!(storage.load(0, &mut buf), Err(Error::Authentication));
+
+        storage.store(0, 1, [2; NONCE_LEN], b"certificate").unwrap();
+        storage.advance_rollback_counter(2).unwrap();
+        assert_eq!(storage.load(0, &mut buf), Err(Error::Rollback));
+        assert_eq!(
+            storage.store(0, 1, [3; NONCE_LEN], b"old"),
+            Err(Error::Rollback)
+        );
+        storage.store(0, 2, [4; NONCE_LEN], b"new").unwrap();
+        assert_eq!(storage.load(0, &mut buf), Ok(3));
+    }
+
+    #[test]
+    fn store_alternates_sectors() {
+        let mut storage = storage();
+        storage.store(0, 1, [1; NONCE_LEN], b"first").unwrap();
+        storage.store(0, 1, [2; NONCE_LEN], b"second").unwrap();
+        let mut buf = [0; 32];
+        assert_eq!(storage.load(0, &mut buf), Ok(6));
+        assert_eq!(&buf[..6], b"second");
+
+        // The first sector was erased once the second blob was complete.
+        let (mut flash, aead, counter) = storage.free();
+        assert!(flash.bytes_mut()[..SECTOR].iter().all(|&b| b == 0xFF));
+        assert_eq!(flash.bytes_mut()[SECTOR..SECTOR + 4], MAGIC);
+
+        // A store interrupted before its magic number leaves the old blob.
+        flash.bytes_mut()[4..HEADER_LEN].fill(0);
+        let mut storage = SecureStorage::new(flash, aead, counter, 0, 4 * SECTOR as u32).unwrap();
+        assert_eq!(storage.load(0, &mut buf), Ok(6));
+        assert_eq!(&buf[..6], b"second");
+    }
+}
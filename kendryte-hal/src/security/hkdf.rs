@@ -0,0 +1,89 @@
+use super::util::Secret;
+use super::{DIGEST_LEN, Hasher, Hmac};
+
+/// The requested output is longer than HKDF can produce (255 digests).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidLength;
+
+/// HKDF-Extract (RFC 5869): derives a pseudorandom key from input keying material.
+///
+/// An empty `salt` is treated as a string of zeros.
+pub fn hkdf_extract<H: Hasher>(salt: &[u8], ikm: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut mac = match salt.is_empty() {
+        true => Hmac::<H>::new(&[0; DIGEST_LEN]),
+        false => Hmac::<H>::new(salt),
+    };
+    mac.update(ikm);
+    mac.finalize()
+}
+
+/// HKDF-Expand (RFC 5869): fills `okm` with key material bound to `info`.
+pub fn hkdf_expand<H: Hasher>(
+    prk: &[u8],
+    info: &[u8],
+    okm: &mut [u8],
+) -> Result<(), InvalidLength> {
+    if okm.len() > 255 * DIGEST_LEN {
+        return Err(InvalidLength);
+    }
+    let mut previous: Option<Secret<DIGEST_LEN>> = None;
+    for (counter, chunk) in (1..=255u8).zip(okm.chunks_mut(DIGEST_LEN)) {
+        let mut mac = Hmac::<H>::new(prk);
+        if let Some(previous) = &previous {
+            mac.update(&**previous);
+        }
+        mac.update(info);
+        mac.update(&[counter]);
+        let block = Secret::new(mac.finalize());
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        previous = Some(block);
+    }
+    Ok(())
+}
+
+/// Extracts and expands in one go, filling `okm` with derived key material.
+pub fn hkdf<H: Hasher>(
+    salt: &[u8],
+    ikm: &[u8],
+    info: &[u8],
+    okm: &mut [u8],
+) -> Result<(), InvalidLength> {
+    let prk = Secret::new(hkdf_extract::<H>(salt, ikm));
+    hkdf_expand::<H>(&*prk, info, okm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::Sha256;
+
+    #[test]
+    fn rfc5869_test_case_1() {
+        let ikm = [0x0b; 22];
+        let salt: [u8; 13] = core::array::from_fn(|i| i as u8);
+        let info: [u8; 10] = core::array::from_fn(|i| 0xf0 + i as u8);
+
+        let prk = hkdf_extract::<Sha256>(&salt, &ikm);
+        assert_eq!(
+            prk,
+            *b"\x07\x77\x09\x36\x2c\x2e\x32\xdf\x0d\xdc\x3f\x0d\xc4\x7b\xba\x63\
+               \x90\xb6\xc7\x3b\xb5\x0f\x9c\x31\x22\xec\x84\x4a\xd7\xc2\xb3\xe5"
+        );
+
+        let mut okm = [0; 42];
+        hkdf_expand::<Sha256>(&prk, &info, &mut okm).unwrap();
+        assert_eq!(
+            okm,
+            *b"\x3c\xb2\x5f\x25\xfa\xac\xd5\x7a\x90\x43\x4f\x64\xd0\x36\x2f\x2a\
+               \x2d\x2d\x0a\x90\xcf\x1a\x5a\x4c\x5d\xb0\x2d\x56\xec\xc4\xc5\xbf\
+               \x34\x00\x72\x08\xd5\xb8\x87\x18\x58\x65"
+        );
+
+        let mut too_long = [0; 255 * DIGEST_LEN + 1];
+        assert_eq!(
+            hkdf::<Sha256>(&salt, &ikm, &info, &mut too_long),
+            Err(InvalidLength)
+        );
+    }
+}
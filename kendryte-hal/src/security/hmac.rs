@@ -0,0 +1,85 @@
+use super::util::{constant_time_eq, zeroize};
+use super::{BLOCK_LEN, DIGEST_LEN, Hasher};
+
+/// Keyed-hash message authentication code (RFC 2104) over a [`Hasher`].
+///
+/// `Hmac<Sha256>` is HMAC-SHA256, `Hmac<Sm3>` is HMAC-SM3.
+///
+/// [`Sm3`]: super::Sm3
+#[derive(Clone)]
+pub struct Hmac<H: Hasher> {
+    inner: H,
+    outer: H,
+}
+
+impl<H: Hasher> Hmac<H> {
+    /// Creates a new MAC computation with the given key.
+    ///
+    /// Keys longer than the block size are hashed first.
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            block[..DIGEST_LEN].copy_from_slice(&H::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = H::default();
+        let mut outer = H::default();
+        let mut pad = block.map(|byte| byte ^ 0x36);
+        inner.update(&pad);
+        pad = block.map(|byte| byte ^ 0x5c);
+        outer.update(&pad);
+        zeroize(&mut block);
+        zeroize(&mut pad);
+        Self { inner, outer }
+    }
+
+    /// Absorbs `data` into the MAC.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Completes the MAC and returns the tag.
+    pub fn finalize(self) -> [u8; DIGEST_LEN] {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+
+    /// Completes the MAC and checks it against `tag` in constant time.
+    pub fn verify(self, tag: &[u8]) -> bool {
+        constant_time_eq(&self.finalize(), tag)
+    }
+}
+
+/// Computes the MAC of `data` under `key` in one go.
+pub fn hmac<H: Hasher>(key: &[u8], data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut mac = Hmac::<H>::new(key);
+    mac.update(data);
+    mac.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::Sha256;
+
+    #[test]
+    fn rfc4231_vectors() {
+        // Test case 2.
+        assert_eq!(
+            hmac::<Sha256>(b"Jefe", b"what do ya want for nothing?"),
+            *b"\x5b\xdc\xc1\x46\xbf\x60\x75\x4e\x6a\x04\x24\x26\x08\x95\x75\xc7\
+               \x5a\x00\x3f\x08\x9d\x27\x39\x83\x9d\xec\x58\xb9\x64\xec\x38\x43"
+        );
+        // Test case 6: key longer than the block size.
+        let mut mac = Hmac::<Sha256>::new(&[0xaa; 131]);
+        mac.update(b"Test Using Larger Than Block-Size Key - Hash Key First");
+        assert!(mac.clone().verify(
+            b"\x60\xe4\x31\x59\x1e\xe0\xb6\x7f\x0d\x8a\x26\xaa\xcb\xf5\xb7\x7f\
+              \x8e\x0b\xc6\x21\x37\x28\xc5\x14\x05\x46\x04\x0f\x0e\xe3\x7f\x54"
+        ));
+        assert!(!mac.verify(&[0; DIGEST_LEN]));
+    }
+}
@@ -0,0 +1,82 @@
+//! Cryptographic helpers for device security.
+//!
+//! Hash functions implement [`Hasher`], on which the keyed constructions
+//! ([`Hmac`], [`hkdf`]) are built. [`util`] has the helpers for comparing
+//! and clearing secrets.
+mod hkdf;
+mod hmac;
+mod sha256;
+mod sm3;
+pub mod util;
+
+pub use hkdf::{InvalidLength, hkdf, hkdf_expand, hkdf_extract};
+pub use hmac::{Hmac, hmac};
+pub use sha256::Sha256;
+pub use sm3::Sm3;
+
+/// Length in bytes of a digest.
+pub const DIGEST_LEN: usize = 32;
+
+/// Length in bytes of the hash function input block.
+pub const BLOCK_LEN: usize = 64;
+
+/// A 256-bit hash function with a 64-byte block, such as SHA-256 or SM3.
+pub trait Hasher: Default {
+    /// Absorbs `data` into the hash state.
+    fn update(&mut self, data: &[u8]);
+    /// Completes the hash and returns the digest.
+    fn finalize(self) -> [u8; DIGEST_LEN];
+
+    /// Hashes `data` in one go.
+    fn digest(data: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut hasher = Self::default();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// Block buffer with Merkle-Damgard strengthening, shared by SHA-256 and SM3.
+#[derive(Clone)]
+struct BlockBuffer {
+    block: [u8; BLOCK_LEN],
+    len: usize,
+    total: u64,
+}
+
+impl BlockBuffer {
+    const fn new() -> Self {
+        Self {
+            block: [0; BLOCK_LEN],
+            len: 0,
+            total: 0,
+        }
+    }
+
+    /// Appends `data`, calling `compress` on each completed block.
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; BLOCK_LEN])) {
+        self.total = self.total.wrapping_add(data.len() as u64);
+        while !data.is_empty() {
+            let n = (BLOCK_LEN - self.len).min(data.len());
+            self.block[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len == BLOCK_LEN {
+                compress(&self.block);
+                self.len = 0;
+            }
+        }
+    }
+
+    /// Pads the message with its big-endian bit length and compresses the final blocks.
+    fn finalize(mut self, mut compress: impl FnMut(&[u8; BLOCK_LEN])) {
+        let bits = self.total.wrapping_mul(8);
+        self.block[self.len] = 0x80;
+        self.block[self.len + 1..].fill(0);
+        if self.len + 1 > BLOCK_LEN - 8 {
+            compress(&self.block);
+            self.block.fill(0);
+        }
+        self.block[BLOCK_LEN - 8..].copy_from_slice(&bits.to_be_bytes());
+        compress(&self.block);
+    }
+}
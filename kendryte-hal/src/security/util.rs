@@ -0,0 +1,149 @@
+//! Helpers for handling secrets.
+//!
+//! Key material should not outlive its use: keep it in a [`Secret`], which
+//! clears itself when dropped, clear other buffers with [`zeroize`], and
+//! call [`scrub_stack`] after deep computations on keys whose frames may
+//! have left copies below the stack pointer.
+//!
+//! ```ignore
+//! let key = Secret::new(derive_storage_key::<Sha256>(&otp_secret, b"wifi"));
+//! let storage = SecureStorage::new(flash, Cipher::new(&key), counter, OFFSET, LEN)?;
+//! drop(key);
+//! scrub_stack::<2048>();
+//! ```
+//!
+//! With the `zeroize` feature, [`Secret`] implements the traits of the
+//! `zeroize` crate, so it can be held by types that require them.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{Ordering, compiler_fence};
+
+/// Compares two byte strings in constant time with respect to their contents.
+///
+/// Only the lengths, which are not secret, decide an early return.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y));
+    // Keep the comparison from being short-circuited by the optimizer.
+    core::hint::black_box(diff) == 0
+}
+
+/// Clears `buf` with writes the compiler cannot remove, even if `buf` is
+/// never read again.
+pub fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        // SAFETY: `byte` is a valid, aligned reference.
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Clears `N` bytes of the stack below the caller's frame.
+///
+/// Functions that returned leave their locals, keys included, on the
+/// unused part of the stack; this overwrites them. `N` should cover the
+/// deepest call made on the secrets.
+#[inline(never)]
+pub fn scrub_stack<const N: usize>() {
+    let mut area = [0u8; N];
+    zeroize(&mut area);
+    core::hint::black_box(&area);
+}
+
+/// Key buffer cleared when dropped.
+///
+/// Dereferences to the bytes; formatting shows only the length.
+#[derive(Clone)]
+pub struct Secret<const N: usize>([u8; N]);
+
+impl<const N: usize> Secret<N> {
+    /// Wraps `key`; the caller's copy should be cleared if it outlives this.
+    #[inline]
+    pub const fn new(key: [u8; N]) -> Self {
+        Self(key)
+    }
+}
+
+impl<const N: usize> Default for Secret<N> {
+    #[inline]
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> Deref for Secret<N> {
+    type Target = [u8; N];
+
+    #[inline]
+    fn deref(&self) -> &[u8; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> DerefMut for Secret<N> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8; N] {
+        &mut self.0
+    }
+}
+
+impl<const N: usize> PartialEq for Secret<N> {
+    /// Compares in constant time.
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl<const N: usize> Eq for Secret<N> {}
+
+impl<const N: usize> fmt::Debug for Secret<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret<{}>(..)", N)
+    }
+}
+
+impl<const N: usize> Drop for Secret<N> {
+    #[inline]
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const N: usize> zeroize::Zeroize for Secret<N> {
+    #[inline]
+    fn zeroize(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const N: usize> zeroize::ZeroizeOnDrop for Secret<N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_and_clear() {
+        assert!(constant_time_eq(b"key", b"key"));
+        assert!(!constant_time_eq(b"key", b"kez"));
+        assert!(!constant_time_eq(b"key", b"keys"));
+
+        let mut buf = [0xA5; 16];
+        zeroize(&mut buf[4..]);
+        assert_eq!(buf[..4], [0xA5; 4]);
+        assert_eq!(buf[4..], [0; 12]);
+
+        let mut secret = Secret::new([1; 4]);
+        secret[0] = 2;
+        assert_eq!(*secret, [2, 1, 1, 1]);
+        assert_eq!(secret, Secret::new([2, 1, 1, 1]));
+        assert_ne!(secret, Secret::default());
+        scrub_stack::<256>();
+    }
+}
@@ -0,0 +1,399 @@
+//! Production self-tests.
+//!
+//! A manufacturing build lists one [`Test`] per check, each a closure
+//! capturing the peripheral it exercises, and runs them at boot with [`run`]
+//! or from the shell with [`command`]. The routines in this module cover the
+//! common checks: serial and SPI loopback, memory patterns, timer rates and
+//! random number health.
+//!
+//! ```ignore
+//! let uart = RefCell::new(uart3.with_timeout(timer, Duration::from_millis(10)));
+//! let tests = [
+//!     Test::new("uart3-loopback", &|| selftest::serial_loopback(&mut *uart.borrow_mut())),
+//!     Test::new("ddr-pattern", &|| selftest::memory_pattern(unsafe { ddr_scratch() })),
+//! ];
+//! let selftest = |out: &mut dyn fmt::Write, args: &[&str]| selftest::command(&tests, out, args);
+//! shell.register("selftest", &selftest)?;
+//! ```
+//!
+//! Every test prints one line and the run ends with a summary, so a fixture
+//! can parse the console:
+//!
+//! ```text
+//! PASS uart3-loopback
+//! FAIL ddr-pattern: data mismatch at 0x80001234
+//! SKIP sd-init: no card
+//! selftest: 1 passed, 1 failed, 1 skipped
+//! ```
+
+use crate::shell::CommandError;
+use crate::time::{Duration, Instant};
+use crate::timeout::Monotonic;
+use core::fmt;
+use embedded_hal::spi::SpiBus;
+
+/// Result of a test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Outcome {
+    Pass,
+    /// The check failed, with a reason and the address or value involved.
+    Fail {
+        reason: &'static str,
+        at: Option<usize>,
+    },
+    /// The check could not run, such as for a missing card.
+    Skip(&'static str),
+}
+
+impl Outcome {
+    /// Fails with `reason`.
+    #[inline]
+    pub const fn fail(reason: &'static str) -> Self {
+        Outcome::Fail { reason, at: None }
+    }
+
+    /// Fails with `reason` at the address or value `at`.
+    #[inline]
+    pub const fn fail_at(reason: &'static str, at: usize) -> Self {
+        Outcome::Fail {
+            reason,
+            at: Some(at),
+        }
+    }
+}
+
+/// A named check.
+#[derive(Clone, Copy)]
+pub struct Test<'a> {
+    pub name: &'a str,
+    pub run: &'a dyn Fn() -> Outcome,
+}
+
+impl<'a> Test<'a> {
+    /// Creates a test.
+    #[inline]
+    pub const fn new(name: &'a str, run: &'a dyn Fn() -> Outcome) -> Self {
+        Self { name, run }
+    }
+}
+
+/// Counts of a run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+impl Summary {
+    /// Checks whether no test failed.
+    #[inline]
+    pub fn ok(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+/// Runs the tests whose name starts with `filter`, writing one line per test and a summary.
+pub fn run(
+    tests: &[Test<'_>],
+    filter: &str,
+    out: &mut dyn fmt::Write,
+) -> Result<Summary, fmt::Error> {
+    let mut summary = Summary::default();
+    for test in tests.iter().filter(|test| test.name.starts_with(filter)) {
+        match (test.run)() {
+            Outcome::Pass => {
+                summary.passed += 1;
+                writeln!(out, "PASS {}", test.name)?;
+            }
+            Outcome::Fail { reason, at } => {
+                summary.failed += 1;
+                write!(out, "FAIL {}: {reason}", test.name)?;
+                match at {
+                    Some(at) => writeln!(out, " at {at:#x}")?,
+                    None => writeln!(out)?,
+                }
+            }
+            Outcome::Skip(reason) => {
+                summary.skipped += 1;
+                writeln!(out, "SKIP {}: {reason}", test.name)?;
+            }
+        }
+    }
+    writeln!(
+        out,
+        "selftest: {} passed, {} failed, {} skipped",
+        summary.passed, summary.failed, summary.skipped
+    )?;
+    Ok(summary)
+}
+
+/// `selftest [prefix]`: runs all tests, or those whose name starts with the prefix.
+pub fn command(
+    tests: &[Test<'_>],
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> Result<(), CommandError> {
+    let filter = match args {
+        [_] => "",
+        [_, filter] => filter,
+        _ => return Err(CommandError::Usage),
+    };
+    match run(tests, filter, out)? {
+        summary if summary.ok() => Ok(()),
+        _ => Err(CommandError::Failed),
+    }
+}
+
+/// Bytes sent by the loopback tests, covering every bit and both edges of each.
+pub(crate) const PATTERN: [u8; 8] = [0x55, 0xAA, 0x00, 0xFF, 0x01, 0x80, 0x7E, 0xC3];
+
+/// Sends a pattern and expects it back, through a TX to RX jumper.
+///
+/// The UART can run the same check without a jumper, see
+/// [`BlockingUart::self_test`](crate::uart::BlockingUart::self_test).
+///
+/// Bound the serial port with a timeout so a missing jumper fails rather than hangs.
+pub fn serial_loopback<S: embedded_io::Read + embedded_io::Write>(serial: &mut S) -> Outcome {
+    if serial.write_all(&PATTERN).is_err() || serial.flush().is_err() {
+        return Outcome::fail("write failed");
+    }
+    let mut echo = [0; PATTERN.len()];
+    let mut n = 0;
+    while n < echo.len() {
+        match serial.read(&mut echo[n..]) {
+            Ok(0) | Err(_) => return Outcome::fail_at("no echo of byte", n),
+            Ok(read) => n += read,
+        }
+    }
+    check_echo(&echo)
+}
+
+/// Compares the bytes received by a loopback test with [`PATTERN`].
+pub(crate) fn check_echo(echo: &[u8; PATTERN.len()]) -> Outcome {
+    match PATTERN.iter().zip(echo).position(|(sent, got)| sent != got) {
+        Some(i) => Outcome::fail_at("wrong echo of byte", i),
+        None => Outcome::Pass,
+    }
+}
+
+/// Exchanges a pattern through a MOSI to MISO jumper.
+pub fn spi_loopback<SPI: SpiBus>(spi: &mut SPI) -> Outcome {
+    let mut echo = [0; PATTERN.len()];
+    if spi.transfer(&mut echo, &PATTERN).is_err() || spi.flush().is_err() {
+        return Outcome::fail("transfer failed");
+    }
+    check_echo(&echo)
+}
+
+/// Checks a RAM region with walking-bit, own-address and inverted-address patterns.
+///
+/// Catches stuck and coupled data lines and shorted or open address lines.
+/// The region's content is destroyed. A failure reports the address of the
+/// first wrong word.
+pub fn memory_pattern(region: &mut [u32]) -> Outcome {
+    let base = region.as_ptr() as usize;
+    let at = |i: usize| base + 4 * i;
+    // Walking ones and zeros on the first word: data lines.
+    if let Some(word) = region.first_mut() {
+        for bit in 0..32 {
+            for pattern in [1u32 << bit, !(1u32 << bit)] {
+                let word: *mut u32 = word;
+                unsafe { word.write_volatile(pattern) };
+                if unsafe { word.read_volatile() } != pattern {
+                    return Outcome::fail_at("data line fault", base);
+                }
+            }
+        }
+    }
+    // Each word holds its own address, then its inverse: address lines and cells.
+    for invert in [0, u32::MAX] {
+        for (i, word) in region.iter_mut().enumerate() {
+            let word: *mut u32 = word;
+            unsafe { word.write_volatile(at(i) as u32 ^ invert) };
+        }
+        for (i, word) in region.iter().enumerate() {
+            let word: *const u32 = word;
+            if unsafe { word.read_volatile() } != at(i) as u32 ^ invert {
+                return Outcome::fail_at("data mismatch", at(i));
+            }
+        }
+    }
+    Outcome::Pass
+}
+
+/// Checks that a counter runs at `hz` within `tolerance_ppm`, measured over
+/// `window` on a reference `timer`.
+///
+/// Suits an RTC or a peripheral timer, read through `counter`.
+pub fn counter_rate<M: Monotonic>(
+    timer: &M,
+    mut counter: impl FnMut() -> u64,
+    hz: u64,
+    window: Duration,
+    tolerance_ppm: u64,
+) -> Outcome {
+    let start = Instant::now(timer);
+    let first = counter();
+    let end = start + window;
+    while Instant::now(timer) < end {
+        core::hint::spin_loop();
+    }
+    let ticks = counter().wrapping_sub(first);
+    let elapsed = (Instant::now(timer) - start).as_nanos();
+    if ticks == 0 {
+        return Outcome::fail("counter stopped");
+    }
+    let expected = hz as u128 * elapsed / 1_000_000_000;
+    let error = (ticks as u128).abs_diff(expected) * 1_000_000;
+    if error > expected * tolerance_ppm as u128 {
+        return Outcome::fail_at("counter rate off, ticks", ticks as usize);
+    }
+    Outcome::Pass
+}
+
+/// Runs the repetition count and adaptive proportion health tests of NIST
+/// SP 800-90B on `samples` bytes of a random source.
+///
+/// The cutoffs assume at least 4 bits of entropy per byte and a false
+/// alarm rate of 2^-20: no byte repeats 6 times in a row, and no byte
+/// takes more than 84 of a window of 512.
+pub fn rng_health(mut next: impl FnMut() -> u8, samples: usize) -> Outcome {
+    const REPETITION_CUTOFF: usize = 6;
+    const WINDOW: usize = 512;
+    const PROPORTION_CUTOFF: usize = 84;
+
+    let mut last = None;
+    let mut repeats = 0;
+    let mut reference = 0;
+    let mut matches = 0;
+    for i in 0..samples {
+        let sample = next();
+        if last == Some(sample) {
+            repeats += 1;
+            if repeats >= REPETITION_CUTOFF {
+                return Outcome::fail_at("repetition count exceeded at sample", i);
+            }
+        } else {
+            repeats = 1;
+            last = Some(sample);
+        }
+        if i % WINDOW == 0 {
+            reference = sample;
+            matches = 1;
+        } else if sample == reference {
+            matches += 1;
+            if matches > PROPORTION_CUTOFF {
+                return Outcome::fail_at("adaptive proportion exceeded at sample", i);
+            }
+        }
+    }
+    Outcome::Pass
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Text;
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use embedded_hal::spi::ErrorType;
+
+    /// SPI bus with MISO stuck low after `good` bytes.
+    struct Jumper {
+        good: usize,
+    }
+
+    impl ErrorType for Jumper {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for Jumper {
+        fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+            words.fill(0);
+            Ok(())
+        }
+
+        fn write(&mut self, _: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+            for (i, (read, write)) in read.iter_mut().zip(write).enumerate() {
+                *read = if i < self.good { *write } else { 0 };
+            }
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, _: &mut [u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn runs_and_reports() {
+        let mut ram = [0u32; 64];
+        let memory = Outcome::Pass == memory_pattern(&mut ram);
+        let spi_pass = || spi_loopback(&mut Jumper { good: 8 });
+        let spi_fail = || spi_loopback(&mut Jumper { good: 3 });
+        let sd = || Outcome::Skip("no card");
+        let tests = [
+            Test::new("spi0", &spi_pass),
+            Test::new("spi1", &spi_fail),
+            Test::new("sd", &sd),
+        ];
+        let mut out = Text::<128>::new();
+        let summary = run(&tests, "", &mut out).unwrap();
+        assert!(memory);
+        assert_eq!(
+            out.as_str(),
+            "PASS spi0\nFAIL spi1: wrong echo of byte at 0x3\nSKIP sd: no card\n\
+             selftest: 1 passed, 1 failed, 1 skipped\n"
+        );
+        assert!(!summary.ok());
+
+        let mut out = Text::<256>::new();
+        assert_eq!(command(&tests, &mut out, &["selftest", "spi0"]), Ok(()));
+        assert_eq!(
+            command(&tests, &mut out, &["selftest", "spi"]),
+            Err(CommandError::Failed)
+        );
+    }
+
+    #[test]
+    fn rng_health_cutoffs() {
+        let state = Cell::new(0x1234_5678u32);
+        let xorshift = || {
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            state.set(x);
+            x as u8
+        };
+        assert_eq!(rng_health(xorshift, 4096), Outcome::Pass);
+        assert_eq!(
+            rng_health(|| 7, 4096),
+            Outcome::fail_at("repetition count exceeded at sample", 5)
+        );
+        let counter = Cell::new(0u8);
+        let biased = || {
+            counter.set(counter.get().wrapping_add(1));
+            if counter.get() % 2 == 1 {
+                0
+            } else {
+                counter.get()
+            }
+        };
+        assert_eq!(
+            rng_health(biased, 4096),
+            Outcome::fail_at("adaptive proportion exceeded at sample", 168)
+        );
+    }
+}
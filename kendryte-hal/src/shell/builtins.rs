@@ -0,0 +1,223 @@
+//! Bring-up commands for the shell.
+//!
+//! Each command takes the state it needs, the memory map, IOMUX or clock
+//! tree, and is registered as a closure capturing it. Memory accesses are
+//! checked against the [`MemoryMap`] first, so an address typed at the
+//! console only reaches regions whose creator vouched for them in the unsafe
+//! [`MemoryMap::new`], and is only written where they are writable.
+
+use super::CommandError;
+use crate::clocks::Clocks;
+use crate::debug::mem::{AccessError, MemoryMap};
+use crate::debug::report;
+use crate::iomux::ops::{PadOps, Pull};
+use crate::iomux::pad::Strength;
+use crate::iomux::{self, FlexPad};
+use arbitrary_int::{u3, u4};
+use core::fmt;
+
+/// Parses a decimal number, or a hexadecimal one prefixed by `0x`.
+pub fn parse_number(s: &str) -> Result<usize, CommandError> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| CommandError::InvalidArgument)
+}
+
+/// Reports an access the memory map refuses.
+fn checked<T>(out: &mut dyn fmt::Write, result: Result<T, AccessError>) -> Result<T, CommandError> {
+    result.or_else(|e| {
+        writeln!(out, "{e:?}")?;
+        Err(CommandError::InvalidArgument)
+    })
+}
+
+/// `peek <address> [words]`: dumps 32-bit words, four per line.
+///
+/// Addresses outside `map` are refused.
+pub fn peek(
+    map: &MemoryMap<'_>,
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> Result<(), CommandError> {
+    let (address, words) = match args {
+        [_, address] => (parse_number(address)?, 1),
+        [_, address, words] => (parse_number(address)?, parse_number(words)?),
+        _ => return Err(CommandError::Usage),
+    };
+    checked(out, map.check(address, words.saturating_mul(4)))?;
+    for i in 0..words {
+        let word = address + i * 4;
+        if i % 4 == 0 {
+            if i != 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{word:#010x}:")?;
+        }
+        let value = checked(out, map.read_u32(word))?;
+        write!(out, " {value:08x}")?;
+    }
+    Ok(writeln!(out)?)
+}
+
+/// `poke <address> <value>`: writes a 32-bit word.
+///
+/// Addresses outside the writable regions of `map` are refused.
+pub fn poke(
+    map: &MemoryMap<'_>,
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> Result<(), CommandError> {
+    let [_, address, value] = args else {
+        return Err(CommandError::Usage);
+    };
+    let address = parse_number(address)?;
+    let value = u32::try_from(parse_number(value)?).map_err(|_| CommandError::InvalidArgument)?;
+    checked(out, map.write_u32(address, value))
+}
+
+/// `dump <address> <bytes>`: hexdumps memory or registers.
+pub fn dump(
+    map: &MemoryMap<'_>,
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> Result<(), CommandError> {
+    let [_, address, len] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (address, len) = (parse_number(address)?, parse_number(len)?);
+    checked(out, map.check(address, len))?;
+    Ok(map.dump(out, address, len)?)
+}
+
+/// `pad <n> [in|out|inout|off | func <0-7> | pull up|down|none | ds <0-15>]`:
+/// shows or changes the configuration of a pad.
+pub fn pad(
+    iomux: &'static iomux::RegisterBlock,
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> Result<(), CommandError> {
+    let Some(n) = args.get(1) else {
+        return Err(CommandError::Usage);
+    };
+    let n = parse_number(n)?;
+    let pad = FlexPad::new(iomux.pads.get(n).ok_or(CommandError::InvalidArgument)?);
+    match args[2..] {
+        [] => {}
+        ["in"] => {
+            pad.set_input();
+        }
+        ["out"] => {
+            pad.set_output();
+        }
+        ["inout"] => {
+            pad.set_bidirectional();
+        }
+        ["off"] => {
+            pad.set_disabled();
+        }
+        ["func", function] => match parse_number(function)? {
+            f @ 0..=7 => {
+                pad.set_function_select(u3::new(f as u8));
+            }
+            _ => return Err(CommandError::InvalidArgument),
+        },
+        ["pull", pull] => {
+            let pull = match pull {
+                "up" => Pull::Up,
+                "down" => Pull::Down,
+                "none" => Pull::None,
+                _ => return Err(CommandError::InvalidArgument),
+            };
+            pad.set_pull(pull);
+        }
+        ["ds", strength] => match parse_number(strength)? {
+            s @ 0..=15 => {
+                pad.set_drive_strength(Strength::new_with_raw_value(u4::new(s as u8)));
+            }
+            _ => return Err(CommandError::InvalidArgument),
+        },
+        _ => return Err(CommandError::Usage),
+    }
+
+    Ok(writeln!(
+        out,
+        "pad {n}: func {} {} pull {} ds {} slew {:?} schmitt {} {:?} level {}",
+        pad.function_select(),
+        report::direction(&pad),
+        report::pull(&pad),
+        pad.drive_strength().raw_value(),
+        pad.slew_rate(),
+        pad.is_schmitt_trigger_enabled(),
+        pad.io_voltage(),
+        pad.input_data(),
+    )?)
+}
+
+/// `clocks`: lists peripheral clock frequencies.
+pub fn clocks(
+    clocks: &Clocks,
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> Result<(), CommandError> {
+    if args.len() != 1 {
+        return Err(CommandError::Usage);
+    }
+    Ok(report::clocks(out, clocks)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::mem::{Region, RegionKind};
+    use crate::test_support::Text;
+    use core::fmt::Write as _;
+
+    #[test]
+    fn peek_and_poke() {
+        let mut words = [0x1234_5678u32, 0];
+        let base = words.as_mut_ptr() as usize;
+        let mut first = Text::<24>::new();
+        write!(first, "{base:#x}").unwrap();
+        let mut second = Text::<24>::new();
+        write!(second, "{}", base + 4).unwrap();
+        let (first, second) = (first.as_str(), second.as_str());
+
+        let regions = [Region {
+            name: "TEST",
+            start: base,
+            len: 8,
+            kind: RegionKind::Peripheral,
+            writable: true,
+        }];
+        // Only accessed through the map until it is dropped.
+        let map = unsafe { MemoryMap::new(&regions) };
+
+        let mut out = Text::<128>::new();
+        poke(&map, &mut out, &["poke", second, "0xcafe"]).unwrap();
+        peek(&map, &mut out, &["peek", first, "2"]).unwrap();
+        assert!(out.as_str().ends_with(": 12345678 0000cafe\n"));
+        assert_eq!(words[1], 0xcafe);
+
+        assert_eq!(
+            peek(&map, &mut out, &["peek", first, "3"]),
+            Err(CommandError::InvalidArgument)
+        );
+        assert_eq!(peek(&map, &mut out, &["peek"]), Err(CommandError::Usage));
+
+        let read_only = [Region {
+            writable: false,
+            ..regions[0]
+        }];
+        // As above.
+        let map = unsafe { MemoryMap::new(&read_only) };
+        assert_eq!(
+            poke(&map, &mut out, &["poke", second, "1"]),
+            Err(CommandError::InvalidArgument)
+        );
+        assert_eq!(words[1], 0xcafe);
+        assert_eq!(parse_number("0x1F"), Ok(31));
+        assert_eq!(parse_number("x"), Err(CommandError::InvalidArgument));
+    }
+}
@@ -0,0 +1,422 @@
+//! Interactive command shell over a serial console.
+//!
+//! [`Shell`] reads a console through [`embedded_io`], edits the line with
+//! backspace, `Ctrl-C`, `Ctrl-U` and arrow-key history, and dispatches it to
+//! registered commands:
+//!
+//! ```ignore
+//! // No driver runs while registers are poked from the console.
+//! let map = unsafe { MemoryMap::k230() };
+//! let iomux = unsafe { &*IOMUX::ptr() };
+//! let peek = |out: &mut dyn fmt::Write, args: &[&str]| builtins::peek(&map, out, args);
+//! let pad = |out: &mut dyn fmt::Write, args: &[&str]| builtins::pad(iomux, out, args);
+//! let mut shell = Shell::<_, 8>::new(uart, "k230> ");
+//! shell.register("peek", &peek)?;
+//! shell.register("pad", &pad)?;
+//! shell.start()?;
+//! loop {
+//!     shell.poll()?;
+//! }
+//! ```
+//!
+//! Command output goes through [`core::fmt::Write`], so handlers use
+//! `write!` and do not depend on the console type.
+
+pub mod builtins;
+
+use core::fmt::{self, Write as _};
+use embedded_io::{Read, Write};
+
+/// Longest command line.
+pub const MAX_LINE: usize = 128;
+
+/// Largest number of words in a command line, including the command name.
+pub const MAX_ARGS: usize = 8;
+
+/// Number of command lines kept for recall.
+const HISTORY: usize = 4;
+
+/// Error returned by a command handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandError {
+    /// Wrong number or form of arguments.
+    Usage,
+    /// An argument is out of range.
+    InvalidArgument,
+    /// The command failed.
+    Failed,
+}
+
+impl From<fmt::Error> for CommandError {
+    fn from(_: fmt::Error) -> Self {
+        CommandError::Failed
+    }
+}
+
+/// Error returned when every command slot is taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CommandsFull;
+
+/// Command handler, called with the output and the words of the line, command name first.
+pub type Handler<'a> = &'a dyn Fn(&mut dyn fmt::Write, &[&str]) -> Result<(), CommandError>;
+
+struct Command<'a> {
+    name: &'a str,
+    handler: Handler<'a>,
+}
+
+/// Progress through an ANSI escape sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Escape {
+    None,
+    Esc,
+    Csi,
+}
+
+/// Command shell holding up to `N` commands.
+pub struct Shell<'a, IO, const N: usize> {
+    io: IO,
+    prompt: &'a str,
+    commands: [Option<Command<'a>>; N],
+    line: [u8; MAX_LINE],
+    len: usize,
+    history: [([u8; MAX_LINE], usize); HISTORY],
+    /// Lines entered so far, of which the last [`HISTORY`] are kept.
+    entered: usize,
+    /// How far back in the history the line was recalled from, 0 when editing.
+    browse: usize,
+    escape: Escape,
+    last_cr: bool,
+}
+
+impl<'a, IO: Read + Write, const N: usize> Shell<'a, IO, N> {
+    /// Creates a shell on `io` with no commands but `help`.
+    pub fn new(io: IO, prompt: &'a str) -> Self {
+        Self {
+            io,
+            prompt,
+            commands: [const { None }; N],
+            line: [0; MAX_LINE],
+            len: 0,
+            history: [([0; MAX_LINE], 0); HISTORY],
+            entered: 0,
+            browse: 0,
+            escape: Escape::None,
+            last_cr: false,
+        }
+    }
+
+    /// Registers `handler` as command `name`.
+    pub fn register(&mut self, name: &'a str, handler: Handler<'a>) -> Result<(), CommandsFull> {
+        let slot = self
+            .commands
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_none_or(|command| command.name == name))
+            .ok_or(CommandsFull)?;
+        *slot = Some(Command { name, handler });
+        Ok(())
+    }
+
+    /// Prints the prompt.
+    pub fn start(&mut self) -> Result<(), IO::Error> {
+        self.io.write_all(self.prompt.as_bytes())
+    }
+
+    /// Reads what the console has received and processes it, blocking until
+    /// at least one byte arrives.
+    pub fn poll(&mut self) -> Result<(), IO::Error> {
+        let mut buf = [0; 16];
+        let n = self.io.read(&mut buf)?;
+        for &byte in &buf[..n] {
+            self.process(byte)?;
+        }
+        Ok(())
+    }
+
+    /// Processes one received byte.
+    pub fn process(&mut self, byte: u8) -> Result<(), IO::Error> {
+        let last_cr = core::mem::replace(&mut self.last_cr, byte == b'\r');
+        match (self.escape, byte) {
+            (Escape::Esc, b'[') => self.escape = Escape::Csi,
+            (Escape::Csi, b'A') => {
+                self.escape = Escape::None;
+                if self.browse < self.entered.min(HISTORY) {
+                    self.browse += 1;
+                    self.recall()?;
+                }
+            }
+            (Escape::Csi, b'B') => {
+                self.escape = Escape::None;
+                if self.browse > 0 {
+                    self.browse -= 1;
+                    self.recall()?;
+                }
+            }
+            (Escape::Esc | Escape::Csi, _) => self.escape = Escape::None,
+            (Escape::None, 0x1B) => self.escape = Escape::Esc,
+            (Escape::None, b'\n') if last_cr => {}
+            (Escape::None, b'\r' | b'\n') => self.execute()?,
+            (Escape::None, 0x08 | 0x7F) => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    self.io.write_all(b"\x08 \x08")?;
+                }
+            }
+            // Ctrl-C abandons the line.
+            (Escape::None, 0x03) => {
+                self.len = 0;
+                self.browse = 0;
+                self.io.write_all(b"^C\r\n")?;
+                self.start()?;
+            }
+            // Ctrl-U erases the line.
+            (Escape::None, 0x15) => {
+                self.len = 0;
+                self.redraw()?;
+            }
+            (Escape::None, 0x20..=0x7E) => {
+                if self.len < MAX_LINE {
+                    self.line[self.len] = byte;
+                    self.len += 1;
+                    self.io.write_all(&[byte])?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Releases the console.
+    pub fn free(self) -> IO {
+        self.io
+    }
+
+    /// Replaces the line with the history entry selected by `browse`.
+    fn recall(&mut self) -> Result<(), IO::Error> {
+        match self.browse {
+            0 => self.len = 0,
+            back => {
+                let (line, len) = &self.history[(self.entered - back) % HISTORY];
+                self.line[..*len].copy_from_slice(&line[..*len]);
+                self.len = *len;
+            }
+        }
+        self.redraw()
+    }
+
+    fn redraw(&mut self) -> Result<(), IO::Error> {
+        self.io.write_all(b"\r\x1b[K")?;
+        self.io.write_all(self.prompt.as_bytes())?;
+        self.io.write_all(&self.line[..self.len])
+    }
+
+    fn execute(&mut self) -> Result<(), IO::Error> {
+        self.io.write_all(b"\r\n")?;
+        let line = self.line;
+        let len = core::mem::take(&mut self.len);
+        self.browse = 0;
+        // Only printable ASCII is ever stored.
+        let line = core::str::from_utf8(&line[..len]).unwrap_or_default();
+
+        let mut args = [""; MAX_ARGS];
+        let mut argc = 0;
+        for word in line.split_ascii_whitespace() {
+            if argc == MAX_ARGS {
+                argc += 1;
+                break;
+            }
+            args[argc] = word;
+            argc += 1;
+        }
+        if argc > 0 {
+            self.remember(line);
+        }
+
+        let mut out = Output {
+            io: &mut self.io,
+            error: None,
+        };
+        // Write errors are kept in `out.error`.
+        let _ = match argc {
+            0 => Ok(()),
+            n if n > MAX_ARGS => out.write_str("error: too many arguments\n"),
+            _ => dispatch(&self.commands, &mut out, &args[..argc]),
+        };
+        if let Some(error) = out.error {
+            return Err(error);
+        }
+        self.start()
+    }
+
+    fn remember(&mut self, line: &str) {
+        if self.entered > 0 {
+            let (last, len) = &self.history[(self.entered - 1) % HISTORY];
+            if &last[..*len] == line.as_bytes() {
+                return;
+            }
+        }
+        let entry = &mut self.history[self.entered % HISTORY];
+        entry.0[..line.len()].copy_from_slice(line.as_bytes());
+        entry.1 = line.len();
+        self.entered += 1;
+    }
+}
+
+/// Runs the command named by `args[0]`, reporting errors on `out`.
+fn dispatch(
+    commands: &[Option<Command<'_>>],
+    out: &mut dyn fmt::Write,
+    args: &[&str],
+) -> fmt::Result {
+    let name = args[0];
+    if name == "help" {
+        out.write_str("help")?;
+        for command in commands.iter().flatten() {
+            write!(out, " {}", command.name)?;
+        }
+        return out.write_str("\n");
+    }
+    let Some(command) = commands.iter().flatten().find(|c| c.name == name) else {
+        return writeln!(out, "unknown command: {name}");
+    };
+    match (command.handler)(out, args) {
+        Ok(()) => Ok(()),
+        Err(CommandError::Usage) => writeln!(out, "error: usage"),
+        Err(CommandError::InvalidArgument) => writeln!(out, "error: invalid argument"),
+        Err(CommandError::Failed) => writeln!(out, "error: {name} failed"),
+    }
+}
+
+/// Console as a [`fmt::Write`], translating line feeds for terminals.
+struct Output<'w, W: Write> {
+    io: &'w mut W,
+    error: Option<W::Error>,
+}
+
+impl<W: Write> fmt::Write for Output<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut lines = s.split('\n');
+        let mut result = self
+            .io
+            .write_all(lines.next().unwrap_or_default().as_bytes());
+        for line in lines {
+            result = result
+                .and_then(|()| self.io.write_all(b"\r\n"))
+                .and_then(|()| self.io.write_all(line.as_bytes()));
+        }
+        result.map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    /// Console replaying `input` and recording output.
+    struct Console {
+        input: &'static [u8],
+        out: [u8; 1024],
+        len: usize,
+    }
+
+    impl Console {
+        fn new(input: &'static [u8]) -> Self {
+            Self {
+                input,
+                out: [0; 1024],
+                len: 0,
+            }
+        }
+
+        fn output(&self) -> &str {
+            core::str::from_utf8(&self.out[..self.len]).unwrap()
+        }
+
+        fn count(&self, needle: &str) -> usize {
+            self.output().matches(needle).count()
+        }
+    }
+
+    impl embedded_io::ErrorType for Console {
+        type Error = Infallible;
+    }
+
+    impl Read for Console {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let n = buf.len().min(self.input.len());
+            buf[..n].copy_from_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            Ok(n)
+        }
+    }
+
+    impl Write for Console {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.out[self.len..self.len + buf.len()].copy_from_slice(buf);
+            self.len += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    fn echo(out: &mut dyn fmt::Write, args: &[&str]) -> Result<(), CommandError> {
+        match args {
+            [_, words @ ..] if !words.is_empty() => {
+                for word in words {
+                    write!(out, "<{word}>")?;
+                }
+                Ok(writeln!(out)?)
+            }
+            _ => Err(CommandError::Usage),
+        }
+    }
+
+    fn run(input: &'static [u8]) -> Console {
+        let mut shell = Shell::<_, 2>::new(Console::new(input), "> ");
+        shell.register("echo", &echo).unwrap();
+        while !shell.io.input.is_empty() {
+            shell.poll().unwrap();
+        }
+        shell.free()
+    }
+
+    #[test]
+    fn edits_and_dispatches() {
+        let console = run(b"ecx\x7fho  a b\r\necho\rnope\rhelp\n");
+        assert_eq!(console.count("<a><b>\r\n> "), 1);
+        assert_eq!(console.count("error: usage"), 1);
+        assert_eq!(console.count("unknown command: nope"), 1);
+        assert_eq!(console.count("help echo\r\n"), 1);
+    }
+
+    #[test]
+    fn recalls_history() {
+        let console = run(b"echo 1\recho 2\r\x1b[A\x1b[A\r\x1b[A\x1b[B\x15echo 3\r");
+        assert_eq!(console.count("<1>"), 2);
+        assert_eq!(console.count("<2>"), 1);
+        assert_eq!(console.count("<3>"), 1);
+    }
+
+    #[test]
+    fn registration() {
+        let mut shell = Shell::<_, 1>::new(Console::new(b""), "> ");
+        assert_eq!(shell.register("echo", &echo), Ok(()));
+        assert_eq!(shell.register("echo", &echo), Ok(()));
+        assert_eq!(shell.register("other", &echo), Err(CommandsFull));
+        let mut out = Output {
+            io: &mut shell.io,
+            error: None,
+        };
+        out.write_str("a\nb").unwrap();
+        assert_eq!(shell.io.output(), "a\r\nb");
+    }
+}
@@ -0,0 +1,172 @@
+//! GPIO based software PWM.
+//!
+//! Drives up to `N` output pins from one periodic timer interrupt, for boards
+//! where the hardware PWM pads are taken. Call [`SoftPwm::on_tick`] from the
+//! timer interrupt; the PWM frequency is the tick rate divided by the period.
+
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+/// Software PWM over `N` output pins sharing one period.
+pub struct SoftPwm<P, const N: usize> {
+    pins: [P; N],
+    duty: [u16; N],
+    /// Duty latched at the start of the current cycle.
+    active: [u16; N],
+    period: u16,
+    tick: u16,
+}
+
+impl<P: OutputPin, const N: usize> SoftPwm<P, N> {
+    /// Creates a software PWM with all channels at 0% duty.
+    ///
+    /// `period` is the number of ticks per PWM cycle, and the duty resolution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(pins: [P; N], period: u16) -> Self {
+        assert!(period != 0, "period must be non-zero");
+        Self {
+            pins,
+            duty: [0; N],
+            active: [0; N],
+            period,
+            tick: 0,
+        }
+    }
+
+    /// Returns the number of ticks per PWM cycle.
+    #[inline]
+    pub fn period(&self) -> u16 {
+        self.period
+    }
+
+    /// Sets the duty of `channel` in ticks, clamped to the period.
+    /// Takes effect from the start of the next cycle.
+    #[inline]
+    pub fn set_duty(&mut self, channel: usize, duty: u16) {
+        self.duty[channel] = duty.min(self.period);
+    }
+
+    /// Returns the duty of `channel` in ticks.
+    #[inline]
+    pub fn duty(&self, channel: usize) -> u16 {
+        self.duty[channel]
+    }
+
+    /// Returns a handle to `channel` implementing [`SetDutyCycle`].
+    #[inline]
+    pub fn channel(&mut self, channel: usize) -> Channel<'_, P, N> {
+        assert!(channel < N, "channel out of range");
+        Channel { pwm: self, channel }
+    }
+
+    /// Advances the PWM by one tick; call from the timer interrupt.
+    ///
+    /// Pins are only written on edges, keeping the interrupt short.
+    pub fn on_tick(&mut self) -> Result<(), P::Error> {
+        if self.tick == 0 {
+            self.active = self.duty;
+        }
+        for (pin, &duty) in self.pins.iter_mut().zip(&self.active) {
+            if self.tick == 0 && duty != 0 {
+                pin.set_high()?;
+            } else if self.tick == duty {
+                pin.set_low()?;
+            }
+        }
+        self.tick += 1;
+        if self.tick == self.period {
+            self.tick = 0;
+        }
+        Ok(())
+    }
+
+    /// Releases the pins.
+    #[inline]
+    pub fn free(self) -> [P; N] {
+        self.pins
+    }
+}
+
+/// One channel of a [`SoftPwm`].
+pub struct Channel<'a, P, const N: usize> {
+    pwm: &'a mut SoftPwm<P, N>,
+    channel: usize,
+}
+
+impl<P, const N: usize> ErrorType for Channel<'_, P, N> {
+    type Error = Infallible;
+}
+
+impl<P: OutputPin, const N: usize> SetDutyCycle for Channel<'_, P, N> {
+    #[inline]
+    fn max_duty_cycle(&self) -> u16 {
+        self.pwm.period
+    }
+
+    #[inline]
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.pwm.set_duty(self.channel, duty);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Pin {
+        high: bool,
+        writes: usize,
+    }
+
+    impl embedded_hal::digital::ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.high = false;
+            self.writes += 1;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.high = true;
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn duty_cycles() {
+        let mut pwm = SoftPwm::new([Pin::default(), Pin::default(), Pin::default()], 4);
+        pwm.set_duty(0, 1);
+        pwm.channel(1).set_duty_cycle_fully_on().unwrap();
+        pwm.set_duty(2, 0);
+
+        let mut high = [0; 3];
+        for _ in 0..8 {
+            pwm.on_tick().unwrap();
+            for (count, pin) in high.iter_mut().zip(&pwm.pins) {
+                *count += pin.high as usize;
+            }
+        }
+        assert_eq!(high, [2, 8, 0]);
+
+        let pins = pwm.free();
+        assert_eq!(pins[1].writes, 2);
+        assert!(!pins[2].high);
+    }
+
+    #[test]
+    fn duty_clamped() {
+        let mut pwm = SoftPwm::new([Pin::default()], 10);
+        pwm.set_duty(0, 20);
+        assert_eq!(pwm.duty(0), 10);
+    }
+}
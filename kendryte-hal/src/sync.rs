@@ -0,0 +1,563 @@
+//! Locks and channels safe against interrupts and the other hart.
+//!
+//! Every lock masks interrupts on the hart that holds it, so a handler on
+//! the same hart can never spin on a lock its own thread holds, and spins
+//! with an atomic swap (`amoswap`) against the other hart.
+//!
+//! A lock can instead be given a priority ceiling: the highest PLIC priority
+//! of any interrupt that takes it. Once a threshold hook is registered with
+//! [`set_threshold_hook`], holding such a lock raises the hart's PLIC
+//! threshold to the ceiling rather than masking every interrupt, so more
+//! urgent interrupts keep running:
+//!
+//! ```ignore
+//! sync::set_threshold_hook(|threshold| plic.set_threshold(hart, threshold));
+//! static FRAMES: Mutex<FrameQueue> = Mutex::with_ceiling(FrameQueue::new(), 3);
+//! ```
+
+use crate::futures::{AtomicWaker, IrqFuture};
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// Sets the PLIC priority threshold of the current hart, returning the previous one.
+pub type ThresholdHook = fn(u8) -> u8;
+
+static THRESHOLD_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers the hook locks with a ceiling use to raise the PLIC threshold.
+pub fn set_threshold_hook(hook: ThresholdHook) {
+    THRESHOLD_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+fn threshold_hook() -> Option<ThresholdHook> {
+    let hook = THRESHOLD_HOOK.load(Ordering::Acquire);
+    // Only ever stored from a `ThresholdHook`.
+    (!hook.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), ThresholdHook>(hook) })
+}
+
+/// Runs `f` with machine interrupts masked on the current hart.
+#[inline]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    let _mask = Mask::interrupts();
+    f()
+}
+
+/// How a held lock keeps interrupts of its hart out, undone on drop.
+enum Mask {
+    /// Machine interrupts masked; whether they were enabled before.
+    Interrupts(bool),
+    /// PLIC threshold raised; the previous threshold.
+    Threshold(u8),
+}
+
+impl Mask {
+    fn interrupts() -> Self {
+        #[cfg(target_arch = "riscv64")]
+        {
+            let mstatus: usize;
+            unsafe { core::arch::asm!("csrrci {0}, mstatus, 8", out(reg) mstatus) };
+            Mask::Interrupts(mstatus & 8 != 0)
+        }
+        #[cfg(not(target_arch = "riscv64"))]
+        Mask::Interrupts(false)
+    }
+
+    fn acquire(ceiling: Option<u8>) -> Self {
+        match (ceiling, threshold_hook()) {
+            (Some(ceiling), Some(hook)) => {
+                let previous = hook(ceiling);
+                // Never lower a threshold an outer lock raised further.
+                if previous > ceiling {
+                    hook(previous);
+                }
+                Mask::Threshold(previous)
+            }
+            _ => Mask::interrupts(),
+        }
+    }
+}
+
+impl Drop for Mask {
+    fn drop(&mut self) {
+        match *self {
+            #[cfg(target_arch = "riscv64")]
+            Mask::Interrupts(true) => unsafe { core::arch::asm!("csrsi mstatus, 8") },
+            Mask::Interrupts(_) => {}
+            Mask::Threshold(previous) => {
+                if let Some(hook) = threshold_hook() {
+                    hook(previous);
+                }
+            }
+        }
+    }
+}
+
+/// Mutual exclusion lock.
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    ceiling: Option<u8>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Creates a lock that masks interrupts while held.
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            ceiling: None,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Creates a lock that raises the PLIC threshold to `ceiling` while held.
+    #[inline]
+    pub const fn with_ceiling(value: T, ceiling: u8) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            ceiling: Some(ceiling),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Takes the lock, spinning while the other hart holds it.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let mask = Mask::acquire(self.ceiling);
+        while self.locked.swap(true, Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+        MutexGuard {
+            mutex: self,
+            _mask: mask,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Takes the lock if it is free.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mask = Mask::acquire(self.ceiling);
+        if self.locked.swap(true, Ordering::Acquire) {
+            None
+        } else {
+            Some(MutexGuard {
+                mutex: self,
+                _mask: mask,
+                _not_send: PhantomData,
+            })
+        }
+    }
+
+    /// Returns the value, which the exclusive borrow guarantees is unlocked.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Releases the value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Guard of a locked [`Mutex`], unlocking it on drop.
+///
+/// The guard restores the interrupt mask of the hart that took the lock, so
+/// it cannot be sent to another hart:
+///
+/// ```compile_fail
+/// use kendryte_hal::sync::Mutex;
+///
+/// fn send<T: Send>(_: T) {}
+/// static LOCK: Mutex<u32> = Mutex::new(0);
+/// send(LOCK.lock());
+/// ```
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+    _mask: Mask,
+    /// Restores the mask of the hart that took the lock, so stays on it.
+    _not_send: PhantomData<*const ()>,
+}
+
+// Sharing the guard only shares the value.
+unsafe impl<T: Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // Held by this guard alone.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // Held by this guard alone.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Unlocked before the mask drops and lets interrupts in.
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Set in the state of a [`RwLock`] held for writing.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// Lock allowing several readers or one writer.
+pub struct RwLock<T> {
+    /// Number of readers, or [`WRITER`].
+    state: AtomicUsize,
+    ceiling: Option<u8>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a lock that masks interrupts while held.
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            ceiling: None,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Creates a lock that raises the PLIC threshold to `ceiling` while held.
+    #[inline]
+    pub const fn with_ceiling(value: T, ceiling: u8) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            ceiling: Some(ceiling),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Takes the lock for reading, spinning while it is held for writing.
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        let mask = Mask::acquire(self.ceiling);
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & WRITER == 0
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return ReadGuard {
+                    lock: self,
+                    _mask: mask,
+                    _not_send: PhantomData,
+                };
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Takes the lock for writing, spinning while it is held.
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        let mask = Mask::acquire(self.ceiling);
+        while self
+            .state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        WriteGuard {
+            lock: self,
+            _mask: mask,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Takes the lock for writing if it is free.
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+        let mask = Mask::acquire(self.ceiling);
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| WriteGuard {
+                lock: self,
+                _mask: mask,
+                _not_send: PhantomData,
+            })
+    }
+
+    /// Returns the value, which the exclusive borrow guarantees is unlocked.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Releases the value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Guard of a [`RwLock`] held for reading.
+pub struct ReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _mask: Mask,
+    /// Restores the mask of the hart that took the lock, so stays on it.
+    _not_send: PhantomData<*const ()>,
+}
+
+// Sharing the guard only shares the value.
+unsafe impl<T: Sync> Sync for ReadGuard<'_, T> {}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // No writer while any reader holds the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Guard of a [`RwLock`] held for writing.
+pub struct WriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _mask: Mask,
+    /// Restores the mask of the hart that took the lock, so stays on it.
+    _not_send: PhantomData<*const ()>,
+}
+
+// Sharing the guard only shares the value.
+unsafe impl<T: Sync> Sync for WriteGuard<'_, T> {}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // Held by this guard alone.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // Held by this guard alone.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+/// Ring buffer of a [`Channel`].
+struct Ring<T, const N: usize> {
+    items: [MaybeUninit<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T, const N: usize> Drop for Ring<T, N> {
+    fn drop(&mut self) {
+        for i in 0..self.len {
+            // The first `len` items from `head` are initialized.
+            unsafe { self.items[(self.head + i) % N].assume_init_drop() };
+        }
+    }
+}
+
+/// Bounded first-in first-out queue of `N` items between tasks, harts and handlers.
+pub struct Channel<T, const N: usize> {
+    ring: Mutex<Ring<T, N>>,
+    /// Woken when an item is sent.
+    receiver: AtomicWaker,
+    /// Woken when an item is received.
+    sender: AtomicWaker,
+}
+
+impl<T, const N: usize> Channel<T, N> {
+    /// Creates an empty channel whose lock masks interrupts.
+    pub const fn new() -> Self {
+        Self::from_mutex(Mutex::new(Self::EMPTY))
+    }
+
+    /// Creates an empty channel whose lock raises the PLIC threshold to `ceiling`.
+    pub const fn with_ceiling(ceiling: u8) -> Self {
+        Self::from_mutex(Mutex::with_ceiling(Self::EMPTY, ceiling))
+    }
+
+    const EMPTY: Ring<T, N> = Ring {
+        items: [const { MaybeUninit::uninit() }; N],
+        head: 0,
+        len: 0,
+    };
+
+    const fn from_mutex(ring: Mutex<Ring<T, N>>) -> Self {
+        Self {
+            ring,
+            receiver: AtomicWaker::new(),
+            sender: AtomicWaker::new(),
+        }
+    }
+
+    /// Queues `item`, or returns it if the channel is full.
+    pub fn try_send(&self, item: T) -> Result<(), T> {
+        {
+            let mut ring = self.ring.lock();
+            if ring.len == N {
+                return Err(item);
+            }
+            let tail = (ring.head + ring.len) % N;
+            ring.items[tail].write(item);
+            ring.len += 1;
+        }
+        self.receiver.wake();
+        Ok(())
+    }
+
+    /// Takes the oldest item, if any.
+    pub fn try_receive(&self) -> Option<T> {
+        let item = {
+            let mut ring = self.ring.lock();
+            if ring.len == 0 {
+                return None;
+            }
+            let head = ring.head;
+            ring.head = (head + 1) % N;
+            ring.len -= 1;
+            // Counted in `len` until just now.
+            unsafe { ring.items[head].assume_init_read() }
+        };
+        self.sender.wake();
+        Some(item)
+    }
+
+    /// Returns a future queuing `item` once there is room.
+    ///
+    /// One task at a time may wait to send.
+    pub fn send(&self, item: T) -> impl Future<Output = ()> + '_
+    where
+        T: Unpin,
+    {
+        let mut item = Some(item);
+        IrqFuture::new(&self.sender, move || match item.take() {
+            Some(value) => match self.try_send(value) {
+                Ok(()) => Some(()),
+                Err(value) => {
+                    item = Some(value);
+                    None
+                }
+            },
+            None => Some(()),
+        })
+    }
+
+    /// Returns a future taking the oldest item once there is one.
+    ///
+    /// One task at a time may wait to receive.
+    pub fn receive(&self) -> impl Future<Output = T> + '_ {
+        IrqFuture::new(&self.receiver, || self.try_receive())
+    }
+
+    /// Returns the number of queued items.
+    pub fn len(&self) -> usize {
+        self.ring.lock().len
+    }
+
+    /// Checks whether no item is queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::pin::pin;
+    use core::sync::atomic::AtomicU8;
+    use core::task::{Context, Poll, Waker};
+
+    #[test]
+    fn mutex_and_rwlock() {
+        let mutex = Mutex::new(1);
+        {
+            let mut guard = mutex.lock();
+            *guard += 1;
+            assert!(mutex.try_lock().is_none());
+        }
+        assert_eq!(*mutex.try_lock().unwrap(), 2);
+
+        let lock = RwLock::new(5);
+        let (a, b) = (lock.read(), lock.read());
+        assert_eq!(*a + *b, 10);
+        assert!(lock.try_write().is_none());
+        drop((a, b));
+        *lock.write() = 6;
+        assert_eq!(lock.into_inner(), 6);
+    }
+
+    #[test]
+    fn ceiling_raises_threshold() {
+        static THRESHOLD: AtomicU8 = AtomicU8::new(1);
+        set_threshold_hook(|threshold| THRESHOLD.swap(threshold, Ordering::Relaxed));
+
+        let mutex = Mutex::with_ceiling((), 4);
+        let lock = RwLock::with_ceiling((), 2);
+        {
+            let _outer = mutex.lock();
+            assert_eq!(THRESHOLD.load(Ordering::Relaxed), 4);
+            let _inner = lock.read();
+            assert_eq!(THRESHOLD.load(Ordering::Relaxed), 4);
+        }
+        assert_eq!(THRESHOLD.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn channel_order_and_futures() {
+        let channel = Channel::<u8, 2>::new();
+        assert_eq!(channel.try_send(1), Ok(()));
+        assert_eq!(channel.try_send(2), Ok(()));
+        assert_eq!(channel.try_send(3), Err(3));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut send = pin!(channel.send(3));
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(channel.try_receive(), Some(1));
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(()));
+
+        let mut receive = pin!(channel.receive());
+        assert_eq!(receive.as_mut().poll(&mut cx), Poll::Ready(2));
+        assert_eq!(channel.try_receive(), Some(3));
+        assert!(channel.is_empty());
+    }
+}
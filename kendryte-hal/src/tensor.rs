@@ -0,0 +1,549 @@
+//! Tensor views over NPU buffers.
+//!
+//! A [`Tensor`] or [`TensorMut`] borrows a DMA buffer and records its shape,
+//! [`Layout`] and row stride, so element indexing, layout conversion and
+//! (de)quantization agree with the layout the KPU reads and writes:
+//!
+//! ```ignore
+//! let geometry = Geometry::aligned(Shape::new(1, 224, 224, 3), Layout::Nhwc, KPU_ALIGN);
+//! let mut input = TensorMut::<u8>::new(&mut dma_buf, geometry)?;
+//! input.copy_from(&frame)?;
+//! ```
+
+/// Alignment in bytes of buffers shared with the KPU, one cache line, so
+/// cache maintenance on a tensor never touches neighbouring data.
+pub const KPU_ALIGN: usize = 64;
+
+/// Errors in describing a tensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TensorError {
+    /// The buffer does not start on a [`KPU_ALIGN`] boundary.
+    Misaligned,
+    /// The buffer is shorter than the shape and strides require.
+    TooShort,
+    /// The row stride is shorter than a row.
+    InvalidStride,
+    /// Two tensors, or a tensor and its quantization, differ in shape.
+    ShapeMismatch,
+}
+
+/// Memory order of the dimensions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Layout {
+    /// Batch, height, width, channel; channels interleaved, as images are captured.
+    Nhwc,
+    /// Batch, channel, height, width; one plane per channel.
+    Nchw,
+}
+
+/// Size of each dimension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Shape {
+    pub n: usize,
+    pub h: usize,
+    pub w: usize,
+    pub c: usize,
+}
+
+impl Shape {
+    /// Creates a shape.
+    #[inline]
+    pub const fn new(n: usize, h: usize, w: usize, c: usize) -> Self {
+        Self { n, h, w, c }
+    }
+
+    /// Returns the number of elements.
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.n * self.h * self.w * self.c
+    }
+
+    /// Checks whether the shape holds no element.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Placement of a tensor's elements in its buffer.
+///
+/// A row is the innermost contiguous run of elements: `w * c` elements in
+/// [`Layout::Nhwc`] and `w` elements in [`Layout::Nchw`]. Rows start
+/// `row_stride` elements apart, leaving padding the KPU may require.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Geometry {
+    pub shape: Shape,
+    pub layout: Layout,
+    /// Elements from the start of one row to the start of the next.
+    pub row_stride: usize,
+}
+
+impl Geometry {
+    /// Returns the geometry of densely packed rows.
+    #[inline]
+    pub const fn packed(shape: Shape, layout: Layout) -> Self {
+        Self {
+            shape,
+            layout,
+            row_stride: Self::row_len(shape, layout),
+        }
+    }
+
+    /// Returns the geometry with each row padded to a multiple of `align` elements.
+    #[inline]
+    pub const fn aligned(shape: Shape, layout: Layout, align: usize) -> Self {
+        Self {
+            shape,
+            layout,
+            row_stride: Self::row_len(shape, layout).next_multiple_of(align),
+        }
+    }
+
+    const fn row_len(shape: Shape, layout: Layout) -> usize {
+        match layout {
+            Layout::Nhwc => shape.w * shape.c,
+            Layout::Nchw => shape.w,
+        }
+    }
+
+    /// Returns the number of rows.
+    #[inline]
+    pub const fn rows(&self) -> usize {
+        match self.layout {
+            Layout::Nhwc => self.shape.n * self.shape.h,
+            Layout::Nchw => self.shape.n * self.shape.c * self.shape.h,
+        }
+    }
+
+    /// Returns the number of elements a buffer needs to hold the tensor.
+    pub const fn required_len(&self) -> usize {
+        match self.rows() {
+            0 => 0,
+            rows => (rows - 1) * self.row_stride + Self::row_len(self.shape, self.layout),
+        }
+    }
+
+    /// Returns the buffer index of an element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of the shape.
+    pub fn offset(&self, n: usize, h: usize, w: usize, c: usize) -> usize {
+        let s = self.shape;
+        assert!(
+            n < s.n && h < s.h && w < s.w && c < s.c,
+            "index out of shape"
+        );
+        match self.layout {
+            Layout::Nhwc => (n * s.h + h) * self.row_stride + w * s.c + c,
+            Layout::Nchw => ((n * s.c + c) * s.h + h) * self.row_stride + w,
+        }
+    }
+
+    fn check<T>(&self, data: &[T]) -> Result<(), TensorError> {
+        if self.row_stride < Self::row_len(self.shape, self.layout) {
+            return Err(TensorError::InvalidStride);
+        }
+        if data.as_ptr() as usize % KPU_ALIGN != 0 {
+            return Err(TensorError::Misaligned);
+        }
+        if data.len() < self.required_len() {
+            return Err(TensorError::TooShort);
+        }
+        Ok(())
+    }
+}
+
+/// Read-only tensor over a buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct Tensor<'a, T> {
+    data: &'a [T],
+    geometry: Geometry,
+}
+
+impl<'a, T: Copy> Tensor<'a, T> {
+    /// Views `data` as a tensor, checking alignment and length.
+    pub fn new(data: &'a [T], geometry: Geometry) -> Result<Self, TensorError> {
+        geometry.check(data)?;
+        Ok(Self { data, geometry })
+    }
+
+    /// Returns the geometry.
+    #[inline]
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// Returns the underlying buffer, including padding.
+    #[inline]
+    pub fn data(&self) -> &'a [T] {
+        self.data
+    }
+
+    /// Returns an element.
+    #[inline]
+    pub fn get(&self, n: usize, h: usize, w: usize, c: usize) -> T {
+        self.data[self.geometry.offset(n, h, w, c)]
+    }
+}
+
+/// Writable tensor over a buffer.
+#[derive(Debug)]
+pub struct TensorMut<'a, T> {
+    data: &'a mut [T],
+    geometry: Geometry,
+}
+
+impl<'a, T: Copy> TensorMut<'a, T> {
+    /// Views `data` as a tensor, checking alignment and length.
+    pub fn new(data: &'a mut [T], geometry: Geometry) -> Result<Self, TensorError> {
+        geometry.check(data)?;
+        Ok(Self { data, geometry })
+    }
+
+    /// Returns the geometry.
+    #[inline]
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// Returns a read-only view.
+    #[inline]
+    pub fn as_tensor(&self) -> Tensor<'_, T> {
+        Tensor {
+            data: self.data,
+            geometry: self.geometry,
+        }
+    }
+
+    /// Returns an element.
+    #[inline]
+    pub fn get(&self, n: usize, h: usize, w: usize, c: usize) -> T {
+        self.data[self.geometry.offset(n, h, w, c)]
+    }
+
+    /// Sets an element.
+    #[inline]
+    pub fn set(&mut self, n: usize, h: usize, w: usize, c: usize, value: T) {
+        self.data[self.geometry.offset(n, h, w, c)] = value;
+    }
+
+    /// Copies `src` into this tensor, converting layout and stride.
+    pub fn copy_from(&mut self, src: &Tensor<'_, T>) -> Result<(), TensorError> {
+        let shape = self.geometry.shape;
+        if src.geometry.shape != shape {
+            return Err(TensorError::ShapeMismatch);
+        }
+        for_each_index(shape, |n, h, w, c| {
+            self.set(n, h, w, c, src.get(n, h, w, c))
+        });
+        Ok(())
+    }
+}
+
+fn for_each_index(shape: Shape, mut f: impl FnMut(usize, usize, usize, usize)) {
+    for n in 0..shape.n {
+        for h in 0..shape.h {
+            for w in 0..shape.w {
+                for c in 0..shape.c {
+                    f(n, h, w, c);
+                }
+            }
+        }
+    }
+}
+
+/// Affine quantization, `real = scale * (q - zero_point)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QuantParams {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+/// Quantization of a whole tensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quantization<'a> {
+    /// One set of parameters for every element.
+    PerTensor(QuantParams),
+    /// One set of parameters per channel.
+    PerChannel(&'a [QuantParams]),
+}
+
+impl Quantization<'_> {
+    /// Returns the parameters of channel `c`.
+    #[inline]
+    pub fn channel(&self, c: usize) -> QuantParams {
+        match self {
+            Quantization::PerTensor(params) => *params,
+            Quantization::PerChannel(params) => params[c],
+        }
+    }
+
+    fn check(&self, shape: Shape) -> Result<(), TensorError> {
+        match self {
+            Quantization::PerChannel(params) if params.len() != shape.c => {
+                Err(TensorError::ShapeMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Integer element types the KPU quantizes to.
+pub trait Quantized: Copy {
+    /// Widens the element.
+    fn to_i32(self) -> i32;
+
+    /// Narrows a value, saturating.
+    fn saturating_from_i32(value: i32) -> Self;
+}
+
+impl Quantized for i8 {
+    #[inline]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+
+    #[inline]
+    fn saturating_from_i32(value: i32) -> Self {
+        value.clamp(i8::MIN as i32, i8::MAX as i32) as i8
+    }
+}
+
+impl Quantized for u8 {
+    #[inline]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+
+    #[inline]
+    fn saturating_from_i32(value: i32) -> Self {
+        value.clamp(u8::MIN as i32, u8::MAX as i32) as u8
+    }
+}
+
+impl QuantParams {
+    /// Quantizes a real value, rounding to nearest and saturating.
+    #[inline]
+    pub fn quantize<Q: Quantized>(&self, value: f32) -> Q {
+        let q = value / self.scale;
+        // Round half away from zero without `f32::round`, which needs std.
+        let q = (if q >= 0.0 { q + 0.5 } else { q - 0.5 }) as i32;
+        Q::saturating_from_i32(q.saturating_add(self.zero_point))
+    }
+
+    /// Returns the real value of a quantized element.
+    #[inline]
+    pub fn dequantize<Q: Quantized>(&self, q: Q) -> f32 {
+        self.scale * (q.to_i32() - self.zero_point) as f32
+    }
+}
+
+impl<Q: Quantized> Tensor<'_, Q> {
+    /// Returns the real value of an element.
+    #[inline]
+    pub fn dequantize(
+        &self,
+        quant: &Quantization<'_>,
+        n: usize,
+        h: usize,
+        w: usize,
+        c: usize,
+    ) -> f32 {
+        quant.channel(c).dequantize(self.get(n, h, w, c))
+    }
+}
+
+impl<Q: Quantized> TensorMut<'_, Q> {
+    /// Quantizes `src` into this tensor, converting layout and stride.
+    pub fn quantize_from(
+        &mut self,
+        src: &Tensor<'_, f32>,
+        quant: &Quantization<'_>,
+    ) -> Result<(), TensorError> {
+        let shape = self.geometry.shape;
+        if src.geometry.shape != shape {
+            return Err(TensorError::ShapeMismatch);
+        }
+        quant.check(shape)?;
+        for_each_index(shape, |n, h, w, c| {
+            let q = quant.channel(c).quantize(src.get(n, h, w, c));
+            self.set(n, h, w, c, q);
+        });
+        Ok(())
+    }
+}
+
+/// IEEE 754 half precision float, as stored in KPU buffers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(transparent)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct F16(pub u16);
+
+impl F16 {
+    /// Converts from single precision, rounding to nearest even.
+    pub const fn from_f32(value: f32) -> F16 {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exp = ((bits >> 23) & 0xFF) as i32;
+        let man = bits & 0x7F_FFFF;
+        if exp == 0xFF {
+            let nan = if man != 0 { 0x200 } else { 0 };
+            return F16(sign | 0x7C00 | nan);
+        }
+        let exp = exp - 127 + 15;
+        if exp >= 0x1F {
+            return F16(sign | 0x7C00);
+        }
+        if exp <= 0 {
+            if exp < -10 {
+                return F16(sign);
+            }
+            // Subnormal: shift the mantissa with its implicit bit into place.
+            let man = man | 0x80_0000;
+            let shift = (14 - exp) as u32;
+            let rounded = (man + (1 << (shift - 1)) - 1 + ((man >> shift) & 1)) >> shift;
+            return F16(sign | rounded as u16);
+        }
+        // A carry out of the mantissa correctly bumps the exponent.
+        let rounded = (man + 0xFFF + ((man >> 13) & 1)) >> 13;
+        let half = ((exp as u32) << 10) + rounded;
+        if half >= 0x7C00 {
+            return F16(sign | 0x7C00);
+        }
+        F16(sign | half as u16)
+    }
+
+    /// Converts to single precision, exactly.
+    pub const fn to_f32(self) -> f32 {
+        let sign = ((self.0 & 0x8000) as u32) << 16;
+        let exp = ((self.0 >> 10) & 0x1F) as u32;
+        let man = (self.0 & 0x3FF) as u32;
+        let bits = match exp {
+            0 if man == 0 => sign,
+            0 => {
+                // Subnormal: normalize the leading one into the implicit bit.
+                let shift = man.leading_zeros() - 21;
+                let man = (man << shift) & 0x3FF;
+                sign | ((113 - shift) << 23) | (man << 13)
+            }
+            0x1F => sign | 0x7F80_0000 | (man << 13),
+            _ => sign | ((exp + 112) << 23) | (man << 13),
+        };
+        f32::from_bits(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(align(64))]
+    struct Aligned<T>(T);
+
+    #[test]
+    fn geometry_offsets() {
+        let shape = Shape::new(1, 2, 3, 4);
+        let nhwc = Geometry::aligned(shape, Layout::Nhwc, 16);
+        assert_eq!(nhwc.row_stride, 16);
+        assert_eq!(nhwc.offset(0, 1, 2, 3), 16 + 2 * 4 + 3);
+        assert_eq!(nhwc.required_len(), 16 + 12);
+        let nchw = Geometry::packed(shape, Layout::Nchw);
+        assert_eq!(nchw.offset(0, 1, 2, 3), (3 * 2 + 1) * 3 + 2);
+        assert_eq!(nchw.required_len(), shape.len());
+    }
+
+    #[test]
+    fn checks_buffers() {
+        let mut buf = Aligned([0u8; 128]);
+        let geometry = Geometry::packed(Shape::new(1, 4, 4, 3), Layout::Nhwc);
+        assert!(Tensor::new(&buf.0[..48], geometry).is_ok());
+        assert_eq!(
+            Tensor::new(&buf.0[..47], geometry).err(),
+            Some(TensorError::TooShort)
+        );
+        assert_eq!(
+            Tensor::new(&buf.0[1..], geometry).err(),
+            Some(TensorError::Misaligned)
+        );
+        let bad = Geometry {
+            row_stride: 11,
+            ..geometry
+        };
+        assert_eq!(
+            TensorMut::new(&mut buf.0, bad).err(),
+            Some(TensorError::InvalidStride)
+        );
+    }
+
+    #[test]
+    fn layout_conversion() {
+        let shape = Shape::new(1, 2, 2, 3);
+        let mut src = Aligned([0u8; 64]);
+        for (i, v) in src.0[..12].iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        let mut dst = Aligned([0; 64]);
+        let src = Tensor::new(&src.0, Geometry::packed(shape, Layout::Nhwc)).unwrap();
+        let mut dst = TensorMut::new(&mut dst.0, Geometry::packed(shape, Layout::Nchw)).unwrap();
+        dst.copy_from(&src).unwrap();
+        assert_eq!(
+            &dst.as_tensor().data()[..12],
+            &[0, 3, 6, 9, 1, 4, 7, 10, 2, 5, 8, 11]
+        );
+    }
+
+    #[test]
+    fn per_channel_quantization() {
+        let shape = Shape::new(1, 1, 2, 2);
+        let src = Aligned([1.0, -1.0, 300.0, 0.26]);
+        let src = Tensor::new(&src.0, Geometry::packed(shape, Layout::Nhwc)).unwrap();
+        let params = [
+            QuantParams {
+                scale: 0.5,
+                zero_point: 0,
+            },
+            QuantParams {
+                scale: 0.01,
+                zero_point: 10,
+            },
+        ];
+        let quant = Quantization::PerChannel(&params);
+        let mut dst = Aligned([0i8; 4]);
+        let mut dst = TensorMut::new(&mut dst.0, Geometry::packed(shape, Layout::Nhwc)).unwrap();
+        dst.quantize_from(&src, &quant).unwrap();
+        assert_eq!(&dst.as_tensor().data()[..4], &[2, -90, 127, 36]);
+        assert_eq!(dst.as_tensor().dequantize(&quant, 0, 0, 0, 0), 1.0);
+        let short = Quantization::PerChannel(&params[..1]);
+        assert_eq!(
+            dst.quantize_from(&src, &short),
+            Err(TensorError::ShapeMismatch)
+        );
+    }
+
+    #[test]
+    fn f16_round_trip() {
+        for (value, bits) in [
+            (0.0, 0x0000),
+            (1.0, 0x3C00),
+            (-2.5, 0xC100),
+            (65504.0, 0x7BFF),
+            (5.960_464_5e-8, 0x0001),
+            (f32::INFINITY, 0x7C00),
+        ] {
+            assert_eq!(F16::from_f32(value), F16(bits));
+            assert_eq!(F16(bits).to_f32(), value);
+        }
+        assert_eq!(F16::from_f32(1e6), F16(0x7C00));
+        assert_eq!(F16::from_f32(1.0 + 1.0 / 4096.0), F16(0x3C00));
+        assert!(F16::from_f32(f32::NAN).to_f32().is_nan());
+    }
+}
@@ -0,0 +1,146 @@
+//! Fixtures shared by the unit tests.
+
+use crate::timeout::Monotonic;
+use core::cell::Cell;
+use core::fmt;
+use embedded_hal::delay::DelayNs;
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use embedded_time::rate::Hertz;
+
+/// Buffer of `N` bytes collecting formatted text.
+pub(crate) struct Text<const N: usize>([u8; N], usize);
+
+impl<const N: usize> Text<N> {
+    pub(crate) const fn new() -> Self {
+        Self([0; N], 0)
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.0[..self.1]).unwrap()
+    }
+}
+
+impl<const N: usize> fmt::Write for Text<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0[self.1..self.1 + s.len()].copy_from_slice(s.as_bytes());
+        self.1 += s.len();
+        Ok(())
+    }
+}
+
+/// Counter advancing by one tick per read.
+pub(crate) struct Ticks {
+    count: Cell<u64>,
+    hz: u32,
+}
+
+impl Ticks {
+    /// Creates a counter at zero, ticking at `hz`.
+    pub(crate) const fn new(hz: u32) -> Self {
+        Self {
+            count: Cell::new(0),
+            hz,
+        }
+    }
+
+    /// Returns the count without advancing it.
+    pub(crate) fn count(&self) -> u64 {
+        self.count.get()
+    }
+}
+
+impl Monotonic for Ticks {
+    fn now(&self) -> u64 {
+        let now = self.count.get();
+        self.count.set(now + 1);
+        now
+    }
+
+    fn frequency(&self) -> Hertz {
+        Hertz::new(self.hz)
+    }
+}
+
+/// Delay returning at once, adding up the time it was asked to wait.
+pub(crate) struct NoDelay(u64);
+
+impl NoDelay {
+    pub(crate) const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Returns the total time asked for, in nanoseconds.
+    pub(crate) fn waited_ns(&self) -> u64 {
+        self.0
+    }
+}
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0 += ns as u64;
+    }
+}
+
+/// NOR flash in RAM of `SECTORS` erase sectors of `SECTOR` bytes, read a
+/// byte and written 4 bytes at a time.
+pub(crate) struct RamFlash<const SECTOR: usize, const SECTORS: usize>([[u8; SECTOR]; SECTORS]);
+
+impl<const SECTOR: usize, const SECTORS: usize> RamFlash<SECTOR, SECTORS> {
+    /// Creates an erased flash.
+    pub(crate) const fn new() -> Self {
+        Self([[0xFF; SECTOR]; SECTORS])
+    }
+
+    /// Returns the contents of the flash, to corrupt them.
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        self.0.as_flattened_mut()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct FlashError;
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        NorFlashErrorKind::Other
+    }
+}
+
+impl<const SECTOR: usize, const SECTORS: usize> ErrorType for RamFlash<SECTOR, SECTORS> {
+    type Error = FlashError;
+}
+
+impl<const SECTOR: usize, const SECTORS: usize> ReadNorFlash for RamFlash<SECTOR, SECTORS> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.0.as_flattened()[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SECTOR * SECTORS
+    }
+}
+
+impl<const SECTOR: usize, const SECTORS: usize> NorFlash for RamFlash<SECTOR, SECTORS> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), FlashError> {
+        self.bytes_mut()[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        let cells = &mut self.bytes_mut()[offset..offset + bytes.len()];
+        for (cell, byte) in cells.iter_mut().zip(bytes) {
+            *cell &= byte;
+        }
+        Ok(())
+    }
+}
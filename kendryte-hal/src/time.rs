@@ -0,0 +1,229 @@
+//! Points in time since boot.
+//!
+//! An [`Instant`] counts nanoseconds on a 64-bit timeline, so it outlives
+//! any product, compares and subtracts like `std::time::Instant`, and does
+//! not change meaning with the frequency of the counter behind it. Readings
+//! of a [`Monotonic`] counter become instants with [`Instant::now`]:
+//!
+//! ```ignore
+//! let start = Instant::now(&timer);
+//! work();
+//! let took: Duration = Instant::now(&timer) - start;
+//! ```
+//!
+//! Counters narrower than 64 bits are widened with a [`WrapExtender`].
+
+use crate::timeout::Monotonic;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::sync::atomic::{AtomicU64, Ordering};
+pub use core::time::Duration;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A point in time, in nanoseconds since the counter started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Instant {
+    nanos: u64,
+}
+
+impl Instant {
+    /// The instant the counter started.
+    pub const ZERO: Instant = Instant { nanos: 0 };
+
+    /// Returns the current time of `timer`.
+    #[inline]
+    pub fn now<M: Monotonic>(timer: &M) -> Self {
+        Self::from_ticks(timer.now(), timer.frequency().0)
+    }
+
+    /// Converts a counter value at `hz` ticks per second, rounding down.
+    #[inline]
+    pub fn from_ticks(ticks: u64, hz: u32) -> Self {
+        let nanos = ticks as u128 * NANOS_PER_SEC / hz as u128;
+        Self::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    /// Creates an instant `nanos` nanoseconds after the start.
+    #[inline]
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self { nanos }
+    }
+
+    /// Returns the nanoseconds since the start.
+    #[inline]
+    pub const fn as_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// Returns the microseconds since the start, as logging timestamps use.
+    #[inline]
+    pub const fn as_micros(&self) -> u64 {
+        self.nanos / 1_000
+    }
+
+    /// Returns the counter value at `hz` ticks per second, rounding up.
+    #[inline]
+    pub fn to_ticks(&self, hz: u32) -> u64 {
+        let ticks = (self.nanos as u128 * hz as u128).div_ceil(NANOS_PER_SEC);
+        u64::try_from(ticks).unwrap_or(u64::MAX)
+    }
+
+    /// Returns the time since `earlier`, or `None` if it is later than `self`.
+    #[inline]
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.nanos
+            .checked_sub(earlier.nanos)
+            .map(Duration::from_nanos)
+    }
+
+    /// Returns the time since `earlier`, zero if it is later than `self`.
+    #[inline]
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    /// Returns the instant `duration` later, or `None` past the end of the timeline.
+    #[inline]
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.nanos.checked_add(nanos).map(Instant::from_nanos)
+    }
+
+    /// Returns the instant `duration` earlier, or `None` before the start.
+    #[inline]
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.nanos.checked_sub(nanos).map(Instant::from_nanos)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturates at the end of the timeline.
+    #[inline]
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .unwrap_or(Instant::from_nanos(u64::MAX))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    #[inline]
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturates at the start of the timeline.
+    #[inline]
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).unwrap_or(Instant::ZERO)
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    #[inline]
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Saturates at zero if `earlier` is later.
+    #[inline]
+    fn sub(self, earlier: Instant) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+}
+
+/// Widens a wrapping 32-bit hardware counter to 64 bits.
+///
+/// Each reading is placed after the last one seen, counting a wrap whenever
+/// the counter went backwards by more than half its range. Readings must be
+/// extended at least once per half wrap period to catch every wrap; a
+/// reading slightly older than the last one, as from a context preempted
+/// between reading and extending, is placed before it rather than a whole
+/// wrap ahead.
+pub struct WrapExtender {
+    last: AtomicU64,
+}
+
+impl WrapExtender {
+    /// Creates an extender starting at zero.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the 64-bit value of a 32-bit counter reading.
+    pub fn extend(&self, raw: u32) -> u64 {
+        let mut last = self.last.load(Ordering::Acquire);
+        loop {
+            let delta = raw.wrapping_sub(last as u32);
+            if delta > u32::MAX / 2 {
+                // Older than the last reading.
+                return last.wrapping_sub(delta.wrapping_neg() as u64);
+            }
+            let value = last.wrapping_add(delta as u64);
+            match self
+                .last
+                .compare_exchange_weak(last, value, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return value,
+                Err(current) => last = current,
+            }
+        }
+    }
+}
+
+impl Default for WrapExtender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instant_arithmetic() {
+        let t = Instant::from_ticks(27_000_000, 27_000_000);
+        assert_eq!(t, Instant::from_nanos(1_000_000_000));
+        assert_eq!(t.to_ticks(27_000_000), 27_000_000);
+        // One nanosecond past a tick needs the next tick.
+        assert_eq!(Instant::from_nanos(38).to_ticks(27_000_000), 2);
+
+        let later = t + Duration::from_millis(5);
+        assert_eq!(later - t, Duration::from_millis(5));
+        assert_eq!(t - later, Duration::ZERO);
+        assert_eq!(t.checked_duration_since(later), None);
+        assert_eq!(later - Duration::from_secs(10), Instant::ZERO);
+        assert_eq!(t + Duration::MAX, Instant::from_nanos(u64::MAX));
+        assert_eq!(later.as_micros(), 1_005_000);
+    }
+
+    #[test]
+    fn extends_wrapping_counter() {
+        let wide = WrapExtender::new();
+        assert_eq!(wide.extend(10), 10);
+        assert_eq!(wide.extend(0x7000_0000), 0x7000_0000);
+        assert_eq!(wide.extend(0xE000_0000), 0xE000_0000);
+        assert_eq!(wide.extend(0xFFFF_FFF0), 0xFFFF_FFF0);
+        assert_eq!(wide.extend(5), 0x1_0000_0005);
+        // A stale reading from before the wrap stays before it.
+        assert_eq!(wide.extend(0xFFFF_FFF8), 0xFFFF_FFF8);
+        assert_eq!(wide.extend(0x7000_0000), 0x1_7000_0000);
+        assert_eq!(wide.extend(0xE000_0000), 0x1_E000_0000);
+        assert_eq!(wide.extend(0x10), 0x2_0000_0010);
+    }
+}
@@ -0,0 +1,235 @@
+//! Timeouts for polled driver operations.
+//!
+//! [`WithTimeout`] wraps a driver and polls its non-blocking operations
+//! against a deadline measured on a [`Monotonic`] timer, turning a hung bus
+//! into [`TimeoutError::Timeout`] instead of freezing the firmware:
+//!
+//! ```ignore
+//! let mut uart = uart.with_timeout(timer, Duration::from_millis(5));
+//! uart.write_all(b"ping")?;
+//! ```
+
+use crate::time::{Duration, Instant};
+use embedded_hal_nb::nb;
+use embedded_time::rate::Hertz;
+
+/// Free running hardware counter used to measure timeouts.
+pub trait Monotonic {
+    /// Returns the current counter value. The counter is expected not to wrap.
+    fn now(&self) -> u64;
+
+    /// Returns the counter frequency.
+    fn frequency(&self) -> Hertz;
+}
+
+impl<M: Monotonic> Monotonic for &M {
+    #[inline]
+    fn now(&self) -> u64 {
+        (*self).now()
+    }
+
+    #[inline]
+    fn frequency(&self) -> Hertz {
+        (*self).frequency()
+    }
+}
+
+/// Error of an operation run with a timeout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeoutError<E> {
+    /// The operation did not complete in time.
+    Timeout,
+    /// The operation failed.
+    Other(E),
+}
+
+impl<E: embedded_io::Error> embedded_io::Error for TimeoutError<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            TimeoutError::Timeout => embedded_io::ErrorKind::TimedOut,
+            TimeoutError::Other(e) => e.kind(),
+        }
+    }
+}
+
+/// A point in time after which an operation is abandoned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl Deadline {
+    /// Returns the deadline `timeout` from now on `timer`.
+    #[inline]
+    pub fn after<M: Monotonic>(timer: &M, timeout: Duration) -> Self {
+        Self::at(Instant::now(timer) + timeout)
+    }
+
+    /// Returns the deadline at `instant`.
+    #[inline]
+    pub const fn at(instant: Instant) -> Self {
+        Deadline { at: instant }
+    }
+
+    /// Returns when the deadline passes.
+    #[inline]
+    pub fn instant(&self) -> Instant {
+        self.at
+    }
+
+    /// Checks whether the deadline has passed.
+    #[inline]
+    pub fn expired<M: Monotonic>(&self, timer: &M) -> bool {
+        Instant::now(timer) >= self.at
+    }
+}
+
+/// A driver whose operations are bounded by a timeout.
+pub struct WithTimeout<T, M> {
+    inner: T,
+    timer: M,
+    timeout: Duration,
+}
+
+impl<T, M: Monotonic> WithTimeout<T, M> {
+    /// Wraps `inner`, bounding each operation by `timeout` measured on `timer`.
+    #[inline]
+    pub fn new(inner: T, timer: M, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timer,
+            timeout,
+        }
+    }
+
+    /// Sets the timeout of subsequent operations.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Polls `op` until it completes, fails or the timeout expires.
+    pub fn run<R, E>(
+        &mut self,
+        mut op: impl FnMut(&mut T) -> nb::Result<R, E>,
+    ) -> Result<R, TimeoutError<E>> {
+        let deadline = Deadline::after(&self.timer, self.timeout);
+        loop {
+            match op(&mut self.inner) {
+                Ok(value) => return Ok(value),
+                Err(nb::Error::Other(e)) => return Err(TimeoutError::Other(e)),
+                Err(nb::Error::WouldBlock) if deadline.expired(&self.timer) => {
+                    return Err(TimeoutError::Timeout);
+                }
+                Err(nb::Error::WouldBlock) => {}
+            }
+        }
+    }
+
+    /// Returns a reference to the wrapped driver.
+    #[inline]
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Releases the driver and the timer.
+    #[inline]
+    pub fn free(self) -> (T, M) {
+        (self.inner, self.timer)
+    }
+}
+
+/// Extension trait adding [`with_timeout`](TimeoutExt::with_timeout) to drivers.
+pub trait TimeoutExt: Sized {
+    /// Bounds the operations of the driver by `timeout` measured on `timer`.
+    #[inline]
+    fn with_timeout<M: Monotonic>(self, timer: M, timeout: Duration) -> WithTimeout<Self, M> {
+        WithTimeout::new(self, timer, timeout)
+    }
+}
+
+impl<T> TimeoutExt for T {}
+
+impl<T, M> embedded_io::ErrorType for WithTimeout<T, M>
+where
+    T: embedded_hal_nb::serial::ErrorType,
+    T::Error: embedded_io::Error,
+{
+    type Error = TimeoutError<T::Error>;
+}
+
+impl<T, M> embedded_io::Read for WithTimeout<T, M>
+where
+    T: embedded_hal_nb::serial::Read<u8>,
+    T::Error: embedded_io::Error,
+    M: Monotonic,
+{
+    /// Reads at least one byte, waiting up to the timeout for the first.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.run(|inner| inner.read())?;
+        let mut n = 1;
+        while n < buf.len() {
+            match self.inner.read() {
+                Ok(byte) => buf[n] = byte,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(TimeoutError::Other(e)),
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<T, M> embedded_io::Write for WithTimeout<T, M>
+where
+    T: embedded_hal_nb::serial::Write<u8>,
+    T::Error: embedded_io::Error,
+    M: Monotonic,
+{
+    /// Writes one byte, waiting up to the timeout for room in the transmitter.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        match buf.first() {
+            Some(&byte) => self.run(|inner| inner.write(byte)).map(|()| 1),
+            None => Ok(0),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.run(|inner| inner.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Ticks;
+
+    #[test]
+    fn times_out() {
+        let mut driver = ().with_timeout(Ticks::new(1_000), Duration::from_millis(5));
+        let result: Result<(), TimeoutError<()>> = driver.run(|_| Err(nb::Error::WouldBlock));
+        assert_eq!(result, Err(TimeoutError::Timeout));
+        let (_, timer) = driver.free();
+        assert!((6..=8).contains(&timer.count()));
+    }
+
+    #[test]
+    fn completes_before_deadline() {
+        let mut polls = 0;
+        let mut driver = ().with_timeout(Ticks::new(1_000), Duration::from_millis(5));
+        let result: Result<u8, TimeoutError<()>> = driver.run(|_| {
+            polls += 1;
+            match polls {
+                3 => Ok(42),
+                _ => Err(nb::Error::WouldBlock),
+            }
+        });
+        assert_eq!(result, Ok(42));
+        let result: Result<u8, TimeoutError<u8>> = driver.run(|_| Err(nb::Error::Other(7)));
+        assert_eq!(result, Err(TimeoutError::Other(7)));
+    }
+}
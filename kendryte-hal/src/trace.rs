@@ -0,0 +1,415 @@
+//! Timestamped event tracing for interrupt latency and jitter.
+//!
+//! Handlers record events into a [`Trace`] buffer, which a low-priority
+//! task drains to the host, where `cargo xtask trace` decodes them and
+//! reports handler durations and the jitter between activations:
+//!
+//! ```ignore
+//! static TRACE: Trace<512> = Trace::new(kendryte_rt::arch::timer::now);
+//!
+//! fn i2s_handler() {
+//!     TRACE.irq_enter(I2S_IRQ);
+//!     refill();
+//!     TRACE.irq_exit(I2S_IRQ);
+//! }
+//!
+//! // In the idle loop.
+//! TRACE.drain(|event| uart.write_all(&event.to_bytes()).unwrap());
+//! ```
+//!
+//! Recording is a compare-and-swap and an atomic store, safe from any
+//! handler and from the other hart. While the buffer is full new events are
+//! dropped and counted rather than overwriting undrained ones.
+//!
+//! Each event is a little endian u64: the [`Kind`] in bits 63 to 60, the ID
+//! in bits 59 to 48 and the low 48 bits of the timer count below, which
+//! wrap after about 120 days at 27 MHz.
+//!
+//! # Stream format
+//!
+//! [`Trace::flush`] sends events over a byte link, such as a spare UART,
+//! that may lose bytes. The stream starts with a header, sent again by
+//! [`write_header`] whenever the host may have attached:
+//!
+//! | Offset | Length | Field |
+//! |---|---|---|
+//! | 0 | 4 | Magic `KTRC` |
+//! | 4 | 4 | Timer frequency in Hz, u32 LE |
+//!
+//! followed by one frame per event: [`SYNC`], the 8 event bytes and their
+//! XOR, so the host can find the next frame after a lost byte.
+//!
+//! Schedulers report context switches with [`Trace::task_begin`] and
+//! [`Trace::task_end`] around each task poll or run, and [`Trace::idle`]
+//! before waiting for interrupts.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use embedded_io::Write;
+
+/// Magic bytes starting a trace stream.
+pub const MAGIC: &[u8; 4] = b"KTRC";
+/// Byte starting every event frame of a trace stream.
+pub const SYNC: u8 = 0xA5;
+
+/// Largest event ID; larger IDs are truncated to 12 bits.
+pub const MAX_ID: u16 = 0xFFF;
+
+const TIMESTAMP_BITS: u32 = 48;
+const ID_BITS: u32 = 12;
+
+/// What an event records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Kind {
+    /// An interrupt handler started; the ID is the interrupt number.
+    IrqEnter = 1,
+    /// An interrupt handler returned; the ID is the interrupt number.
+    IrqExit = 2,
+    /// A DMA transfer completed; the ID is the channel.
+    DmaDone = 3,
+    /// A user marker.
+    Marker = 4,
+    /// A task started running; the ID is the task.
+    TaskBegin = 5,
+    /// A task stopped running; the ID is the task.
+    TaskEnd = 6,
+    /// The hart went idle, waiting for interrupts.
+    Idle = 7,
+    /// Events were dropped while the buffer was full; the ID is how many,
+    /// at most [`MAX_ID`].
+    Overflow = 8,
+}
+
+impl Kind {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            1 => Some(Kind::IrqEnter),
+            2 => Some(Kind::IrqExit),
+            3 => Some(Kind::DmaDone),
+            4 => Some(Kind::Marker),
+            5 => Some(Kind::TaskBegin),
+            6 => Some(Kind::TaskEnd),
+            7 => Some(Kind::Idle),
+            8 => Some(Kind::Overflow),
+            _ => None,
+        }
+    }
+}
+
+/// A recorded event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Event {
+    /// What happened.
+    pub kind: Kind,
+    /// Interrupt number, DMA channel or marker ID, at most [`MAX_ID`].
+    pub id: u16,
+    /// Timer count, truncated to 48 bits.
+    pub timestamp: u64,
+}
+
+impl Event {
+    /// Packs the event into its u64 encoding, never zero.
+    #[inline]
+    pub fn to_bits(&self) -> u64 {
+        ((self.kind as u64) << (TIMESTAMP_BITS + ID_BITS))
+            | (((self.id & MAX_ID) as u64) << TIMESTAMP_BITS)
+            | (self.timestamp & ((1 << TIMESTAMP_BITS) - 1))
+    }
+
+    /// Unpacks an event, or `None` for an unknown kind.
+    #[inline]
+    pub fn from_bits(bits: u64) -> Option<Self> {
+        Some(Event {
+            kind: Kind::from_bits((bits >> (TIMESTAMP_BITS + ID_BITS)) as u8)?,
+            id: (bits >> TIMESTAMP_BITS) as u16 & MAX_ID,
+            timestamp: bits & ((1 << TIMESTAMP_BITS) - 1),
+        })
+    }
+
+    /// Returns the encoding as sent to the host.
+    #[inline]
+    pub fn to_bytes(&self) -> [u8; 8] {
+        self.to_bits().to_le_bytes()
+    }
+
+    /// Returns the event framed for a trace stream.
+    pub fn to_frame(&self) -> [u8; 10] {
+        let mut frame = [0; 10];
+        frame[0] = SYNC;
+        frame[1..9].copy_from_slice(&self.to_bytes());
+        frame[9] = frame[1..9].iter().fold(0, |check, byte| check ^ byte);
+        frame
+    }
+}
+
+/// Writes the header of a trace stream stamped at `hz`.
+pub fn write_header<W: Write>(out: &mut W, hz: u32) -> Result<(), W::Error> {
+    out.write_all(MAGIC)?;
+    out.write_all(&hz.to_le_bytes())
+}
+
+/// Lock-free buffer of up to `N` events.
+///
+/// Any number of contexts may record; one drains.
+pub struct Trace<const N: usize> {
+    /// Encoded events; zero marks a slot claimed but not yet written.
+    slots: [AtomicU64; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicUsize,
+    now: fn() -> u64,
+}
+
+impl<const N: usize> Trace<N> {
+    /// Creates an empty buffer timestamping events with `now`.
+    pub const fn new(now: fn() -> u64) -> Self {
+        Self {
+            slots: [const { AtomicU64::new(0) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            now,
+        }
+    }
+
+    /// Records an event of `kind` with `id`, timestamped now.
+    pub fn record(&self, kind: Kind, id: u16) {
+        let event = Event {
+            kind,
+            id,
+            timestamp: (self.now)(),
+        };
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            if head.wrapping_sub(self.tail.load(Ordering::Acquire)) >= N {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(1),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        self.slots[head % N].store(event.to_bits(), Ordering::Release);
+    }
+
+    /// Records the start of the handler of interrupt `irq`.
+    #[inline]
+    pub fn irq_enter(&self, irq: u16) {
+        self.record(Kind::IrqEnter, irq);
+    }
+
+    /// Records the end of the handler of interrupt `irq`.
+    #[inline]
+    pub fn irq_exit(&self, irq: u16) {
+        self.record(Kind::IrqExit, irq);
+    }
+
+    /// Records the completion of a transfer on DMA `channel`.
+    #[inline]
+    pub fn dma_done(&self, channel: u16) {
+        self.record(Kind::DmaDone, channel);
+    }
+
+    /// Records user marker `id`.
+    #[inline]
+    pub fn marker(&self, id: u16) {
+        self.record(Kind::Marker, id);
+    }
+
+    /// Records that task `task` started running.
+    #[inline]
+    pub fn task_begin(&self, task: u16) {
+        self.record(Kind::TaskBegin, task);
+    }
+
+    /// Records that task `task` stopped running.
+    #[inline]
+    pub fn task_end(&self, task: u16) {
+        self.record(Kind::TaskEnd, task);
+    }
+
+    /// Records that the hart went idle.
+    #[inline]
+    pub fn idle(&self) {
+        self.record(Kind::Idle, 0);
+    }
+
+    /// Passes the recorded events to `f` in order and returns how many.
+    ///
+    /// Stops early at an event still being written; it is passed on the
+    /// next call. Must not be called from two contexts at once.
+    pub fn drain(&self, mut f: impl FnMut(Event)) -> usize {
+        let mut count = 0;
+        loop {
+            let tail = self.tail.load(Ordering::Relaxed);
+            if tail == self.head.load(Ordering::Acquire) {
+                break;
+            }
+            let bits = self.slots[tail % N].swap(0, Ordering::Acquire);
+            if bits == 0 {
+                break;
+            }
+            self.tail.store(tail.wrapping_add(1), Ordering::Release);
+            if let Some(event) = Event::from_bits(bits) {
+                f(event);
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the number of events dropped while the buffer was full, and resets it.
+    #[inline]
+    pub fn take_dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    /// Drains the recorded events to `out` as stream frames.
+    ///
+    /// Events dropped since the last flush are reported first as an
+    /// [`Kind::Overflow`] event. Returns the number of frames written; on a
+    /// write error the rest of the drained events are lost.
+    pub fn flush<W: Write>(&self, out: &mut W) -> Result<usize, W::Error> {
+        let mut written = 0;
+        let dropped = self.take_dropped();
+        if dropped > 0 {
+            let overflow = Event {
+                kind: Kind::Overflow,
+                id: dropped.min(MAX_ID as usize) as u16,
+                timestamp: (self.now)(),
+            };
+            out.write_all(&overflow.to_frame())?;
+            written += 1;
+        }
+        let mut result = Ok(());
+        self.drain(|event| {
+            if result.is_ok() {
+                result = out.write_all(&event.to_frame());
+                written += 1;
+            }
+        });
+        result.map(|()| written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+    fn tick() -> u64 {
+        CLOCK.fetch_add(10, Ordering::Relaxed)
+    }
+
+    #[test]
+    fn record_and_drain() {
+        let trace: Trace<4> = Trace::new(tick);
+        trace.irq_enter(33);
+        trace.irq_exit(33);
+        trace.dma_done(2);
+        trace.marker(MAX_ID + 2);
+        trace.marker(7);
+        assert_eq!(trace.take_dropped(), 1);
+
+        let mut events = [None; 4];
+        let mut i = 0;
+        assert_eq!(
+            trace.drain(|event| {
+                events[i] = Some(event);
+                i += 1;
+            }),
+            4
+        );
+        let event = |kind, id, timestamp| {
+            Some(Event {
+                kind,
+                id,
+                timestamp,
+            })
+        };
+        assert_eq!(
+            events,
+            [
+                event(Kind::IrqEnter, 33, 0),
+                event(Kind::IrqExit, 33, 10),
+                event(Kind::DmaDone, 2, 20),
+                event(Kind::Marker, 1, 30),
+            ]
+        );
+
+        // Slots are reused once drained.
+        trace.marker(9);
+        assert_eq!(trace.drain(|event| assert_eq!(event.id, 9)), 1);
+        assert_eq!(trace.drain(|_| unreachable!()), 0);
+    }
+
+    #[test]
+    fn stream() {
+        struct Sink([u8; 64], usize);
+
+        #[derive(Debug)]
+        struct Full;
+
+        impl embedded_io::Error for Full {
+            fn kind(&self) -> embedded_io::ErrorKind {
+                embedded_io::ErrorKind::Other
+            }
+        }
+
+        impl embedded_io::ErrorType for Sink {
+            type Error = Full;
+        }
+
+        impl Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+                self.0[self.1..self.1 + buf.len()].copy_from_slice(buf);
+                self.1 += buf.len();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        fn zero() -> u64 {
+            0
+        }
+
+        let trace: Trace<2> = Trace::new(zero);
+        trace.task_begin(3);
+        trace.idle();
+        trace.marker(1);
+        let mut sink = Sink([0; 64], 0);
+        write_header(&mut sink, 27_000_000).unwrap();
+        assert_eq!(trace.flush(&mut sink).ok(), Some(3));
+        assert_eq!(&sink.0[..8], b"KTRC\xC0\xFC\x9B\x01");
+        // Overflow of one event, then the two recorded.
+        assert_eq!(&sink.0[8..18], &[SYNC, 0, 0, 0, 0, 0, 0, 0x01, 0x80, 0x81]);
+        assert_eq!(&sink.0[18..28], &[SYNC, 0, 0, 0, 0, 0, 0, 0x03, 0x50, 0x53]);
+        assert_eq!(&sink.0[28..38], &[SYNC, 0, 0, 0, 0, 0, 0, 0, 0x70, 0x70]);
+        assert_eq!(sink.1, 38);
+    }
+
+    #[test]
+    fn encoding() {
+        let event = Event {
+            kind: Kind::IrqExit,
+            id: 0xABC,
+            timestamp: 0x1234_5678_9ABC,
+        };
+        assert_eq!(event.to_bits(), 0x2ABC_1234_5678_9ABC);
+        assert_eq!(Event::from_bits(event.to_bits()), Some(event));
+        assert_eq!(Event::from_bits(0xF000_0000_0000_0000), None);
+        assert_eq!(event.to_bytes()[7], 0x2A);
+    }
+}
@@ -6,40 +6,174 @@ pub use tx::BlockingUartTx;
 
 use super::pad::FlexPad;
 use crate::clocks::Clocks;
+use crate::coexist::{self, Resource};
+use crate::error::Error;
 use crate::instance::Numbered;
-use crate::uart::RegisterBlock;
+use crate::selftest::{self, Outcome};
 use crate::uart::config::{Config, set_divisor, set_parity_mode, set_stop_bits, set_word_length};
-use crate::uart::config::{disable_fifo, enable_fifo};
+use crate::uart::config::{disable_fifo, enable_fifo, set_loopback, set_nine_bit_mode};
 use crate::uart::error::UartError;
 use crate::uart::pad::{IntoUartSin, IntoUartSout};
+use crate::uart::{IerDlh, Lsr, RbrThrDll, RegisterBlock};
+use arbitrary_int::u9;
 use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU8, Ordering};
+use embedded_hal_nb::nb;
 
-/// Checks if the UART is ready to read data.
-pub(crate) fn read_ready(uart: &RegisterBlock) -> bool {
-    uart.lsr.read().data_ready()
+/// Line errors that a read can report, by latch code minus one.
+const LINE_ERRORS: [UartError; 4] = [
+    UartError::Overrun,
+    UartError::Break,
+    UartError::Framing,
+    UartError::Parity,
+];
+
+/// Line error of a UART seen by either half and not yet reported by a read.
+///
+/// Reading the line status register clears its error flags, and both
+/// halves poll it, so whichever half reads an error keeps it here for the
+/// receiver.
+pub(crate) struct ErrorLatch(AtomicU8);
+
+/// Error latches of UART0 to UART4.
+static ERROR_LATCHES: [ErrorLatch; 5] = [const { ErrorLatch(AtomicU8::new(0)) }; 5];
+
+impl ErrorLatch {
+    /// Returns the latch of UART `N`, cleared.
+    pub(crate) fn of<const N: usize>() -> &'static Self {
+        let latch = &ERROR_LATCHES[N];
+        latch.take();
+        latch
+    }
+
+    /// Keeps `error`, unless an earlier error is still unreported.
+    pub(crate) fn set(&self, error: UartError) {
+        if let Some(index) = LINE_ERRORS.iter().position(|e| *e == error) {
+            let code = index as u8 + 1;
+            let _ = self
+                .0
+                .compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns and clears the kept error.
+    pub(crate) fn take(&self) -> Option<UartError> {
+        match self.0.swap(0, Ordering::Relaxed) {
+            0 => None,
+            code => Some(LINE_ERRORS[code as usize - 1]),
+        }
+    }
+
+    /// Checks if an error is kept.
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// Reads the line status register once, keeping its line error in `latch`.
+///
+/// The character received with the error is discarded, so it is not read
+/// as valid data after the error is reported.
+pub(crate) fn line_status(uart: &RegisterBlock, latch: &ErrorLatch) -> Lsr {
+    let lsr = uart.lsr.read();
+    if let Some(error) = line_status_error(&lsr) {
+        if lsr.data_ready() {
+            let _ = uart.rbr_thr_dll.read();
+        }
+        latch.set(error);
+    }
+    lsr
+}
+
+/// Checks if the UART has a character or a line error to read.
+pub(crate) fn read_ready(uart: &RegisterBlock, latch: &ErrorLatch) -> bool {
+    let lsr = line_status(uart, latch);
+    latch.is_set() || lsr.data_ready()
 }
 
 /// Checks if the UART is ready to write data.
-pub(crate) fn write_ready(uart: &RegisterBlock) -> bool {
-    uart.lsr.read().transmitter_empty() || uart.lsr.read().transmitter_holding_empty()
+pub(crate) fn write_ready(uart: &RegisterBlock, latch: &ErrorLatch) -> bool {
+    let lsr = line_status(uart, latch);
+    lsr.transmitter_empty() || lsr.transmitter_holding_empty()
+}
+
+/// Checks if the transmitter has sent all data.
+pub(crate) fn transmitter_empty(uart: &RegisterBlock, latch: &ErrorLatch) -> bool {
+    line_status(uart, latch).transmitter_empty()
+}
+
+/// Returns the error reported by a line status register value, if any.
+///
+/// Reading the line status register clears these flags, so callers must
+/// evaluate a single read for both the error and the data ready state.
+pub(crate) fn line_status_error(lsr: &Lsr) -> Option<UartError> {
+    if lsr.overrun_error() {
+        Some(UartError::Overrun)
+    } else if lsr.break_interrupt() {
+        Some(UartError::Break)
+    } else if lsr.framing_error() {
+        Some(UartError::Framing)
+    } else if lsr.parity_error() {
+        Some(UartError::Parity)
+    } else {
+        None
+    }
+}
+
+/// Updates the interrupt enable register with interrupts masked.
+///
+/// The halves of a split UART each own some of its bits, and may change them
+/// from different tasks or handlers without undoing each other's changes.
+pub(crate) fn modify_interrupts(uart: &RegisterBlock, f: impl FnOnce(IerDlh) -> IerDlh) {
+    crate::sync::free(|| unsafe { uart.ier_dlh.modify(f) });
+}
+
+/// Reads a single 9-bit character from UART without blocking.
+///
+/// A line error kept in `latch` is returned before any further character.
+pub(crate) fn read_word(uart: &RegisterBlock, latch: &ErrorLatch) -> nb::Result<u9, UartError> {
+    let lsr = line_status(uart, latch);
+    if let Some(error) = latch.take() {
+        return Err(nb::Error::Other(error));
+    }
+    match lsr.data_ready() {
+        true => Ok(uart.rbr_thr_dll.read().receiver_buffer_9bits()),
+        false => Err(nb::Error::WouldBlock),
+    }
 }
 
 /// Reads data from UART in a blocking manner.
 ///
 /// This function attempts to read data from the UART into the provided buffer.
 /// It will read as much data as possible until either the buffer is full or no more data is available.
-/// Returns the number of bytes actually read.
-pub(crate) fn blocking_read(uart: &RegisterBlock, buf: &mut [u8]) -> usize {
+/// Returns the number of bytes actually read, and the line error that stopped the read, if any.
+pub(crate) fn blocking_read(
+    uart: &RegisterBlock,
+    latch: &ErrorLatch,
+    buf: &mut [u8],
+) -> (usize, Option<UartError>) {
     let mut count = 0_usize;
     for ch in buf {
-        if read_ready(uart) {
-            *ch = uart.rbr_thr_dll.read().receiver_buffer();
-            count += 1;
-        } else {
-            break;
+        match read_word(uart, latch) {
+            Ok(word) => {
+                *ch = word.value() as u8;
+                count += 1;
+            }
+            Err(nb::Error::WouldBlock) => break,
+            Err(nb::Error::Other(error)) => return (count, Some(error)),
         }
     }
-    count
+    (count, None)
+}
+
+/// Writes a single 9-bit character to the transmit holding register.
+///
+/// The register is written without reading it first, since a read would pop the receive buffer.
+pub(crate) fn write_word(uart: &RegisterBlock, word: u9) {
+    unsafe {
+        uart.rbr_thr_dll
+            .write(RbrThrDll::new_with_raw_value(0).with_transmitter_holding_9bits(word));
+    }
 }
 
 /// Writes data to UART in a blocking manner.
@@ -47,13 +181,11 @@ pub(crate) fn blocking_read(uart: &RegisterBlock, buf: &mut [u8]) -> usize {
 /// This function attempts to write data from the provided buffer to the UART.
 /// It will write as much data as possible until either all data is written or the FIFO becomes full.
 /// Returns the number of bytes actually written.
-pub(crate) fn blocking_write(uart: &RegisterBlock, buf: &[u8]) -> usize {
+pub(crate) fn blocking_write(uart: &RegisterBlock, latch: &ErrorLatch, buf: &[u8]) -> usize {
     let mut count = 0_usize;
     for ch in buf {
-        if write_ready(uart) {
-            unsafe {
-                uart.rbr_thr_dll.modify(|r| r.with_transmitter_holding(*ch));
-            }
+        if write_ready(uart, latch) {
+            write_word(uart, u9::new(*ch as u16));
             count += 1;
         } else {
             break;
@@ -65,8 +197,8 @@ pub(crate) fn blocking_write(uart: &RegisterBlock, buf: &[u8]) -> usize {
 /// Flushes the UART transmitter by waiting until all data has been sent.
 ///
 /// This function blocks until the transmitter is completely empty.
-pub(crate) fn blocking_flush(uart: &RegisterBlock) {
-    while !uart.lsr.read().transmitter_empty() {
+pub(crate) fn blocking_flush(uart: &RegisterBlock, latch: &ErrorLatch) {
+    while !transmitter_empty(uart, latch) {
         core::hint::spin_loop();
     }
 }
@@ -76,6 +208,7 @@ pub(crate) fn blocking_flush(uart: &RegisterBlock) {
 /// This struct implements blocking read and write operations for UART communication.
 pub struct BlockingUart<'i, 't, 'r> {
     inner: &'static RegisterBlock,
+    errors: &'static ErrorLatch,
     tx: Option<BlockingUartTx<'i, 't>>,
     rx: Option<BlockingUartRx<'i, 'r>>,
     _marker: PhantomData<&'i ()>,
@@ -85,16 +218,21 @@ impl<'i, 't, 'r> BlockingUart<'i, 't, 'r> {
     /// Creates a new BlockingUart instance with the specified configuration.
     ///
     /// This function initializes the UART with the provided configuration parameters.
-    /// Returns a new BlockingUart instance.
+    /// Returns a new BlockingUart instance, or an error, without touching the
+    /// UART, if Linux owns the UART, see [`coexist`](crate::coexist), or the
+    /// baud rate cannot be generated, see [`Config::build`].
     pub fn new<const N: usize>(
         instance: impl Numbered<'i, N, R = RegisterBlock>,
         tx: Option<impl IntoUartSout<'t, N>>,
         rx: Option<impl IntoUartSin<'r, N>>,
         config: Config,
         clocks: Clocks,
-    ) -> Self {
+    ) -> Result<Self, Error> {
+        coexist::claim(Resource::Uart(N as u8))?;
+        let divisor = config.divisor(clocks.uart_sclk::<N>())?;
         let inner = instance.inner();
-        Self::configure::<N>(inner, config, clocks);
+        Self::configure(inner, config, divisor);
+        let errors = ErrorLatch::of::<N>();
 
         let mut blocking_uart_tx = None;
         let mut blocking_uart_rx = None;
@@ -103,6 +241,7 @@ impl<'i, 't, 'r> BlockingUart<'i, 't, 'r> {
             let tx = tx.into_uart_sout();
             blocking_uart_tx = Some(BlockingUartTx {
                 inner,
+                errors,
                 tx,
                 _marker: PhantomData,
             });
@@ -112,23 +251,25 @@ impl<'i, 't, 'r> BlockingUart<'i, 't, 'r> {
             let rx = rx.into_uart_sin();
             blocking_uart_rx = Some(BlockingUartRx {
                 inner,
+                errors,
                 rx,
                 _marker: PhantomData,
             })
         }
 
-        BlockingUart {
+        Ok(BlockingUart {
             inner,
+            errors,
             tx: blocking_uart_tx,
             rx: blocking_uart_rx,
             _marker: PhantomData,
-        }
+        })
     }
 
     /// Configures the UART peripheral with the specified settings.
     /// Disables all UART interrupts first.
-    /// Sets the baud rate, parity, stop bits, word length, and FIFO mode.
-    fn configure<const N: usize>(uart: &'static RegisterBlock, config: Config, clocks: Clocks) {
+    /// Sets the baud rate divisor, parity, stop bits, word length, FIFO mode, 9-bit mode and loopback.
+    fn configure(uart: &'static RegisterBlock, config: Config, divisor: u16) {
         unsafe {
             uart.ier_dlh.modify(|r| {
                 r.with_modem_status_interrupt_enable(false)
@@ -139,20 +280,65 @@ impl<'i, 't, 'r> BlockingUart<'i, 't, 'r> {
             });
         }
 
-        let divisor = clocks.uart_sclk::<N>().0 / (16_u32 * config.baud.0);
-        set_divisor(uart, divisor as u16);
+        set_divisor(uart, divisor);
         set_parity_mode(uart, config.parity_mode);
         set_stop_bits(uart, config.stop_bits);
         set_word_length(uart, config.word_length);
 
+        set_nine_bit_mode(uart, config.nine_bit_mode);
+        set_loopback(uart, config.loopback);
+
         match config.fifo {
             true => enable_fifo(uart),
             false => disable_fifo(uart),
         }
     }
 
+    /// Checks the transmitter and receiver through the internal loopback.
+    ///
+    /// Sends the self-test pattern with the transmitter looped back to the
+    /// receiver, so no jumper is needed and nothing appears on the pads,
+    /// and expects it back. Data waiting in the receiver is discarded, and
+    /// the loopback setting of the configuration is restored afterwards.
+    pub fn self_test(&mut self) -> Outcome {
+        /// Polls of the line status per character, enough for 9600 baud.
+        const POLLS: u32 = 1 << 20;
+
+        let uart = self.inner;
+        let mcr = uart.mcr.read();
+        set_loopback(uart, true);
+        while !matches!(read_word(uart, self.errors), Err(nb::Error::WouldBlock)) {}
+        let mut echo = [0; selftest::PATTERN.len()];
+        let mut outcome = None;
+        for (i, byte) in selftest::PATTERN.iter().enumerate() {
+            write_word(uart, u9::new(*byte as u16));
+            let mut polls = 0;
+            let received = loop {
+                match read_word(uart, self.errors) {
+                    Ok(word) => break Ok(word.value() as u8),
+                    Err(nb::Error::WouldBlock) if polls < POLLS => polls += 1,
+                    Err(nb::Error::WouldBlock) => break Err("no echo of byte"),
+                    Err(nb::Error::Other(_)) => break Err("line error on byte"),
+                }
+            };
+            match received {
+                Ok(byte) => echo[i] = byte,
+                Err(reason) => {
+                    outcome = Some(Outcome::fail_at(reason, i));
+                    break;
+                }
+            }
+        }
+        unsafe { uart.mcr.write(mcr) };
+        outcome.unwrap_or_else(|| selftest::check_echo(&echo))
+    }
+
     /// Splits the BlockingUart into separate transmitter and receiver handles.
     /// Returns ownership of the transmitter and receiver, if available.
+    ///
+    /// Each half owns its own interrupts, so one task can transmit while
+    /// another receives, each woken by its own interrupt, without sharing
+    /// the UART behind a lock.
     pub fn split(
         self,
     ) -> (
@@ -175,16 +361,16 @@ impl<'i, 't, 'r> embedded_io::Read for BlockingUart<'i, 't, 'r> {
 
 impl<'i, 't, 'r> embedded_io::Write for BlockingUart<'i, 't, 'r> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        self.tx.as_mut().ok_or(UartError::NotFoundRx)?.write(buf)
+        self.tx.as_mut().ok_or(UartError::NotFoundTx)?.write(buf)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        self.tx.as_mut().ok_or(UartError::NotFoundRx)?.flush()
+        self.tx.as_mut().ok_or(UartError::NotFoundTx)?.flush()
     }
     fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
         self.tx
             .as_mut()
-            .ok_or(UartError::NotFoundRx)?
+            .ok_or(UartError::NotFoundTx)?
             .write_all(buf)
     }
 }
@@ -197,7 +383,7 @@ impl<'i, 't, 'r> embedded_io::ReadReady for BlockingUart<'i, 't, 'r> {
 
 impl<'i, 't, 'r> embedded_io::WriteReady for BlockingUart<'i, 't, 'r> {
     fn write_ready(&mut self) -> Result<bool, Self::Error> {
-        self.tx.as_mut().ok_or(UartError::NotFoundRx)?.write_ready()
+        self.tx.as_mut().ok_or(UartError::NotFoundTx)?.write_ready()
     }
 }
 
@@ -213,10 +399,56 @@ impl<'i, 't, 'r> embedded_hal_nb::serial::Read for BlockingUart<'i, 't, 'r> {
 
 impl<'i, 't, 'r> embedded_hal_nb::serial::Write for BlockingUart<'i, 't, 'r> {
     fn write(&mut self, word: u8) -> embedded_hal_nb::nb::Result<(), Self::Error> {
-        self.tx.as_mut().ok_or(UartError::NotFoundRx)?.write(word)
+        self.tx.as_mut().ok_or(UartError::NotFoundTx)?.write(word)
     }
 
     fn flush(&mut self) -> embedded_hal_nb::nb::Result<(), Self::Error> {
-        self.tx.as_mut().ok_or(UartError::NotFoundRx)?.flush()
+        self.tx.as_mut().ok_or(UartError::NotFoundTx)?.flush()
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 't, 'r> embedded_hal_02::serial::Read<u8> for BlockingUart<'i, 't, 'r> {
+    type Error = UartError;
+
+    fn read(&mut self) -> embedded_hal_nb::nb::Result<u8, Self::Error> {
+        embedded_hal_nb::serial::Read::read(self)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 't, 'r> embedded_hal_02::serial::Write<u8> for BlockingUart<'i, 't, 'r> {
+    type Error = UartError;
+
+    fn write(&mut self, word: u8) -> embedded_hal_nb::nb::Result<(), Self::Error> {
+        embedded_hal_nb::serial::Write::write(self, word)
+    }
+
+    fn flush(&mut self) -> embedded_hal_nb::nb::Result<(), Self::Error> {
+        embedded_hal_nb::serial::Write::flush(self)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 't, 'r> embedded_hal_02::blocking::serial::write::Default<u8>
+    for BlockingUart<'i, 't, 'r>
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latch_keeps_first_error_until_taken() {
+        let latch = ErrorLatch::of::<0>();
+        assert!(!latch.is_set());
+        latch.set(UartError::Parity);
+        latch.set(UartError::Overrun);
+        assert!(latch.is_set());
+        assert_eq!(latch.take(), Some(UartError::Parity));
+        assert_eq!(latch.take(), None);
+        latch.set(UartError::Break);
+        assert!(ErrorLatch::of::<0>().take().is_none());
     }
 }
@@ -1,35 +1,59 @@
 use crate::iomux::FlexPad;
-use crate::uart::blocking::{blocking_read, read_ready};
+use crate::uart::blocking::{ErrorLatch, blocking_read, modify_interrupts, read_ready, read_word};
 use crate::uart::{RegisterBlock, UartError};
+use arbitrary_int::u9;
 use core::marker::PhantomData;
+use embedded_hal_nb::nb;
 
 /// A UART receiver for blocking operations.
 /// This struct implements blocking read operations for UART communication.
 pub struct BlockingUartRx<'i, 'r> {
     /// Holds a reference to the UART register block.
     pub(crate) inner: &'static RegisterBlock,
+    /// Line error seen by either half, reported by the next read.
+    pub(crate) errors: &'static ErrorLatch,
     /// Contains a mutable handle to the RX pad.
     pub(crate) rx: FlexPad<'r>,
     /// Uses PhantomData for lifetime tracking.
     pub(crate) _marker: PhantomData<&'i ()>,
 }
 
+impl<'i, 'r> BlockingUartRx<'i, 'r> {
+    /// Enables or disables the received data and line status interrupts.
+    ///
+    /// A handler reads until the receiver is empty, which also reports the
+    /// line errors that raised the interrupt.
+    pub fn set_interrupt(&mut self, enable: bool) {
+        modify_interrupts(self.inner, |r| {
+            r.with_receive_data_available_interrupt_enable(enable)
+                .with_receive_line_status_interrupt_enable(enable)
+        });
+    }
+
+    /// Reads a single 9-bit character.
+    ///
+    /// In 9-bit mode the ninth bit is set for address characters.
+    pub fn read_9bit(&mut self) -> nb::Result<u9, UartError> {
+        read_word(self.inner, self.errors)
+    }
+}
+
 impl<'i, 'r> embedded_io::ErrorType for BlockingUartRx<'i, 'r> {
     type Error = UartError;
 }
 
 impl<'i, 'r> embedded_io::Read for BlockingUartRx<'i, 'r> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut count = 0_usize;
-        for ch in buf {
-            if self.inner.lsr.read().data_ready() {
-                *ch = self.inner.rbr_thr_dll.read().receiver_buffer();
-                count += 1;
-            } else {
-                break;
+        match blocking_read(self.inner, self.errors, buf) {
+            (0, Some(error)) => Err(error),
+            (count, error) => {
+                // Reported by the next read, after the data before it.
+                if let Some(error) = error {
+                    self.errors.set(error);
+                }
+                Ok(count)
             }
         }
-        Ok(count)
     }
 }
 
@@ -38,18 +62,22 @@ impl<'i, 'r> embedded_hal_nb::serial::ErrorType for BlockingUartRx<'i, 'r> {
 }
 
 impl<'i, 'r> embedded_hal_nb::serial::Read for BlockingUartRx<'i, 'r> {
-    fn read(&mut self) -> embedded_hal_nb::nb::Result<u8, Self::Error> {
-        let mut buf = [0];
-        let len = blocking_read(&self.inner, &mut buf);
-        match len {
-            0 => Err(embedded_hal_nb::nb::Error::WouldBlock),
-            _ => Ok(buf[0]),
-        }
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.read_9bit().map(|word| word.value() as u8)
     }
 }
 
 impl<'i, 'r> embedded_io::ReadReady for BlockingUartRx<'i, 'r> {
     fn read_ready(&mut self) -> Result<bool, Self::Error> {
-        Ok(read_ready(&self.inner))
+        Ok(read_ready(self.inner, self.errors))
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 'r> embedded_hal_02::serial::Read<u8> for BlockingUartRx<'i, 'r> {
+    type Error = UartError;
+
+    fn read(&mut self) -> embedded_hal_nb::nb::Result<u8, Self::Error> {
+        embedded_hal_nb::serial::Read::read(self)
     }
 }
@@ -1,30 +1,89 @@
 use crate::iomux::FlexPad;
-use crate::uart::blocking::{blocking_flush, blocking_write, write_ready};
+use crate::uart::blocking::{
+    ErrorLatch, blocking_flush, blocking_write, modify_interrupts, transmitter_empty, write_ready,
+    write_word,
+};
 use crate::uart::{RegisterBlock, UartError};
+use arbitrary_int::u9;
 use core::marker::PhantomData;
+use embedded_hal_nb::nb;
 
 /// A UART transmitter for blocking operations.
 /// This struct implements blocking write operations for UART communication.
 pub struct BlockingUartTx<'i, 't> {
     /// Holds a reference to the UART register block.
     pub(crate) inner: &'static RegisterBlock,
+    /// Keeps line errors seen while polling for the receiver.
+    pub(crate) errors: &'static ErrorLatch,
     /// Contains a mutable handle to the TX pad.
     pub(crate) tx: FlexPad<'t>,
     /// Uses PhantomData for lifetime tracking.
     pub(crate) _marker: PhantomData<&'i ()>,
 }
 
+impl<'i, 't> BlockingUartTx<'i, 't> {
+    /// Starts transmitting a break condition.
+    ///
+    /// The line is held low until [`stop_break`](Self::stop_break) is called.
+    /// Callers should flush pending data first, otherwise it is corrupted by the break.
+    pub fn start_break(&mut self) {
+        unsafe {
+            self.inner.lcr.modify(|r| r.with_break_control_enable(true));
+        }
+    }
+
+    /// Stops transmitting a break condition.
+    pub fn stop_break(&mut self) {
+        unsafe {
+            self.inner
+                .lcr
+                .modify(|r| r.with_break_control_enable(false));
+        }
+    }
+
+    /// Enables or disables the transmit holding register empty interrupt.
+    ///
+    /// The interrupt stays pending while there is room to write, so a
+    /// handler fills the FIFO and disables it once there is nothing left
+    /// to send.
+    pub fn set_interrupt(&mut self, enable: bool) {
+        modify_interrupts(self.inner, |r| {
+            r.with_transmit_empty_interrupt_enable(enable)
+        });
+    }
+
+    /// Writes a single 9-bit character.
+    ///
+    /// Only meaningful when 9-bit mode is enabled in the configuration.
+    pub fn write_9bit(&mut self, word: u9) -> nb::Result<(), UartError> {
+        match write_ready(self.inner, self.errors) {
+            true => {
+                write_word(self.inner, word);
+                Ok(())
+            }
+            false => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Writes an address character in 9-bit mode.
+    ///
+    /// The ninth bit is set so that receivers in address match mode can select on it.
+    pub fn write_address(&mut self, address: u8) -> nb::Result<(), UartError> {
+        self.write_9bit(u9::new(0x100 | address as u16))
+    }
+}
+
 impl<'i, 't> embedded_io::ErrorType for BlockingUartTx<'i, 't> {
     type Error = UartError;
 }
 
 impl<'i, 't> embedded_io::Write for BlockingUartTx<'i, 't> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        Ok(blocking_write(&self.inner, buf))
+        Ok(blocking_write(self.inner, self.errors, buf))
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        blocking_flush(&self.inner);
+        blocking_flush(self.inner, self.errors);
         Ok(())
     }
     fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
@@ -44,15 +103,11 @@ impl<'i, 't> embedded_hal_nb::serial::ErrorType for BlockingUartTx<'i, 't> {
 
 impl<'i, 't> embedded_hal_nb::serial::Write for BlockingUartTx<'i, 't> {
     fn write(&mut self, word: u8) -> embedded_hal_nb::nb::Result<(), Self::Error> {
-        let len = blocking_write(&self.inner, &[word]);
-        match len {
-            0 => Err(embedded_hal_nb::nb::Error::WouldBlock),
-            _ => Ok(()),
-        }
+        self.write_9bit(u9::new(word as u16))
     }
 
     fn flush(&mut self) -> embedded_hal_nb::nb::Result<(), Self::Error> {
-        match self.inner.lsr.read().transmitter_empty() {
+        match transmitter_empty(self.inner, self.errors) {
             true => Ok(()),
             false => Err(embedded_hal_nb::nb::Error::WouldBlock),
         }
@@ -61,6 +116,22 @@ impl<'i, 't> embedded_hal_nb::serial::Write for BlockingUartTx<'i, 't> {
 
 impl<'i, 't> embedded_io::WriteReady for BlockingUartTx<'i, 't> {
     fn write_ready(&mut self) -> Result<bool, Self::Error> {
-        Ok(write_ready(&self.inner))
+        Ok(write_ready(self.inner, self.errors))
     }
 }
+
+#[cfg(feature = "eh02")]
+impl<'i, 't> embedded_hal_02::serial::Write<u8> for BlockingUartTx<'i, 't> {
+    type Error = UartError;
+
+    fn write(&mut self, word: u8) -> embedded_hal_nb::nb::Result<(), Self::Error> {
+        embedded_hal_nb::serial::Write::write(self, word)
+    }
+
+    fn flush(&mut self) -> embedded_hal_nb::nb::Result<(), Self::Error> {
+        embedded_hal_nb::serial::Write::flush(self)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<'i, 't> embedded_hal_02::blocking::serial::write::Default<u8> for BlockingUartTx<'i, 't> {}
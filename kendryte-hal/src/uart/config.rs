@@ -1,8 +1,23 @@
-use crate::uart::{ParityType, RegisterBlock, StopBits, WordLength};
-use embedded_time::rate::Baud;
+use crate::uart::{ParityType, RegisterBlock, StopBits, TransmitMode, WordLength};
+use embedded_time::rate::{Baud, Hertz};
+
+/// Largest baud rate error accepted by [`Config::divisor`], in percent.
+const MAX_BAUD_ERROR_PERCENT: u32 = 3;
+
+/// Errors from validating a [`Config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// The baud rate is zero.
+    ZeroBaud,
+    /// The baud rate cannot be generated from the UART clock within 3%.
+    BaudOutOfRange,
+}
 
 /// Represents different parity checking modes for UART communication.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ParityMode {
     /// No parity checking.
     None,
@@ -16,13 +31,31 @@ pub enum ParityMode {
     Low,
 }
 
+/// 9-bit (multidrop) data mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NineBitMode {
+    /// Regular 5 to 8 bit characters.
+    Disabled,
+    /// 9-bit characters, where the ninth bit marks an address character.
+    ///
+    /// When `address` is set, the receiver ignores incoming data until
+    /// an address character matching it is received.
+    Enabled { address: Option<u8> },
+}
+
 /// Configuration struct for UART settings.
 ///
 /// This struct contains all configurable parameters for the UART interface.
 /// Including divisor, parity mode, stop bits and word length settings.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Config {
     /// The divisor value for baud rate generation.
+    #[cfg_attr(feature = "serde", serde(with = "baud"))]
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
     pub baud: Baud,
     /// The parity checking mode.
     pub parity_mode: ParityMode,
@@ -30,7 +63,15 @@ pub struct Config {
     pub stop_bits: StopBits,
     /// Length of data words.
     pub word_length: WordLength,
+    /// Whether the transmit and receive FIFOs are enabled.
     pub fifo: bool,
+    /// 9-bit (multidrop) data mode.
+    pub nine_bit_mode: NineBitMode,
+    /// Whether the transmitter is looped back to the receiver inside the UART.
+    ///
+    /// The TX pad is held idle and the RX pad ignored while set.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub loopback: bool,
 }
 
 impl Config {
@@ -41,6 +82,9 @@ impl Config {
     /// - No parity.
     /// - 1 stop bit.
     /// - 8 bits word length.
+    /// - FIFO disabled.
+    /// - 9-bit mode disabled.
+    /// - Loopback disabled.
     pub fn new() -> Self {
         Self {
             baud: Baud::new(115200),
@@ -48,6 +92,8 @@ impl Config {
             stop_bits: StopBits::_1,
             word_length: WordLength::_8,
             fifo: false,
+            nine_bit_mode: NineBitMode::Disabled,
+            loopback: false,
         }
     }
 
@@ -79,6 +125,74 @@ impl Config {
         self.fifo = fifo;
         self
     }
+
+    /// Sets the 9-bit (multidrop) data mode.
+    pub fn set_nine_bit_mode(mut self, nine_bit_mode: NineBitMode) -> Self {
+        self.nine_bit_mode = nine_bit_mode;
+        self
+    }
+
+    /// Sets the internal loopback.
+    pub fn set_loopback(mut self, loopback: bool) -> Self {
+        self.loopback = loopback;
+        self
+    }
+
+    /// Validates the configuration against the UART clock.
+    ///
+    /// Returns the configuration unchanged if the UART can generate it, so
+    /// settings are checked at the end of the builder chain:
+    ///
+    /// ```ignore
+    /// let config = Config::new()
+    ///     .set_baud(Baud::new(921_600))
+    ///     .set_parity_mode(ParityMode::Even)
+    ///     .build(clocks.uart_sclk::<0>())?;
+    /// ```
+    pub fn build(self, uart_sclk: Hertz) -> Result<Self, ConfigError> {
+        self.divisor(uart_sclk)?;
+        Ok(self)
+    }
+
+    /// Computes the baud rate divisor for the UART clock, rounded to nearest.
+    pub fn divisor(&self, uart_sclk: Hertz) -> Result<u16, ConfigError> {
+        let baud = self.baud.0 as u64;
+        if baud == 0 {
+            return Err(ConfigError::ZeroBaud);
+        }
+        let sclk = uart_sclk.0 as u64;
+        let divisor = (sclk + 8 * baud) / (16 * baud);
+        let divisor = u16::try_from(divisor)
+            .ok()
+            .filter(|&d| d != 0)
+            .ok_or(ConfigError::BaudOutOfRange)?;
+        let actual = sclk / (16 * divisor as u64);
+        if actual.abs_diff(baud) * 100 > baud * MAX_BAUD_ERROR_PERCENT as u64 {
+            return Err(ConfigError::BaudOutOfRange);
+        }
+        Ok(divisor)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serializes a baud rate as its integer value.
+#[cfg(feature = "serde")]
+mod baud {
+    use embedded_time::rate::Baud;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(baud: &Baud, serializer: S) -> Result<S::Ok, S::Error> {
+        baud.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Baud, D::Error> {
+        u32::deserialize(deserializer).map(Baud::new)
+    }
 }
 
 /// Gets the current divisor value from UART registers.
@@ -181,13 +295,64 @@ pub(crate) fn set_word_length(uart: &RegisterBlock, word_length: WordLength) {
     }
 }
 
+/// Enables the transmit and receive FIFOs.
 pub(crate) fn enable_fifo(uart: &RegisterBlock) {
     unsafe {
         uart.iir_fcr.modify(|r| r.with_fifo_enable(true));
     }
 }
+
+/// Disables the transmit and receive FIFOs.
 pub(crate) fn disable_fifo(uart: &RegisterBlock) {
     unsafe {
         uart.iir_fcr.modify(|r| r.with_fifo_enable(false));
     }
 }
+
+/// Loops the transmitter back to the receiver, or connects both to the pads.
+pub(crate) fn set_loopback(uart: &RegisterBlock, loopback: bool) {
+    unsafe {
+        uart.mcr.modify(|r| r.with_loopback_mode_enable(loopback));
+    }
+}
+
+/// Sets the 9-bit (multidrop) data mode in UART registers.
+///
+/// Address characters are sent by writing the ninth bit directly to the transmit holding register.
+pub(crate) fn set_nine_bit_mode(uart: &RegisterBlock, nine_bit_mode: NineBitMode) {
+    let (enable, address) = match nine_bit_mode {
+        NineBitMode::Disabled => (false, None),
+        NineBitMode::Enabled { address } => (true, address),
+    };
+    unsafe {
+        if let Some(address) = address {
+            uart.rar.write(address as u32);
+        }
+        uart.lcr_ext.modify(|r| {
+            r.with_nine_bit_data_enable(enable)
+                .with_address_match_enable(address.is_some())
+                .with_send_address(false)
+                .with_transmit_mode(TransmitMode::Direct)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisor_validation() {
+        let sclk = Hertz::new(50_000_000);
+        assert_eq!(Config::new().divisor(sclk), Ok(27));
+        let config = Config::new().set_baud(Baud::new(3_125_000));
+        assert_eq!(config.divisor(sclk), Ok(1));
+        let config = Config::new().set_baud(Baud::new(0));
+        assert_eq!(config.build(sclk), Err(ConfigError::ZeroBaud));
+        // Halfway between divisors 1 and 2.
+        let config = Config::new().set_baud(Baud::new(2_000_000));
+        assert_eq!(config.build(sclk), Err(ConfigError::BaudOutOfRange));
+        let config = Config::new().set_baud(Baud::new(10));
+        assert_eq!(config.build(sclk), Err(ConfigError::BaudOutOfRange));
+    }
+}
@@ -1,5 +1,6 @@
 /// Indicate different error conditions that may occur during UART communication.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum UartError {
     /// Framing error occurred.
     Framing,
@@ -7,6 +8,8 @@ pub enum UartError {
     Parity,
     /// Overrun error occurred.
     Overrun,
+    /// Break condition detected on the line.
+    Break,
     /// Transmit (TX) resource not found.
     NotFoundTx,
     /// Receive (RX) resource not found.
@@ -15,12 +18,21 @@ pub enum UartError {
 
 impl embedded_io::Error for UartError {
     fn kind(&self) -> embedded_io::ErrorKind {
-        embedded_io::ErrorKind::Other
+        match self {
+            UartError::Framing | UartError::Parity => embedded_io::ErrorKind::InvalidData,
+            UartError::NotFoundTx | UartError::NotFoundRx => embedded_io::ErrorKind::NotFound,
+            UartError::Overrun | UartError::Break => embedded_io::ErrorKind::Other,
+        }
     }
 }
 
 impl embedded_hal_nb::serial::Error for UartError {
     fn kind(&self) -> embedded_hal_nb::serial::ErrorKind {
-        embedded_hal_nb::serial::ErrorKind::Other
+        match self {
+            UartError::Framing => embedded_hal_nb::serial::ErrorKind::FrameFormat,
+            UartError::Parity => embedded_hal_nb::serial::ErrorKind::Parity,
+            UartError::Overrun => embedded_hal_nb::serial::ErrorKind::Overrun,
+            _ => embedded_hal_nb::serial::ErrorKind::Other,
+        }
     }
 }
@@ -0,0 +1,53 @@
+use crate::memory_map;
+use crate::uart::RegisterBlock;
+
+/// Number of UART instances.
+pub const COUNT: usize = 5;
+
+const BASES: [usize; COUNT] = [
+    memory_map::UART0,
+    memory_map::UART1,
+    memory_map::UART2,
+    memory_map::UART3,
+    memory_map::UART4,
+];
+
+/// Compile-time description of UART instance `N`.
+///
+/// Naming an instance the chip does not have, such as `Uart<5>`, fails to
+/// compile. The pads the instance can use are the ones implementing
+/// [`IntoUartSout<N>`](super::pad::IntoUartSout) and the other pad traits.
+pub struct Uart<const N: usize>;
+
+impl<const N: usize> Uart<N> {
+    /// Base address of the registers.
+    pub const BASE: usize = {
+        assert!(N < COUNT, "the chip has UART0 to UART4 only");
+        BASES[N]
+    };
+
+    /// PLIC interrupt number.
+    pub const IRQ: u16 = {
+        assert!(N < COUNT, "the chip has UART0 to UART4 only");
+        16 + N as u16
+    };
+
+    /// Returns the registers.
+    #[inline]
+    pub const fn registers() -> &'static RegisterBlock {
+        // SAFETY: the address is that of the instance's register block.
+        unsafe { &*(Self::BASE as *const RegisterBlock) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances() {
+        assert_eq!(Uart::<0>::BASE, 0x9140_0000);
+        assert_eq!(Uart::<2>::BASE, 0x9140_2000);
+        assert_eq!(Uart::<4>::IRQ, 20);
+    }
+}
@@ -1,10 +1,12 @@
 mod blocking;
 mod config;
 mod error;
+mod instance;
 pub mod pad;
 mod register;
 
-pub use blocking::BlockingUart;
-pub use config::{Config, ParityMode};
+pub use blocking::{BlockingUart, BlockingUartRx, BlockingUartTx};
+pub use config::{Config, ConfigError, NineBitMode, ParityMode};
 pub use error::UartError;
+pub use instance::{COUNT, Uart};
 pub use register::*;
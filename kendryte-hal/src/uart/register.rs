@@ -78,7 +78,7 @@ pub struct RegisterBlock {
     /// Transmit Address Register.
     pub tar: RW<u32>,
     /// Line Extended Control Register.
-    pub lcr_ext: RW<u32>,
+    pub lcr_ext: RW<LcrExt>,
     _reserved1: [u8; 0x24],
     /// Component Parameter Register.
     pub cpr: RO<u32>,
@@ -116,6 +116,7 @@ pub struct RbrThrDll {
 /// ClearMode determines how the line status register is cleared.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ClearMode {
     /// 0 = Clear on receiver buffer or line status register read.
     OnRbrOrLsrRead = 0,
@@ -159,6 +160,7 @@ pub struct IerDlh {
 /// Identifies different interrupt types.
 #[bitenum(u4, exhaustive = false)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum InterruptType {
     /// 0x0 = Modem status interrupt.
     ModemStatus = 0x0,
@@ -179,6 +181,7 @@ pub enum InterruptType {
 /// DMA transfer mode.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DmaTransferMode {
     /// 0 = Mode 0.
     Mode0 = 0,
@@ -189,6 +192,7 @@ pub enum DmaTransferMode {
 /// Receiver interrupt threshold.
 #[bitenum(u2, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ReceiverInterruptThreshold {
     /// 0 = 1 character.
     OneChar = 0,
@@ -203,6 +207,7 @@ pub enum ReceiverInterruptThreshold {
 /// Transmitter empty threshold.
 #[bitenum(u2, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransmitterEmptyThreshold {
     /// 0 = Empty.
     Empty = 0,
@@ -260,6 +265,8 @@ pub struct IirFcr {
 /// Data word length configuration per UART character.
 #[bitenum(u2, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WordLength {
     /// 5 data bits.
     _5 = 0,
@@ -274,6 +281,8 @@ pub enum WordLength {
 /// Stop bits configuration.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum StopBits {
     /// 1 stop bit.
     _1 = 0,
@@ -284,6 +293,7 @@ pub enum StopBits {
 /// Parity type.
 #[bitenum(u1, exhaustive = true)]
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ParityType {
     /// Odd parity.
     Odd = 0,
@@ -324,6 +334,39 @@ pub struct Lcr {
     pub divisor_latch_access_enable: bool,
 }
 
+/// 9-bit transmit mode.
+#[bitenum(u1, exhaustive = true)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransmitMode {
+    /// 0 = Address is sent from the Transmit Address Register when send address is set.
+    AddressRegister = 0,
+    /// 1 = The 9-bit character is written directly to the Transmit Holding Register.
+    Direct = 1,
+}
+
+/// Line Extended Control Register.
+/// Used to configure 9-bit data transfers and address matching.
+#[bitfield(u32)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct LcrExt {
+    /// Enable 9-bit data for transmit and receive.
+    #[bit(0, rw)]
+    pub nine_bit_data_enable: bool,
+
+    /// Enable address match mode on the receiver.
+    #[bit(1, rw)]
+    pub address_match_enable: bool,
+
+    /// Send the Transmit Address Register content as an address character.
+    #[bit(2, rw)]
+    pub send_address: bool,
+
+    /// 9-bit transmit mode.
+    #[bit(3, rw)]
+    pub transmit_mode: TransmitMode,
+}
+
 /// Modem Control Register.
 /// Used for modem handshaking and additional mode configuration.
 #[bitfield(u32)]
@@ -0,0 +1,141 @@
+//! LZ4 compressed firmware.
+//!
+//! Firmware built with `cargo xtask gen-image --compress` starts with an
+//! 8-byte header of [`MAGIC`] and the uncompressed length (u32 LE),
+//! followed by a single LZ4 block. The compression sits inside the
+//! payload, so the BootROM header and the signature are unchanged and cover
+//! the compressed bytes; a manifest, if any, follows uncompressed.
+//!
+//! A loader unpacks the firmware to its load address:
+//!
+//! ```ignore
+//! let firmware = &payload[VERSION_LEN..];
+//! if let Some((len, block)) = compress::split(firmware) {
+//!     let load = unsafe { core::slice::from_raw_parts_mut(LOAD_ADDRESS as *mut u8, len) };
+//!     compress::decompress(block, load)?;
+//! }
+//! ```
+
+use crate::Error;
+
+/// Magic bytes starting compressed firmware.
+pub const MAGIC: &[u8; 4] = b"KLZ4";
+/// Length of the compressed firmware header.
+pub const HEADER_LEN: usize = 8;
+
+/// Shortest match of the LZ4 format.
+const MIN_MATCH: usize = 4;
+
+/// Splits compressed firmware into its uncompressed length and LZ4 block.
+///
+/// Returns `None` if the firmware is not compressed.
+pub fn split(firmware: &[u8]) -> Option<(usize, &[u8])> {
+    if firmware.len() < HEADER_LEN || firmware[..4] != *MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes([firmware[4], firmware[5], firmware[6], firmware[7]]);
+    Some((len as usize, &firmware[HEADER_LEN..]))
+}
+
+/// Decompresses an LZ4 block into `out`.
+///
+/// Returns the decompressed length; `out` must be at least that long.
+/// Fails with [`Error::DecompressionFailed`] on a malformed block, never
+/// reading or writing outside the buffers.
+pub fn decompress(block: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    let mut input = 0;
+    let mut pos = 0;
+    loop {
+        let token = *block.get(input).ok_or(Error::DecompressionFailed)?;
+        input += 1;
+
+        let literals = length(block, &mut input, (token >> 4) as usize)?;
+        let src = input
+            .checked_add(literals)
+            .and_then(|end| block.get(input..end))
+            .ok_or(Error::DecompressionFailed)?;
+        out.get_mut(pos..pos + literals)
+            .ok_or(Error::DecompressionFailed)?
+            .copy_from_slice(src);
+        input += literals;
+        pos += literals;
+        // The last sequence has literals only.
+        if input == block.len() {
+            return Ok(pos);
+        }
+
+        let offset = match block.get(input..input + 2) {
+            Some(&[lo, hi]) => u16::from_le_bytes([lo, hi]) as usize,
+            _ => return Err(Error::DecompressionFailed),
+        };
+        input += 2;
+        let len = length(block, &mut input, (token & 0x0F) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > pos || len > out.len() - pos {
+            return Err(Error::DecompressionFailed);
+        }
+        // Matches may overlap their own output, repeating a short pattern.
+        for i in pos..pos + len {
+            out[i] = out[i - offset];
+        }
+        pos += len;
+    }
+}
+
+/// Reads a length, extended by 255-byte steps when the token nibble is 15.
+fn length(block: &[u8], input: &mut usize, nibble: usize) -> Result<usize, Error> {
+    let mut len = nibble;
+    if nibble == 0x0F {
+        loop {
+            let byte = *block.get(*input).ok_or(Error::DecompressionFailed)?;
+            *input += 1;
+            len = len
+                .checked_add(byte as usize)
+                .ok_or(Error::DecompressionFailed)?;
+            if byte != 0xFF {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_block() {
+        // "abcabcabcabcabc" + 22 literals: 3 literals, a 12-byte match at
+        // offset 3, then the literal tail with an extended length.
+        #[rustfmt::skip]
+        let block = [
+            0x38, b'a', b'b', b'c', 3, 0,
+            0xF0, 7, b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9',
+            b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'!', b'?',
+        ];
+        let mut firmware = [0; HEADER_LEN + 30];
+        firmware[..4].copy_from_slice(MAGIC);
+        firmware[4..8].copy_from_slice(&37u32.to_le_bytes());
+        firmware[HEADER_LEN..].copy_from_slice(&block);
+
+        let (len, block) = split(&firmware).unwrap();
+        let mut out = [0; 40];
+        assert_eq!(decompress(block, &mut out), Ok(len));
+        assert_eq!(&out[..len], b"abcabcabcabcabc01234567890123456789!?");
+        assert!(split(b"firmware").is_none());
+
+        assert_eq!(
+            decompress(block, &mut [0; 36]),
+            Err(Error::DecompressionFailed)
+        );
+        assert_eq!(
+            decompress(&block[..5], &mut out),
+            Err(Error::DecompressionFailed)
+        );
+        // Offset before the start of the output.
+        assert_eq!(
+            decompress(&[0x10, b'a', 2, 0, 0x00], &mut out),
+            Err(Error::DecompressionFailed)
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{Crypto, Error, Header, VERSION_LEN};
+use aes_gcm::aead::AeadInPlace;
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce, Tag};
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, KeyIvInit};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Length of the AES-GCM tag appended to the ciphertext.
+const TAG_LEN: usize = 16;
+
+/// Symmetric keys the image was encrypted with.
+#[derive(Clone, Copy, Debug)]
+pub struct Keys<'a> {
+    /// AES-256-GCM key.
+    pub aes_key: &'a [u8; 32],
+    /// AES-GCM nonce.
+    pub aes_iv: &'a [u8; 12],
+    /// AES-GCM additional authenticated data.
+    pub aes_aad: &'a [u8],
+    /// SM4-CBC key.
+    pub sm4_key: &'a [u8; 16],
+    /// SM4-CBC initialization vector.
+    pub sm4_iv: &'a [u8; 16],
+}
+
+/// HKDF salt of image keys bound to a device.
+pub const BINDING_SALT: &[u8] = b"kendryte-image device binding";
+
+/// Derives the key of an image bound to the device with unique ID `uid`.
+///
+/// Images generated with `cargo xtask gen-image --bind-uid` are encrypted
+/// with HKDF-SHA256 of the image key and the OTP unique ID, so a copy
+/// fails to decrypt on any other device: AES images fail authentication,
+/// SM4 images their padding check or, rarely, the manifest digests.
+///
+/// ```ignore
+/// let aes_key = bind_key(AES_KEY, otp.unique_id());
+/// let sm4_key = bind_key(SM4_KEY, otp.unique_id());
+/// let keys = Keys { aes_key: &aes_key, sm4_key: &sm4_key, ..KEYS };
+/// ```
+pub fn bind_key<const N: usize>(key: &[u8; N], uid: &[u8]) -> [u8; N] {
+    let mut bound = [0; N];
+    Hkdf::<Sha256>::new(Some(BINDING_SALT), key)
+        .expand(uid, &mut bound)
+        .expect("image keys are far shorter than the HKDF output limit");
+    bound
+}
+
+/// Decrypts the payload of `image` in place and returns the firmware.
+///
+/// The returned firmware excludes the version bytes, and still carries
+/// the manifest if one was embedded. AES images are authenticated by their
+/// GCM tag; verify the signature first for authenticity of the tag itself.
+pub fn decrypt_payload<'a>(
+    header: &Header,
+    image: &'a mut [u8],
+    keys: &Keys,
+) -> Result<&'a [u8], Error> {
+    let payload = header.payload_mut(image);
+    let plaintext: &[u8] = match header.crypto {
+        Crypto::None { .. } => payload,
+        Crypto::Sm4 { .. } => {
+            cbc::Decryptor::<sm4::Sm4>::new(keys.sm4_key.into(), keys.sm4_iv.into())
+                .decrypt_padded_mut::<Pkcs7>(payload)
+                .map_err(|_| Error::DecryptionFailed)?
+        }
+        Crypto::Aes { .. } => {
+            let len = payload
+                .len()
+                .checked_sub(TAG_LEN)
+                .ok_or(Error::InvalidHeader)?;
+            let (ciphertext, tag) = payload.split_at_mut(len);
+            Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(keys.aes_key))
+                .decrypt_in_place_detached(
+                    Nonce::from_slice(keys.aes_iv),
+                    keys.aes_aad,
+                    ciphertext,
+                    Tag::from_slice(tag),
+                )
+                .map_err(|_| Error::DecryptionFailed)?;
+            ciphertext
+        }
+    };
+    plaintext.get(VERSION_LEN..).ok_or(Error::InvalidHeader)
+}
@@ -0,0 +1,206 @@
+use crate::{Error, MAGIC, PAYLOAD_OFFSET};
+
+/// Largest SM2 user ID that fits in the header.
+pub const MAX_ID_LEN: usize = 512 - 32 * 4;
+
+/// Encryption and signature combination of an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encryption {
+    /// No encryption, SHA-256 hash.
+    None = 0,
+    /// SM4-CBC encryption, SM2 signature over the ciphertext.
+    Sm4 = 1,
+    /// AES-256-GCM encryption, RSA-2048 signature over the GCM tag.
+    Aes = 2,
+}
+
+/// SM2 user ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Id {
+    len: usize,
+    bytes: [u8; MAX_ID_LEN],
+}
+
+impl Id {
+    /// Returns the ID bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Hash or signature information of an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crypto {
+    /// SHA-256 of the payload.
+    None { hash: [u8; 32] },
+    /// SM2 signature of the ciphertext, with the signer's ID and public key.
+    Sm4 {
+        id: Id,
+        public_key_x: [u8; 32],
+        public_key_y: [u8; 32],
+        r: [u8; 32],
+        s: [u8; 32],
+    },
+    /// RSA-2048 PKCS#1 v1.5 signature of the GCM tag, with the signer's public key.
+    Aes {
+        n: [u8; 256],
+        e: u32,
+        signature: [u8; 256],
+    },
+}
+
+/// Parsed image header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Length of the payload in bytes, including the GCM tag for AES images.
+    pub payload_len: usize,
+    /// Hash or signature information.
+    pub crypto: Crypto,
+}
+
+impl Header {
+    /// Returns the encryption type of the image.
+    pub fn encryption(&self) -> Encryption {
+        match self.crypto {
+            Crypto::None { .. } => Encryption::None,
+            Crypto::Sm4 { .. } => Encryption::Sm4,
+            Crypto::Aes { .. } => Encryption::Aes,
+        }
+    }
+
+    /// Returns the payload of `image`.
+    pub fn payload<'a>(&self, image: &'a [u8]) -> &'a [u8] {
+        &image[PAYLOAD_OFFSET..PAYLOAD_OFFSET + self.payload_len]
+    }
+
+    /// Returns the payload of `image` for in-place decryption.
+    pub fn payload_mut<'a>(&self, image: &'a mut [u8]) -> &'a mut [u8] {
+        &mut image[PAYLOAD_OFFSET..PAYLOAD_OFFSET + self.payload_len]
+    }
+}
+
+/// Parses the header of an image starting at its magic bytes.
+///
+/// Checks that `image` holds the whole payload.
+pub fn parse_header(image: &[u8]) -> Result<Header, Error> {
+    if image.len() < PAYLOAD_OFFSET {
+        return Err(Error::Truncated);
+    }
+    if image[0..4] != *MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let payload_len = i32::from_le_bytes(array(&image[4..8]));
+    let payload_len = usize::try_from(payload_len).map_err(|_| Error::InvalidHeader)?;
+    if image.len() - PAYLOAD_OFFSET < payload_len {
+        return Err(Error::Truncated);
+    }
+
+    let info = &image[12..PAYLOAD_OFFSET];
+    let crypto = match i32::from_le_bytes(array(&image[8..12])) {
+        0 => Crypto::None {
+            hash: array(&info[..32]),
+        },
+        1 => {
+            let id_len = i32::from_le_bytes(array(&info[..4]));
+            let id_len = usize::try_from(id_len)
+                .ok()
+                .filter(|len| *len <= MAX_ID_LEN)
+                .ok_or(Error::InvalidHeader)?;
+            let mut id = Id {
+                len: id_len,
+                bytes: [0; MAX_ID_LEN],
+            };
+            id.bytes[..id_len].copy_from_slice(&info[4..4 + id_len]);
+            let keys = &info[4 + MAX_ID_LEN..];
+            Crypto::Sm4 {
+                id,
+                public_key_x: array(&keys[0..32]),
+                public_key_y: array(&keys[32..64]),
+                r: array(&keys[64..96]),
+                s: array(&keys[96..128]),
+            }
+        }
+        2 => Crypto::Aes {
+            n: array(&info[..256]),
+            e: u32::from_le_bytes(array(&info[256..260])),
+            signature: array(&info[260..516]),
+        },
+        other => return Err(Error::UnknownEncryption(other)),
+    };
+    Ok(Header {
+        payload_len,
+        crypto,
+    })
+}
+
+/// Copies a slice of known length into an array.
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(bytes);
+    array
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(encryption: i32, info: &[u8], payload: &[u8]) -> [u8; 1024] {
+        let mut image = [0; 1024];
+        image[0..4].copy_from_slice(MAGIC);
+        image[4..8].copy_from_slice(&(payload.len() as i32).to_le_bytes());
+        image[8..12].copy_from_slice(&encryption.to_le_bytes());
+        image[12..12 + info.len()].copy_from_slice(info);
+        image[PAYLOAD_OFFSET..PAYLOAD_OFFSET + payload.len()].copy_from_slice(payload);
+        image
+    }
+
+    #[test]
+    fn parse_none() {
+        let image = image(0, &[0xAB; 32], b"\0\0\0\0firmware");
+        let header = parse_header(&image).unwrap();
+        assert_eq!(header.encryption(), Encryption::None);
+        assert_eq!(header.crypto, Crypto::None { hash: [0xAB; 32] });
+        assert_eq!(header.payload(&image), b"\0\0\0\0firmware");
+    }
+
+    #[test]
+    fn parse_sm4() {
+        let mut info = [0; 516];
+        info[..4].copy_from_slice(&16i32.to_le_bytes());
+        info[4..20].copy_from_slice(b"1234567812345678");
+        info[388..420].fill(1);
+        info[484..516].fill(4);
+        let image = image(1, &info, &[0; 32]);
+        let Crypto::Sm4 {
+            id,
+            public_key_x,
+            s,
+            ..
+        } = parse_header(&image).unwrap().crypto
+        else {
+            panic!("expected SM4 header");
+        };
+        assert_eq!(id.as_bytes(), b"1234567812345678");
+        assert_eq!(public_key_x, [1; 32]);
+        assert_eq!(s, [4; 32]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut bad = image(0, &[], &[]);
+        bad[0] = b'X';
+        assert_eq!(parse_header(&bad), Err(Error::BadMagic));
+        assert_eq!(
+            parse_header(&image(3, &[], &[])),
+            Err(Error::UnknownEncryption(3))
+        );
+        assert_eq!(
+            parse_header(&image(0, &[], &[])[..100]),
+            Err(Error::Truncated)
+        );
+
+        let mut long = image(0, &[], &[]);
+        long[4..8].copy_from_slice(&1000i32.to_le_bytes());
+        assert_eq!(parse_header(&long), Err(Error::Truncated));
+    }
+}
@@ -0,0 +1,73 @@
+//! K230 firmware image format.
+//!
+//! Parsing, verification and decryption of the images produced by
+//! `cargo xtask gen-image`, shared by the generator and on-device loaders so the
+//! header format has a single implementation.
+//!
+//! Image layout, starting at [`HEADER_OFFSET`] of the boot medium:
+//!
+//! | Offset | Length | Field |
+//! |---|---|---|
+//! | 0 | 4 | Magic `K230` |
+//! | 4 | 4 | Payload length, i32 LE |
+//! | 8 | 4 | Encryption type, i32 LE |
+//! | 12 | 516 | Hash or signature information, see [`Crypto`] |
+//! | 528 | n | Payload: 4 version bytes, firmware, optional manifest |
+//!
+//! Verification and decryption need the `verify` and `decrypt` features.
+//! RSA signatures (AES images) additionally need the `rsa` feature, which uses `alloc`.
+#![no_std]
+
+pub mod compress;
+#[cfg(feature = "decrypt")]
+mod decrypt;
+mod header;
+pub mod manifest;
+pub mod ota;
+#[cfg(feature = "verify")]
+mod verify;
+
+#[cfg(feature = "decrypt")]
+pub use decrypt::{BINDING_SALT, Keys, bind_key, decrypt_payload};
+pub use header::{Crypto, Encryption, Header, Id, parse_header};
+#[cfg(feature = "rsa")]
+pub use verify::verify_rsa;
+#[cfg(feature = "verify")]
+pub use verify::{verify_signature, verify_sm2};
+
+/// Offset of the image header on the boot medium.
+pub const HEADER_OFFSET: usize = 0x100000;
+
+/// Magic bytes starting the image header.
+pub const MAGIC: &[u8; 4] = b"K230";
+
+/// Offset of the payload from the start of the image header.
+pub const PAYLOAD_OFFSET: usize = 528;
+
+/// Length of the version bytes prepended to the firmware.
+pub const VERSION_LEN: usize = 4;
+
+/// Indicate different error conditions that may occur when unpacking an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The image does not start with the magic bytes.
+    BadMagic,
+    /// The image is shorter than its header says.
+    Truncated,
+    /// The encryption type is not known.
+    UnknownEncryption(i32),
+    /// A header field is out of range.
+    InvalidHeader,
+    /// The payload hash does not match.
+    HashMismatch,
+    /// The signature does not verify.
+    BadSignature,
+    /// The public key in the header is malformed.
+    InvalidKey,
+    /// The payload failed to decrypt or authenticate.
+    DecryptionFailed,
+    /// The compressed firmware is malformed or larger than its buffer.
+    DecompressionFailed,
+    /// The operation is not available with the enabled features.
+    Unsupported,
+}
@@ -0,0 +1,121 @@
+//! Firmware manifest embedded at the end of the payload.
+//!
+//! The manifest is a sequence of `[type: u8][length: u16 LE][value]` records
+//! followed by an 8-byte trailer of the records length (u32 LE) and [`MAGIC`].
+
+/// Magic bytes ending a payload that carries a manifest.
+pub const MAGIC: &[u8; 4] = b"KMF1";
+/// Length of the trailer following the manifest records.
+pub const TRAILER_LEN: usize = 8;
+
+/// Build timestamp, seconds since the Unix epoch as u64 LE.
+pub const TAG_TIMESTAMP: u8 = 0x01;
+/// Git commit hash, as an ASCII string.
+pub const TAG_GIT_HASH: u8 = 0x02;
+/// Semantic version, as an ASCII string.
+pub const TAG_VERSION: u8 = 0x03;
+/// Address the firmware is loaded to, as u64 LE.
+pub const TAG_LOAD_ADDRESS: u8 = 0x04;
+/// Address execution starts at, as u64 LE.
+pub const TAG_ENTRY_POINT: u8 = 0x05;
+/// Boot flags, as u32 LE.
+pub const TAG_BOOT_FLAGS: u8 = 0x06;
+/// Section digest: `[algorithm: u8][name length: u8][name][digest]`.
+pub const TAG_SECTION_DIGEST: u8 = 0x10;
+
+/// Boot flag: start the firmware on CPU1 (the big core) instead of CPU0.
+pub const BOOT_FLAG_CORE1: u32 = 1 << 0;
+/// Boot flag: leave the watchdog running when jumping to the firmware.
+pub const BOOT_FLAG_KEEP_WATCHDOG: u32 = 1 << 1;
+
+/// A manifest record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Record type.
+    pub tag: u8,
+    /// Record value.
+    pub value: &'a [u8],
+}
+
+/// Iterator over the records of a manifest.
+///
+/// Stops at the first malformed record.
+#[derive(Clone, Debug)]
+pub struct Records<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Record<'a>;
+
+    fn next(&mut self) -> Option<Record<'a>> {
+        let (&tag, rest) = self.data.split_first()?;
+        let len = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+        let value = rest.get(2..2 + len)?;
+        self.data = &rest[2 + len..];
+        Some(Record { tag, value })
+    }
+}
+
+/// Locates the manifest at the end of a decrypted payload.
+///
+/// Returns the firmware without the manifest, and the manifest records,
+/// or `None` if the payload carries no manifest.
+pub fn split(payload: &[u8]) -> Option<(&[u8], Records<'_>)> {
+    let trailer = payload.len().checked_sub(TRAILER_LEN)?;
+    if payload[trailer + 4..] != *MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes([
+        payload[trailer],
+        payload[trailer + 1],
+        payload[trailer + 2],
+        payload[trailer + 3],
+    ]) as usize;
+    let start = trailer.checked_sub(len)?;
+    Some((
+        &payload[..start],
+        Records {
+            data: &payload[start..trailer],
+        },
+    ))
+}
+
+/// Returns the value of the first record of type `tag`.
+pub fn find<'a>(records: Records<'a>, tag: u8) -> Option<&'a [u8]> {
+    records
+        .into_iter()
+        .find(|record| record.tag == tag)
+        .map(|record| record.value)
+}
+
+/// Returns the value of the first record of type `tag` as a little endian u64.
+pub fn find_u64(records: Records<'_>, tag: u8) -> Option<u64> {
+    let value = find(records, tag)?;
+    Some(u64::from_le_bytes(value.try_into().ok()?))
+}
+
+/// Returns the value of the first record of type `tag` as a little endian u32.
+pub fn find_u32(records: Records<'_>, tag: u8) -> Option<u32> {
+    let value = find(records, tag)?;
+    Some(u32::from_le_bytes(value.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_manifest() {
+        let payload = b"firmware\x03\x05\x001.2.3\x01\x08\x00\x00\xf1\x53\x65\x00\x00\x00\x00\x13\x00\x00\x00KMF1";
+        let (firmware, records) = split(payload).unwrap();
+        assert_eq!(firmware, b"firmware");
+        assert_eq!(find(records.clone(), TAG_VERSION), Some(&b"1.2.3"[..]));
+        assert_eq!(
+            find_u64(records.clone(), TAG_TIMESTAMP),
+            Some(1_700_000_000)
+        );
+        assert_eq!(find(records, TAG_GIT_HASH), None);
+        assert!(split(b"firmware").is_none());
+    }
+}
@@ -0,0 +1,263 @@
+//! Delta updates between two images.
+//!
+//! A delta, produced by `cargo xtask delta`, rebuilds a new image from the
+//! one already on the device, so an update only transfers what changed:
+//!
+//! | Offset | Length | Field |
+//! |---|---|---|
+//! | 0 | 4 | Magic `KDL1` |
+//! | 4 | 4 | Old image length, u32 LE |
+//! | 8 | 4 | Old image CRC-32, u32 LE |
+//! | 12 | 4 | New image length, u32 LE |
+//! | 16 | 4 | New image CRC-32, u32 LE |
+//! | 20 | n | Operations |
+//!
+//! Numbers within operations are LEB128 varints. An operation is either
+//!
+//! - [`OP_INSERT`] `len` followed by `len` literal bytes, or
+//! - [`OP_ADD`] `offset` `len`, copying `len` bytes of the old image from
+//!   `offset` while adding differences to them, given as runs of `skip`
+//!   `count` followed by `count` bytes added (wrapping) to the old bytes
+//!   after the `skip` unchanged ones, until `len` bytes are covered.
+//!
+//! Code that moved keeps most bytes and changes a few addresses in it, so
+//! its difference runs are short.
+
+/// Magic bytes starting a delta.
+pub const MAGIC: &[u8; 4] = b"KDL1";
+/// Length of the delta header.
+pub const HEADER_LEN: usize = 20;
+/// Operation inserting literal bytes.
+pub const OP_INSERT: u8 = 0x00;
+/// Operation copying old bytes with differences added.
+pub const OP_ADD: u8 = 0x01;
+
+/// Indicate what went wrong applying a delta.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaError<E> {
+    /// The delta does not start with the magic bytes.
+    BadMagic,
+    /// The delta was made against another old image.
+    BaseMismatch,
+    /// An operation is malformed or reaches outside the old image.
+    Corrupt,
+    /// The rebuilt image does not match the length or CRC of the new one.
+    ResultMismatch,
+    /// Writing the rebuilt image failed.
+    Write(E),
+}
+
+/// Computes the CRC-32 (IEEE 802.3) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Incremental CRC-32 (IEEE 802.3).
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Starts a checksum.
+    #[inline]
+    pub const fn new() -> Self {
+        Crc32(!0)
+    }
+
+    /// Adds `data` to the checksum.
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & (self.0 & 1).wrapping_neg());
+            }
+        }
+    }
+
+    /// Returns the checksum.
+    #[inline]
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cursor over the operations of a delta.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn varint(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Rebuilds the new image from `old` and `delta`, passing it to `write` in order.
+///
+/// Returns the length of the new image. The old image is checked before
+/// anything is written, and the new one against its CRC once written; on
+/// [`DeltaError::ResultMismatch`] the written image must be discarded.
+pub fn apply_delta<E>(
+    old: &[u8],
+    delta: &[u8],
+    mut write: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<usize, DeltaError<E>> {
+    if delta.len() < HEADER_LEN || delta[..4] != *MAGIC {
+        return Err(DeltaError::BadMagic);
+    }
+    if le32(delta, 4) as usize != old.len() || le32(delta, 8) != crc32(old) {
+        return Err(DeltaError::BaseMismatch);
+    }
+    let new_len = le32(delta, 12) as usize;
+    let new_crc = le32(delta, 16);
+
+    let mut crc = Crc32::new();
+    let mut written = 0;
+    let mut emit = |bytes: &[u8]| {
+        crc.update(bytes);
+        written += bytes.len();
+        write(bytes).map_err(DeltaError::Write)
+    };
+    let mut ops = Reader {
+        data: &delta[HEADER_LEN..],
+    };
+    while let Some(op) = ops.byte() {
+        match op {
+            OP_INSERT => {
+                let len = ops.varint().ok_or(DeltaError::Corrupt)?;
+                emit(ops.bytes(len).ok_or(DeltaError::Corrupt)?)?;
+            }
+            OP_ADD => {
+                let offset = ops.varint().ok_or(DeltaError::Corrupt)?;
+                let len = ops.varint().ok_or(DeltaError::Corrupt)?;
+                let mut base = offset
+                    .checked_add(len)
+                    .and_then(|end| old.get(offset..end))
+                    .ok_or(DeltaError::Corrupt)?;
+                while !base.is_empty() {
+                    let skip = ops.varint().ok_or(DeltaError::Corrupt)?;
+                    let count = ops.varint().ok_or(DeltaError::Corrupt)?;
+                    if skip.checked_add(count).is_none_or(|run| run > base.len()) {
+                        return Err(DeltaError::Corrupt);
+                    }
+                    emit(&base[..skip])?;
+                    let diff = ops.bytes(count).ok_or(DeltaError::Corrupt)?;
+                    let mut chunk = [0; 64];
+                    for (old, diff) in base[skip..skip + count]
+                        .chunks(chunk.len())
+                        .zip(diff.chunks(chunk.len()))
+                    {
+                        for ((out, old), diff) in chunk.iter_mut().zip(old).zip(diff) {
+                            *out = old.wrapping_add(*diff);
+                        }
+                        emit(&chunk[..old.len()])?;
+                    }
+                    base = &base[skip + count..];
+                }
+            }
+            _ => return Err(DeltaError::Corrupt),
+        }
+    }
+    if written != new_len || crc.finish() != new_crc {
+        return Err(DeltaError::ResultMismatch);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(old: &[u8], new: &[u8], ops: &[u8], out: &mut [u8]) -> usize {
+        out[..4].copy_from_slice(MAGIC);
+        out[4..8].copy_from_slice(&(old.len() as u32).to_le_bytes());
+        out[8..12].copy_from_slice(&crc32(old).to_le_bytes());
+        out[12..16].copy_from_slice(&(new.len() as u32).to_le_bytes());
+        out[16..20].copy_from_slice(&crc32(new).to_le_bytes());
+        out[HEADER_LEN..HEADER_LEN + ops.len()].copy_from_slice(ops);
+        HEADER_LEN + ops.len()
+    }
+
+    #[test]
+    fn applies_and_checks() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let old = b"hello, world";
+        let new = b"Hello, there world!";
+        #[rustfmt::skip]
+        let ops = [
+            OP_ADD, 0, 7, 0, 1, 0xE0, 6, 0, // "hello, " with 'h' - 0x20
+            OP_INSERT, 6, b't', b'h', b'e', b'r', b'e', b' ',
+            OP_ADD, 7, 5, 5, 0, // "world"
+            OP_INSERT, 1, b'!',
+        ];
+        let mut delta = [0; 64];
+        let len = build(old, new, &ops, &mut delta);
+        let delta = &delta[..len];
+
+        let mut out = [0; 32];
+        let mut written = 0;
+        let result = apply_delta(old, delta, |bytes| {
+            out[written..written + bytes.len()].copy_from_slice(bytes);
+            written += bytes.len();
+            Ok::<(), ()>(())
+        });
+        assert_eq!(result, Ok(new.len()));
+        assert_eq!(&out[..written], new);
+
+        let nothing = |_: &[u8]| Ok::<(), ()>(());
+        assert_eq!(
+            apply_delta(b"hello, World", delta, nothing),
+            Err(DeltaError::BaseMismatch)
+        );
+        assert_eq!(
+            apply_delta(old, &delta[..len - 1], nothing),
+            Err(DeltaError::Corrupt)
+        );
+        let mut wrong = [0; 64];
+        let len = build(old, b"Hello, there world?", &ops, &mut wrong);
+        assert_eq!(
+            apply_delta(old, &wrong[..len], nothing),
+            Err(DeltaError::ResultMismatch)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{Crypto, Error, Header};
+use sha2::{Digest, Sha256};
+use signature::Verifier;
+
+/// Verifies the hash or signature of `image` against its header.
+///
+/// The signature is checked with the public key carried in the header; callers
+/// must compare that key against the one they trust (for example its hash in OTP).
+pub fn verify_signature(header: &Header, image: &[u8]) -> Result<(), Error> {
+    let payload = header.payload(image);
+    match &header.crypto {
+        Crypto::None { hash } => match Sha256::digest(payload).as_slice() == hash {
+            true => Ok(()),
+            false => Err(Error::HashMismatch),
+        },
+        Crypto::Sm4 {
+            id,
+            public_key_x,
+            public_key_y,
+            r,
+            s,
+        } => verify_sm2(id.as_bytes(), public_key_x, public_key_y, r, s, payload),
+        #[cfg(feature = "rsa")]
+        Crypto::Aes { n, e, signature } => verify_rsa(n, *e, signature, payload),
+        #[cfg(not(feature = "rsa"))]
+        Crypto::Aes { .. } => Err(Error::Unsupported),
+    }
+}
+
+/// Verifies an SM2 signature over the ciphertext of an SM4 image.
+///
+/// This is the scheme `cargo xtask gen-image` signs with, for loaders that
+/// keep the header fields elsewhere: the signed digest is
+/// `SM3(Z || ciphertext)` with `Z = SM3(ENTL || id || a || b || xG || yG || x || y)`
+/// (GB/T 32918.2), where ENTL is the ID length in bits as u16 BE, and the
+/// signature is `r || s`, all big endian. The ciphertext is the whole
+/// payload, including the CBC padding.
+pub fn verify_sm2(
+    id: &[u8],
+    public_key_x: &[u8; 32],
+    public_key_y: &[u8; 32],
+    r: &[u8; 32],
+    s: &[u8; 32],
+    ciphertext: &[u8],
+) -> Result<(), Error> {
+    use sm2::dsa::{Signature, VerifyingKey};
+
+    let mut point = [0; 65];
+    point[0] = 0x04;
+    point[1..33].copy_from_slice(public_key_x);
+    point[33..].copy_from_slice(public_key_y);
+    let public_key = sm2::PublicKey::from_sec1_bytes(&point).map_err(|_| Error::InvalidKey)?;
+    let id = core::str::from_utf8(id).map_err(|_| Error::InvalidKey)?;
+    let verifying_key = VerifyingKey::new(id, public_key).map_err(|_| Error::InvalidKey)?;
+
+    let signature = Signature::from_scalars(*r, *s).map_err(|_| Error::BadSignature)?;
+    verifying_key
+        .verify(ciphertext, &signature)
+        .map_err(|_| Error::BadSignature)
+}
+
+/// Verifies the RSA-2048 signature of an AES image.
+///
+/// This is the scheme `cargo xtask gen-image` signs with: PKCS#1 v1.5 with
+/// the SHA-256 DigestInfo prefix (not the unprefixed variant) over the
+/// 16-byte GCM tag that ends `payload`. `n` is big endian, as in the header.
+#[cfg(feature = "rsa")]
+pub fn verify_rsa(
+    n: &[u8; 256],
+    e: u32,
+    signature: &[u8; 256],
+    payload: &[u8],
+) -> Result<(), Error> {
+    use rsa::pkcs1v15::{Signature, VerifyingKey};
+    use rsa::{BigUint, RsaPublicKey};
+
+    let tag = payload
+        .len()
+        .checked_sub(16)
+        .map(|start| &payload[start..])
+        .ok_or(Error::InvalidHeader)?;
+    let public_key = RsaPublicKey::new(BigUint::from_bytes_be(n), BigUint::from(e))
+        .map_err(|_| Error::InvalidKey)?;
+    let signature = Signature::try_from(&signature[..]).map_err(|_| Error::BadSignature)?;
+    VerifyingKey::<Sha256>::new(public_key)
+        .verify(tag, &signature)
+        .map_err(|_| Error::BadSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HEADER_OFFSET, PAYLOAD_OFFSET, parse_header};
+
+    /// Reference images generated by `cargo xtask gen-image` with the
+    /// development keys, checked by the xtask tests.
+    const IMAGES: [&[u8]; 3] = [
+        include_bytes!("../../xtask/tests/data/image_none_encryption.img"),
+        include_bytes!("../../xtask/tests/data/image_sm4_encryption.img"),
+        include_bytes!("../../xtask/tests/data/image_aes_encryption.img"),
+    ];
+
+    #[test]
+    fn verify_reference_images() {
+        for image in IMAGES {
+            let image = &image[HEADER_OFFSET..];
+            let header = parse_header(image).unwrap();
+            #[cfg(not(feature = "rsa"))]
+            if let Crypto::Aes { .. } = header.crypto {
+                assert_eq!(verify_signature(&header, image), Err(Error::Unsupported));
+                continue;
+            }
+            assert_eq!(verify_signature(&header, image), Ok(()));
+
+            let mut tampered = image.to_vec();
+            tampered[PAYLOAD_OFFSET + header.payload_len - 1] ^= 1;
+            assert!(verify_signature(&header, &tampered).is_err());
+        }
+    }
+
+    #[test]
+    fn verify_sm2_fields() {
+        let image = &IMAGES[1][HEADER_OFFSET..];
+        let header = parse_header(image).unwrap();
+        let Crypto::Sm4 {
+            id,
+            public_key_x,
+            public_key_y,
+            r,
+            s,
+        } = header.crypto
+        else {
+            panic!("expected SM4 header");
+        };
+        let payload = header.payload(image);
+        assert_eq!(
+            verify_sm2(id.as_bytes(), &public_key_x, &public_key_y, &r, &s, payload),
+            Ok(())
+        );
+        assert_eq!(
+            verify_sm2(b"another id", &public_key_x, &public_key_y, &r, &s, payload),
+            Err(Error::BadSignature)
+        );
+    }
+}
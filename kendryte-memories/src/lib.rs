@@ -0,0 +1,20 @@
+//! Memories of the Kendryte SoCs.
+//!
+//! Shared by `kendryte_hal::memory_map` and the build script of `kendryte-rt`,
+//! which lays out the linker script from the same numbers. The chip is
+//! selected with the `chip-k230` or `chip-k230d` feature, forwarded by both.
+#![no_std]
+
+/// Start of DDR.
+pub const DDR_BASE: usize = 0x0000_0000;
+/// Length of DDR.
+#[cfg(not(feature = "chip-k230d"))]
+pub const DDR_LEN: usize = 0x2000_0000;
+/// Length of DDR.
+#[cfg(feature = "chip-k230d")]
+pub const DDR_LEN: usize = 0x0800_0000;
+
+/// Start of the on-chip SRAM the BootROM loads firmware into.
+pub const SRAM_BASE: usize = 0x8030_0000;
+/// Length of the on-chip SRAM.
+pub const SRAM_LEN: usize = 0x10_0000;
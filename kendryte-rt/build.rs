@@ -1,25 +1,253 @@
+use kendryte_memories as memory_map;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Environment variable naming the memory layout manifest.
+const LAYOUT_VAR: &str = "KENDRYTE_RT_MEMORY_LAYOUT";
+/// Offset of the DDR heap in DDR, which reserved regions must not precede.
+const DDR_HEAP_OFFSET: u64 = 0x1000;
+/// Granule of reserved region addresses and lengths.
+const PAGE: u64 = 0x1000;
+
 fn main() {
+    match (cfg!(feature = "chip-k230"), cfg!(feature = "chip-k230d")) {
+        (true, true) => panic!("features `chip-k230` and `chip-k230d` select different chips"),
+        (false, false) => panic!("select the chip with feature `chip-k230` or `chip-k230d`"),
+        _ => {}
+    }
     let (out, ld) = {
         use std::{env, path::PathBuf};
         let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
         let ld = out.join("kendryte-rt.ld");
         (out, ld)
     };
+    let layout = read_layout();
+    std::fs::write(out.join("mem_layout.rs"), layout_module(&layout)).unwrap();
     #[cfg(feature = "k230")]
-    std::fs::write(&ld, LINKER_SCRIPT_K230).unwrap();
+    {
+        let (spl, ddr) = (&layout.spl, &layout.ddr);
+        // The DDR heap ends where the first reserved region starts.
+        let ddr_heap_end = match layout.regions.iter().map(|r| r.origin).min() {
+            Some(origin) => format!("{origin:#x}"),
+            None => "ORIGIN(DDR) + LENGTH(DDR)".to_string(),
+        };
+        let script = LINKER_SCRIPT_K230
+            .replace("{spl_origin}", &format!("{:#010x}", spl.origin))
+            .replace("{spl_len}", &format!("{:#x}", spl.length))
+            .replace("{ddr_origin}", &format!("{:#010x}", ddr.origin))
+            .replace("{ddr_len}", &format!("{:#010x}", ddr.length))
+            .replace("{ddr_heap_offset}", &format!("{DDR_HEAP_OFFSET:#x}"))
+            .replace("{ddr_heap_end}", &ddr_heap_end)
+            .replace("{mem_layout}", &layout_symbols(&layout.regions));
+        std::fs::write(&ld, script).unwrap();
+    }
 
     println!("cargo:rustc-link-search={}", out.display());
     let _ = (ld, out);
 }
 
+/// Region of memory named in the memory layout manifest.
+struct Region {
+    name: String,
+    origin: u64,
+    length: u64,
+}
+
+impl Region {
+    fn new(name: &str, origin: usize, length: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            origin: origin as u64,
+            length: length as u64,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.origin + self.length
+    }
+}
+
+/// Memories the firmware is linked into, and the reserved DDR regions.
+struct Layout {
+    spl: Region,
+    ddr: Region,
+    regions: Vec<Region>,
+}
+
+/// Reads and checks the manifest named by [`LAYOUT_VAR`], if it is set.
+///
+/// ```toml
+/// # Optional; each defaults to the whole memory of the chip.
+/// [memory.spl]
+/// origin = 0x80300000
+/// length = 0x00100000
+///
+/// [memory.ddr]
+/// origin = 0x00000000
+/// length = 0x10000000
+///
+/// [regions.dma_pool]
+/// origin = 0x0f000000
+/// length = 0x00800000
+/// ```
+fn read_layout() -> Layout {
+    let mut layout = Layout {
+        spl: Region::new("spl", memory_map::SRAM_BASE, memory_map::SRAM_LEN),
+        ddr: Region::new("ddr", memory_map::DDR_BASE, memory_map::DDR_LEN),
+        regions: Vec::new(),
+    };
+    println!("cargo:rerun-if-env-changed={LAYOUT_VAR}");
+    let Some(path) = std::env::var_os(LAYOUT_VAR) else {
+        return layout;
+    };
+    let path = Path::new(&path);
+    println!("cargo:rerun-if-changed={}", path.display());
+    let fail = |message: String| -> ! { panic!("{}: {message}", path.display()) };
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| fail(e.to_string()));
+    let manifest: toml::Table = text.parse().unwrap_or_else(|e| fail(format!("{e}")));
+    let table = |key: &str| match manifest.get(key) {
+        None => toml::Table::new(),
+        Some(toml::Value::Table(entries)) => entries.clone(),
+        Some(_) => fail(format!("`{key}` must be a table")),
+    };
+    let read = |name: &str, entry: &toml::Value| {
+        let number = |key: &str| match entry.get(key).and_then(toml::Value::as_integer) {
+            Some(value) if value >= 0 => value as u64,
+            _ => fail(format!(
+                "region `{name}` needs a non-negative integer `{key}`"
+            )),
+        };
+        Region {
+            name: name.to_string(),
+            origin: number("origin"),
+            length: number("length"),
+        }
+    };
+
+    for (name, entry) in table("memory") {
+        let chip = match name.as_str() {
+            "spl" => &mut layout.spl,
+            "ddr" => &mut layout.ddr,
+            _ => fail(format!("unknown memory `{name}`, expected `spl` or `ddr`")),
+        };
+        let memory = read(&name, &entry);
+        if memory.length == 0 || memory.origin < chip.origin || memory.end() > chip.end() {
+            fail(format!(
+                "memory `{name}` must lie within {:#x} to {:#x}",
+                chip.origin,
+                chip.end()
+            ));
+        }
+        *chip = memory;
+    }
+
+    let ddr = &layout.ddr;
+    for (name, entry) in table("regions") {
+        let valid_name = name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            fail(format!("region name `{name}` must be lowercase snake case"));
+        }
+        if name == "spl" || name == "ddr" {
+            fail(format!("region name `{name}` is taken by a memory"));
+        }
+        #[cfg(feature = "k230")]
+        if script_symbol_names(LINKER_SCRIPT_K230).contains(&name.as_str()) {
+            fail(format!(
+                "region name `{name}` is taken by the runtime's `_{name}_start` and `_{name}_end` symbols"
+            ));
+        }
+        let region = read(&name, &entry);
+        if region.length == 0 || region.origin % PAGE != 0 || region.length % PAGE != 0 {
+            fail(format!(
+                "region `{name}` must be a non-empty run of 4 KiB pages"
+            ));
+        }
+        let heap_start = ddr.origin + DDR_HEAP_OFFSET;
+        if region.origin < heap_start || region.end() > ddr.end() {
+            fail(format!(
+                "region `{name}` must lie within DDR, from {heap_start:#x} to {:#x}",
+                ddr.end()
+            ));
+        }
+        layout.regions.push(region);
+    }
+    let regions = &mut layout.regions;
+    regions.sort_by_key(|r| r.origin);
+    for pair in regions.windows(2) {
+        if pair[0].end() > pair[1].origin {
+            fail(format!(
+                "regions `{}` and `{}` overlap",
+                pair[0].name, pair[1].name
+            ));
+        }
+    }
+    layout
+}
+
+/// Generates the constants included by `kendryte_rt::mem_layout`.
+fn layout_module(layout: &Layout) -> String {
+    let mut module = String::new();
+    let memories = [
+        (&layout.spl, "SRAM the firmware is linked into.".to_string()),
+        (&layout.ddr, "DDR the firmware can use.".to_string()),
+    ];
+    let regions = layout.regions.iter().map(|r| {
+        let doc = format!("Region `{}` of the memory layout manifest.", r.name);
+        (r, doc)
+    });
+    for (r, doc) in memories.into_iter().chain(regions) {
+        writeln!(module, "/// {doc}").unwrap();
+        writeln!(
+            module,
+            "pub const {}: Region = Region::new({:#x}, {:#x});",
+            r.name.to_uppercase(),
+            r.origin,
+            r.length
+        )
+        .unwrap();
+    }
+    module
+}
+
+/// Returns the names `<name>` of the `_<name>_start` and `_<name>_end`
+/// symbols `script` defines, which a region must not define again.
+#[cfg(feature = "k230")]
+fn script_symbol_names(script: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = script
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter_map(|word| {
+            let word = word.strip_prefix('_')?;
+            word.strip_suffix("_start").or(word.strip_suffix("_end"))
+        })
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Generates the `_<name>_start` and `_<name>_end` symbols of each region.
+#[cfg(feature = "k230")]
+fn layout_symbols(regions: &[Region]) -> String {
+    let mut symbols = String::new();
+    for r in regions {
+        writeln!(symbols, "_{}_start = {:#x};", r.name, r.origin).unwrap();
+        writeln!(symbols, "_{}_end = {:#x};", r.name, r.end()).unwrap();
+    }
+    symbols
+}
+
 #[cfg(feature = "k230")]
-const LINKER_SCRIPT_K230: &[u8] = b"
+const LINKER_SCRIPT_K230: &str = "
 OUTPUT_ARCH(riscv)
 
 ENTRY(_start)
 
 MEMORY {
-    SPL : ORIGIN = 0x80300000, LENGTH = 0x100000
+    SPL : ORIGIN = {spl_origin}, LENGTH = {spl_len}
+    DDR : ORIGIN = {ddr_origin}, LENGTH = {ddr_len}
 }
 
 SECTIONS
@@ -57,8 +285,24 @@ SECTIONS
         ebss = .;
     } > SPL
 
+    .noinit (NOLOAD) : ALIGN(8) {
+        *(.noinit .noinit.*)
+    } > SPL
+
+    .heap (NOLOAD) : ALIGN(16) {
+        _sheap = .;
+    } > SPL
+    _eheap = ORIGIN(SPL) + LENGTH(SPL);
+
+    PROVIDE(_ddr_heap_start = ORIGIN(DDR) + {ddr_heap_offset});
+    PROVIDE(_ddr_heap_end = {ddr_heap_end});
+
+    _ram_start = ORIGIN(SPL);
+    _ram_end = ORIGIN(SPL) + LENGTH(SPL);
+
     /DISCARD/ : {
         *(.eh_frame)
     }
 }
-";
+
+{mem_layout}";
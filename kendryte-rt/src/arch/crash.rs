@@ -0,0 +1,262 @@
+//! Crash dumps of fatal exceptions.
+//!
+//! The [trap entry](super::trap) sends every exception to [`start_trap`],
+//! which switches to a dedicated trap stack, so stack overflows are caught
+//! too, and records every register, the trap CSRs and a frame-pointer
+//! backtrace into a `.noinit` RAM section before halting the hart. That
+//! section is not cleared at start, so after a warm reset the application
+//! can print the dump:
+//!
+//! ```ignore
+//! if let Some(dump) = kendryte_rt::arch::crash::take() {
+//!     writeln!(console, "{dump}").ok();
+//! }
+//! ```
+//!
+//! `cargo xtask symbolicate` resolves the addresses of the printed dump
+//! against the firmware ELF. Backtraces need frame pointers, enabled with
+//! `-C force-frame-pointers=yes`.
+
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Marks a valid dump, "CRSH".
+const MAGIC: usize = 0x4352_5348;
+
+/// Largest number of backtrace frames recorded.
+pub const BACKTRACE_DEPTH: usize = 16;
+
+/// Size of the stack the trap handler runs on.
+const TRAP_STACK_SIZE: usize = 4 * 1024;
+
+/// ABI names of `x0` to `x31`.
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// State of the hart when a fatal exception was taken.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrashDump {
+    magic: usize,
+    /// Registers `x0` to `x31`; `sp` is the stack pointer at the trap.
+    pub regs: [usize; 32],
+    /// Machine cause register.
+    pub mcause: usize,
+    /// Machine exception program counter register.
+    pub mepc: usize,
+    /// Machine trap value register, the faulting address or instruction.
+    pub mtval: usize,
+    /// Machine status register.
+    pub mstatus: usize,
+    /// Return addresses, innermost first.
+    pub backtrace: [usize; BACKTRACE_DEPTH],
+    /// Number of valid entries in `backtrace`.
+    pub depth: usize,
+}
+
+impl CrashDump {
+    /// Returns a description of the exception.
+    pub fn cause(&self) -> &'static str {
+        cause_name(self.mcause)
+    }
+
+    /// Returns the recorded return addresses, innermost first.
+    #[inline]
+    pub fn backtrace(&self) -> &[usize] {
+        &self.backtrace[..self.depth.min(BACKTRACE_DEPTH)]
+    }
+}
+
+/// Returns a description of a machine cause register value.
+pub fn cause_name(mcause: usize) -> &'static str {
+    if mcause >> (usize::BITS - 1) != 0 {
+        return "unexpected interrupt";
+    }
+    match mcause {
+        0 => "instruction address misaligned",
+        1 => "instruction access fault",
+        2 => "illegal instruction",
+        3 => "breakpoint",
+        4 => "load address misaligned",
+        5 => "load access fault",
+        6 => "store address misaligned",
+        7 => "store access fault",
+        8 => "environment call from U-mode",
+        9 => "environment call from S-mode",
+        11 => "environment call from M-mode",
+        12 => "instruction page fault",
+        13 => "load page fault",
+        15 => "store page fault",
+        _ => "unknown exception",
+    }
+}
+
+/// Prints the dump in the format `cargo xtask symbolicate` reads.
+impl fmt::Display for CrashDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "crash: {}", self.cause())?;
+        writeln!(
+            f,
+            "mcause={:#x} mepc={:#x} mtval={:#x} mstatus={:#x}",
+            self.mcause, self.mepc, self.mtval, self.mstatus
+        )?;
+        for (i, (name, value)) in REGISTER_NAMES.iter().zip(&self.regs).enumerate().skip(1) {
+            write!(f, "{name}={value:#x}")?;
+            f.write_str(if i % 4 == 3 { "\n" } else { " " })?;
+        }
+        f.write_str("backtrace:")?;
+        for address in self.backtrace() {
+            write!(f, " {address:#x}")?;
+        }
+        writeln!(f)
+    }
+}
+
+#[unsafe(link_section = ".noinit")]
+static mut CRASH: MaybeUninit<CrashDump> = MaybeUninit::uninit();
+
+/// Takes the dump of the last crash, if one was recorded since it was last taken.
+pub fn take() -> Option<CrashDump> {
+    let crash = &raw mut CRASH;
+    // The magic word is checked before trusting the rest of uninitialized RAM.
+    unsafe {
+        let magic = &raw mut (*crash.cast::<CrashDump>()).magic;
+        if magic.read_volatile() != MAGIC {
+            return None;
+        }
+        magic.write_volatile(0);
+        Some(crash.cast::<CrashDump>().read_volatile())
+    }
+}
+
+/// Exception entry: saves the registers on the trap stack and records a crash dump.
+#[cfg(target_arch = "riscv64")]
+#[unsafe(naked)]
+pub unsafe extern "C" fn start_trap() -> ! {
+    use crate::arch::rvi::Stack;
+
+    #[unsafe(link_section = ".bss.uninit")]
+    static mut TRAP_STACK: Stack<TRAP_STACK_SIZE> = Stack([0; TRAP_STACK_SIZE]);
+
+    core::arch::naked_asm!(
+        // Keep the faulting stack pointer, it may be the cause
+        "csrw   mscratch, sp
+         la     sp, {stack} + {stack_size} - 256",
+        "sd     x1, 8(sp)
+         sd     x3, 24(sp)
+         sd     x4, 32(sp)
+         sd     x5, 40(sp)
+         sd     x6, 48(sp)
+         sd     x7, 56(sp)
+         sd     x8, 64(sp)
+         sd     x9, 72(sp)
+         sd     x10, 80(sp)
+         sd     x11, 88(sp)
+         sd     x12, 96(sp)
+         sd     x13, 104(sp)
+         sd     x14, 112(sp)
+         sd     x15, 120(sp)
+         sd     x16, 128(sp)
+         sd     x17, 136(sp)
+         sd     x18, 144(sp)
+         sd     x19, 152(sp)
+         sd     x20, 160(sp)
+         sd     x21, 168(sp)
+         sd     x22, 176(sp)
+         sd     x23, 184(sp)
+         sd     x24, 192(sp)
+         sd     x25, 200(sp)
+         sd     x26, 208(sp)
+         sd     x27, 216(sp)
+         sd     x28, 224(sp)
+         sd     x29, 232(sp)
+         sd     x30, 240(sp)
+         sd     x31, 248(sp)
+         sd     zero, 0(sp)
+         csrr   t0, mscratch
+         sd     t0, 16(sp)",
+        "mv     a0, sp
+         call   {handler}",
+        stack      = sym TRAP_STACK,
+        stack_size = const TRAP_STACK_SIZE,
+        handler    = sym record,
+    )
+}
+
+/// Set while recording, so a fault in the handler itself halts instead of recursing.
+#[cfg(target_arch = "riscv64")]
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_arch = "riscv64")]
+extern "C" fn record(regs: &[usize; 32]) -> ! {
+    unsafe extern "C" {
+        static _ram_start: u8;
+        static _ram_end: u8;
+    }
+
+    if !RECORDING.swap(true, Ordering::Relaxed) {
+        let (mcause, mepc, mtval, mstatus): (usize, usize, usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "csrr {0}, mcause",
+                "csrr {1}, mepc",
+                "csrr {2}, mtval",
+                "csrr {3}, mstatus",
+                out(reg) mcause,
+                out(reg) mepc,
+                out(reg) mtval,
+                out(reg) mstatus,
+            );
+        }
+        let ram = unsafe { (&raw const _ram_start as usize)..(&raw const _ram_end as usize) };
+        let (backtrace, depth) = unwind(regs[8], ram);
+        let dump = CrashDump {
+            magic: MAGIC,
+            regs: *regs,
+            mcause,
+            mepc,
+            mtval,
+            mstatus,
+            backtrace,
+            depth,
+        };
+        unsafe { (&raw mut CRASH).cast::<CrashDump>().write_volatile(dump) };
+    }
+    loop {
+        unsafe { core::arch::asm!("wfi") };
+    }
+}
+
+/// Follows the frame pointer chain from `fp`, staying within `ram`.
+///
+/// With frame pointers, the return address is saved just below the frame
+/// pointer and the caller's frame pointer below that.
+fn unwind(mut fp: usize, ram: core::ops::Range<usize>) -> ([usize; BACKTRACE_DEPTH], usize) {
+    let mut backtrace = [0; BACKTRACE_DEPTH];
+    let mut depth = 0;
+    let word = size_of::<usize>();
+    while depth < BACKTRACE_DEPTH && fp % word == 0 && fp >= ram.start + 2 * word && fp <= ram.end {
+        // Bounds and alignment checked above.
+        let (ra, next) = unsafe {
+            (
+                ((fp - word) as *const usize).read_volatile(),
+                ((fp - 2 * word) as *const usize).read_volatile(),
+            )
+        };
+        if ra == 0 {
+            break;
+        }
+        backtrace[depth] = ra;
+        depth += 1;
+        // Frames move up the stack; anything else is a corrupt chain.
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+    (backtrace, depth)
+}
@@ -0,0 +1,215 @@
+//! Hart identity, per-hart storage and parking.
+//!
+//! Dual-core applications keep one instance of per-core state, such as a
+//! console UART, for each hart in a [`HartLocal`], declared with
+//! [`hart_local!`](crate::hart_local):
+//!
+//! ```ignore
+//! use kendryte_hal::sync::Mutex;
+//! use kendryte_rt::arch::hart;
+//!
+//! kendryte_rt::hart_local! {
+//!     static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+//! }
+//!
+//! *CONSOLE.get().lock() = Some(console);
+//! ```
+//!
+//! A hart with nothing to do waits in [`park`] until another hart calls
+//! [`unpark`] on it.
+//!
+//! Only hart 0 runs the `#[entry]` function, after it initializes `.data`
+//! and `.bss`. Every other hart waits in [`park`] on its own stack until
+//! hart 0 gives it a function to run with [`start`]:
+//!
+//! ```ignore
+//! hart::start(1, || loop { render_frame() });
+//! ```
+
+#[cfg(target_arch = "riscv64")]
+use core::ptr;
+#[cfg(target_arch = "riscv64")]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(target_arch = "riscv64")]
+use kendryte_hal::memory_map;
+
+/// Number of harts of the largest supported SoC, the two K230 cores.
+pub const MAX_HARTS: usize = 2;
+
+/// Returns the ID of the hart running the caller.
+#[cfg(target_arch = "riscv64")]
+#[inline(always)]
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe { core::arch::asm!("csrr {0}, mhartid", out(reg) id) };
+    id
+}
+
+/// Checks whether the caller runs on hart 0, the hart that boots.
+#[cfg(target_arch = "riscv64")]
+#[inline]
+pub fn is_boot_hart() -> bool {
+    hart_id() == 0
+}
+
+/// One value per hart, each only reached from its own hart.
+///
+/// Interrupt handlers on a hart reach the same value as the code they
+/// interrupt, so a static needs `T: Sync`, as for any other static.
+pub struct HartLocal<T, const N: usize = MAX_HARTS> {
+    values: [T; N],
+}
+
+impl<T, const N: usize> HartLocal<T, N> {
+    /// Creates the storage from the value of each hart.
+    #[inline]
+    pub const fn new(values: [T; N]) -> Self {
+        Self { values }
+    }
+
+    /// Returns the value of the hart running the caller.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hart ID is not below `N`.
+    #[cfg(target_arch = "riscv64")]
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.values[hart_id()]
+    }
+
+    /// Returns the values of all harts, for inspection once they are parked.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut [T; N] {
+        &mut self.values
+    }
+}
+
+/// Declares statics holding one value per hart.
+///
+/// The initializer must be a constant expression, evaluated once for each hart.
+#[macro_export]
+macro_rules! hart_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)+) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::arch::hart::HartLocal<$ty> =
+                $crate::arch::hart::HartLocal::new(
+                    [const { $init }; $crate::arch::hart::MAX_HARTS],
+                );
+        )+
+    };
+}
+
+/// Wake-up flags of the parked harts.
+#[cfg(target_arch = "riscv64")]
+static WAKE: [AtomicBool; MAX_HARTS] = [const { AtomicBool::new(false) }; MAX_HARTS];
+
+/// Machine software interrupt bit of `mie`.
+#[cfg(target_arch = "riscv64")]
+const MIE_MSIE: usize = 1 << 3;
+
+/// Returns the software interrupt pending register of `hart`.
+#[cfg(target_arch = "riscv64")]
+fn msip(hart: usize) -> *mut u32 {
+    (memory_map::CLINT + hart * 4) as *mut u32
+}
+
+/// Waits until another hart calls [`unpark`] on the caller's hart.
+///
+/// A wake-up sent before the call is not lost; the hart returns at once.
+/// The hart sleeps in `wfi` until the software interrupt [`unpark`] sends,
+/// with interrupts masked so the interrupt is not taken.
+#[cfg(target_arch = "riscv64")]
+pub fn park() {
+    let hart = hart_id();
+    let wake = &WAKE[hart];
+    let (mstatus, mie): (usize, usize);
+    unsafe {
+        core::arch::asm!(
+            "csrrci {0}, mstatus, 8",
+            "csrrs  {1}, mie, {2}",
+            out(reg) mstatus,
+            out(reg) mie,
+            in(reg) MIE_MSIE,
+        )
+    };
+    loop {
+        // Cleared before the flag is checked, so a wake-up in between still ends `wfi`.
+        unsafe { msip(hart).write_volatile(0) };
+        if wake.swap(false, Ordering::Acquire) {
+            break;
+        }
+        unsafe { core::arch::asm!("wfi") };
+    }
+    unsafe {
+        core::arch::asm!(
+            "csrc   mie, {0}",
+            "csrs   mstatus, {1}",
+            in(reg) MIE_MSIE & !mie,
+            in(reg) mstatus & 8,
+        )
+    };
+}
+
+/// Wakes `hart` from [`park`], or makes its next call return at once.
+///
+/// # Panics
+///
+/// Panics if `hart` is not below [`MAX_HARTS`].
+#[cfg(target_arch = "riscv64")]
+pub fn unpark(hart: usize) {
+    WAKE[hart].store(true, Ordering::Release);
+    // The flag must be visible before the interrupt wakes the hart.
+    unsafe {
+        core::arch::asm!("fence w, o");
+        msip(hart).write_volatile(1);
+    }
+}
+
+/// Functions the other harts run once started, kept in `.data` so they
+/// read as unset before hart 0 initializes memory.
+#[cfg(target_arch = "riscv64")]
+#[unsafe(link_section = ".data.hart_entry")]
+static ENTRY: [AtomicPtr<()>; MAX_HARTS] = [const { AtomicPtr::new(ptr::null_mut()) }; MAX_HARTS];
+
+/// Starts `hart`, parked since reset, running `entry` on its own stack.
+///
+/// # Panics
+///
+/// Panics if `hart` is 0 or not below [`MAX_HARTS`].
+#[cfg(target_arch = "riscv64")]
+pub fn start(hart: usize, entry: fn() -> !) {
+    assert!(hart != 0, "hart 0 runs the entry function");
+    ENTRY[hart].store(entry as *mut (), Ordering::Release);
+    unpark(hart);
+}
+
+/// Parks a hart other than hart 0 on reset until [`start`] gives it an entry.
+#[cfg(target_arch = "riscv64")]
+pub(crate) extern "C" fn secondary_start() -> ! {
+    let entry = &ENTRY[hart_id()];
+    loop {
+        // Wake-up flags are undefined until hart 0 clears `.bss`, so a
+        // wake-up only counts once the entry is set.
+        park();
+        let entry = entry.load(Ordering::Acquire);
+        if !entry.is_null() {
+            // Only ever stored from a `fn() -> !`.
+            let entry: fn() -> ! = unsafe { core::mem::transmute(entry) };
+            entry();
+        }
+    }
+}
+
+/// Stops the caller's hart for good, waiting for interrupts only.
+pub fn halt() -> ! {
+    loop {
+        #[cfg(target_arch = "riscv64")]
+        unsafe {
+            core::arch::asm!("wfi")
+        };
+        #[cfg(not(target_arch = "riscv64"))]
+        core::hint::spin_loop();
+    }
+}
@@ -1,2 +1,8 @@
+pub mod crash;
+pub mod hart;
+pub mod perf;
 pub mod rve;
 pub mod rvi;
+#[cfg(target_arch = "riscv64")]
+pub mod timer;
+pub mod trap;
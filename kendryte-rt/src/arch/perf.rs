@@ -0,0 +1,238 @@
+//! Hardware performance counters.
+//!
+//! Reads the cycle and retired instruction counters and programs the
+//! C908 event counters `mhpmcounter3` to `mhpmcounter6`, for measuring
+//! code on target:
+//!
+//! ```ignore
+//! use kendryte_rt::arch::perf::{self, Counter, Event};
+//!
+//! perf::configure(Counter::Hpm3, Event::L1DCacheReadMiss);
+//! let sample = kendryte_rt::bench!(16, copy(&src, &mut dst));
+//! let per_copy = sample.per_iteration(16);
+//! ```
+//!
+//! Counters run in machine mode, as the runtime does. Only [`Sample`]
+//! arithmetic is available when building for the host.
+
+/// Events counted by the C908 event counters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Event {
+    /// Counting disabled.
+    None = 0x00,
+    /// L1 instruction cache accesses.
+    L1ICacheAccess = 0x01,
+    /// L1 instruction cache misses.
+    L1ICacheMiss = 0x02,
+    /// Instruction micro-TLB misses.
+    ITlbMiss = 0x03,
+    /// Data micro-TLB misses.
+    DTlbMiss = 0x04,
+    /// Joint TLB misses.
+    JTlbMiss = 0x05,
+    /// Mispredicted conditional branches.
+    BranchMispredict = 0x06,
+    /// Retired conditional branches.
+    Branch = 0x07,
+    /// L1 data cache read accesses.
+    L1DCacheReadAccess = 0x0C,
+    /// L1 data cache read misses.
+    L1DCacheReadMiss = 0x0D,
+    /// L1 data cache write accesses.
+    L1DCacheWriteAccess = 0x0E,
+    /// L1 data cache write misses.
+    L1DCacheWriteMiss = 0x0F,
+}
+
+/// Programmable event counters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    Hpm3,
+    Hpm4,
+    Hpm5,
+    Hpm6,
+}
+
+impl Counter {
+    /// All programmable counters, in order.
+    pub const ALL: [Counter; 4] = [Counter::Hpm3, Counter::Hpm4, Counter::Hpm5, Counter::Hpm6];
+}
+
+#[cfg(target_arch = "riscv64")]
+macro_rules! read_csr {
+    ($csr:literal) => {{
+        let value: u64;
+        unsafe { core::arch::asm!(concat!("csrr {0}, ", $csr), out(reg) value) };
+        value
+    }};
+}
+
+#[cfg(target_arch = "riscv64")]
+macro_rules! write_csr {
+    ($csr:literal, $value:expr) => {{
+        let value: u64 = $value;
+        unsafe { core::arch::asm!(concat!("csrw ", $csr, ", {0}"), in(reg) value) };
+    }};
+}
+
+/// Returns the number of cycles elapsed.
+#[cfg(target_arch = "riscv64")]
+#[inline(always)]
+pub fn cycles() -> u64 {
+    read_csr!("mcycle")
+}
+
+/// Returns the number of instructions retired.
+#[cfg(target_arch = "riscv64")]
+#[inline(always)]
+pub fn instructions() -> u64 {
+    read_csr!("minstret")
+}
+
+/// Returns the value of an event counter.
+#[cfg(target_arch = "riscv64")]
+#[inline(always)]
+pub fn read(counter: Counter) -> u64 {
+    match counter {
+        Counter::Hpm3 => read_csr!("mhpmcounter3"),
+        Counter::Hpm4 => read_csr!("mhpmcounter4"),
+        Counter::Hpm5 => read_csr!("mhpmcounter5"),
+        Counter::Hpm6 => read_csr!("mhpmcounter6"),
+    }
+}
+
+/// Selects the event `counter` counts, resets it, and starts counting.
+#[cfg(target_arch = "riscv64")]
+pub fn configure(counter: Counter, event: Event) {
+    let event = event as u64;
+    match counter {
+        Counter::Hpm3 => {
+            write_csr!("mhpmevent3", event);
+            write_csr!("mhpmcounter3", 0);
+        }
+        Counter::Hpm4 => {
+            write_csr!("mhpmevent4", event);
+            write_csr!("mhpmcounter4", 0);
+        }
+        Counter::Hpm5 => {
+            write_csr!("mhpmevent5", event);
+            write_csr!("mhpmcounter5", 0);
+        }
+        Counter::Hpm6 => {
+            write_csr!("mhpmevent6", event);
+            write_csr!("mhpmcounter6", 0);
+        }
+    }
+    // Clear the inhibit bit of the cycle, instret and event counters.
+    let inhibit = read_csr!("mcountinhibit");
+    write_csr!(
+        "mcountinhibit",
+        inhibit & !(0b101 | 1 << (counter as u64 + 3))
+    );
+}
+
+/// Counter values at one point in time, or the difference of two.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sample {
+    /// Cycles.
+    pub cycles: u64,
+    /// Retired instructions.
+    pub instructions: u64,
+    /// Event counters, indexed like [`Counter::ALL`].
+    pub events: [u64; 4],
+}
+
+impl Sample {
+    /// Reads all counters.
+    #[cfg(target_arch = "riscv64")]
+    #[inline(always)]
+    pub fn now() -> Self {
+        Sample {
+            cycles: cycles(),
+            instructions: instructions(),
+            events: Counter::ALL.map(read),
+        }
+    }
+
+    /// Returns the counts since `earlier`.
+    pub fn since(&self, earlier: &Sample) -> Sample {
+        Sample {
+            cycles: self.cycles.wrapping_sub(earlier.cycles),
+            instructions: self.instructions.wrapping_sub(earlier.instructions),
+            events: core::array::from_fn(|i| self.events[i].wrapping_sub(earlier.events[i])),
+        }
+    }
+
+    /// Divides the counts by the number of iterations measured.
+    pub fn per_iteration(&self, iterations: u64) -> Sample {
+        let iterations = iterations.max(1);
+        Sample {
+            cycles: self.cycles / iterations,
+            instructions: self.instructions / iterations,
+            events: self.events.map(|count| count / iterations),
+        }
+    }
+
+    /// Returns the event count of `counter`.
+    #[inline]
+    pub fn event(&self, counter: Counter) -> u64 {
+        self.events[counter as usize]
+    }
+}
+
+/// Measures the scope it lives in, passing the counts to a callback on drop.
+///
+/// ```ignore
+/// let _guard = perf::Guard::new(|sample| log_cycles(sample.cycles));
+/// ```
+#[cfg(target_arch = "riscv64")]
+pub struct Guard<F: FnOnce(Sample)> {
+    start: Sample,
+    report: Option<F>,
+}
+
+#[cfg(target_arch = "riscv64")]
+impl<F: FnOnce(Sample)> Guard<F> {
+    /// Starts measuring.
+    #[inline(always)]
+    pub fn new(report: F) -> Self {
+        Guard {
+            start: Sample::now(),
+            report: Some(report),
+        }
+    }
+
+    /// Returns the counts so far.
+    #[inline(always)]
+    pub fn elapsed(&self) -> Sample {
+        Sample::now().since(&self.start)
+    }
+}
+
+#[cfg(target_arch = "riscv64")]
+impl<F: FnOnce(Sample)> Drop for Guard<F> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let sample = self.elapsed();
+        if let Some(report) = self.report.take() {
+            report(sample);
+        }
+    }
+}
+
+/// Runs an expression a number of times and returns the total [`Sample`].
+///
+/// ```ignore
+/// let sample = bench!(100, checksum(&buffer));
+/// ```
+#[macro_export]
+macro_rules! bench {
+    ($iterations:expr, $body:expr) => {{
+        let start = $crate::arch::perf::Sample::now();
+        for _ in 0..$iterations {
+            core::hint::black_box($body);
+        }
+        $crate::arch::perf::Sample::now().since(&start)
+    }};
+}
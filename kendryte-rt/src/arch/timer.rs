@@ -0,0 +1,54 @@
+//! The machine timer, read through the `time` CSR.
+//!
+//! The timer counts at [`Clocks::timer`] whatever the CPU frequency, so it
+//! backs delays and timeouts:
+//!
+//! ```ignore
+//! let mut delay = kendryte_rt::arch::timer::delay(c);
+//! delay.delay_ms(500);
+//! ```
+
+use embedded_time::rate::Hertz;
+use kendryte_hal::clocks::Clocks;
+use kendryte_hal::delay::Delay;
+use kendryte_hal::timeout::Monotonic;
+
+/// Returns the machine timer count.
+#[inline(always)]
+pub fn now() -> u64 {
+    let ticks: u64;
+    unsafe { core::arch::asm!("csrr {0}, time", out(reg) ticks) };
+    ticks
+}
+
+/// The machine timer as a [`Monotonic`] counter.
+#[derive(Clone, Copy, Debug)]
+pub struct MachineTimer {
+    clocks: Clocks,
+}
+
+impl MachineTimer {
+    /// Creates the counter, with its frequency taken from `clocks`.
+    #[inline]
+    pub fn new(clocks: Clocks) -> Self {
+        Self { clocks }
+    }
+}
+
+impl Monotonic for MachineTimer {
+    #[inline]
+    fn now(&self) -> u64 {
+        now()
+    }
+
+    #[inline]
+    fn frequency(&self) -> Hertz {
+        self.clocks.timer()
+    }
+}
+
+/// Returns a delay measured on the machine timer.
+#[inline]
+pub fn delay(clocks: Clocks) -> Delay<MachineTimer> {
+    Delay::new(MachineTimer::new(clocks))
+}
@@ -0,0 +1,124 @@
+//! Trap entry and interrupt dispatch.
+//!
+//! The runtime points `mtvec` at [`_start_trap`](start_trap). Exceptions
+//! are fatal and go to the [crash recorder](super::crash); interrupts are
+//! handled on the interrupted stack by the handler registered with
+//! [`set_handler`], and execution resumes where it was interrupted:
+//!
+//! ```ignore
+//! kendryte_rt::arch::trap::set_handler(|code| match code {
+//!     trap::MACHINE_EXTERNAL => plic_dispatch(),
+//!     trap::MACHINE_TIMER => scheduler_tick(),
+//!     _ => {}
+//! });
+//! ```
+//!
+//! Handlers run with machine interrupts disabled. An interrupt arriving
+//! without a handler is disabled in `mie`, so it cannot fire forever.
+
+use super::rvi::TrapFrame;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Interrupt code of the machine software interrupt, the inter-hart interrupt.
+pub const MACHINE_SOFTWARE: usize = 3;
+/// Interrupt code of the machine timer interrupt.
+pub const MACHINE_TIMER: usize = 7;
+/// Interrupt code of the machine external interrupt, from the PLIC.
+pub const MACHINE_EXTERNAL: usize = 11;
+
+/// Handles the interrupt of the given code, `mcause` without its interrupt bit.
+pub type Handler = fn(usize);
+
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers the handler of every interrupt.
+pub fn set_handler(handler: Handler) {
+    HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+fn handler() -> Option<Handler> {
+    let handler = HANDLER.load(Ordering::Acquire);
+    // Only ever stored from a `Handler`.
+    (!handler.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), Handler>(handler) })
+}
+
+/// Size of the saved [`TrapFrame`], rounded up to keep `sp` 16-byte aligned.
+#[cfg(target_arch = "riscv64")]
+const FRAME_SIZE: usize = size_of::<TrapFrame>().next_multiple_of(16);
+
+/// Trap entry: sends exceptions to the crash recorder and dispatches interrupts.
+#[cfg(target_arch = "riscv64")]
+#[unsafe(naked)]
+#[unsafe(export_name = "_start_trap")]
+pub unsafe extern "C" fn start_trap() {
+    core::arch::naked_asm!(
+        // Exceptions keep every register for the dump
+        "csrw   mscratch, t0
+         csrr   t0, mcause
+         bltz   t0, 1f
+         csrr   t0, mscratch
+         j      {crash}",
+        // Interrupts save the caller-saved registers on the current stack
+        "1:  csrr   t0, mscratch
+             addi   sp, sp, -{frame_size}
+             sd     ra, 0(sp)
+             sd     t0, 8(sp)
+             sd     t1, 16(sp)
+             sd     t2, 24(sp)
+             sd     a0, 32(sp)
+             sd     a1, 40(sp)
+             sd     a2, 48(sp)
+             sd     a3, 56(sp)
+             sd     a4, 64(sp)
+             sd     a5, 72(sp)
+             sd     a6, 80(sp)
+             sd     a7, 88(sp)
+             sd     t3, 96(sp)
+             sd     t4, 104(sp)
+             sd     t5, 112(sp)
+             sd     t6, 120(sp)
+             csrr   t0, mcause
+             sd     t0, 128(sp)
+             csrr   t0, mepc
+             sd     t0, 136(sp)
+             csrr   t0, mstatus
+             sd     t0, 144(sp)",
+        "mv     a0, sp
+         call   {dispatch}",
+        "ld     t0, 136(sp)
+         csrw   mepc, t0
+         ld     t0, 144(sp)
+         csrw   mstatus, t0
+         ld     ra, 0(sp)
+         ld     t0, 8(sp)
+         ld     t1, 16(sp)
+         ld     t2, 24(sp)
+         ld     a0, 32(sp)
+         ld     a1, 40(sp)
+         ld     a2, 48(sp)
+         ld     a3, 56(sp)
+         ld     a4, 64(sp)
+         ld     a5, 72(sp)
+         ld     a6, 80(sp)
+         ld     a7, 88(sp)
+         ld     t3, 96(sp)
+         ld     t4, 104(sp)
+         ld     t5, 112(sp)
+         ld     t6, 120(sp)
+         addi   sp, sp, {frame_size}
+         mret",
+        crash      = sym super::crash::start_trap,
+        dispatch   = sym dispatch,
+        frame_size = const FRAME_SIZE,
+    )
+}
+
+#[cfg(target_arch = "riscv64")]
+extern "C" fn dispatch(frame: &mut TrapFrame) {
+    let code = frame.mcause & !(1 << (usize::BITS - 1));
+    match handler() {
+        Some(handler) => handler(code),
+        None => unsafe { core::arch::asm!("csrc mie, {0}", in(reg) 1usize << code) },
+    }
+}
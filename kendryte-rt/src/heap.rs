@@ -0,0 +1,361 @@
+//! Heap allocator over the linker-defined SRAM and DDR heap regions.
+//!
+//! Enabled with the `alloc` feature. Each region is a first-fit free list
+//! [`Heap`]; the global allocator serves requests from [`SRAM`] and falls
+//! back to [`DDR`] once it is initialized. Code that wants a specific region,
+//! such as a frame pool in DDR, allocates from that heap directly.
+//!
+//! Each heap is guarded by a [`Mutex`], so both harts and interrupt handlers
+//! can allocate from it.
+//!
+//! | Region | Start             | End             | Initialized by                   |
+//! |:-------|:------------------|:----------------|:---------------------------------|
+//! | SRAM   | `_sheap`          | `_eheap`        | the runtime, on start            |
+//! | DDR    | `_ddr_heap_start` | `_ddr_heap_end` | [`init_ddr`], after DDR training |
+//!
+//! The SRAM heap takes the memory after `.noinit` up to the end of SRAM. The
+//! DDR heap defaults to all of DDR but its first page, as address 0 cannot be
+//! returned by an allocator, and ends at the first region of the memory
+//! layout manifest, see [`mem_layout`](crate::mem_layout). The symbols are
+//! `PROVIDE`d and can be overridden to reserve part of DDR.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use kendryte_hal::sync::Mutex;
+
+/// Free block header, stored in the free memory itself.
+struct Hole {
+    size: usize,
+    next: *mut Hole,
+}
+
+/// Granule of every block address and size, so a hole header always fits.
+const UNIT: usize = size_of::<Hole>();
+
+struct Inner {
+    /// Free list sorted by address.
+    head: *mut Hole,
+    start: usize,
+    end: usize,
+    used: usize,
+}
+
+// The holes lie in the heap's own memory and are only reached under its lock.
+unsafe impl Send for Inner {}
+
+/// A first-fit, address-ordered free list allocator over one region.
+pub struct Heap {
+    inner: Mutex<Inner>,
+}
+
+impl Heap {
+    /// Creates a heap with no memory.
+    pub const fn empty() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                head: ptr::null_mut(),
+                start: 0,
+                end: 0,
+                used: 0,
+            }),
+        }
+    }
+
+    /// Gives the heap the `len` bytes at `start`, trimmed to its granule.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be unused, stay valid for the rest of the program and
+    /// not be given to any other heap. The heap must be empty.
+    pub unsafe fn init(&self, start: *mut u8, len: usize) {
+        let start_addr = align_up(start as usize, UNIT);
+        let end_addr = (start as usize + len) & !(UNIT - 1);
+        let mut inner = self.inner.lock();
+        if end_addr >= start_addr + UNIT {
+            let hole = start_addr as *mut Hole;
+            unsafe {
+                hole.write(Hole {
+                    size: end_addr - start_addr,
+                    next: ptr::null_mut(),
+                })
+            };
+            *inner = Inner {
+                head: hole,
+                start: start_addr,
+                end: end_addr,
+                used: 0,
+            };
+        }
+    }
+
+    /// Returns the number of bytes the heap manages.
+    pub fn size(&self) -> usize {
+        let inner = self.inner.lock();
+        inner.end - inner.start
+    }
+
+    /// Returns the number of bytes allocated, including rounding.
+    pub fn used(&self) -> usize {
+        self.inner.lock().used
+    }
+
+    /// Checks whether `ptr` lies in the heap's memory.
+    pub fn contains(&self, ptr: *mut u8) -> bool {
+        let inner = self.inner.lock();
+        (inner.start..inner.end).contains(&(ptr as usize))
+    }
+
+    /// Allocates a block for `layout`, or returns `None` if no hole fits.
+    pub fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = align_up(layout.size().max(1), UNIT);
+        let align = layout.align().max(UNIT);
+        let mut inner = self.inner.lock();
+        let mut link: *mut *mut Hole = &mut inner.head;
+        // Holes are only touched inside the heap's own memory.
+        unsafe {
+            while let Some(hole) = NonNull::new(*link) {
+                let hole = hole.as_ptr();
+                let (hole_start, hole_size, next) = (hole as usize, (*hole).size, (*hole).next);
+                let start = align_up(hole_start, align);
+                let front = start - hole_start;
+                if front + size > hole_size {
+                    link = &mut (*hole).next;
+                    continue;
+                }
+                let back = hole_size - front - size;
+                // Both paddings are multiples of the granule, so each
+                // one is either empty or can hold a header.
+                let mut rest = next;
+                if back != 0 {
+                    let tail = (start + size) as *mut Hole;
+                    tail.write(Hole {
+                        size: back,
+                        next: rest,
+                    });
+                    rest = tail;
+                }
+                if front != 0 {
+                    (*hole).size = front;
+                    (*hole).next = rest;
+                } else {
+                    *link = rest;
+                }
+                inner.used += size;
+                return NonNull::new(start as *mut u8);
+            }
+        }
+        None
+    }
+
+    /// Returns a block to the heap, merging it with adjacent holes.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`alloc`](Self::alloc) of this heap
+    /// with the same `layout`, and not freed since.
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        let size = align_up(layout.size().max(1), UNIT);
+        let start = ptr.as_ptr() as usize;
+        let mut inner = self.inner.lock();
+        inner.used -= size;
+        let mut prev: *mut Hole = ptr::null_mut();
+        let mut next = inner.head;
+        unsafe {
+            while !next.is_null() && (next as usize) < start {
+                prev = next;
+                next = (*next).next;
+            }
+            let block = start as *mut Hole;
+            block.write(Hole { size, next });
+            if !next.is_null() && start + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+            if prev.is_null() {
+                inner.head = block;
+            } else if prev as usize + (*prev).size == start {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else {
+                (*prev).next = block;
+            }
+        }
+    }
+}
+
+#[inline]
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// Heap in on-chip SRAM, initialized on start.
+pub static SRAM: Heap = Heap::empty();
+
+/// Heap in DDR, initialized by [`init_ddr`].
+pub static DDR: Heap = Heap::empty();
+
+unsafe extern "C" {
+    static mut _sheap: u8;
+    static mut _eheap: u8;
+    static mut _ddr_heap_start: u8;
+    static mut _ddr_heap_end: u8;
+}
+
+/// Gives the SRAM heap region to [`SRAM`].
+pub(crate) fn init_sram() {
+    unsafe {
+        let (start, end) = (&raw mut _sheap, &raw mut _eheap);
+        SRAM.init(start, end as usize - start as usize);
+    }
+}
+
+/// Gives the DDR heap region to [`DDR`].
+///
+/// # Safety
+///
+/// DDR must be initialized, and the region unused by anything else. Call once.
+pub unsafe fn init_ddr() {
+    unsafe {
+        let (start, end) = (&raw mut _ddr_heap_start, &raw mut _ddr_heap_end);
+        DDR.init(start, end as usize - start as usize);
+    }
+}
+
+/// Hook called with the layout of an allocation that failed in both heaps.
+pub type OomHook = fn(Layout);
+
+static OOM_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Sets the hook called when the global allocator runs out of memory.
+///
+/// The hook runs before the allocation fails, to log or shed caches; the
+/// caller then sees the failure, which `alloc` turns into a panic.
+pub fn set_oom_hook(hook: OomHook) {
+    OOM_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+struct Allocator;
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match SRAM.alloc(layout).or_else(|| DDR.alloc(layout)) {
+            Some(ptr) => ptr.as_ptr(),
+            None => {
+                let hook = OOM_HOOK.load(Ordering::Acquire);
+                if !hook.is_null() {
+                    // Only ever stored from an `OomHook`.
+                    let hook: OomHook = unsafe { core::mem::transmute(hook) };
+                    hook(layout);
+                }
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let Some(block) = NonNull::new(ptr) else {
+            return;
+        };
+        let heap = if SRAM.contains(ptr) { &SRAM } else { &DDR };
+        unsafe { heap.dealloc(block, layout) };
+    }
+}
+
+// Host tests keep the allocator of `std`.
+#[cfg(not(test))]
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(align(4096))]
+    struct Arena([u8; 4096]);
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn splits_and_coalesces() {
+        let mut arena = Arena([0; 4096]);
+        let heap = Heap::empty();
+        unsafe { heap.init(arena.0.as_mut_ptr(), arena.0.len()) };
+        assert_eq!(heap.size(), 4096);
+
+        let a = heap.alloc(layout(64, 8)).unwrap();
+        let b = heap.alloc(layout(60, 8)).unwrap();
+        let c = heap.alloc(layout(64, 8)).unwrap();
+        assert_eq!(b.as_ptr() as usize - a.as_ptr() as usize, 64);
+        assert_eq!(c.as_ptr() as usize - b.as_ptr() as usize, 64);
+        assert_eq!(heap.used(), 192);
+
+        // Freeing `b` then `a` leaves one hole in front of `c`.
+        unsafe {
+            heap.dealloc(b, layout(60, 8));
+            heap.dealloc(a, layout(64, 8));
+        }
+        assert_eq!(heap.alloc(layout(128, 8)), Some(a));
+        unsafe {
+            heap.dealloc(a, layout(128, 8));
+            heap.dealloc(c, layout(64, 8));
+        }
+        assert_eq!(heap.used(), 0);
+        assert_eq!(heap.alloc(layout(4096, 8)), Some(a));
+    }
+
+    #[test]
+    fn aligns_past_the_header() {
+        let mut arena = Arena([0; 4096]);
+        let heap = Heap::empty();
+        unsafe { heap.init(arena.0.as_mut_ptr(), arena.0.len()) };
+
+        let first = heap.alloc(layout(16, 8)).unwrap();
+        let aligned = heap.alloc(layout(32, 1024)).unwrap();
+        assert_eq!(aligned.as_ptr() as usize % 1024, 0);
+        // The padding in front of the aligned block stays free.
+        let second = heap.alloc(layout(16, 8)).unwrap();
+        assert_eq!(second.as_ptr() as usize, first.as_ptr() as usize + 16);
+        assert!(second < aligned);
+    }
+
+    #[test]
+    fn exhaustion_returns_none() {
+        let mut arena = Arena([0; 4096]);
+        let heap = Heap::empty();
+        assert_eq!(heap.alloc(layout(1, 1)), None);
+        unsafe { heap.init(arena.0.as_mut_ptr(), arena.0.len()) };
+
+        let block = heap.alloc(layout(4000, 8)).unwrap();
+        assert_eq!(heap.alloc(layout(128, 8)), None);
+        assert_eq!(heap.alloc(layout(8, 4096)), None);
+        unsafe { heap.dealloc(block, layout(4000, 8)) };
+        assert!(heap.alloc(layout(128, 8)).is_some());
+    }
+
+    #[test]
+    fn global_allocator_reallocs_and_reports_oom() {
+        static mut ARENA: Arena = Arena([0; 4096]);
+        static FAILED: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+        unsafe { SRAM.init((&raw mut ARENA).cast(), 4096) };
+        set_oom_hook(|layout| FAILED.store(layout.size(), Ordering::Relaxed));
+
+        unsafe {
+            let small = Allocator.alloc(layout(16, 8));
+            small.copy_from(b"kendryte".as_ptr(), 8);
+            let other = Allocator.alloc(layout(16, 8));
+            let grown = Allocator.realloc(small, layout(16, 8), 256);
+            assert!(!grown.is_null());
+            assert_ne!(grown, small);
+            assert_eq!(core::slice::from_raw_parts(grown, 8), b"kendryte");
+
+            assert!(Allocator.alloc(layout(8192, 8)).is_null());
+            assert_eq!(FAILED.load(Ordering::Relaxed), 8192);
+            Allocator.dealloc(grown, layout(256, 8));
+            Allocator.dealloc(other, layout(16, 8));
+        }
+        assert_eq!(SRAM.used(), 0);
+    }
+}
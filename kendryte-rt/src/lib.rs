@@ -2,13 +2,16 @@
 #![no_std]
 #![allow(unused)]
 pub mod arch;
+#[cfg(feature = "alloc")]
+pub mod heap;
+pub mod mem_layout;
 pub mod soc;
 
 pub use kendryte_rt_macros::entry;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "k230")] {
-        pub use soc::k230::Peripherals;
+        pub use soc::k230::{Peripherals, report};
         pub use kendryte_hal::clocks::Clocks;
         #[doc(hidden)]
         pub use soc::k230::__rom_init_params;
@@ -0,0 +1,62 @@
+//! Memories and reserved DDR regions of the memory layout manifest.
+//!
+//! The memories the firmware is linked into and the DMA pools, IPC rings
+//! and framebuffers reserved in DDR are placed by one TOML manifest, named
+//! by the `KENDRYTE_RT_MEMORY_LAYOUT` environment variable at build time,
+//! usually from the application's `.cargo/config.toml`:
+//!
+//! ```toml
+//! [env]
+//! KENDRYTE_RT_MEMORY_LAYOUT = { value = "memory.toml", relative = true }
+//! ```
+//!
+//! ```toml
+//! # memory.toml
+//! [memory.ddr]
+//! origin = 0x00000000
+//! length = 0x10000000
+//!
+//! [regions.dma_pool]
+//! origin = 0x0f000000
+//! length = 0x00800000
+//!
+//! [regions.ipc]
+//! origin = 0x0ff00000
+//! length = 0x00100000
+//! ```
+//!
+//! The `spl` and `ddr` memories default to the whole SRAM and DDR of the
+//! selected chip, and may only be narrowed; they become [`SPL`] and [`DDR`]
+//! here and the `SPL` and `DDR` regions of the linker script. Each reserved
+//! region becomes a constant too, `DMA_POOL` and `IPC` above, and the linker
+//! symbols `_dma_pool_start` and `_dma_pool_end` for assembly and C code.
+//! Regions are whole 4 KiB pages of DDR that do not overlap, which the build
+//! checks; the DDR heap ends at the lowest one.
+
+/// Region of physical memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Region {
+    #[inline]
+    pub const fn new(start: usize, len: usize) -> Self {
+        Self { start, len }
+    }
+
+    /// Returns the address past the region.
+    #[inline]
+    pub const fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// Returns the region as a pointer to its first byte.
+    #[inline]
+    pub const fn as_ptr(&self) -> *mut u8 {
+        self.start as *mut u8
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/mem_layout.rs"));
@@ -2,29 +2,66 @@ mod pads;
 mod peripheral;
 
 use crate::soc::k230::pads::Pads;
-use kendryte_hal::{clocks::Clocks, gpio, iomux, uart};
+use core::fmt;
+use kendryte_hal::{clocks::Clocks, gpio, iomux, memory_map, uart};
 
-#[cfg(all(feature = "k230"))]
+pub use peripheral::PAD_FUNCTIONS;
+
+#[cfg(all(feature = "k230", target_arch = "riscv64"))]
 #[unsafe(naked)]
 #[unsafe(link_section = ".text.entry")]
 #[unsafe(export_name = "_start")]
 unsafe extern "C" fn start() -> ! {
+    use crate::arch::hart::{self, MAX_HARTS};
     use crate::arch::rvi::Stack;
     use crate::soc::main;
     const STACK_SIZE: usize = 32 * 1024;
 
+    // Left out of `.bss`, so hart 0 clearing it does not wipe the stacks
+    // the other harts already run on.
     #[unsafe(link_section = ".bss.uninit")]
-    static mut STACK: Stack<STACK_SIZE> = Stack([0; STACK_SIZE]);
+    static mut STACKS: [Stack<STACK_SIZE>; MAX_HARTS] =
+        [const { Stack([0; STACK_SIZE]) }; MAX_HARTS];
 
     core::arch::naked_asm!(
         // Disable interrupt
         "csrw   mie, zero",
 
-        // Prepare programming language stack
-        "la     sp, {stack}
-             li     t0, {stack_size}
+        // Halt harts without a stack
+        "csrr   t0, mhartid
+             li     t1, {max_harts}
+             bgeu   t0, t1, 3f",
+
+        // Prepare programming language stack, the top of the hart's own
+        "addi   t0, t0, 1
+             li     t1, {stack_size}
+             mul    t0, t0, t1
+             la     sp, {stacks}
              add    sp, sp, t0",
 
+        // Dispatch interrupts and record fatal exceptions as crash dumps
+        "la     t0, {trap}
+             csrw   mtvec, t0",
+
+        // Other harts wait for hart 0 to start them
+        "csrr   t0, mhartid
+             beqz   t0, 1f
+             call   {secondary}
+         1:",
+
+        // Copy `.data` section, if it is not loaded in place
+        "la     t1, sdata
+             la     t2, edata
+             la     t3, sidata
+             beq    t1, t3, 2f
+         1:  bgeu   t1, t2, 2f
+             lw     t4, 0(t3)
+             sw     t4, 0(t1)
+             addi   t1, t1, 4
+             addi   t3, t3, 4
+             j      1b
+         2:",
+
         // Clear `.bss` section
         "la     t1, sbss
              la     t2, ebss
@@ -42,8 +79,11 @@ unsafe extern "C" fn start() -> ! {
          3:  wfi
              j    3b",
 
-        stack      = sym STACK,
+        max_harts  = const MAX_HARTS,
+        stacks     = sym STACKS,
         stack_size = const STACK_SIZE,
+        trap       = sym crate::arch::trap::start_trap,
+        secondary  = sym hart::secondary_start,
         main       = sym main,
     )
 }
@@ -51,22 +91,36 @@ unsafe extern "C" fn start() -> ! {
 macro_rules! soc {
     (
         $(
-            $(#[$doc:meta])*
+            $(#[$attr:meta])*
             pub struct $Ty:ident => $paddr:expr, $DerefTy:ty;
         )+
     ) => {
         $(
-            $(#[$doc])*
+            $(#[$attr])*
             #[allow(non_camel_case_types)]
             pub struct $Ty (());
 
+            // Attributes such as `#[cfg]` apply to the impls as well.
+            $(#[$attr])*
             impl $Ty {
                 #[inline]
                 pub const fn ptr() -> *const $DerefTy {
                     $paddr as *const $DerefTy
                 }
+
+                /// Creates another handle to the peripheral.
+                ///
+                /// # Safety
+                ///
+                /// The caller must make sure no driver owning the peripheral
+                /// makes conflicting register accesses.
+                #[inline]
+                pub const unsafe fn steal() -> Self {
+                    Self(())
+                }
             }
 
+            $(#[$attr])*
             impl core::ops::Deref for $Ty {
                 type Target = $DerefTy;
 
@@ -76,6 +130,7 @@ macro_rules! soc {
                 }
             }
 
+            $(#[$attr])*
             impl core::convert::AsRef<$DerefTy> for $Ty {
                 #[inline(always)]
                 fn as_ref(&self) -> & 'static $DerefTy {
@@ -87,14 +142,14 @@ macro_rules! soc {
 }
 
 soc! {
-    pub struct IOMUX => 0x9110_5000, iomux::RegisterBlock;
-    pub struct GPIO0 => 0x9140_B000, gpio::RegisterBlock;
-    pub struct GPIO1 => 0x9140_C000, gpio::RegisterBlock;
-    pub struct UART0 => 0x9140_0000, uart::RegisterBlock;
-    pub struct UART1 => 0x9140_1000, uart::RegisterBlock;
-    pub struct UART2 => 0x9140_2000, uart::RegisterBlock;
-    pub struct UART3 => 0x9140_3000, uart::RegisterBlock;
-    pub struct UART4 => 0x9140_4000, uart::RegisterBlock;
+    pub struct IOMUX => memory_map::IOMUX, iomux::RegisterBlock;
+    pub struct GPIO0 => memory_map::GPIO0, gpio::RegisterBlock;
+    pub struct GPIO1 => memory_map::GPIO1, gpio::RegisterBlock;
+    pub struct UART0 => memory_map::UART0, uart::RegisterBlock;
+    pub struct UART1 => memory_map::UART1, uart::RegisterBlock;
+    pub struct UART2 => memory_map::UART2, uart::RegisterBlock;
+    pub struct UART3 => memory_map::UART3, uart::RegisterBlock;
+    pub struct UART4 => memory_map::UART4, uart::RegisterBlock;
 }
 
 /// Peripherals available on ROM start.
@@ -109,20 +164,34 @@ pub struct Peripherals {
     pub uart4: UART4,
 }
 
+/// Writes the pads in use with their signals, and the peripheral clocks.
+///
+/// Reads the IOMUX registers only, so it can run at any time, e.g. from a
+/// console command.
+pub fn report(out: &mut dyn fmt::Write) -> fmt::Result {
+    let iomux = unsafe { &*IOMUX::ptr() };
+    kendryte_hal::debug::report::report(out, iomux, PAD_FUNCTIONS, &Clocks)
+}
+
 // Used by macros only.
 #[allow(unused)]
 #[doc(hidden)]
 #[inline(always)]
 pub fn __rom_init_params() -> (Peripherals, Clocks) {
-    let peripherals = Peripherals {
-        iomux: Pads::new(),
-        gpio0: GPIO0(()),
-        gpio1: GPIO1(()),
-        uart0: UART0(()),
-        uart1: UART1(()),
-        uart2: UART2(()),
-        uart3: UART3(()),
-        uart4: UART4(()),
+    #[cfg(feature = "alloc")]
+    crate::heap::init_sram();
+    // SAFETY: called once on start, before any driver exists.
+    let peripherals = unsafe {
+        Peripherals {
+            iomux: Pads::new(),
+            gpio0: GPIO0::steal(),
+            gpio1: GPIO1::steal(),
+            uart0: UART0::steal(),
+            uart1: UART1::steal(),
+            uart2: UART2::steal(),
+            uart3: UART3::steal(),
+            uart4: UART4::steal(),
+        }
     };
     (peripherals, Clocks)
 }
@@ -4,6 +4,7 @@ use arbitrary_int::u3;
 use kendryte_hal::gpio::RegisterBlock;
 use kendryte_hal::gpio::pad::{IntoGpio, Port};
 use kendryte_hal::instance::{Instance, Numbered};
+use kendryte_hal::iomux::map::{PadFunction, Signal};
 use kendryte_hal::iomux::ops::PadOps;
 use kendryte_hal::iomux::{FlexPad, IntoFlexPad};
 
@@ -57,10 +58,19 @@ gpio! {
 
 macro_rules! pad_gpio {
     (
+        $table:ident;
         $(
            ($pad_num:expr, $function_select:expr, $gpio_num:expr, $port:expr, $pin_num:expr)
         ),+ $(,)?
     ) => {
+        pub(crate) const $table: &[PadFunction] = &[
+            $(PadFunction {
+                pad: $pad_num,
+                function: $function_select,
+                signal: Signal::Gpio { gpio: $gpio_num, port: $port, pin: $pin_num },
+            }),+
+        ];
+
         $(
             impl IntoGpio<'static, $gpio_num> for Pad<$pad_num> {
                 const PORT: Port = $port;
@@ -102,6 +112,7 @@ macro_rules! pad_gpio {
 }
 
 pad_gpio! {
+    GPIO_PADS;
     // GPIO Group 0
     (0, 1, 0, Port::A, 0),
     (1, 1, 0, Port::A, 1),
@@ -1,2 +1,15 @@
 mod gpio;
 mod uart;
+
+use kendryte_hal::iomux::map::PadFunction;
+
+/// Signals of every pad function, peripherals before GPIO.
+pub const PAD_FUNCTIONS: &[&[PadFunction]] = &[
+    uart::UART_SOUT_PADS,
+    uart::UART_SIN_PADS,
+    uart::UART_RTS_PADS,
+    uart::UART_CTS_PADS,
+    uart::UART_DE_PADS,
+    uart::UART_RE_PADS,
+    gpio::GPIO_PADS,
+];
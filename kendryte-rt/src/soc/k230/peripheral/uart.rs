@@ -2,12 +2,13 @@ use crate::soc::k230::pads::Pad;
 use crate::soc::k230::{UART0, UART1, UART2, UART3, UART4};
 use arbitrary_int::u3;
 use kendryte_hal::instance::{Instance, Numbered};
+use kendryte_hal::iomux::map::{PadFunction, Signal, UartLine};
 use kendryte_hal::iomux::ops::PadOps;
 use kendryte_hal::iomux::{FlexPad, IntoFlexPad};
-use kendryte_hal::uart::RegisterBlock;
 use kendryte_hal::uart::pad::{
     IntoUartCts, IntoUartDe, IntoUartRe, IntoUartRts, IntoUartSin, IntoUartSout,
 };
+use kendryte_hal::uart::{RegisterBlock, Uart};
 
 macro_rules! uart {
     (
@@ -21,7 +22,7 @@ macro_rules! uart {
 
                 #[inline]
                 fn inner(self) -> &'static Self::R {
-                    unsafe { &*<$UARTx>::ptr() }
+                    Uart::<$n>::registers()
                 }
             }
 
@@ -32,7 +33,7 @@ macro_rules! uart {
 
                 #[inline]
                 fn inner(self) -> &'static Self::R {
-                    unsafe { &*<$UARTx>::ptr() }
+                    Uart::<$n>::registers()
                 }
             }
 
@@ -51,10 +52,19 @@ uart! {
 
 macro_rules! pad_uart_sout {
  (
+        $table:ident;
         $(
            ($pad_num:expr, $function_select:expr,$uart_num:expr)
         ),+ $(,)?
     )=> {
+      pub(crate) const $table: &[PadFunction] = &[
+          $(PadFunction {
+              pad: $pad_num,
+              function: $function_select,
+              signal: Signal::Uart { uart: $uart_num, line: UartLine::Sout },
+          }),+
+      ];
+
       $(
         impl IntoUartSout<'static,$uart_num> for Pad<$pad_num> {
             fn into_uart_sout(self) -> FlexPad<'static> {
@@ -75,6 +85,7 @@ macro_rules! pad_uart_sout {
 }
 
 pad_uart_sout! {
+    UART_SOUT_PADS;
     (38, 1, 0),
 
     (40, 1, 1),
@@ -95,10 +106,19 @@ pad_uart_sout! {
 
 macro_rules! pad_uart_sin {
     (
+        $table:ident;
         $(
             ($pad_num:expr, $function_select:expr, $uart_num:expr)
         ),+ $(,)?
     ) => {
+        pub(crate) const $table: &[PadFunction] = &[
+            $(PadFunction {
+                pad: $pad_num,
+                function: $function_select,
+                signal: Signal::Uart { uart: $uart_num, line: UartLine::Sin },
+            }),+
+        ];
+
         $(
             impl IntoUartSin<'static, $uart_num> for Pad<$pad_num> {
                 fn into_uart_sin(self) -> FlexPad<'static> {
@@ -120,6 +140,7 @@ macro_rules! pad_uart_sin {
 }
 
 pad_uart_sin! {
+    UART_SIN_PADS;
     (39, 1, 0),
 
     (41, 1, 1),
@@ -140,10 +161,19 @@ pad_uart_sin! {
 
 macro_rules! pad_uart_rts {
     (
+        $table:ident;
         $(
             ($pad_num:expr, $function_select:expr, $uart_num:expr)
         ),+ $(,)?
     ) => {
+        pub(crate) const $table: &[PadFunction] = &[
+            $(PadFunction {
+                pad: $pad_num,
+                function: $function_select,
+                signal: Signal::Uart { uart: $uart_num, line: UartLine::Rts },
+            }),+
+        ];
+
         $(
             impl IntoUartRts<'static, $uart_num> for Pad<$pad_num> {
                 fn into_uart_rts(self) -> FlexPad<'static> {
@@ -165,6 +195,7 @@ macro_rules! pad_uart_rts {
 }
 
 pad_uart_rts! {
+    UART_RTS_PADS;
     (42, 1, 1),
 
     (46, 1, 2),
@@ -176,10 +207,19 @@ pad_uart_rts! {
 
 macro_rules! pad_uart_cts {
     (
+        $table:ident;
         $(
             ($pad_num:expr, $function_select:expr, $uart_num:expr)
         ),+ $(,)?
     ) => {
+        pub(crate) const $table: &[PadFunction] = &[
+            $(PadFunction {
+                pad: $pad_num,
+                function: $function_select,
+                signal: Signal::Uart { uart: $uart_num, line: UartLine::Cts },
+            }),+
+        ];
+
         $(
             impl IntoUartCts<'static, $uart_num> for Pad<$pad_num> {
                 fn into_uart_cts(self) -> FlexPad<'static> {
@@ -201,6 +241,7 @@ macro_rules! pad_uart_cts {
 }
 
 pad_uart_cts! {
+    UART_CTS_PADS;
     (43, 1, 1),
 
     (47, 1, 2),
@@ -212,10 +253,19 @@ pad_uart_cts! {
 
 macro_rules! pad_uart_de {
     (
+        $table:ident;
         $(
             ($pad_num:expr, $function_select:expr, $uart_num:expr)
         ),+ $(,)?
     ) => {
+        pub(crate) const $table: &[PadFunction] = &[
+            $(PadFunction {
+                pad: $pad_num,
+                function: $function_select,
+                signal: Signal::Uart { uart: $uart_num, line: UartLine::De },
+            }),+
+        ];
+
         $(
             impl IntoUartDe<'static, $uart_num> for Pad<$pad_num> {
                 fn into_uart_de(self) -> FlexPad<'static> {
@@ -237,15 +287,25 @@ macro_rules! pad_uart_de {
 }
 
 pad_uart_de! {
+    UART_DE_PADS;
     (62, 2, 3)
 }
 
 macro_rules! pad_uart_re {
     (
+        $table:ident;
         $(
             ($pad_num:expr, $function_select:expr, $uart_num:expr)
         ),+ $(,)?
     ) => {
+        pub(crate) const $table: &[PadFunction] = &[
+            $(PadFunction {
+                pad: $pad_num,
+                function: $function_select,
+                signal: Signal::Uart { uart: $uart_num, line: UartLine::Re },
+            }),+
+        ];
+
         $(
             impl IntoUartRe<'static, $uart_num> for Pad<$pad_num> {
                 fn into_uart_re(self) -> FlexPad<'static> {
@@ -267,5 +327,6 @@ macro_rules! pad_uart_re {
 }
 
 pad_uart_re! {
+    UART_RE_PADS;
     (63, 2, 3)
 }
@@ -0,0 +1,180 @@
+//! Delta generation between two firmware images.
+//!
+//! Produces the format applied on the device by
+//! `kendryte_image::ota::apply_delta`. Regions of the new image are matched
+//! against the old one through an index of short windows; matches are grown
+//! while most bytes agree, so code that moved and had a few addresses
+//! patched is sent as a copy with sparse differences, and everything else
+//! as literals.
+
+use crate::error::{XtaskError, XtaskResult};
+use kendryte_image::ota::{crc32, HEADER_LEN, MAGIC, OP_ADD, OP_INSERT};
+use std::collections::HashMap;
+
+/// Length of the windows matched exactly to find a copy source.
+const WINDOW: usize = 16;
+/// Distance between indexed windows of the old image.
+const STRIDE: usize = 8;
+/// Granularity at which a match is grown past differing bytes.
+const CHUNK: usize = 16;
+/// Unchanged bytes shorter than this are kept inside a difference run.
+const MIN_SKIP: usize = 3;
+
+/// Generate a delta rebuilding `new` from `old`.
+pub fn diff(old: &[u8], new: &[u8]) -> XtaskResult<Vec<u8>> {
+    let len = |image: &[u8]| {
+        u32::try_from(image.len()).map_err(|_| {
+            XtaskError::DeltaError(format!("image of {} bytes is too large", image.len()))
+        })
+    };
+    let mut delta = Vec::with_capacity(HEADER_LEN + new.len() / 4);
+    delta.extend_from_slice(MAGIC);
+    delta.extend_from_slice(&len(old)?.to_le_bytes());
+    delta.extend_from_slice(&crc32(old).to_le_bytes());
+    delta.extend_from_slice(&len(new)?.to_le_bytes());
+    delta.extend_from_slice(&crc32(new).to_le_bytes());
+
+    let mut index = HashMap::new();
+    if old.len() >= WINDOW {
+        for offset in (0..=old.len() - WINDOW).step_by(STRIDE) {
+            index.entry(&old[offset..offset + WINDOW]).or_insert(offset);
+        }
+    }
+
+    let mut literal = 0;
+    let mut pos = 0;
+    while pos + WINDOW <= new.len() {
+        let Some(&found) = index.get(&new[pos..pos + WINDOW]) else {
+            pos += 1;
+            continue;
+        };
+        // Back over equal bytes the stride skipped, then forward.
+        let (mut start, mut from) = (pos, found);
+        while start > literal && from > 0 && new[start - 1] == old[from - 1] {
+            start -= 1;
+            from -= 1;
+        }
+        let mut end = pos + WINDOW;
+        let mut end_old = found + WINDOW;
+        let equal_run = |end: &mut usize, end_old: &mut usize| {
+            while *end < new.len() && *end_old < old.len() && new[*end] == old[*end_old] {
+                *end += 1;
+                *end_old += 1;
+            }
+        };
+        equal_run(&mut end, &mut end_old);
+        loop {
+            let n = CHUNK.min(new.len() - end).min(old.len() - end_old);
+            let same = (0..n).filter(|&i| new[end + i] == old[end_old + i]).count();
+            if n == 0 || same * 2 < n {
+                break;
+            }
+            end += n;
+            end_old += n;
+            equal_run(&mut end, &mut end_old);
+        }
+
+        insert(&mut delta, &new[literal..start]);
+        add(&mut delta, from, &old[from..end_old], &new[start..end]);
+        literal = end;
+        pos = end;
+    }
+    insert(&mut delta, &new[literal..]);
+    Ok(delta)
+}
+
+fn varint(delta: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        delta.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    delta.push(value as u8);
+}
+
+fn insert(delta: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    delta.push(OP_INSERT);
+    varint(delta, bytes.len());
+    delta.extend_from_slice(bytes);
+}
+
+fn add(delta: &mut Vec<u8>, offset: usize, old: &[u8], new: &[u8]) {
+    delta.push(OP_ADD);
+    varint(delta, offset);
+    varint(delta, new.len());
+    let diff: Vec<u8> = new
+        .iter()
+        .zip(old)
+        .map(|(n, o)| n.wrapping_sub(*o))
+        .collect();
+    let zeros = |from: usize| diff[from..].iter().take_while(|&&d| d == 0).count();
+    let mut pos = 0;
+    while pos < diff.len() {
+        let skip = zeros(pos);
+        let start = pos + skip;
+        let mut end = start;
+        while end < diff.len() {
+            end += diff[end..].iter().take_while(|&&d| d != 0).count();
+            let gap = zeros(end);
+            if gap >= MIN_SKIP || end + gap == diff.len() {
+                break;
+            }
+            end += gap;
+        }
+        varint(delta, skip);
+        varint(delta, end - start);
+        delta.extend_from_slice(&diff[start..end]);
+        pos = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kendryte_image::ota::apply_delta;
+
+    fn apply(old: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        apply_delta(old, delta, |bytes| {
+            out.extend_from_slice(bytes);
+            Ok::<(), ()>(())
+        })
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut seed = 0x1234_5678u32;
+        let old: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+
+        // Code inserted near the start shifts the rest, which has a few
+        // addresses patched.
+        let mut new = old[..1000].to_vec();
+        new.extend_from_slice(&[0xAA; 300]);
+        new.extend_from_slice(&old[1000..]);
+        for i in (2000..new.len()).step_by(512) {
+            new[i] = new[i].wrapping_add(0x12);
+        }
+        new.truncate(new.len() - 100);
+
+        let delta = diff(&old, &new).unwrap();
+        assert_eq!(apply(&old, &delta), new);
+        assert!(delta.len() < new.len() / 20, "{} bytes", delta.len());
+
+        for (old, new) in [
+            (&[][..], &b"abc"[..]),
+            (b"abc", b""),
+            (b"short", b"shorter"),
+        ] {
+            assert_eq!(apply(old, &diff(old, new).unwrap()), new);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Device tree overlay for Linux running beside the firmware.
+//!
+//! A board file lists the peripherals and pads the firmware on the little
+//! core drives and the memory it shares with Linux, one item per line:
+//!
+//! ```text
+//! # Console and status LED of the firmware.
+//! claim uart3 50 51
+//! claim gpio0 20
+//! # IPC rings and the coexist descriptor.
+//! reserve ipc=0x0ff00000+0xff000
+//! reserve coexist=0x0ffff000+0x1000
+//! ```
+//!
+//! The overlay marks the claimed peripherals `status = "reserved"`, so
+//! Linux leaves them alone, adds the regions to `/reserved-memory` with
+//! `no-map`, and records the claimed pads in an `amp-firmware` node.
+//! Peripherals are referred to by the labels of the K230 Linux device tree.
+
+use crate::error::{XtaskError, XtaskResult};
+use crate::size::{parse_region, MemoryRegion};
+use std::fmt::Write;
+
+/// Peripherals that can be claimed, by their device tree label.
+///
+/// These match the `kendryte_hal::coexist::Resource` instances.
+pub const PERIPHERALS: &[&str] = &[
+    "uart0", "uart1", "uart2", "uart3", "uart4", "gpio0", "gpio1", "i2c0", "i2c1", "i2c2", "i2c3",
+    "i2c4", "spi0", "spi1", "spi2", "pwm", "lsadc",
+];
+
+/// Number of IO pads of the K230.
+pub const PADS: u8 = 64;
+
+/// A peripheral driven by the firmware, with the pads it uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub peripheral: String,
+    pub pads: Vec<u8>,
+}
+
+/// What the firmware takes from Linux.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Board {
+    pub claims: Vec<Claim>,
+    pub reserved: Vec<MemoryRegion>,
+}
+
+/// Parse a board file.
+pub fn parse_board(text: &str) -> XtaskResult<Board> {
+    let mut board = Board::default();
+    for (number, line) in text.lines().enumerate() {
+        let error =
+            |message: String| XtaskError::BoardError(format!("line {}: {}", number + 1, message));
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => {}
+            Some("claim") => {
+                let peripheral = words
+                    .next()
+                    .ok_or_else(|| error("missing peripheral".into()))?;
+                if !PERIPHERALS.contains(&peripheral) {
+                    return Err(error(format!("unknown peripheral {}", peripheral)));
+                }
+                if board.claims.iter().any(|c| c.peripheral == peripheral) {
+                    return Err(error(format!("{} claimed twice", peripheral)));
+                }
+                let mut pads = Vec::new();
+                for pad in words {
+                    let pad = pad
+                        .parse()
+                        .ok()
+                        .filter(|pad| *pad < PADS)
+                        .ok_or_else(|| error(format!("invalid pad {}", pad)))?;
+                    let mut claimed = board.claims.iter().flat_map(|c| &c.pads);
+                    if pads.contains(&pad) || claimed.any(|p| *p == pad) {
+                        return Err(error(format!("pad {} claimed twice", pad)));
+                    }
+                    pads.push(pad);
+                }
+                board.claims.push(Claim {
+                    peripheral: peripheral.to_string(),
+                    pads,
+                });
+            }
+            Some("reserve") => {
+                let region = words
+                    .next()
+                    .ok_or_else(|| error("missing region".into()))
+                    .and_then(|region| {
+                        parse_region(region)
+                            .map_err(|_| error(format!("invalid region {}", region)))
+                    })?;
+                if region.length == 0 {
+                    return Err(error(format!("region {} is empty", region.name)));
+                }
+                board.reserved.push(region);
+            }
+            Some(keyword) => return Err(error(format!("unknown keyword {}", keyword))),
+        }
+    }
+    Ok(board)
+}
+
+/// Render the device tree overlay source of a board.
+///
+/// `source` names the board file in the header comment.
+pub fn render(board: &Board, source: &str) -> String {
+    let mut out = String::new();
+    let mut fragment = 0;
+    let mut open = |out: &mut String, target: String| {
+        writeln!(out, "\tfragment@{} {{", fragment).unwrap();
+        writeln!(out, "\t\t{};", target).unwrap();
+        writeln!(out, "\t\t__overlay__ {{").unwrap();
+        fragment += 1;
+    };
+    let close = |out: &mut String| out.push_str("\t\t};\n\t};\n");
+
+    writeln!(
+        out,
+        "// Generated by `cargo xtask dts` from {}; do not edit.",
+        source
+    )
+    .unwrap();
+    out.push_str("/dts-v1/;\n/plugin/;\n\n/ {\n");
+    if !board.reserved.is_empty() {
+        open(&mut out, "target-path = \"/reserved-memory\"".into());
+        for region in &board.reserved {
+            writeln!(out, "\t\t\t{}@{:x} {{", region.name, region.origin).unwrap();
+            writeln!(
+                out,
+                "\t\t\t\treg = <{}>;",
+                cells(region.origin, region.length)
+            )
+            .unwrap();
+            out.push_str("\t\t\t\tno-map;\n\t\t\t};\n");
+        }
+        close(&mut out);
+    }
+    for claim in &board.claims {
+        open(&mut out, format!("target = <&{}>", claim.peripheral));
+        out.push_str("\t\t\tstatus = \"reserved\";\n");
+        close(&mut out);
+    }
+    let pads: Vec<_> = board
+        .claims
+        .iter()
+        .flat_map(|c| &c.pads)
+        .map(|pad| pad.to_string())
+        .collect();
+    if !pads.is_empty() {
+        open(&mut out, "target-path = \"/\"".into());
+        out.push_str("\t\t\tamp-firmware {\n");
+        out.push_str("\t\t\t\tcompatible = \"kendryte,amp-firmware\";\n");
+        writeln!(out, "\t\t\t\tkendryte,pads = <{}>;", pads.join(" ")).unwrap();
+        out.push_str("\t\t\t};\n");
+        close(&mut out);
+    }
+    out.push_str("};\n");
+    out
+}
+
+/// Format an address and a length as two cells each.
+fn cells(origin: u64, length: u64) -> String {
+    format!(
+        "0x{:x} 0x{:x} 0x{:x} 0x{:x}",
+        origin >> 32,
+        origin & 0xffff_ffff,
+        length >> 32,
+        length & 0xffff_ffff
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "\
+# Console and status LED.
+claim uart3 50 51
+claim gpio0 20
+
+reserve ipc=0x0ff00000+0xff000 # rings
+";
+
+    #[test]
+    fn test_parse_board() {
+        let board = parse_board(BOARD).unwrap();
+        assert_eq!(
+            board.claims[0],
+            Claim {
+                peripheral: "uart3".into(),
+                pads: vec![50, 51],
+            }
+        );
+        assert_eq!(board.reserved[0].origin, 0x0ff0_0000);
+        assert!(parse_board("claim uart9").is_err());
+        assert!(parse_board("claim uart1 3\nclaim uart2 3").is_err());
+        assert!(parse_board("claim uart1 64").is_err());
+        assert!(parse_board("reserve ipc=0x0+0").is_err());
+        let error = parse_board("\nmap ipc").unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{}", error);
+    }
+
+    #[test]
+    fn test_render() {
+        let board = parse_board(BOARD).unwrap();
+        let dts = render(&board, "board.txt");
+        assert!(dts.contains("/plugin/;"));
+        assert!(dts.contains(
+            "\t\t\tipc@ff00000 {\n\t\t\t\treg = <0x0 0xff00000 0x0 0xff000>;\n\t\t\t\tno-map;\n"
+        ));
+        assert!(dts.contains("\tfragment@1 {\n\t\ttarget = <&uart3>;\n"));
+        assert!(dts.contains("\tfragment@2 {\n\t\ttarget = <&gpio0>;\n"));
+        assert!(dts.contains("kendryte,pads = <50 51 20>;"));
+        assert!(dts.ends_with("\t\t};\n\t};\n};\n"));
+        assert_eq!(dts.matches('{').count(), dts.matches('}').count());
+    }
+}
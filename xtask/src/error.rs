@@ -13,6 +13,26 @@ pub enum XtaskError {
     #[error("Invalid encryption type!")]
     InvalidEncryptionType,
 
+    /// Error for invalid cipher specification.
+    #[error("Invalid cipher!")]
+    InvalidCipher,
+
+    /// Error for invalid signature algorithm specification.
+    #[error("Invalid signature algorithm!")]
+    InvalidSignatureAlgorithm,
+
+    /// Error for a cipher and signature combination the BootROM does not accept.
+    #[error("The BootROM does not accept {0}")]
+    UnsupportedCombination(String),
+
+    /// Error for invalid output format specification.
+    #[error("Invalid output format!")]
+    InvalidOutputFormat,
+
+    /// Error for an image extending past the 32-bit address space of the output format.
+    #[error("Image ends at 0x{0:x}, beyond the 32-bit address space")]
+    AddressOutOfRange(u64),
+
     /// Wrapper for standard I/O errors.
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
@@ -32,6 +52,62 @@ pub enum XtaskError {
     /// Errors when parsing RSA key components.
     #[error("RSA parse error: {0}")]
     RsaParseError(String),
+
+    /// Error for firmware too large to be described by the image header.
+    #[error("Firmware of {0} bytes is too large for the image header")]
+    FirmwareTooLarge(u64),
+
+    /// Errors from a signing backend.
+    #[error("Signer error: {0}")]
+    SignerError(String),
+
+    /// Error for a signer whose key does not match the scheme of the encryption type.
+    #[error("Encryption type requires a {0} signer")]
+    SignerMismatch(String),
+
+    /// Errors from key pair or certificate generation.
+    #[error("Certificate error: {0}")]
+    CertificateError(#[from] rcgen::Error),
+
+    /// Error for a device secret that is not valid hex.
+    #[error("Invalid device secret: {0}")]
+    InvalidDeviceSecret(#[from] hex::FromHexError),
+
+    /// Errors when encoding or decoding a firmware manifest.
+    #[error("Manifest error: {0}")]
+    ManifestError(String),
+
+    /// Errors when reading a firmware ELF file.
+    #[error("ELF error: {0}")]
+    ElfError(#[from] object::read::Error),
+
+    /// Errors when reading a linker map or memory region.
+    #[error("Size report error: {0}")]
+    SizeError(String),
+
+    /// Errors when parsing a crash dump.
+    #[error("Crash dump error: {0}")]
+    CrashDumpError(String),
+
+    /// Errors when generating a delta between two images.
+    #[error("Delta error: {0}")]
+    DeltaError(String),
+
+    /// Error for binding an unencrypted image to a device.
+    #[error("Binding to a device requires an encrypted image")]
+    BindingRequiresEncryption,
+
+    /// Errors when decoding a trace capture.
+    #[error("Trace error: {0}")]
+    TraceError(String),
+
+    /// Errors when parsing a board file.
+    #[error("Board file error: {0}")]
+    BoardError(String),
+
+    /// Error for data too large to fit in a secure storage blob.
+    #[error("Data of {0} bytes is too large for a secure storage blob")]
+    BlobTooLarge(usize),
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,123 @@
+//! LZ4 compression of the firmware in the payload.
+//!
+//! The layout is described in `kendryte_image::compress`, which also holds
+//! the decompressor used by loaders. The compressor is a single greedy pass
+//! with a hash table of the last position of each 4-byte sequence, which
+//! is the LZ4 fast mode: a little larger than the best LZ4 output, but the
+//! decompression speed that matters on the device is the same.
+
+use crate::error::{XtaskError, XtaskResult};
+use kendryte_image::compress::{HEADER_LEN, MAGIC};
+
+/// Shortest match of the LZ4 format.
+const MIN_MATCH: usize = 4;
+/// The last bytes of a block are always literals.
+const LAST_LITERALS: usize = 5;
+/// The last match starts at least this far before the end of the block.
+const MATCH_LIMIT: usize = 12;
+/// Farthest offset a match can refer back to.
+const MAX_OFFSET: usize = 0xFFFF;
+/// Bits of the hash table index.
+const HASH_BITS: u32 = 16;
+
+/// Compress firmware into the header and LZ4 block loaders unpack.
+pub fn compress(firmware: &[u8]) -> XtaskResult<Vec<u8>> {
+    let len = u32::try_from(firmware.len())
+        .map_err(|_| XtaskError::FirmwareTooLarge(firmware.len() as u64))?;
+    let mut out = Vec::with_capacity(HEADER_LEN + firmware.len() / 2);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&len.to_le_bytes());
+
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+    while pos + MATCH_LIMIT < firmware.len() {
+        let sequence = &firmware[pos..pos + MIN_MATCH];
+        let hash = u32::from_le_bytes(sequence.try_into().unwrap()).wrapping_mul(2_654_435_761)
+            >> (32 - HASH_BITS);
+        let candidate = std::mem::replace(&mut table[hash as usize], pos);
+        if candidate == usize::MAX
+            || pos - candidate > MAX_OFFSET
+            || firmware[candidate..candidate + MIN_MATCH] != *sequence
+        {
+            pos += 1;
+            continue;
+        }
+        let mut len = MIN_MATCH;
+        while pos + len < firmware.len() - LAST_LITERALS
+            && firmware[candidate + len] == firmware[pos + len]
+        {
+            len += 1;
+        }
+        write_sequence(
+            &mut out,
+            &firmware[anchor..pos],
+            Some((pos - candidate, len)),
+        );
+        pos += len;
+        anchor = pos;
+    }
+    write_sequence(&mut out, &firmware[anchor..], None);
+    Ok(out)
+}
+
+/// Write a sequence of literals followed by a match of `(offset, length)`.
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) << 4) | match_len.min(15)) as u8);
+    write_length(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(out, match_len);
+    }
+}
+
+/// Write the extension of a length that does not fit the token nibble.
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 15 {
+        return;
+    }
+    let mut rest = len - 15;
+    while rest >= 0xFF {
+        out.push(0xFF);
+        rest -= 0xFF;
+    }
+    out.push(rest as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kendryte_image::compress::{decompress, split};
+
+    fn round_trip(firmware: &[u8]) -> usize {
+        let compressed = compress(firmware).unwrap();
+        let (len, block) = split(&compressed).unwrap();
+        let mut out = vec![0; len];
+        assert_eq!(decompress(block, &mut out), Ok(firmware.len()));
+        assert_eq!(out, firmware);
+        compressed.len()
+    }
+
+    #[test]
+    fn compress_round_trip() {
+        // Code-like data: repeated instruction patterns with varying fields.
+        let firmware: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| [0x13, (i % 7) as u8, 0x05, (i % 300 / 10) as u8])
+            .collect();
+        assert!(round_trip(&firmware) < firmware.len() / 4);
+
+        round_trip(&[]);
+        round_trip(b"short");
+        round_trip(&[0; 1000]);
+        let mut seed = 1u32;
+        let noise: Vec<u8> = (0..5000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        round_trip(&noise);
+    }
+}
@@ -0,0 +1,214 @@
+//! Output container formats for generated images.
+//!
+//! The raw BootROM image is what the K230 boots from; UF2 and Intel HEX wrap
+//! the same bytes so they can be written by UF2 bootloaders and generic
+//! flashers, placed at a base address on the boot medium.
+//!
+//! Ref: https://github.com/microsoft/uf2
+
+use crate::error::{XtaskError, XtaskResult};
+use std::io::Write;
+use std::str::FromStr;
+
+/// Output formats supported by the image generator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Raw BootROM image.
+    #[default]
+    Bin,
+    /// USB Flashing Format.
+    Uf2,
+    /// Intel HEX.
+    Hex,
+}
+
+impl OutputFormat {
+    /// Conventional file extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Bin => "img",
+            OutputFormat::Uf2 => "uf2",
+            OutputFormat::Hex => "hex",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = XtaskError;
+
+    /// Parse output format from string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bin" => Ok(Self::Bin),
+            "uf2" => Ok(Self::Uf2),
+            "hex" => Ok(Self::Hex),
+            _ => Err(XtaskError::InvalidOutputFormat),
+        }
+    }
+}
+
+/// Write a raw `image` to `out` in `format`.
+/// `base_address` and `family_id` are ignored for the raw format.
+pub fn write_format<W: Write>(
+    format: OutputFormat,
+    image: &[u8],
+    base_address: u32,
+    family_id: Option<u32>,
+    out: &mut W,
+) -> XtaskResult<()> {
+    match format {
+        OutputFormat::Bin => Ok(out.write_all(image)?),
+        OutputFormat::Uf2 => write_uf2(image, base_address, family_id, out),
+        OutputFormat::Hex => write_ihex(image, base_address, out),
+    }
+}
+
+const UF2_MAGIC_START0: u32 = 0x0A32_4655;
+const UF2_MAGIC_START1: u32 = 0x9E5D_5157;
+const UF2_MAGIC_END: u32 = 0x0AB1_6F30;
+const UF2_FLAG_FAMILY_ID: u32 = 0x0000_2000;
+/// Payload bytes carried by each 512-byte UF2 block.
+pub const UF2_PAYLOAD_LEN: usize = 256;
+
+/// Write `image` as UF2 blocks targeting `base_address`.
+/// `family_id` is recorded in every block when given, so bootloaders can
+/// reject images meant for another board.
+pub fn write_uf2<W: Write>(
+    image: &[u8],
+    base_address: u32,
+    family_id: Option<u32>,
+    out: &mut W,
+) -> XtaskResult<()> {
+    check_range(base_address, image.len())?;
+    let blocks = image.chunks(UF2_PAYLOAD_LEN);
+    let num_blocks = blocks.len() as u32;
+    let (flags, family_id) = match family_id {
+        Some(id) => (UF2_FLAG_FAMILY_ID, id),
+        None => (0, 0),
+    };
+
+    for (index, chunk) in blocks.enumerate() {
+        let mut block = [0u8; 512];
+        let address = base_address + (index * UF2_PAYLOAD_LEN) as u32;
+        let words = [
+            UF2_MAGIC_START0,
+            UF2_MAGIC_START1,
+            flags,
+            address,
+            UF2_PAYLOAD_LEN as u32,
+            index as u32,
+            num_blocks,
+            family_id,
+        ];
+        for (field, word) in block.chunks_exact_mut(4).zip(words) {
+            field.copy_from_slice(&word.to_le_bytes());
+        }
+        // The final block is zero padded to a full payload.
+        block[32..32 + chunk.len()].copy_from_slice(chunk);
+        block[508..].copy_from_slice(&UF2_MAGIC_END.to_le_bytes());
+        out.write_all(&block)?;
+    }
+    Ok(())
+}
+
+const IHEX_DATA: u8 = 0x00;
+const IHEX_END_OF_FILE: u8 = 0x01;
+const IHEX_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+/// Data bytes per Intel HEX record.
+const IHEX_RECORD_LEN: usize = 16;
+
+/// Write `image` as Intel HEX records starting at `base_address`.
+pub fn write_ihex<W: Write>(image: &[u8], base_address: u32, out: &mut W) -> XtaskResult<()> {
+    check_range(base_address, image.len())?;
+    let mut upper = None;
+    let mut address = base_address;
+    for chunk in image.chunks(IHEX_RECORD_LEN) {
+        // Records cannot cross a 64 KiB boundary.
+        let room = 0x1_0000 - (address & 0xFFFF) as usize;
+        let (head, tail) = chunk.split_at(chunk.len().min(room));
+        for part in [head, tail] {
+            if part.is_empty() {
+                continue;
+            }
+            let high = (address >> 16) as u16;
+            if upper != Some(high) {
+                write_ihex_record(out, 0, IHEX_EXTENDED_LINEAR_ADDRESS, &high.to_be_bytes())?;
+                upper = Some(high);
+            }
+            write_ihex_record(out, address as u16, IHEX_DATA, part)?;
+            address = address.wrapping_add(part.len() as u32);
+        }
+    }
+    write_ihex_record(out, 0, IHEX_END_OF_FILE, &[])
+}
+
+fn write_ihex_record<W: Write>(out: &mut W, offset: u16, kind: u8, data: &[u8]) -> XtaskResult<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend(offset.to_be_bytes());
+    record.push(kind);
+    record.extend(data);
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_add(*b))
+        .wrapping_neg();
+    record.push(checksum);
+    writeln!(out, ":{}", hex::encode_upper(record))?;
+    Ok(())
+}
+
+/// Both formats address a 32-bit space.
+fn check_range(base_address: u32, len: usize) -> XtaskResult<()> {
+    let end = base_address as u64 + len as u64;
+    if end > 1 << 32 {
+        return Err(XtaskError::AddressOutOfRange(end));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uf2_blocks() {
+        let image: Vec<u8> = (0..=255).cycle().take(300).collect();
+        let mut out = Vec::new();
+        write_uf2(&image, 0x1000, Some(0x1234_5678), &mut out).unwrap();
+        assert_eq!(out.len(), 2 * 512);
+
+        let word = |block: usize, index: usize| {
+            let at = block * 512 + index * 4;
+            u32::from_le_bytes(out[at..at + 4].try_into().unwrap())
+        };
+        assert_eq!(word(0, 0), UF2_MAGIC_START0);
+        assert_eq!(word(0, 2), UF2_FLAG_FAMILY_ID);
+        assert_eq!(word(1, 3), 0x1100);
+        assert_eq!(word(1, 5), 1);
+        assert_eq!(word(1, 6), 2);
+        assert_eq!(word(1, 7), 0x1234_5678);
+        assert_eq!(word(1, 127), UF2_MAGIC_END);
+        assert_eq!(&out[512 + 32..512 + 32 + 44], &image[256..]);
+        assert!(out[512 + 32 + 44..512 + 508].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn ihex_records() {
+        let mut out = Vec::new();
+        write_ihex(&[0x01, 0x02, 0x03], 0x0001_FFFE, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ":020000040001F9\n\
+             :02FFFE000102FE\n\
+             :020000040002F8\n\
+             :0100000003FC\n\
+             :00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn address_out_of_range() {
+        let mut out = Vec::new();
+        assert!(write_uf2(&[0; 4], u32::MAX - 1, None, &mut out).is_err());
+        assert!(write_ihex(&[0; 4], u32::MAX - 1, &mut out).is_err());
+    }
+}
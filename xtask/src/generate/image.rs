@@ -2,22 +2,23 @@
 
 use crate::error::{XtaskError, XtaskResult};
 use crate::generate::config::{
-    ADD_AUTH_DATA, D, E, ID, ID_LEN, INITIAL_AES_IV, INITIAL_AES_KEY, K, MAGIC, N, PRIVATE_KEY,
-    PUBLIC_KEY_X, PUBLIC_KEY_Y, SM4_IV, SM4_KEY, VERSION,
+    ADD_AUTH_DATA, INITIAL_AES_IV, INITIAL_AES_KEY, MAGIC, SM4_IV, SM4_KEY, VERSION,
 };
-use aes_gcm::{AeadInPlace, Aes256Gcm, Key, KeyInit, Nonce, Tag};
-use cbc::cipher::KeyIvInit;
-use cipher::block_padding::Pkcs7;
-use cipher::BlockEncryptMut;
-use num_bigint_dig::BigUint;
+use crate::generate::signer::{
+    verify_rsa_tag, verify_sm2_prehash, PublicKey, RsaSigner, SignatureScheme, Signer, Sm2Signer,
+};
+use aes::Aes256;
+use cipher::generic_array::GenericArray;
+use cipher::{BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit, StreamCipher};
+use ctr::Ctr32BE;
+use ghash::universal_hash::UniversalHash;
+use ghash::GHash;
+use kendryte_image::bind_key;
 use primeorder::PrimeCurveParams;
-use rsa::pkcs1v15::SigningKey;
-use rsa::signature::{SignatureEncoding, Signer};
-use rsa::RsaPrivateKey;
 use sha2::{Digest, Sha256};
-use sm2::elliptic_curve::ScalarPrimitive;
-use sm2::{FieldBytes, Scalar, SecretKey, Sm2};
+use sm2::Sm2;
 use sm3::Sm3;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::str::FromStr;
 
 /// Encryption types supported for firmware.
@@ -43,69 +44,271 @@ impl FromStr for EncryptionType {
     }
 }
 
+/// Payload ciphers, selectable independently of the signature with `--cipher`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    None,
+    Sm4,
+    Aes,
+}
+
+impl FromStr for Cipher {
+    type Err = XtaskError;
+
+    /// Parse cipher from string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "sm4" => Ok(Self::Sm4),
+            "aes" => Ok(Self::Aes),
+            _ => Err(XtaskError::InvalidCipher),
+        }
+    }
+}
+
+/// Hash or signature algorithms, selectable independently of the cipher with `--signature`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// SHA-256 hash only, no signature.
+    Sha256,
+    Sm2,
+    Rsa,
+}
+
+impl FromStr for SignatureAlgorithm {
+    type Err = XtaskError;
+
+    /// Parse signature algorithm from string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sha256" => Ok(Self::Sha256),
+            "sm2" => Ok(Self::Sm2),
+            "rsa" => Ok(Self::Rsa),
+            _ => Err(XtaskError::InvalidSignatureAlgorithm),
+        }
+    }
+}
+
+impl EncryptionType {
+    /// Returns the encryption type combining `cipher` and `signature`.
+    ///
+    /// The K230 BootROM reads a single encryption type field, so only the
+    /// combinations it defines are accepted: no cipher with SHA-256, SM4
+    /// with SM2, and AES with RSA.
+    pub fn from_parts(cipher: Cipher, signature: SignatureAlgorithm) -> XtaskResult<Self> {
+        match (cipher, signature) {
+            (Cipher::None, SignatureAlgorithm::Sha256) => Ok(Self::None),
+            (Cipher::Sm4, SignatureAlgorithm::Sm2) => Ok(Self::Sm4),
+            (Cipher::Aes, SignatureAlgorithm::Rsa) => Ok(Self::Aes),
+            _ => Err(XtaskError::UnsupportedCombination(format!(
+                "{:?} with {:?}",
+                cipher, signature
+            ))),
+        }
+    }
+
+    /// Returns the cipher of the encryption type.
+    pub fn cipher(self) -> Cipher {
+        match self {
+            Self::None => Cipher::None,
+            Self::Sm4 => Cipher::Sm4,
+            Self::Aes => Cipher::Aes,
+        }
+    }
+
+    /// Returns the hash or signature algorithm of the encryption type.
+    pub fn signature(self) -> SignatureAlgorithm {
+        match self {
+            Self::None => SignatureAlgorithm::Sha256,
+            Self::Sm4 => SignatureAlgorithm::Sm2,
+            Self::Aes => SignatureAlgorithm::Rsa,
+        }
+    }
+}
+
 /// Generate a firmware image for the K230 platform.
 /// This function creates an image with the specified encryption type.
 /// The image includes a header, cryptographic information, and the firmware data.
 /// The image is padded to a multiple of 512 bytes.
 /// Returns the generated image as a vector of bytes.
 pub fn gen_image(firmware: &[u8], encryption: EncryptionType) -> XtaskResult<Vec<u8>> {
+    let mut image = Cursor::new(Vec::new());
+    write_image(firmware, encryption, &mut image)?;
+    Ok(image.into_inner())
+}
+
+/// Generate a firmware image for the K230 platform into a seekable sink.
+/// The firmware is read, hashed and encrypted in chunks of `CHUNK_LEN` bytes,
+/// so memory use does not grow with the firmware size. Header fields that depend
+/// on the whole payload (length, hash, signature) are written as placeholders and
+/// filled in once the payload has been streamed.
+/// The image is written from the current position of `image`.
+/// Returns the number of bytes written.
+pub fn write_image<R: Read, W: Write + Seek>(
+    firmware: R,
+    encryption: EncryptionType,
+    image: &mut W,
+) -> XtaskResult<u64> {
+    let signer: Option<Box<dyn Signer>> = match encryption {
+        EncryptionType::None => None,
+        EncryptionType::Sm4 => Some(Box::new(Sm2Signer::development()?)),
+        EncryptionType::Aes => Some(Box::new(RsaSigner::development()?)),
+    };
+    write_signed_image(firmware, encryption, signer.as_deref(), image)
+}
+
+/// Returns the signature scheme an encryption type is signed with, if any.
+pub fn signature_scheme(encryption: EncryptionType) -> Option<SignatureScheme> {
+    match encryption {
+        EncryptionType::None => None,
+        EncryptionType::Sm4 => Some(SignatureScheme::Sm2),
+        EncryptionType::Aes => Some(SignatureScheme::Rsa),
+    }
+}
+
+/// Generate a firmware image like [`write_image`], signing with `signer`.
+/// The signer must use the scheme of the encryption type, see [`signature_scheme`];
+/// it is not used for unencrypted images.
+pub fn write_signed_image<R: Read, W: Write + Seek>(
+    firmware: R,
+    encryption: EncryptionType,
+    signer: Option<&dyn Signer>,
+    image: &mut W,
+) -> XtaskResult<u64> {
+    write_bound_image(firmware, encryption, signer, None, image)
+}
+
+/// Generate a signed firmware image like [`write_signed_image`], optionally
+/// bound to the device with unique ID `uid`.
+/// A bound image is encrypted with keys derived from the ID by
+/// `kendryte_image::bind_key`, so it only decrypts on that device.
+pub fn write_bound_image<R: Read, W: Write + Seek>(
+    firmware: R,
+    encryption: EncryptionType,
+    signer: Option<&dyn Signer>,
+    uid: Option<&[u8]>,
+    image: &mut W,
+) -> XtaskResult<u64> {
+    let signer = match (signature_scheme(encryption), signer) {
+        (None, _) => None,
+        (Some(scheme), Some(signer)) if signer.public_key().scheme() == scheme => Some(signer),
+        (Some(scheme), _) => return Err(XtaskError::SignerMismatch(format!("{:?}", scheme))),
+    };
+
+    let mut aes_key: [u8; 32] = INITIAL_AES_KEY.try_into().unwrap();
+    let mut sm4_key: [u8; 16] = SM4_KEY.try_into().unwrap();
+    if let Some(uid) = uid {
+        if let EncryptionType::None = encryption {
+            return Err(XtaskError::BindingRequiresEncryption);
+        }
+        aes_key = bind_key(&aes_key, uid);
+        sm4_key = bind_key(&sm4_key, uid);
+        println!("bound to device: {}", hex::encode(uid));
+    }
+
     println!("----- Generating image -----");
-    let mut image = vec![0; 0x100000];
-    image.extend(MAGIC.as_bytes());
+    let start = image.stream_position()?;
+    write_zeros(image, 0x100000)?;
+    image.write_all(MAGIC.as_bytes())?;
     println!("the magic is: {}", MAGIC);
 
+    // Prepend the version bytes to the firmware data.
+    let firmware_with_version = VERSION.chain(firmware);
+
     match encryption {
-        EncryptionType::None => handle_none_encryption(&mut image, firmware)?,
-        EncryptionType::Sm4 => handle_sm4_encryption(&mut image, firmware)?,
-        EncryptionType::Aes => handle_aes_encryption(&mut image, firmware)?,
+        EncryptionType::None => handle_none_encryption(image, firmware_with_version)?,
+        EncryptionType::Sm4 => {
+            handle_sm4_encryption(image, firmware_with_version, signer.unwrap(), &sm4_key)?
+        }
+        EncryptionType::Aes => {
+            handle_aes_encryption(image, firmware_with_version, signer.unwrap(), &aes_key)?
+        }
     }
 
-    if image.len() % 512 != 0 {
-        let padding_size = 512 - image.len() % 512;
-        image.extend(vec![0; padding_size]);
+    let mut len = image.stream_position()? - start;
+    if len % 512 != 0 {
+        let padding_size = 512 - len % 512;
+        write_zeros(image, padding_size as usize)?;
+        len += padding_size;
     }
 
-    Ok(image)
+    Ok(len)
 }
 
-/// Prepare the firmware data with version information.
-/// This function prepends the version bytes to the firmware data.
-/// Returns a new vector containing the version and firmware.
-fn prepare_firmware_with_version(firmware: &[u8]) -> Vec<u8> {
-    let mut firmware_with_version: Vec<u8> = Vec::with_capacity(VERSION.len() + firmware.len());
-    firmware_with_version.extend(VERSION);
-    firmware_with_version.extend(firmware);
-    firmware_with_version
+/// Size of the chunks the firmware is streamed in. A multiple of the cipher block size.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Read until `buf` is full or the reader is exhausted.
+/// Only the last chunk of a stream is shorter than `buf`, which keeps block
+/// ciphers and GHASH aligned across chunks.
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
 }
 
-/// Add header information to the image.
+/// Write `len` zero bytes.
+fn write_zeros<W: Write>(image: &mut W, len: usize) -> io::Result<()> {
+    io::copy(&mut io::repeat(0).take(len as u64), image).map(|_| ())
+}
+
+/// Overwrite previously reserved bytes at `pos`, then return to the current position.
+fn patch<W: Write + Seek>(image: &mut W, pos: u64, data: &[u8]) -> io::Result<()> {
+    let end = image.stream_position()?;
+    image.seek(SeekFrom::Start(pos))?;
+    image.write_all(data)?;
+    image.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Prepare header information for the image.
 /// The header includes the firmware length and encryption type.
 /// The length and encryption type are stored as little-endian 32-bit integers.
-fn add_header_info(image: &mut Vec<u8>, len: i32, encryption: EncryptionType) {
-    image.extend(len.to_le_bytes());
-    image.extend((encryption as i32).to_le_bytes());
+fn header_info(len: u64, encryption: EncryptionType) -> XtaskResult<Vec<u8>> {
+    let len = i32::try_from(len).map_err(|_| XtaskError::FirmwareTooLarge(len))?;
+    let mut header = Vec::with_capacity(8);
+    header.extend(len.to_le_bytes());
+    header.extend((encryption as i32).to_le_bytes());
+    Ok(header)
 }
 
 /// Handle the case of no encryption for the firmware image.
 /// This function adds a SHA-256 hash of the firmware to the image.
 /// The hash is followed by padding and the firmware data itself.
-fn handle_none_encryption(image: &mut Vec<u8>, firmware: &[u8]) -> XtaskResult<()> {
+fn handle_none_encryption<R: Read, W: Write + Seek>(
+    image: &mut W,
+    mut firmware_with_version: R,
+) -> XtaskResult<()> {
     println!("----- NO ENCRYPTION + HASH-256 -----");
-    let firmware_with_version = prepare_firmware_with_version(firmware);
-
-    add_header_info(
-        image,
-        firmware_with_version.len() as i32,
-        EncryptionType::None,
-    );
+    // Reserve header information and hash.
+    let header_pos = image.stream_position()?;
+    write_zeros(image, 8 + 516)?;
 
     let mut hasher = Sha256::new();
-    hasher.update(firmware_with_version.as_slice());
+    let mut len = 0;
+    let mut chunk = vec![0; CHUNK_LEN];
+    loop {
+        let n = read_chunk(&mut firmware_with_version, &mut chunk)?;
+        hasher.update(&chunk[..n]);
+        image.write_all(&chunk[..n])?;
+        len += n as u64;
+        if n < CHUNK_LEN {
+            break;
+        }
+    }
+
     let hash = hasher.finalize();
     println!("hash: {}", hex::encode(&hash));
-    image.extend(hash);
-    image.extend(vec![0; 516 - 32]);
-    image.extend(firmware_with_version);
+    let mut header = header_info(len, EncryptionType::None)?;
+    header.extend(hash);
+    patch(image, header_pos, &header)?;
 
     Ok(())
 }
@@ -113,22 +316,57 @@ fn handle_none_encryption(image: &mut Vec<u8>, firmware: &[u8]) -> XtaskResult<(
 /// Handle the case of SM4 encryption for the firmware image.
 /// This function encrypts the firmware using SM4-CBC and signs it with SM2.
 /// The image includes the signature, public key, and encrypted firmware.
-fn handle_sm4_encryption(image: &mut Vec<u8>, firmware: &[u8]) -> XtaskResult<()> {
+fn handle_sm4_encryption<R: Read, W: Write + Seek>(
+    image: &mut W,
+    mut firmware_with_version: R,
+    signer: &dyn Signer,
+    key: &[u8; 16],
+) -> XtaskResult<()> {
     println!("----- SM4-CBC + SM2 -----");
-    let firmware_with_version = prepare_firmware_with_version(firmware);
-
-    let ciphertext = encrypt_sm4(&firmware_with_version);
-
-    // Add header information.
-    add_header_info(image, ciphertext.len() as i32, EncryptionType::Sm4);
+    let PublicKey::Sm2 { id, x, y } = signer.public_key() else {
+        return Err(XtaskError::SignerMismatch("Sm2".to_string()));
+    };
+    // Reserve header information and SM2 information.
+    let header_pos = image.stream_position()?;
+    let id_info = prepare_id_info(id)?;
+    write_zeros(image, 8 + id_info.len() + 32 * 4)?;
+
+    // Perform SM4-CBC encryption, hashing the ciphertext for the signature.
+    let mut cipher = Sm4CbcStream::new(key);
+    let mut hasher = sm2_message_hasher(id, x, y);
+    let mut len = 0;
+    let mut chunk = vec![0; CHUNK_LEN + 16];
+    loop {
+        let n = read_chunk(&mut firmware_with_version, &mut chunk[..CHUNK_LEN])?;
+        let ciphertext = match n {
+            CHUNK_LEN => cipher.encrypt(&mut chunk[..n]),
+            _ => cipher.encrypt_last(&mut chunk, n),
+        };
+        hasher.update(&*ciphertext);
+        image.write_all(ciphertext)?;
+        len += ciphertext.len() as u64;
+        if n < CHUNK_LEN {
+            break;
+        }
+    }
 
-    let (signature, r, s) = prepare_sm2_signature(&ciphertext)?;
+    let e = hasher.finalize();
+    let signature = signer.sign(&e)?;
+    if signature.len() != 64 {
+        return Err(XtaskError::SignerError(format!(
+            "expected a 64-byte SM2 signature, got {} bytes",
+            signature.len()
+        )));
+    }
+    verify_sm2_prehash(signer.public_key(), &e, &signature)?;
+    let (r, s) = signature.split_at(32);
     println!("signature: {}", hex::encode(&signature));
-    println!("r: {}", hex::encode(&r));
-    println!("s: {}", hex::encode(&s));
-    add_sm2_info(image, r.as_slice(), s.as_slice());
-    // Add encrypted data.
-    image.extend(ciphertext);
+    println!("r: {}", hex::encode(r));
+    println!("s: {}", hex::encode(s));
+
+    let mut header = header_info(len, EncryptionType::Sm4)?;
+    add_sm2_info(&mut header, &id_info, x, y, r, s);
+    patch(image, header_pos, &header)?;
 
     Ok(())
 }
@@ -136,100 +374,154 @@ fn handle_sm4_encryption(image: &mut Vec<u8>, firmware: &[u8]) -> XtaskResult<()
 /// Handle the case of AES encryption for the firmware image.
 /// This function encrypts the firmware using AES-GCM and signs the tag with RSA-2048.
 /// The image includes the RSA signature, public key, and encrypted firmware.
-fn handle_aes_encryption(image: &mut Vec<u8>, firmware: &[u8]) -> XtaskResult<()> {
+fn handle_aes_encryption<R: Read, W: Write + Seek>(
+    image: &mut W,
+    mut firmware_with_version: R,
+    signer: &dyn Signer,
+    key: &[u8; 32],
+) -> XtaskResult<()> {
     println!("----- AES-GCM + RSA-2048 -----");
-    let firmware_with_version = prepare_firmware_with_version(firmware);
+    let PublicKey::Rsa { n, e } = signer.public_key() else {
+        return Err(XtaskError::SignerMismatch("Rsa".to_string()));
+    };
+    // Reserve header information, public key and signature.
+    let header_pos = image.stream_position()?;
+    let reserved = 8 + 256 + 4 + 256;
+    write_zeros(image, reserved)?;
 
     // Perform AES-GCM encryption.
-    let (ciphertext, tag) = encrypt_aes(&firmware_with_version)?;
-
+    let mut cipher = AesGcmStream::new(key, INITIAL_AES_IV, ADD_AUTH_DATA);
+    let mut len = 0;
+    let mut chunk = vec![0; CHUNK_LEN];
+    loop {
+        let n = read_chunk(&mut firmware_with_version, &mut chunk)?;
+        cipher.encrypt(&mut chunk[..n]);
+        image.write_all(&chunk[..n])?;
+        len += n as u64;
+        if n < CHUNK_LEN {
+            break;
+        }
+    }
+    // The tag is appended to the ciphertext.
+    let tag = cipher.finalize();
+    image.write_all(&tag)?;
+    len += tag.len() as u64;
     println!("tag: {}", hex::encode(&tag));
-    // Add header information.
-    add_header_info(image, ciphertext.len() as i32, EncryptionType::Aes);
 
     // Generate and add RSA signature.
-    let (signature, n, e) = prepare_rsa_signature(tag)?;
+    let signature = signer.sign(&tag)?;
+    verify_rsa_tag(signer.public_key(), &tag, &signature)?;
+    let e = e.to_le_bytes();
     println!("signature: {}", hex::encode(&signature));
-    println!("n: {}", hex::encode(&n));
-    println!("e: {}", hex::encode(&e));
-
-    image.extend(n);
-    image.extend(e);
-    image.extend(signature);
-    // Add encrypted data.
-    image.extend(&ciphertext);
+    println!("n: {}", hex::encode(n));
+    println!("e: {}", hex::encode(e));
+
+    let mut header = header_info(len, EncryptionType::Aes)?;
+    header.extend(n);
+    header.extend(e);
+    header.extend(signature);
+    if header.len() != reserved {
+        return Err(XtaskError::SignerError(
+            "RSA-2048 key and signature expected".to_string(),
+        ));
+    }
+    patch(image, header_pos, &header)?;
 
     Ok(())
 }
 
-/// Encrypt the firmware using AES-GCM.
-/// Returns the ciphertext and authentication tag.
-/// The tag is appended to the ciphertext.
-fn encrypt_aes(firmware_with_version: &[u8]) -> XtaskResult<(Vec<u8>, Tag)> {
-    let key = Key::<Aes256Gcm>::from_slice(INITIAL_AES_KEY);
-    let nonce = Nonce::from_slice(INITIAL_AES_IV);
-    let cipher = Aes256Gcm::new(key);
-
-    let mut ciphertext = firmware_with_version.to_vec();
-    // Perform AES-GCM encryption and get authentication tag.
-    let tag = cipher
-        .encrypt_in_place_detached(nonce, ADD_AUTH_DATA, &mut ciphertext)
-        .map_err(|e| XtaskError::AesError(e.to_string()))?;
-    ciphertext.extend(&tag);
-    Ok((ciphertext, tag))
+/// Streaming AES-256-GCM encryption with a 96-bit nonce (NIST SP 800-38D).
+/// Data must be passed in multiples of 16 bytes, except for the last call.
+struct AesGcmStream {
+    ctr: Ctr32BE<Aes256>,
+    ghash: GHash,
+    tag_mask: aes::Block,
+    aad_len: u64,
+    len: u64,
 }
 
-/// Prepare an RSA signature for the AES-GCM tag.
-/// This function constructs the RSA private key from components and signs the tag.
-/// Returns the signature, modulus (n), and exponent (e) as byte vectors.
-fn prepare_rsa_signature(tag: Tag) -> XtaskResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
-    // Parse RSA key components.
-    let n = hex::encode(N);
-    let n = BigUint::parse_bytes(n.as_bytes(), 16).ok_or(XtaskError::RsaParseError(
-        "Failed to parse N for RSA".to_string(),
-    ))?;
-
-    let e = u32::from_str_radix(&E[2..], 16)
-        .map_err(|_| XtaskError::RsaParseError("Failed to parse E for RSA".to_string()))?;
-    let e_le_bytes = e.to_le_bytes();
-    let e = BigUint::from(e);
-    let d = hex::encode(D);
-    let d = BigUint::parse_bytes(d.as_bytes(), 16).ok_or(XtaskError::RsaParseError(
-        "Failed to parse D for RSA".to_string(),
-    ))?;
-
-    // Create RSA private key from components.
-    let private_key = RsaPrivateKey::from_components(
-        n.clone(),
-        e.clone(),
-        d.clone(),
-        Vec::new(), // Prime factors omitted for simplicity.
-    )?;
-
-    // Generate RSA signature using PKCS#1 v1.5 padding.
-    let signing_key = SigningKey::<Sha256>::new(private_key);
-    let signature = signing_key.sign(&tag).to_vec();
-
-    Ok((signature, n.to_bytes_be(), e_le_bytes.to_vec()))
+impl AesGcmStream {
+    fn new(key: &[u8], iv: &[u8], aad: &[u8]) -> Self {
+        let cipher = Aes256::new(GenericArray::from_slice(key));
+        // Hash subkey H = E(K, 0^128).
+        let mut h = aes::Block::default();
+        cipher.encrypt_block(&mut h);
+
+        // Pre-counter block J0 = IV || 0^31 || 1.
+        let mut j0 = [0; 16];
+        j0[..12].copy_from_slice(iv);
+        j0[15] = 1;
+        let mut tag_mask = aes::Block::from(j0);
+        cipher.encrypt_block(&mut tag_mask);
+
+        // Encryption starts from inc32(J0).
+        j0[15] = 2;
+        let ctr = Ctr32BE::<Aes256>::new(GenericArray::from_slice(key), &j0.into());
+
+        let mut ghash = GHash::new(&h);
+        ghash.update_padded(aad);
+        Self {
+            ctr,
+            ghash,
+            tag_mask,
+            aad_len: aad.len() as u64,
+            len: 0,
+        }
+    }
+
+    fn encrypt(&mut self, data: &mut [u8]) {
+        self.ctr.apply_keystream(data);
+        self.ghash.update_padded(data);
+        self.len += data.len() as u64;
+    }
+
+    fn finalize(mut self) -> [u8; 16] {
+        let mut lengths = [0; 16];
+        lengths[..8].copy_from_slice(&(self.aad_len * 8).to_be_bytes());
+        lengths[8..].copy_from_slice(&(self.len * 8).to_be_bytes());
+        self.ghash.update(&[lengths.into()]);
+        let mut tag: [u8; 16] = self.ghash.finalize().into();
+        for (byte, mask) in tag.iter_mut().zip(self.tag_mask) {
+            *byte ^= mask;
+        }
+        tag
+    }
 }
 
-/// Encrypt the firmware using SM4-CBC with PKCS7 padding.
-/// Returns the ciphertext as a vector of bytes.
-fn encrypt_sm4(firmware_with_version: &[u8]) -> Vec<u8> {
-    type Sm4CbcEnc = cbc::Encryptor<sm4::Sm4>;
-    let cipher = Sm4CbcEnc::new(SM4_KEY.into(), SM4_IV.into());
-    cipher.encrypt_padded_vec_mut::<Pkcs7>(&firmware_with_version)
+/// Streaming SM4-CBC encryption with PKCS7 padding.
+struct Sm4CbcStream {
+    cipher: cbc::Encryptor<sm4::Sm4>,
 }
 
-/// Prepare an SM2 signature for the ciphertext.
-/// This function calculates the SM3 hash and signs it using the SM2 private key.
-/// Returns the signature and its r and s components.
-fn prepare_sm2_signature(ciphertext: &[u8]) -> XtaskResult<(Vec<u8>, FieldBytes, FieldBytes)> {
-    // Signing.
-    let sk = ScalarPrimitive::from_slice(PRIVATE_KEY)?;
-    let secret_key = SecretKey::new(sk);
-    let signing_key = sm2::dsa::SigningKey::new(ID, &secret_key)?;
+impl Sm4CbcStream {
+    fn new(key: &[u8; 16]) -> Self {
+        Self {
+            cipher: cbc::Encryptor::<sm4::Sm4>::new(key.into(), SM4_IV.into()),
+        }
+    }
 
+    /// Encrypt whole blocks in place.
+    fn encrypt<'a>(&mut self, data: &'a mut [u8]) -> &'a [u8] {
+        for block in data.chunks_exact_mut(16) {
+            self.cipher
+                .encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        data
+    }
+
+    /// Pad the last `len` bytes of data in `buf` and encrypt them in place.
+    /// `buf` must have room for a full block of padding.
+    fn encrypt_last<'a>(&mut self, buf: &'a mut [u8], len: usize) -> &'a [u8] {
+        let padding = 16 - len % 16;
+        buf[len..len + padding].fill(padding as u8);
+        self.encrypt(&mut buf[..len + padding])
+    }
+}
+
+/// Prepare the SM3 hasher for an SM2 signature.
+/// The hasher is initialised with Z, the hash of the user ID and curve parameters,
+/// and the ciphertext is then fed to it as it is produced.
+fn sm2_message_hasher(id: &str, public_key_x: &[u8], public_key_y: &[u8]) -> Sm3 {
     // Get curve parameters for SM3 hash calculation.
     let a = Sm2::EQUATION_A.to_bytes();
     let b = Sm2::EQUATION_B.to_bytes();
@@ -238,51 +530,35 @@ fn prepare_sm2_signature(ciphertext: &[u8]) -> XtaskResult<(Vec<u8>, FieldBytes,
 
     // Prepare Z value for SM2 signature (user ID and curve parameters).
     let mut z = vec![];
-    z.extend(ID_LEN);
-    z.extend(ID.as_bytes());
+    // ENTL: the ID length in bits.
+    z.extend(((id.len() * 8) as u16).to_be_bytes());
+    z.extend(id.as_bytes());
     z.extend(&a);
     z.extend(&b);
     z.extend(&x_g);
     z.extend(&y_g);
-    z.extend(PUBLIC_KEY_X);
-    z.extend(PUBLIC_KEY_Y);
+    z.extend(public_key_x);
+    z.extend(public_key_y);
 
     let mut hasher = Sm3::new();
     hasher.update(&z);
     let z_a = hasher.finalize();
 
-    // Calculate message hash for signing.
-    let mut m = vec![];
-    m.extend_from_slice(&z_a);
-    m.extend_from_slice(&ciphertext);
-
+    // Message hash for signing starts with Z.
     let mut hasher = Sm3::new();
-    hasher.update(&m);
-    let e = hasher.finalize();
-
-    let k = Scalar::from_slice(K)?;
-    let signature = signing_key.sign_prehash_with_k(&k, &e)?;
-
-    let r = signature.r().to_bytes();
-    let s = signature.s().to_bytes();
-
-    let mut signature: Vec<u8> = Vec::with_capacity(r.len() + s.len());
-    signature.extend(&r);
-    signature.extend(&s);
-
-    Ok((signature, r, s))
+    hasher.update(&z_a);
+    hasher
 }
 
-/// Add SM2-related information to the image.
+/// Add SM2-related information to the image header.
 /// This includes the ID info, public key, and signature components r and s.
-fn add_sm2_info(image: &mut Vec<u8>, r: &[u8], s: &[u8]) {
+fn add_sm2_info(image: &mut Vec<u8>, id_info: &[u8], x: &[u8], y: &[u8], r: &[u8], s: &[u8]) {
     // Add ID information.
-    let id_info = prepare_id_info();
-    image.extend(&id_info);
+    image.extend(id_info);
 
     // Add public key and signature.
-    image.extend(PUBLIC_KEY_X);
-    image.extend(PUBLIC_KEY_Y);
+    image.extend(x);
+    image.extend(y);
     image.extend(r);
     image.extend(s);
 }
@@ -290,16 +566,19 @@ fn add_sm2_info(image: &mut Vec<u8>, r: &[u8], s: &[u8]) {
 /// Prepare the ID information for the image.
 /// The ID info includes the ID length, ID bytes, and padding.
 /// Returns the ID info as a vector of bytes.
-fn prepare_id_info() -> Vec<u8> {
+fn prepare_id_info(id: &str) -> XtaskResult<Vec<u8>> {
     let mut id_info = Vec::new();
-    let id = ID.as_bytes();
+    let id = id.as_bytes();
+    if id.len() > 512 - 32 * 4 {
+        return Err(XtaskError::SignerError("SM2 ID too long".to_string()));
+    }
     let id_len_bytes = (id.len() as i32).to_le_bytes();
 
     id_info.extend(&id_len_bytes);
     id_info.extend(id);
     id_info.extend(vec![0; 512 - 32 * 4 - id.len()]); // Add padding.
 
-    id_info
+    Ok(id_info)
 }
 
 #[cfg(test)]
@@ -326,6 +605,79 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_aes_gcm_stream_matches_one_shot() {
+        use super::AesGcmStream;
+        use crate::generate::config::{ADD_AUTH_DATA, INITIAL_AES_IV, INITIAL_AES_KEY};
+        use aes_gcm::{AeadInPlace, Aes256Gcm, Key, KeyInit, Nonce};
+
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let mut expected = data.clone();
+        let expected_tag = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(INITIAL_AES_KEY))
+            .encrypt_in_place_detached(
+                Nonce::from_slice(INITIAL_AES_IV),
+                ADD_AUTH_DATA,
+                &mut expected,
+            )
+            .unwrap();
+
+        let mut actual = data.clone();
+        let mut cipher = AesGcmStream::new(INITIAL_AES_KEY, INITIAL_AES_IV, ADD_AUTH_DATA);
+        for chunk in actual.chunks_mut(64) {
+            cipher.encrypt(chunk);
+        }
+        assert_eq!(actual, expected);
+        assert_eq!(cipher.finalize().as_slice(), expected_tag.as_slice());
+    }
+
+    #[test]
+    fn test_sm4_cbc_stream_matches_one_shot() {
+        use super::Sm4CbcStream;
+        use crate::generate::config::{SM4_IV, SM4_KEY};
+        use cipher::block_padding::Pkcs7;
+        use cipher::{BlockEncryptMut, KeyIvInit};
+
+        for len in [0, 15, 16, 100, 128] {
+            let data: Vec<u8> = (0..len as u32).map(|i| i as u8).collect();
+            let expected = cbc::Encryptor::<sm4::Sm4>::new(SM4_KEY.into(), SM4_IV.into())
+                .encrypt_padded_vec_mut::<Pkcs7>(&data);
+
+            let mut cipher = Sm4CbcStream::new(SM4_KEY.try_into().unwrap());
+            let mut actual = Vec::new();
+            let whole = len / 32 * 32;
+            for chunk in data[..whole].chunks(32) {
+                actual.extend(cipher.encrypt(&mut chunk.to_vec()));
+            }
+            let mut last = data[whole..].to_vec();
+            last.resize(last.len() + 16, 0);
+            actual.extend(cipher.encrypt_last(&mut last, len - whole));
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_cipher_signature_matrix() {
+        use crate::generate::image::{Cipher, SignatureAlgorithm};
+
+        let ciphers = ["none", "sm4", "aes"];
+        let signatures = ["sha256", "sm2", "rsa"];
+        let mut accepted = 0;
+        for cipher in ciphers {
+            for signature in signatures {
+                let cipher: Cipher = cipher.parse().unwrap();
+                let signature: SignatureAlgorithm = signature.parse().unwrap();
+                if let Ok(encryption) = EncryptionType::from_parts(cipher, signature) {
+                    assert_eq!(encryption.cipher(), cipher);
+                    assert_eq!(encryption.signature(), signature);
+                    accepted += 1;
+                }
+            }
+        }
+        assert_eq!(accepted, 3);
+        assert!("des".parse::<Cipher>().is_err());
+        assert!("ecdsa-p256".parse::<SignatureAlgorithm>().is_err());
+    }
+
     #[test]
     fn test_none_encryption() {
         let firmware = include_bytes!("../../../xtask/tests/data/firmware.bin");
@@ -346,6 +698,111 @@ mod tests {
         assert_hashes_match(&actual, expected);
     }
 
+    #[test]
+    fn test_unpack_round_trip() {
+        use crate::generate::config::{
+            ADD_AUTH_DATA, INITIAL_AES_IV, INITIAL_AES_KEY, SM4_IV, SM4_KEY,
+        };
+        use kendryte_image::{decrypt_payload, parse_header, verify_signature, Keys};
+
+        let firmware = include_bytes!("../../../xtask/tests/data/firmware.bin");
+        let keys = Keys {
+            aes_key: INITIAL_AES_KEY.try_into().unwrap(),
+            aes_iv: INITIAL_AES_IV.try_into().unwrap(),
+            aes_aad: ADD_AUTH_DATA,
+            sm4_key: SM4_KEY.try_into().unwrap(),
+            sm4_iv: SM4_IV.try_into().unwrap(),
+        };
+
+        for encryption in [
+            EncryptionType::None,
+            EncryptionType::Sm4,
+            EncryptionType::Aes,
+        ] {
+            let mut image = gen_image(firmware, encryption).expect("Encryption failed");
+            let image = &mut image[kendryte_image::HEADER_OFFSET..];
+
+            let header = parse_header(image).expect("Invalid header");
+            assert_eq!(header.encryption() as i32, encryption as i32);
+            verify_signature(&header, image).expect("Verification failed");
+
+            // The last payload byte is covered by every scheme, including the AES tag.
+            let mut tampered = image.to_vec();
+            tampered[kendryte_image::PAYLOAD_OFFSET + header.payload_len - 1] ^= 1;
+            assert!(verify_signature(&header, &tampered).is_err());
+
+            let decrypted = decrypt_payload(&header, image, &keys).expect("Decryption failed");
+            assert_eq!(decrypted, firmware);
+        }
+    }
+
+    #[test]
+    fn test_bound_image() {
+        use crate::error::XtaskError;
+        use crate::generate::config::{
+            ADD_AUTH_DATA, INITIAL_AES_IV, INITIAL_AES_KEY, SM4_IV, SM4_KEY,
+        };
+        use crate::generate::image::{signature_scheme, write_bound_image};
+        use crate::generate::signer::{RsaSigner, SignatureScheme, Signer, Sm2Signer};
+        use kendryte_image::{bind_key, decrypt_payload, parse_header, Keys};
+        use std::io::Cursor;
+
+        let firmware = include_bytes!("../../../xtask/tests/data/firmware.bin");
+        let uid: &[u8] = b"\x01\x23\x45\x67\x89\xab\xcd\xef";
+        let aes_key = bind_key(INITIAL_AES_KEY.try_into().unwrap(), uid);
+        let sm4_key = bind_key(SM4_KEY.try_into().unwrap(), uid);
+        let other = bind_key(INITIAL_AES_KEY.try_into().unwrap(), b"another device");
+        let keys = Keys {
+            aes_key: &aes_key,
+            aes_iv: INITIAL_AES_IV.try_into().unwrap(),
+            aes_aad: ADD_AUTH_DATA,
+            sm4_key: &sm4_key,
+            sm4_iv: SM4_IV.try_into().unwrap(),
+        };
+
+        for encryption in [EncryptionType::Sm4, EncryptionType::Aes] {
+            let scheme = signature_scheme(encryption).unwrap();
+            let signer: Box<dyn Signer> = match scheme {
+                SignatureScheme::Sm2 => Box::new(Sm2Signer::development().unwrap()),
+                SignatureScheme::Rsa => Box::new(RsaSigner::development().unwrap()),
+            };
+            let mut image = Cursor::new(Vec::new());
+            write_bound_image(
+                &firmware[..],
+                encryption,
+                Some(signer.as_ref()),
+                Some(uid),
+                &mut image,
+            )
+            .expect("Encryption failed");
+            let mut image = image.into_inner();
+            let image = &mut image[kendryte_image::HEADER_OFFSET..];
+            let header = parse_header(image).expect("Invalid header");
+
+            if let EncryptionType::Aes = encryption {
+                let copied = Keys {
+                    aes_key: &other,
+                    ..keys
+                };
+                assert!(decrypt_payload(&header, &mut image.to_vec(), &copied).is_err());
+            }
+            let decrypted = decrypt_payload(&header, image, &keys).expect("Decryption failed");
+            assert_eq!(decrypted, firmware);
+        }
+
+        let mut image = Cursor::new(Vec::new());
+        assert!(matches!(
+            write_bound_image(
+                &firmware[..],
+                EncryptionType::None,
+                None,
+                Some(uid),
+                &mut image
+            ),
+            Err(XtaskError::BindingRequiresEncryption)
+        ));
+    }
+
     #[test]
     fn test_sm4_encryption() {
         let firmware = include_bytes!("../../../xtask/tests/data/firmware.bin");
@@ -0,0 +1,302 @@
+//! Firmware manifest embedded in the image payload.
+//!
+//! The manifest is a sequence of TLV records appended to the firmware, followed
+//! by a trailer, so it is covered by the image hash or signature and can be
+//! located from the end of the decrypted payload:
+//!
+//! ```text
+//! [firmware][record]...[record][manifest length: u32 LE][MANIFEST_MAGIC]
+//! ```
+//!
+//! Each record is `[type: u8][length: u16 LE][value]`.
+
+use crate::error::{XtaskError, XtaskResult};
+use sha2::{Digest, Sha256};
+use sm3::Sm3;
+use std::io::{self, Read};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Record types and trailer are shared with the on-device parser.
+pub use kendryte_image::manifest::{
+    MAGIC as MANIFEST_MAGIC, TAG_BOOT_FLAGS, TAG_ENTRY_POINT, TAG_GIT_HASH, TAG_LOAD_ADDRESS,
+    TAG_SECTION_DIGEST, TAG_TIMESTAMP, TAG_VERSION, TRAILER_LEN,
+};
+
+/// Hash algorithms used for section digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256 = 1,
+    Sm3 = 2,
+}
+
+impl DigestAlgorithm {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(DigestAlgorithm::Sha256),
+            2 => Some(DigestAlgorithm::Sm3),
+            _ => None,
+        }
+    }
+}
+
+/// Digest of a named section of the firmware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionDigest {
+    pub name: String,
+    pub algorithm: DigestAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+/// Build metadata describing a firmware image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// Build time in seconds since the Unix epoch.
+    pub timestamp: Option<u64>,
+    /// Git commit hash the firmware was built from.
+    pub git_hash: Option<String>,
+    /// Semantic version of the firmware.
+    pub version: Option<String>,
+    /// Address the firmware is loaded to.
+    pub load_address: Option<u64>,
+    /// Address execution starts at.
+    pub entry_point: Option<u64>,
+    /// Boot flags, see `kendryte_image::manifest::BOOT_FLAG_*`.
+    pub boot_flags: Option<u32>,
+    /// Per-section digests.
+    pub sections: Vec<SectionDigest>,
+}
+
+impl Manifest {
+    /// Collect build metadata from the environment.
+    /// The timestamp honours `SOURCE_DATE_EPOCH` for reproducible builds, and
+    /// the git hash is omitted when not building from a git checkout.
+    pub fn from_environment(version: Option<String>) -> Manifest {
+        let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|d| d.as_secs())
+            });
+        let git_hash = Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|hash| hash.trim().to_string());
+
+        Manifest {
+            timestamp,
+            git_hash,
+            version,
+            ..Manifest::default()
+        }
+    }
+
+    /// Add SHA-256 and SM3 digests of a section.
+    pub fn add_section(&mut self, name: &str, data: &[u8]) {
+        // Reading from a slice cannot fail.
+        self.add_section_from(name, data).unwrap();
+    }
+
+    /// Add SHA-256 and SM3 digests of a section, streamed from `reader`.
+    pub fn add_section_from<R: Read>(&mut self, name: &str, mut reader: R) -> io::Result<()> {
+        let mut sha256 = Sha256::new();
+        let mut sm3 = Sm3::new();
+        let mut chunk = vec![0; 64 * 1024];
+        loop {
+            let n = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            sha256.update(&chunk[..n]);
+            sm3.update(&chunk[..n]);
+        }
+
+        let digests = [
+            (DigestAlgorithm::Sha256, sha256.finalize().to_vec()),
+            (DigestAlgorithm::Sm3, sm3.finalize().to_vec()),
+        ];
+        for (algorithm, digest) in digests {
+            self.sections.push(SectionDigest {
+                name: name.to_string(),
+                algorithm,
+                digest,
+            });
+        }
+        Ok(())
+    }
+
+    /// Encode the manifest records followed by the trailer.
+    pub fn encode(&self) -> XtaskResult<Vec<u8>> {
+        let mut records = Vec::new();
+        if let Some(timestamp) = self.timestamp {
+            push_record(&mut records, TAG_TIMESTAMP, &timestamp.to_le_bytes())?;
+        }
+        if let Some(git_hash) = &self.git_hash {
+            push_record(&mut records, TAG_GIT_HASH, git_hash.as_bytes())?;
+        }
+        if let Some(version) = &self.version {
+            push_record(&mut records, TAG_VERSION, version.as_bytes())?;
+        }
+        if let Some(load_address) = self.load_address {
+            push_record(&mut records, TAG_LOAD_ADDRESS, &load_address.to_le_bytes())?;
+        }
+        if let Some(entry_point) = self.entry_point {
+            push_record(&mut records, TAG_ENTRY_POINT, &entry_point.to_le_bytes())?;
+        }
+        if let Some(boot_flags) = self.boot_flags {
+            push_record(&mut records, TAG_BOOT_FLAGS, &boot_flags.to_le_bytes())?;
+        }
+        for section in &self.sections {
+            let name = section.name.as_bytes();
+            if name.len() > u8::MAX as usize {
+                return Err(XtaskError::ManifestError(format!(
+                    "section name too long: {}",
+                    section.name
+                )));
+            }
+            let mut value = vec![section.algorithm as u8, name.len() as u8];
+            value.extend(name);
+            value.extend(&section.digest);
+            push_record(&mut records, TAG_SECTION_DIGEST, &value)?;
+        }
+
+        let len = records.len() as u32;
+        records.extend(len.to_le_bytes());
+        records.extend(MANIFEST_MAGIC);
+        Ok(records)
+    }
+
+    /// Append the encoded manifest to `firmware`.
+    pub fn append_to(&self, firmware: &[u8]) -> XtaskResult<Vec<u8>> {
+        let mut payload = firmware.to_vec();
+        payload.extend(self.encode()?);
+        Ok(payload)
+    }
+
+    /// Locate and decode the manifest at the end of a payload.
+    /// Returns `None` if the payload carries no manifest.
+    pub fn parse(payload: &[u8]) -> XtaskResult<Option<Manifest>> {
+        if payload.len() < TRAILER_LEN || !payload.ends_with(MANIFEST_MAGIC) {
+            return Ok(None);
+        }
+        let trailer = payload.len() - TRAILER_LEN;
+        let len = u32::from_le_bytes(payload[trailer..trailer + 4].try_into().unwrap()) as usize;
+        let mut records = payload[..trailer]
+            .len()
+            .checked_sub(len)
+            .map(|start| &payload[start..trailer])
+            .ok_or_else(|| XtaskError::ManifestError("manifest length out of range".into()))?;
+
+        let mut manifest = Manifest::default();
+        while !records.is_empty() {
+            if records.len() < 3 {
+                return Err(XtaskError::ManifestError("truncated record".into()));
+            }
+            let tag = records[0];
+            let len = u16::from_le_bytes([records[1], records[2]]) as usize;
+            let value = records
+                .get(3..3 + len)
+                .ok_or_else(|| XtaskError::ManifestError("truncated record".into()))?;
+            records = &records[3 + len..];
+
+            match tag {
+                TAG_TIMESTAMP => manifest.timestamp = Some(decode_u64(value)?),
+                TAG_LOAD_ADDRESS => manifest.load_address = Some(decode_u64(value)?),
+                TAG_ENTRY_POINT => manifest.entry_point = Some(decode_u64(value)?),
+                TAG_BOOT_FLAGS => {
+                    let value = value.try_into().map_err(|_| {
+                        XtaskError::ManifestError("invalid boot flags record".into())
+                    })?;
+                    manifest.boot_flags = Some(u32::from_le_bytes(value));
+                }
+                TAG_GIT_HASH => manifest.git_hash = Some(decode_string(value)?),
+                TAG_VERSION => manifest.version = Some(decode_string(value)?),
+                TAG_SECTION_DIGEST => {
+                    let invalid = || XtaskError::ManifestError("invalid section record".into());
+                    let (&algorithm, rest) = value.split_first().ok_or_else(invalid)?;
+                    let (&name_len, rest) = rest.split_first().ok_or_else(invalid)?;
+                    let name = rest.get(..name_len as usize).ok_or_else(invalid)?;
+                    manifest.sections.push(SectionDigest {
+                        name: decode_string(name)?,
+                        algorithm: DigestAlgorithm::from_u8(algorithm).ok_or_else(invalid)?,
+                        digest: rest[name_len as usize..].to_vec(),
+                    });
+                }
+                // Unknown records are skipped so the format can grow.
+                _ => {}
+            }
+        }
+        Ok(Some(manifest))
+    }
+}
+
+/// Append a TLV record to `records`.
+fn push_record(records: &mut Vec<u8>, tag: u8, value: &[u8]) -> XtaskResult<()> {
+    let len = u16::try_from(value.len())
+        .map_err(|_| XtaskError::ManifestError(format!("record 0x{:02x} too long", tag)))?;
+    records.push(tag);
+    records.extend(len.to_le_bytes());
+    records.extend(value);
+    Ok(())
+}
+
+fn decode_u64(value: &[u8]) -> XtaskResult<u64> {
+    let value = value
+        .try_into()
+        .map_err(|_| XtaskError::ManifestError("invalid integer record".into()))?;
+    Ok(u64::from_le_bytes(value))
+}
+
+fn decode_string(value: &[u8]) -> XtaskResult<String> {
+    String::from_utf8(value.to_vec())
+        .map_err(|_| XtaskError::ManifestError("invalid string record".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_round_trip() {
+        let firmware = b"firmware";
+        let mut manifest = Manifest {
+            timestamp: Some(1_700_000_000),
+            git_hash: Some("0123456789abcdef".into()),
+            version: Some("1.2.3".into()),
+            load_address: Some(0x8030_0000),
+            entry_point: Some(0x8030_0000),
+            boot_flags: Some(kendryte_image::manifest::BOOT_FLAG_CORE1),
+            sections: Vec::new(),
+        };
+        manifest.add_section("firmware", firmware);
+
+        let payload = manifest.append_to(firmware).unwrap();
+        assert!(payload.starts_with(firmware));
+        assert!(payload.ends_with(MANIFEST_MAGIC));
+        assert_eq!(Manifest::parse(&payload).unwrap(), Some(manifest));
+        assert_eq!(Manifest::parse(firmware).unwrap(), None);
+    }
+
+    #[test]
+    fn section_digests() {
+        let mut manifest = Manifest::default();
+        manifest.add_section("firmware", b"abc");
+        assert_eq!(manifest.sections.len(), 2);
+        assert_eq!(
+            hex::encode(&manifest.sections[0].digest),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex::encode(&manifest.sections[1].digest),
+            "66c7f0f462eeedd9d1f2d46bdc10e4e24167c4875cf2f7a2297da02b8f4ba8e0"
+        );
+    }
+}
@@ -2,5 +2,9 @@
 //!
 //! This module provides functionality for generating image,
 //! including encryption, signing, and proper formatting for the K230 platform.
+pub mod compress;
 pub mod config;
+pub mod format;
 pub mod image;
+pub mod manifest;
+pub mod signer;
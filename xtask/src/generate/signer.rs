@@ -0,0 +1,648 @@
+//! Signing backends for firmware images.
+//!
+//! Image generation only needs a public key to embed in the header and a
+//! signature over a message, so signing is abstracted behind [`Signer`].
+//! Keys can stay in the built-in software signers, behind an external command
+//! (cloud KMS CLIs, `openssl`, YubiKey tools) or inside a PKCS#11 token.
+
+use crate::error::{XtaskError, XtaskResult};
+use crate::generate::config::{D, E, ID, K, N, PRIVATE_KEY, PUBLIC_KEY_X, PUBLIC_KEY_Y};
+use clap::Args;
+use num_bigint_dig::BigUint;
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use rsa::signature::{SignatureEncoding, Signer as _};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::Sha256;
+use signature::hazmat::{PrehashSigner, PrehashVerifier};
+use sm2::elliptic_curve::sec1::ToEncodedPoint;
+use sm2::{FieldBytes, Scalar, SecretKey};
+use sm3::Sm3;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Signature schemes accepted by the BootROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// SM2 over SM3(Z || message); the signer receives the 32-byte prehash.
+    Sm2,
+    /// RSA PKCS#1 v1.5 with SHA-256; the signer receives the message itself.
+    Rsa,
+}
+
+/// Public key embedded in the image header for the device to verify with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    /// SM2 public key and the user ID bound into Z.
+    Sm2 {
+        id: String,
+        x: [u8; 32],
+        y: [u8; 32],
+    },
+    /// RSA modulus (big endian) and public exponent.
+    Rsa { n: Vec<u8>, e: u32 },
+}
+
+impl PublicKey {
+    /// Returns the signature scheme the key belongs to.
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            PublicKey::Sm2 { .. } => SignatureScheme::Sm2,
+            PublicKey::Rsa { .. } => SignatureScheme::Rsa,
+        }
+    }
+}
+
+/// A backend producing image signatures.
+pub trait Signer {
+    /// Public key matching the signing key.
+    fn public_key(&self) -> &PublicKey;
+
+    /// Sign `message`.
+    /// For SM2 the message is the prehash e = SM3(Z || ciphertext) and the signature is r || s.
+    /// For RSA the message is hashed with SHA-256 and signed with PKCS#1 v1.5 padding.
+    fn sign(&self, message: &[u8]) -> XtaskResult<Vec<u8>>;
+}
+
+/// Software RSA signer.
+pub struct RsaSigner {
+    signing_key: SigningKey<Sha256>,
+    public_key: PublicKey,
+}
+
+impl RsaSigner {
+    /// Create a signer from the built-in development key in `config.rs`.
+    pub fn development() -> XtaskResult<Self> {
+        // Parse RSA key components.
+        let n = BigUint::from_bytes_be(N);
+        let e = u32::from_str_radix(&E[2..], 16)
+            .map_err(|_| XtaskError::RsaParseError("Failed to parse E for RSA".to_string()))?;
+        let d = BigUint::from_bytes_be(D);
+
+        // Create RSA private key from components.
+        let private_key = RsaPrivateKey::from_components(
+            n,
+            BigUint::from(e),
+            d,
+            Vec::new(), // Prime factors omitted for simplicity.
+        )?;
+        Self::new(private_key)
+    }
+
+    /// Create a signer from a PKCS#8 PEM private key file.
+    pub fn from_pem_file(path: &Path) -> XtaskResult<Self> {
+        let private_key = RsaPrivateKey::read_pkcs8_pem_file(path)
+            .map_err(|e| XtaskError::RsaParseError(e.to_string()))?;
+        Self::new(private_key)
+    }
+
+    fn new(private_key: RsaPrivateKey) -> XtaskResult<Self> {
+        let public_key = rsa_public_key(&private_key.to_public_key())?;
+        Ok(Self {
+            signing_key: SigningKey::<Sha256>::new(private_key),
+            public_key,
+        })
+    }
+}
+
+impl Signer for RsaSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> XtaskResult<Vec<u8>> {
+        // Generate RSA signature using PKCS#1 v1.5 padding.
+        Ok(self.signing_key.sign(message).to_vec())
+    }
+}
+
+/// Order n of the SM2 curve, big endian.
+const SM2_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x72, 0x03, 0xdf, 0x6b, 0x21, 0xc6, 0x05, 0x2b, 0x53, 0xbb, 0xf4, 0x09, 0x39, 0xd5, 0x41, 0x23,
+];
+
+/// Software SM2 signer.
+///
+/// Nonces are derived per RFC 6979 from the private key and the message,
+/// with SM3 as the hash, so signing needs no random source and a weak one
+/// cannot leak the key; the same image always gets the same signature.
+pub struct Sm2Signer {
+    signing_key: sm2::dsa::SigningKey,
+    secret: FieldBytes,
+    public_key: PublicKey,
+    /// Fixed nonce, only used by the development key to match the reference images.
+    k: Option<Scalar>,
+}
+
+impl Sm2Signer {
+    /// Create a signer from the built-in development key in `config.rs`.
+    pub fn development() -> XtaskResult<Self> {
+        let mut signer = Self::new(&PRIVATE_KEY.try_into().unwrap(), ID)?;
+        let expected = PublicKey::Sm2 {
+            id: ID.to_string(),
+            x: PUBLIC_KEY_X.try_into().unwrap(),
+            y: PUBLIC_KEY_Y.try_into().unwrap(),
+        };
+        if signer.public_key != expected {
+            return Err(XtaskError::SignerError(
+                "development SM2 public key does not match the private key".into(),
+            ));
+        }
+        signer.k = Some(Scalar::from_slice(K)?);
+        Ok(signer)
+    }
+
+    /// Create a signer from a file holding the 32-byte private key in hex.
+    pub fn from_hex_file(path: &Path, id: &str) -> XtaskResult<Self> {
+        let private_key = read_hex_file(path)?;
+        let private_key = private_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| XtaskError::SignerError("SM2 private key must be 32 bytes".into()))?;
+        Self::new(private_key, id)
+    }
+
+    fn new(private_key: &[u8; 32], id: &str) -> XtaskResult<Self> {
+        // Rejects zero and values not below the curve order.
+        let secret_key = SecretKey::from_slice(private_key)?;
+        let point = secret_key.public_key().to_encoded_point(false);
+        let public_key = PublicKey::Sm2 {
+            id: id.to_string(),
+            x: point.x().unwrap().as_slice().try_into().unwrap(),
+            y: point.y().unwrap().as_slice().try_into().unwrap(),
+        };
+        Ok(Self {
+            signing_key: sm2::dsa::SigningKey::new(id, &secret_key)?,
+            secret: secret_key.to_bytes(),
+            public_key,
+            k: None,
+        })
+    }
+
+    /// Derive the RFC 6979 nonce for the prehash `e`.
+    fn nonce(&self, e: &FieldBytes) -> XtaskResult<Scalar> {
+        let order = FieldBytes::from_slice(&SM2_ORDER);
+        let k = rfc6979::generate_k::<Sm3, _>(&self.secret, order, &reduce_order(e), &[]);
+        Ok(Scalar::from_slice(&k)?)
+    }
+}
+
+/// Reduce a 256-bit big endian value modulo the SM2 order (RFC 6979 bits2octets).
+fn reduce_order(value: &FieldBytes) -> FieldBytes {
+    let mut value = *value;
+    // Values are below 2^256 < 2n, so one subtraction suffices.
+    if value.as_slice() >= SM2_ORDER.as_slice() {
+        let mut borrow = false;
+        for (byte, n) in value.iter_mut().rev().zip(SM2_ORDER.iter().rev()) {
+            let (difference, b1) = byte.overflowing_sub(*n);
+            let (difference, b2) = difference.overflowing_sub(borrow as u8);
+            *byte = difference;
+            borrow = b1 || b2;
+        }
+    }
+    value
+}
+
+/// Check an SM2 signature over the prehash `e` against `public_key`.
+///
+/// Image generation checks every signature before writing it: a signer whose
+/// key does not match the public key in the header, or a bad nonce, would
+/// otherwise produce an image the BootROM refuses to boot.
+pub fn verify_sm2_prehash(public_key: &PublicKey, e: &[u8], signature: &[u8]) -> XtaskResult<()> {
+    let PublicKey::Sm2 { id, x, y } = public_key else {
+        return Err(XtaskError::SignerMismatch("Sm2".to_string()));
+    };
+    let mut point = vec![0x04];
+    point.extend(x);
+    point.extend(y);
+    let verifying_key = sm2::dsa::VerifyingKey::new(id, sm2::PublicKey::from_sec1_bytes(&point)?)?;
+    let signature = sm2::dsa::Signature::from_slice(signature)?;
+    verifying_key.verify_prehash(e, &signature).map_err(|_| {
+        XtaskError::SignerError("SM2 signature does not verify against the public key".into())
+    })
+}
+
+/// Check an RSA-2048 signature over the GCM `tag` of an AES image against `public_key`.
+///
+/// Like [`verify_sm2_prehash`], this catches a command or token signing with
+/// another key before the signature is written to an image.
+pub fn verify_rsa_tag(public_key: &PublicKey, tag: &[u8], signature: &[u8]) -> XtaskResult<()> {
+    let PublicKey::Rsa { n, e } = public_key else {
+        return Err(XtaskError::SignerMismatch("Rsa".to_string()));
+    };
+    let (Ok(n), Ok(signature)) = (n.as_slice().try_into(), signature.try_into()) else {
+        return Err(XtaskError::SignerError(
+            "RSA-2048 key and signature expected".to_string(),
+        ));
+    };
+    kendryte_image::verify_rsa(n, *e, signature, tag).map_err(|_| {
+        XtaskError::SignerError("RSA signature does not verify against the public key".into())
+    })
+}
+
+impl Signer for Sm2Signer {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> XtaskResult<Vec<u8>> {
+        if message.len() != 32 {
+            return Err(XtaskError::SignerError(
+                "SM2 prehash must be 32 bytes".into(),
+            ));
+        }
+        let e = FieldBytes::from_slice(message);
+        let k = match self.k {
+            Some(k) => k,
+            None => self.nonce(e)?,
+        };
+        let signature = self.signing_key.sign_prehash_with_k(&k, e)?;
+        Ok(signature.to_bytes().to_vec())
+    }
+}
+
+/// Signer delegating to an external command.
+///
+/// The command is run through `sh -c`, receives the message on stdin and must
+/// write the raw signature to stdout, e.g. `openssl dgst -sha256 -sign key.pem`
+/// for RSA. This covers cloud KMS CLIs and hardware token tools.
+pub struct CommandSigner {
+    command: String,
+    public_key: PublicKey,
+}
+
+impl CommandSigner {
+    /// Create a signer running `command`, whose key matches `public_key`.
+    pub fn new(command: String, public_key: PublicKey) -> Self {
+        Self {
+            command,
+            public_key,
+        }
+    }
+}
+
+impl Signer for CommandSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> XtaskResult<Vec<u8>> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        // Fed from another thread, so a command writing output before it has
+        // read all of its input cannot leave both sides blocked on full pipes.
+        // Dropping stdin closes it, so the command sees the end of the message.
+        let (written, output) = std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(message));
+            let output = child.wait_with_output();
+            (writer.join().unwrap(), output)
+        });
+        let output = output?;
+        if !output.status.success() {
+            return Err(XtaskError::SignerError(format!(
+                "signing command failed: {}",
+                output.status
+            )));
+        }
+        written?;
+        Ok(output.stdout)
+    }
+}
+
+/// Signer using a private key stored in a PKCS#11 token (HSM, YubiKey via ykcs11).
+///
+/// Only RSA keys are supported, as SM2 has no standard PKCS#11 mechanism.
+/// The user PIN is read from the `PKCS11_PIN` environment variable.
+#[cfg(feature = "pkcs11")]
+pub struct Pkcs11Signer {
+    session: cryptoki::session::Session,
+    key: cryptoki::object::ObjectHandle,
+    public_key: PublicKey,
+}
+
+#[cfg(feature = "pkcs11")]
+impl Pkcs11Signer {
+    /// Open the first token of `module` and find the key pair labelled `label`.
+    pub fn new(module: &Path, label: &str) -> XtaskResult<Self> {
+        use cryptoki::context::{CInitializeArgs, Pkcs11};
+        use cryptoki::object::{Attribute, AttributeType, ObjectClass};
+        use cryptoki::session::UserType;
+        use cryptoki::types::AuthPin;
+
+        let error = |e: cryptoki::error::Error| XtaskError::SignerError(e.to_string());
+        let pkcs11 = Pkcs11::new(module).map_err(error)?;
+        pkcs11
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(error)?;
+        let slot = *pkcs11
+            .get_slots_with_token()
+            .map_err(error)?
+            .first()
+            .ok_or_else(|| XtaskError::SignerError("no PKCS#11 token found".into()))?;
+        let session = pkcs11.open_ro_session(slot).map_err(error)?;
+        let pin = std::env::var("PKCS11_PIN")
+            .map_err(|_| XtaskError::SignerError("PKCS11_PIN is not set".into()))?;
+        session
+            .login(UserType::User, Some(&AuthPin::new(pin)))
+            .map_err(error)?;
+
+        let find = |class| {
+            session
+                .find_objects(&[
+                    Attribute::Class(class),
+                    Attribute::Label(label.as_bytes().to_vec()),
+                ])
+                .map_err(error)?
+                .first()
+                .copied()
+                .ok_or_else(|| XtaskError::SignerError(format!("no key labelled {}", label)))
+        };
+        let key = find(ObjectClass::PRIVATE_KEY)?;
+        let public = find(ObjectClass::PUBLIC_KEY)?;
+
+        let attributes = session
+            .get_attributes(
+                public,
+                &[AttributeType::Modulus, AttributeType::PublicExponent],
+            )
+            .map_err(error)?;
+        let (mut n, mut e) = (Vec::new(), Vec::new());
+        for attribute in attributes {
+            match attribute {
+                Attribute::Modulus(value) => n = value,
+                Attribute::PublicExponent(value) => e = value,
+                _ => {}
+            }
+        }
+        let public_key = RsaPublicKey::new(BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e))?;
+
+        Ok(Self {
+            session,
+            key,
+            public_key: rsa_public_key(&public_key)?,
+        })
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+impl Signer for Pkcs11Signer {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> XtaskResult<Vec<u8>> {
+        self.session
+            .sign(
+                &cryptoki::mechanism::Mechanism::Sha256RsaPkcs,
+                self.key,
+                message,
+            )
+            .map_err(|e| XtaskError::SignerError(e.to_string()))
+    }
+}
+
+/// Signer backends selectable on the command line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SignerBackend {
+    /// Built-in software signer.
+    #[default]
+    Software,
+    /// External command.
+    Command,
+    /// PKCS#11 token.
+    Pkcs11,
+}
+
+impl FromStr for SignerBackend {
+    type Err = XtaskError;
+
+    /// Parse signer backend from string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "software" => Ok(Self::Software),
+            "command" => Ok(Self::Command),
+            "pkcs11" => Ok(Self::Pkcs11),
+            _ => Err(XtaskError::SignerError(format!("unknown signer: {}", s))),
+        }
+    }
+}
+
+/// Command line options selecting the signing key.
+#[derive(Args, Debug, Clone)]
+pub struct SignerOptions {
+    /// Signer backend: software (default), command or pkcs11.
+    #[arg(long)]
+    pub signer: Option<SignerBackend>,
+    /// Key file.
+    ///
+    /// software: private key, PKCS#8 PEM for RSA or hex for SM2.
+    /// Without it, the development key from config.rs is used.
+    ///
+    /// command: public key, SPKI PEM for RSA or uncompressed SEC1 hex for SM2.
+    #[arg(long)]
+    pub signing_key: Option<PathBuf>,
+    /// Command producing the signature, for the command backend.
+    #[arg(long)]
+    pub sign_command: Option<String>,
+    /// SM2 user ID bound into the signature.
+    #[arg(long, default_value = ID)]
+    pub sm2_id: String,
+    /// PKCS#11 module path, for the pkcs11 backend.
+    #[arg(long)]
+    pub pkcs11_module: Option<PathBuf>,
+    /// Label of the key pair in the PKCS#11 token.
+    #[arg(long)]
+    pub pkcs11_key_label: Option<String>,
+}
+
+impl SignerOptions {
+    /// Build the signer for `scheme` described by the options.
+    pub fn build(&self, scheme: SignatureScheme) -> XtaskResult<Box<dyn Signer>> {
+        let missing = |option: &str| XtaskError::SignerError(format!("{} is required", option));
+        match (self.signer.unwrap_or_default(), scheme) {
+            (SignerBackend::Software, SignatureScheme::Rsa) => match &self.signing_key {
+                Some(path) => Ok(Box::new(RsaSigner::from_pem_file(path)?)),
+                None => Ok(Box::new(RsaSigner::development()?)),
+            },
+            (SignerBackend::Software, SignatureScheme::Sm2) => match &self.signing_key {
+                Some(path) => Ok(Box::new(Sm2Signer::from_hex_file(path, &self.sm2_id)?)),
+                None => Ok(Box::new(Sm2Signer::development()?)),
+            },
+            (SignerBackend::Command, scheme) => {
+                let command = self
+                    .sign_command
+                    .clone()
+                    .ok_or_else(|| missing("--sign-command"))?;
+                let path = self
+                    .signing_key
+                    .as_ref()
+                    .ok_or_else(|| missing("--signing-key"))?;
+                let public_key = match scheme {
+                    SignatureScheme::Rsa => {
+                        let public_key = RsaPublicKey::read_public_key_pem_file(path)
+                            .map_err(|e| XtaskError::RsaParseError(e.to_string()))?;
+                        rsa_public_key(&public_key)?
+                    }
+                    SignatureScheme::Sm2 => sm2_public_key(&read_hex_file(path)?, &self.sm2_id)?,
+                };
+                Ok(Box::new(CommandSigner::new(command, public_key)))
+            }
+            #[cfg(feature = "pkcs11")]
+            (SignerBackend::Pkcs11, SignatureScheme::Rsa) => {
+                let module = self
+                    .pkcs11_module
+                    .as_ref()
+                    .ok_or_else(|| missing("--pkcs11-module"))?;
+                let label = self
+                    .pkcs11_key_label
+                    .as_ref()
+                    .ok_or_else(|| missing("--pkcs11-key-label"))?;
+                Ok(Box::new(Pkcs11Signer::new(module, label)?))
+            }
+            #[cfg(feature = "pkcs11")]
+            (SignerBackend::Pkcs11, SignatureScheme::Sm2) => Err(XtaskError::SignerError(
+                "PKCS#11 signing supports RSA keys only".into(),
+            )),
+            #[cfg(not(feature = "pkcs11"))]
+            (SignerBackend::Pkcs11, _) => Err(XtaskError::SignerError(
+                "xtask was built without the pkcs11 feature".into(),
+            )),
+        }
+    }
+}
+
+/// Convert an RSA public key to the header representation.
+fn rsa_public_key(public_key: &RsaPublicKey) -> XtaskResult<PublicKey> {
+    let e = public_key.e().to_bytes_be();
+    if e.len() > 4 {
+        return Err(XtaskError::RsaParseError(
+            "RSA exponent does not fit in 32 bits".into(),
+        ));
+    }
+    let mut e_be_bytes = [0; 4];
+    e_be_bytes[4 - e.len()..].copy_from_slice(&e);
+    Ok(PublicKey::Rsa {
+        n: public_key.n().to_bytes_be(),
+        e: u32::from_be_bytes(e_be_bytes),
+    })
+}
+
+/// Parse an uncompressed SEC1 SM2 public key (04 || x || y).
+fn sm2_public_key(point: &[u8], id: &str) -> XtaskResult<PublicKey> {
+    match point {
+        [0x04, rest @ ..] if rest.len() == 64 => Ok(PublicKey::Sm2 {
+            id: id.to_string(),
+            x: rest[..32].try_into().unwrap(),
+            y: rest[32..].try_into().unwrap(),
+        }),
+        _ => Err(XtaskError::SignerError(
+            "SM2 public key must be 65 bytes of uncompressed SEC1".into(),
+        )),
+    }
+}
+
+/// Read a file holding hex, ignoring surrounding whitespace.
+fn read_hex_file(path: &Path) -> XtaskResult<Vec<u8>> {
+    let text = std::fs::read_to_string(path)?;
+    hex::decode(text.trim()).map_err(|e| XtaskError::SignerError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::config::{PUBLIC_KEY_X, PUBLIC_KEY_Y};
+
+    #[test]
+    fn development_keys_match_config() {
+        let signer = Sm2Signer::development().unwrap();
+        assert_eq!(
+            signer.public_key(),
+            &PublicKey::Sm2 {
+                id: ID.to_string(),
+                x: PUBLIC_KEY_X.try_into().unwrap(),
+                y: PUBLIC_KEY_Y.try_into().unwrap(),
+            }
+        );
+
+        let signer = RsaSigner::development().unwrap();
+        let PublicKey::Rsa { n, .. } = signer.public_key() else {
+            panic!("expected an RSA key");
+        };
+        assert_eq!(n.as_slice(), N);
+    }
+
+    #[test]
+    fn sm2_deterministic_nonce() {
+        let mut private_key = [0x5a; 32];
+        private_key[0] = 0x12;
+        let signer = Sm2Signer::new(&private_key, ID).unwrap();
+        let first = signer.sign(&[1; 32]).unwrap();
+        assert_eq!(signer.sign(&[1; 32]).unwrap(), first);
+        assert_ne!(signer.sign(&[2; 32]).unwrap(), first);
+        verify_sm2_prehash(signer.public_key(), &[1; 32], &first).unwrap();
+        assert!(verify_sm2_prehash(signer.public_key(), &[2; 32], &first).is_err());
+
+        // A signature by another key is caught before it reaches an image.
+        let development = Sm2Signer::development().unwrap();
+        let signature = development.sign(&[1; 32]).unwrap();
+        verify_sm2_prehash(development.public_key(), &[1; 32], &signature).unwrap();
+        assert!(verify_sm2_prehash(signer.public_key(), &[1; 32], &signature).is_err());
+
+        assert!(Sm2Signer::new(&[0; 32], ID).is_err());
+        assert!(Sm2Signer::new(&SM2_ORDER, ID).is_err());
+    }
+
+    #[test]
+    fn reduce_by_order() {
+        let mut above = SM2_ORDER;
+        above[31] += 5;
+        let reduced = reduce_order(FieldBytes::from_slice(&above));
+        let mut expected = [0; 32];
+        expected[31] = 5;
+        assert_eq!(reduced.as_slice(), &expected);
+        let below = FieldBytes::from_slice(&[0x11; 32]);
+        assert_eq!(reduce_order(below), *below);
+    }
+
+    #[test]
+    fn command_signer() {
+        let public_key = PublicKey::Rsa {
+            n: N.to_vec(),
+            e: 3,
+        };
+        // `cat` echoes the message back, standing in for a real signing tool.
+        let signer = CommandSigner::new("cat".into(), public_key);
+        assert_eq!(signer.sign(b"message").unwrap(), b"message");
+
+        // More output than a pipe holds, written before all input is read.
+        let message = vec![0x5a; 1 << 20];
+        assert_eq!(signer.sign(&message).unwrap(), message);
+
+        let signer = CommandSigner::new("exit 1".into(), signer.public_key().clone());
+        assert!(signer.sign(b"message").is_err());
+    }
+
+    #[test]
+    fn rsa_signature_is_verified() {
+        let tag = [0x42; 16];
+        let signer = RsaSigner::development().unwrap();
+        let signature = signer.sign(&tag).unwrap();
+        verify_rsa_tag(signer.public_key(), &tag, &signature).unwrap();
+        assert!(verify_rsa_tag(signer.public_key(), &[0x43; 16], &signature).is_err());
+
+        // `cat` returns no RSA signature, whatever its length.
+        let signer = CommandSigner::new("cat".into(), signer.public_key().clone());
+        let echo = signer.sign(&[0; 256]).unwrap();
+        assert!(verify_rsa_tag(signer.public_key(), &tag, &echo).is_err());
+        assert!(verify_rsa_tag(signer.public_key(), &tag, &tag).is_err());
+    }
+}
@@ -5,12 +5,20 @@
 
 extern crate core;
 
-use crate::generate::image::EncryptionType;
+use crate::generate::format::OutputFormat;
+use crate::generate::image::{Cipher, EncryptionType, SignatureAlgorithm};
+use crate::generate::signer::SignerOptions;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+pub mod delta;
+pub mod dts;
 pub mod error;
 pub mod generate;
+pub mod provision;
+pub mod size;
+pub mod symbolicate;
+pub mod trace;
 
 /// CLI structure for the xtask utility.
 #[derive(Parser, Debug)]
@@ -54,7 +62,216 @@ pub enum Command {
         ///     sm4: SM4-CBC + SM2
         ///
         ///     aes: AES-GCM + RSA-2048
-        #[arg(long, short = 'e')]
+        #[arg(long, short = 'e', conflicts_with_all = ["cipher", "signature"])]
         encryption: Option<EncryptionType>,
+        /// Payload cipher, selected with `--signature` instead of `--encryption`.
+        ///
+        /// Parameter: none, sm4, aes
+        #[arg(long, requires = "signature")]
+        cipher: Option<Cipher>,
+        /// Hash or signature algorithm, selected with `--cipher` instead of `--encryption`.
+        ///
+        /// Parameter: sha256, sm2, rsa
+        ///
+        /// The BootROM accepts none + sha256, sm4 + sm2 and aes + rsa.
+        #[arg(long, requires = "cipher")]
+        signature: Option<SignatureAlgorithm>,
+        /// Output format (optional).
+        ///
+        /// Parameter:
+        ///
+        ///     bin: raw BootROM image, `.img` (default)
+        ///
+        ///     uf2: UF2 blocks for drag-and-drop bootloaders, `.uf2`
+        ///
+        ///     hex: Intel HEX for generic flashers, `.hex`
+        #[arg(long, short = 'f')]
+        format: Option<OutputFormat>,
+        /// Address the image is placed at on the boot medium, for the uf2 and hex formats.
+        #[arg(long, value_parser = parse_u32, default_value = "0")]
+        base_address: u32,
+        /// UF2 family ID recorded in every block (optional).
+        #[arg(long, value_parser = parse_u32)]
+        uf2_family_id: Option<u32>,
+        /// Bind the image to one device by its OTP unique ID, in hex (optional).
+        ///
+        /// The encryption keys are derived from the ID, so the image only
+        /// decrypts on that device. Requires sm4 or aes encryption.
+        #[arg(long)]
+        bind_uid: Option<String>,
+        /// Compress the firmware with LZ4, for loaders using `kendryte_image::compress`.
+        ///
+        /// The BootROM cannot start compressed firmware; use this for images
+        /// loaded by a second stage.
+        #[arg(long)]
+        compress: bool,
+        /// Embed a manifest with build metadata and section digests in the image.
+        #[arg(long)]
+        manifest: bool,
+        /// Semantic version recorded in the manifest.
+        #[arg(long, requires = "manifest")]
+        firmware_version: Option<String>,
+        /// Additional section to digest in the manifest, as NAME=PATH (repeatable).
+        ///
+        /// The input firmware is always recorded as the `firmware` section.
+        #[arg(long = "section", value_name = "NAME=PATH", requires = "manifest")]
+        sections: Vec<String>,
+        /// Load address recorded in the manifest, decimal or 0x-prefixed hex.
+        #[arg(long, value_parser = parse_u64, requires = "manifest")]
+        load_address: Option<u64>,
+        /// Entry point recorded in the manifest, decimal or 0x-prefixed hex.
+        ///
+        /// Defaults to the load address when only that is given.
+        #[arg(long, value_parser = parse_u64, requires = "manifest")]
+        entry_point: Option<u64>,
+        /// Boot flags recorded in the manifest, decimal or 0x-prefixed hex.
+        ///
+        ///     bit 0: start on CPU1
+        ///
+        ///     bit 1: keep the watchdog running
+        #[arg(long, value_parser = parse_u32, requires = "manifest")]
+        boot_flags: Option<u32>,
+        /// Signing key selection.
+        #[command(flatten)]
+        signer: SignerOptions,
+    },
+    /// Provision a device identity.
+    ///
+    /// Generates a per-device ECDSA P-256 key pair, writes a self-signed certificate
+    /// (or a CSR with `--csr`) and the private key sealed for secure storage.
+    ///
+    ///     cargo xtask provision --device-id k230-0001 --device-secret 00112233... -o ./provision
+    ///
+    ///     Output: ./provision/k230-0001.crt.pem and ./provision/k230-0001.key.blob
+    Provision {
+        /// Device identifier, used as the certificate common name.
+        #[arg(long)]
+        device_id: String,
+        /// Device-unique secret (OTP or PUF derived) in hex, from which the storage key is derived.
+        #[arg(long)]
+        device_secret: String,
+        /// Label of the storage key, as passed to `derive_storage_key` on the device.
+        #[arg(long, default_value = "identity")]
+        key_label: String,
+        /// Secure storage slot of the private key.
+        #[arg(long, default_value_t = 0)]
+        slot: u16,
+        /// Blob version, checked against the anti-rollback counter.
+        #[arg(long, default_value_t = 0)]
+        version: u32,
+        /// Emit a certificate signing request instead of a self-signed certificate.
+        #[arg(long)]
+        csr: bool,
+        /// Output directory.
+        #[arg(long = "output", short = 'o', default_value = ".")]
+        output: PathBuf,
     },
+    /// Report section sizes and memory usage of a firmware ELF.
+    ///
+    ///     cargo xtask size -i target/riscv64gc-unknown-none-elf/release/uart-demo --map uart-demo.map
+    ///
+    /// The sizes are saved next to the ELF with a `.size` extension, and the next
+    /// report shows the change against them.
+    Size {
+        /// Firmware ELF file path.
+        #[arg(long = "input", short = 'i')]
+        input: PathBuf,
+        /// lld linker map (`-Map`), to list the largest objects.
+        #[arg(long)]
+        map: Option<PathBuf>,
+        /// Number of objects listed from the linker map.
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Memory region as NAME=ORIGIN+LENGTH (repeatable), replacing the K230 defaults.
+        #[arg(long = "region", value_name = "NAME=ORIGIN+LENGTH")]
+        regions: Vec<String>,
+        /// Size limit of the bytes stored in the boot image, decimal or 0x-prefixed hex.
+        #[arg(long, value_parser = parse_u64)]
+        flash_size: Option<u64>,
+        /// Baseline of the previous build (default: the input with a `.size` extension).
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+        /// Do not update the baseline.
+        #[arg(long)]
+        no_save: bool,
+    },
+    /// Resolve the addresses of a crash dump against a firmware ELF.
+    ///
+    ///     cargo xtask symbolicate -i target/riscv64gc-unknown-none-elf/release/uart-demo crash.txt
+    ///
+    /// The dump is the text printed by `kendryte_rt::arch::crash::CrashDump`.
+    Symbolicate {
+        /// Firmware ELF file path.
+        #[arg(long = "input", short = 'i')]
+        input: PathBuf,
+        /// Crash dump file path.
+        dump: PathBuf,
+    },
+    /// Decode a trace captured from `kendryte_hal::trace`.
+    ///
+    ///     cargo xtask trace capture.bin
+    ///
+    /// Lists the events and, per interrupt, the min/mean/max handler
+    /// duration and period between entries, and per task the run time and
+    /// share of the capture.
+    Trace {
+        /// Capture of the drained events, as raw bytes or a trace stream.
+        capture: PathBuf,
+        /// Frequency of the timer the events were stamped with, in Hz
+        /// (default: from the stream header, or 27000000).
+        #[arg(long, value_parser = parse_u32)]
+        frequency: Option<u32>,
+    },
+    /// Generate a delta update from one image to another.
+    ///
+    ///     cargo xtask delta --from uart-demo-1.0.img --to uart-demo-1.1.img
+    ///
+    ///     Output: uart-demo-1.1.delta
+    ///
+    /// The device rebuilds the new image from the old one with
+    /// `kendryte_image::ota::apply_delta`.
+    Delta {
+        /// Image currently on the device.
+        #[arg(long)]
+        from: PathBuf,
+        /// Image to update to.
+        #[arg(long)]
+        to: PathBuf,
+        /// Output file path (default: the new image with a `.delta` extension).
+        #[arg(long = "output", short = 'o')]
+        output: Option<PathBuf>,
+    },
+    /// Generate a device tree overlay for Linux from a board file.
+    ///
+    ///     cargo xtask dts board.txt
+    ///
+    ///     Output: board.dtso
+    ///
+    /// The overlay reserves the peripherals and memory the firmware takes,
+    /// so Linux on the other core leaves them alone.
+    Dts {
+        /// Board file listing the claimed peripherals, pads and shared memory.
+        board: PathBuf,
+        /// Output file path (default: the board file with a `.dtso` extension).
+        #[arg(long = "output", short = 'o')]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal u64.
+fn parse_u64(value: &str) -> Result<u64, String> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+        None => value.replace('_', "").parse(),
+    }
+    .map_err(|e| format!("invalid number {}: {}", value, e))
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal u32.
+fn parse_u32(value: &str) -> Result<u32, String> {
+    let value = parse_u64(value)?;
+    u32::try_from(value).map_err(|_| format!("{} does not fit in 32 bits", value))
 }
@@ -1,6 +1,20 @@
 use clap::Parser;
-use std::fs;
-use xtask::generate::image::gen_image;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use xtask::delta;
+use xtask::dts;
+use xtask::error::XtaskError;
+use xtask::generate::compress;
+use xtask::generate::format::{write_format, OutputFormat};
+use xtask::generate::image::{signature_scheme, write_bound_image, EncryptionType};
+use xtask::generate::manifest::Manifest;
+use xtask::provision::provision;
+use xtask::size::{
+    decode_baseline, encode_baseline, flash_size, k230_regions, parse_map, parse_region,
+    read_sections, render,
+};
+use xtask::symbolicate::{self, parse_dump, read_symbols};
+use xtask::trace;
 use xtask::{Cli, Command};
 
 /// Main function for the xtask utility.
@@ -11,36 +25,380 @@ fn main() {
             input,
             output,
             encryption,
+            cipher,
+            signature,
+            format,
+            base_address,
+            uf2_family_id,
+            bind_uid,
+            compress,
+            manifest,
+            firmware_version,
+            sections,
+            load_address,
+            entry_point,
+            boot_flags,
+            signer,
         } => {
-            let encryption = encryption.unwrap_or_default();
-            let output = output.unwrap_or(input.with_extension("img"));
+            let encryption = match cipher.zip(signature) {
+                Some((cipher, signature)) => match EncryptionType::from_parts(cipher, signature) {
+                    Ok(encryption) => encryption,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                },
+                None => encryption.unwrap_or_default(),
+            };
+            let uid = match bind_uid.map(|uid| hex::decode(uid.trim_start_matches("0x"))) {
+                None => None,
+                Some(Ok(uid)) => Some(uid),
+                Some(Err(e)) => {
+                    println!("Invalid unique ID: {}", e);
+                    return;
+                }
+            };
+            let format = format.unwrap_or_default();
+            let output = output.unwrap_or(input.with_extension(format.extension()));
+
+            // The manifest goes after the firmware, so it is encoded up front
+            // from streamed digests and chained onto the input.
+            let manifest = if manifest {
+                let mut manifest = Manifest::from_environment(firmware_version);
+                manifest.load_address = load_address;
+                manifest.entry_point = entry_point.or(load_address);
+                manifest.boot_flags = boot_flags;
+                let sections =
+                    std::iter::once(format!("firmware={}", input.display())).chain(sections);
+                for section in sections {
+                    let Some((name, path)) = section.split_once('=') else {
+                        println!("Invalid section, expected NAME=PATH: {}", section);
+                        return;
+                    };
+                    let result =
+                        File::open(path).and_then(|file| manifest.add_section_from(name, file));
+                    if let Err(e) = result {
+                        println!("Failed to read section {}: {}", name, e);
+                        return;
+                    }
+                }
+                match manifest.encode() {
+                    Ok(manifest) => manifest,
+                    Err(e) => {
+                        println!("Failed to generate manifest: {}", e);
+                        return;
+                    }
+                }
+            } else {
+                Vec::new()
+            };
+
+            // Compression needs the whole firmware; otherwise it is streamed.
+            let firmware: Box<dyn Read + '_> = if compress {
+                let compressed = fs::read(&input)
+                    .map_err(XtaskError::from)
+                    .and_then(|firmware| compress::compress(&firmware));
+                match compressed {
+                    Ok(firmware) => Box::new(Cursor::new(firmware).chain(manifest.as_slice())),
+                    Err(e) => {
+                        println!("Failed to compress input file: {}", e);
+                        return;
+                    }
+                }
+            } else {
+                match File::open(&input) {
+                    Ok(file) => Box::new(BufReader::new(file).chain(manifest.as_slice())),
+                    Err(e) => {
+                        println!("Failed to read input file: {}", e);
+                        return;
+                    }
+                }
+            };
+
+            let mut image = match File::create(&output) {
+                Ok(file) => BufWriter::new(file),
+                Err(e) => {
+                    println!("Failed to write image: {}", e);
+                    return;
+                }
+            };
+
+            let signer = match signature_scheme(encryption).map(|scheme| signer.build(scheme)) {
+                None => None,
+                Some(Ok(signer)) => Some(signer),
+                Some(Err(e)) => {
+                    println!("Failed to set up signer: {}", e);
+                    return;
+                }
+            };
+
+            // Generate firmware image. The raw image streams straight to the
+            // output; the containers need the whole image to number their blocks.
+            let result = match format {
+                OutputFormat::Bin => write_bound_image(
+                    firmware,
+                    encryption,
+                    signer.as_deref(),
+                    uid.as_deref(),
+                    &mut image,
+                )
+                .map(|_| ()),
+                _ => {
+                    let mut raw = Cursor::new(Vec::new());
+                    write_bound_image(
+                        firmware,
+                        encryption,
+                        signer.as_deref(),
+                        uid.as_deref(),
+                        &mut raw,
+                    )
+                    .and_then(|_| {
+                        write_format(
+                            format,
+                            raw.get_ref(),
+                            base_address,
+                            uf2_family_id,
+                            &mut image,
+                        )
+                    })
+                }
+            };
+            if let Err(e) = result {
+                println!("Failed to generate image: {}", e);
+                return;
+            }
+
+            if let Err(e) = image.flush() {
+                println!("Failed to write image: {}", e);
+                return;
+            }
+
+            println!("Success! Image saved to: {}", output.display());
+        }
+        Command::Provision {
+            device_id,
+            device_secret,
+            key_label,
+            slot,
+            version,
+            csr,
+            output,
+        } => {
+            let device_secret = match hex::decode(device_secret.trim_start_matches("0x")) {
+                Ok(secret) => secret,
+                Err(e) => {
+                    println!("Invalid device secret: {}", e);
+                    return;
+                }
+            };
+
+            let provisioned = match provision(
+                &device_id,
+                &device_secret,
+                key_label.as_bytes(),
+                slot,
+                version,
+                csr,
+            ) {
+                Ok(p) => p,
+                Err(e) => {
+                    println!("Failed to provision device: {}", e);
+                    return;
+                }
+            };
+
+            let extension = if csr { "csr.pem" } else { "crt.pem" };
+            let certificate = output.join(format!("{}.{}", device_id, extension));
+            let key_blob = output.join(format!("{}.key.blob", device_id));
+            let result = fs::create_dir_all(&output)
+                .and_then(|_| fs::write(&certificate, &provisioned.certificate_pem))
+                .and_then(|_| fs::write(&key_blob, &provisioned.key_blob));
+            if let Err(e) = result {
+                println!("Failed to write provisioning output: {}", e);
+                return;
+            }
 
-            let data = match fs::read(input) {
-                Ok(data) => data,
+            println!("Success! Certificate saved to: {}", certificate.display());
+            println!("Sealed private key saved to: {}", key_blob.display());
+        }
+        Command::Size {
+            input,
+            map,
+            top,
+            regions,
+            flash_size: flash_limit,
+            baseline,
+            no_save,
+        } => {
+            let sections = match fs::read(&input) {
+                Ok(elf) => match read_sections(&elf) {
+                    Ok(sections) => sections,
+                    Err(e) => {
+                        println!("Failed to parse input file: {}", e);
+                        return;
+                    }
+                },
                 Err(e) => {
                     println!("Failed to read input file: {}", e);
                     return;
                 }
             };
 
-            // Generate firmware image
-            let image = match gen_image(&data, encryption) {
-                Ok(i) => i,
+            let regions = if regions.is_empty() {
+                k230_regions()
+            } else {
+                match regions.iter().map(|r| parse_region(r)).collect() {
+                    Ok(regions) => regions,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                }
+            };
+
+            let map = match map.map(fs::read_to_string) {
+                None => Vec::new(),
+                Some(Ok(map)) => match parse_map(&map) {
+                    Ok(map) => map,
+                    Err(e) => {
+                        println!("Failed to parse linker map: {}", e);
+                        return;
+                    }
+                },
+                Some(Err(e)) => {
+                    println!("Failed to read linker map: {}", e);
+                    return;
+                }
+            };
+
+            // A missing baseline just means this is the first build.
+            let baseline_path = baseline.unwrap_or(input.with_extension("size"));
+            let previous = fs::read_to_string(&baseline_path)
+                .ok()
+                .map(|text| decode_baseline(&text));
+
+            print!(
+                "{}",
+                render(
+                    &sections,
+                    &regions,
+                    flash_limit,
+                    &map,
+                    top,
+                    previous.as_ref()
+                )
+            );
+
+            if !no_save {
+                let current = encode_baseline(&sections, flash_size(&sections));
+                if let Err(e) = fs::write(&baseline_path, current) {
+                    println!("Failed to write baseline: {}", e);
+                }
+            }
+        }
+        Command::Symbolicate { input, dump } => {
+            let symbols = match fs::read(&input) {
+                Ok(elf) => match read_symbols(&elf) {
+                    Ok(symbols) => symbols,
+                    Err(e) => {
+                        println!("Failed to parse input file: {}", e);
+                        return;
+                    }
+                },
                 Err(e) => {
-                    println!("Failed to generate image: {}", e);
+                    println!("Failed to read input file: {}", e);
                     return;
                 }
             };
 
-            match fs::write(&output, &image) {
-                Ok(_) => (),
+            let text = match fs::read_to_string(&dump) {
+                Ok(text) => text,
                 Err(e) => {
-                    println!("Failed to write image: {}", e);
+                    println!("Failed to read crash dump: {}", e);
                     return;
                 }
+            };
+
+            match parse_dump(&text) {
+                Ok(frames) => print!("{}", symbolicate::render(&symbols, &text, &frames)),
+                Err(e) => println!("Failed to parse crash dump: {}", e),
             }
+        }
+        Command::Trace { capture, frequency } => {
+            let capture = match fs::read(&capture) {
+                Ok(capture) => capture,
+                Err(e) => {
+                    println!("Failed to read trace capture: {}", e);
+                    return;
+                }
+            };
 
-            println!("Success! Image saved to: {}", output.display());
+            match trace::decode(&capture) {
+                Ok(capture) => {
+                    let frequency = frequency.or(capture.frequency).unwrap_or(27_000_000);
+                    print!("{}", trace::render(&capture.events, frequency));
+                    if capture.skipped > 0 {
+                        println!("{} bytes skipped outside valid frames", capture.skipped);
+                    }
+                }
+                Err(e) => println!("Failed to decode trace capture: {}", e),
+            }
+        }
+        Command::Delta { from, to, output } => {
+            let (old, new) = match fs::read(&from).and_then(|old| Ok((old, fs::read(&to)?))) {
+                Ok(images) => images,
+                Err(e) => {
+                    println!("Failed to read input file: {}", e);
+                    return;
+                }
+            };
+
+            let delta = match delta::diff(&old, &new) {
+                Ok(delta) => delta,
+                Err(e) => {
+                    println!("Failed to generate delta: {}", e);
+                    return;
+                }
+            };
+
+            let output = output.unwrap_or(to.with_extension("delta"));
+            if let Err(e) = fs::write(&output, &delta) {
+                println!("Failed to write delta: {}", e);
+                return;
+            }
+
+            println!(
+                "Success! Delta of {} bytes ({:.1}% of the new image) saved to: {}",
+                delta.len(),
+                delta.len() as f64 * 100.0 / new.len().max(1) as f64,
+                output.display()
+            );
+        }
+        Command::Dts { board, output } => {
+            let text = match fs::read_to_string(&board) {
+                Ok(text) => text,
+                Err(e) => {
+                    println!("Failed to read board file: {}", e);
+                    return;
+                }
+            };
+
+            let parsed = match dts::parse_board(&text) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    println!("Failed to parse board file: {}", e);
+                    return;
+                }
+            };
+
+            let source = board.file_name().unwrap_or_default().to_string_lossy();
+            let output = output.unwrap_or(board.with_extension("dtso"));
+            if let Err(e) = fs::write(&output, dts::render(&parsed, &source)) {
+                println!("Failed to write overlay: {}", e);
+                return;
+            }
+
+            println!("Success! Overlay saved to: {}", output.display());
         }
     }
 }
@@ -126,6 +484,112 @@ mod tests {
         let expected_output = input_path.with_extension("img");
         assert!(expected_output.exists());
 
+        Ok(())
+    }
+    #[test]
+    fn test_manifest_with_sections() -> Result<(), Box<dyn std::error::Error>> {
+        let input_file = NamedTempFile::new()?;
+        let section_file = NamedTempFile::new()?;
+        let output_file = NamedTempFile::new()?;
+        std::fs::write(input_file.path(), b"test data")?;
+        std::fs::write(section_file.path(), b"rootfs")?;
+
+        let mut cmd = Command::cargo_bin("xtask")?;
+        cmd.arg("gen-image")
+            .arg("--input")
+            .arg(input_file.path())
+            .arg("--output")
+            .arg(output_file.path())
+            .arg("--manifest")
+            .arg("--firmware-version")
+            .arg("1.2.3")
+            .arg("--section")
+            .arg(format!("rootfs={}", section_file.path().display()));
+
+        cmd.assert().success();
+
+        let image = std::fs::read(output_file.path())?;
+        assert!(image.windows(5).any(|w| w == b"1.2.3"));
+        assert!(image.windows(4).any(|w| w == b"KMF1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_uf2_format() -> Result<(), Box<dyn std::error::Error>> {
+        let input_file = NamedTempFile::new()?;
+        let input_path = input_file.path();
+        std::fs::write(input_path, b"test data")?;
+
+        let mut cmd = Command::cargo_bin("xtask")?;
+        cmd.arg("gen-image")
+            .arg("--input")
+            .arg(input_path)
+            .arg("--format")
+            .arg("uf2");
+
+        cmd.assert().success();
+
+        let output = std::fs::read(input_path.with_extension("uf2"))?;
+        assert_eq!(output.len() % 512, 0);
+        assert_eq!(&output[..4], b"UF2\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_format() -> Result<(), Box<dyn std::error::Error>> {
+        let input_file = NamedTempFile::new()?;
+        let output_file = NamedTempFile::new()?;
+        std::fs::write(input_file.path(), b"test data")?;
+
+        let mut cmd = Command::cargo_bin("xtask")?;
+        cmd.arg("gen-image")
+            .arg("--input")
+            .arg(input_file.path())
+            .arg("--output")
+            .arg(output_file.path())
+            .arg("--format")
+            .arg("hex");
+
+        cmd.assert().success();
+
+        let output = std::fs::read_to_string(output_file.path())?;
+        assert!(output.lines().all(|line| line.starts_with(':')));
+        assert!(output.ends_with(":00000001FF\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_boot_fields() -> Result<(), Box<dyn std::error::Error>> {
+        let input_file = NamedTempFile::new()?;
+        let output_file = NamedTempFile::new()?;
+        std::fs::write(input_file.path(), b"test data")?;
+
+        let mut cmd = Command::cargo_bin("xtask")?;
+        cmd.arg("gen-image")
+            .arg("--input")
+            .arg(input_file.path())
+            .arg("--output")
+            .arg(output_file.path())
+            .arg("--manifest")
+            .arg("--load-address")
+            .arg("0x8030_0000")
+            .arg("--boot-flags")
+            .arg("1");
+
+        cmd.assert().success();
+
+        let image = std::fs::read(output_file.path())?;
+        let mut load_address = vec![0x04, 8, 0];
+        load_address.extend(0x8030_0000u64.to_le_bytes());
+        let mut entry_point = vec![0x05, 8, 0];
+        entry_point.extend(0x8030_0000u64.to_le_bytes());
+        assert!(image.windows(11).any(|w| w == load_address));
+        assert!(image.windows(11).any(|w| w == entry_point));
+        assert!(image.windows(7).any(|w| w == [0x06, 4, 0, 1, 0, 0, 0]));
+
         Ok(())
     }
 }
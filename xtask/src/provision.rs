@@ -0,0 +1,146 @@
+//! Device identity provisioning.
+//!
+//! Generates a per-device key pair and certificate, and seals the private key
+//! into the blob format read by `kendryte_hal::secure_storage`, so it can be
+//! flashed into the secure storage partition along with the firmware.
+
+use crate::error::{XtaskError, XtaskResult};
+use aes_gcm::aead::OsRng;
+use aes_gcm::{AeadCore, AeadInPlace, Aes256Gcm, Key, KeyInit};
+use hkdf::Hkdf;
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+use sha2::Sha256;
+
+/// Magic number at the start of every sealed blob.
+pub const BLOB_MAGIC: &[u8] = b"KSB2";
+/// Length of the sealed blob header.
+pub const BLOB_HEADER_LEN: usize = 28;
+/// Largest data length a sealed blob can hold.
+pub const BLOB_MAX_DATA_LEN: usize = 1024;
+/// HKDF salt used by `kendryte_hal::secure_storage::derive_storage_key`.
+const STORAGE_KEY_SALT: &[u8] = b"kendryte-secure-storage";
+
+/// Output of provisioning a single device.
+pub struct Provisioned {
+    /// Private key sealed into the secure storage blob format.
+    pub key_blob: Vec<u8>,
+    /// PEM encoded self-signed certificate or certificate signing request.
+    pub certificate_pem: String,
+}
+
+/// Generate an ECDSA P-256 identity for `device_id`.
+/// The private key is sealed with the storage key derived from `device_secret`,
+/// the device-unique secret (OTP or PUF) also used on-device.
+/// When `csr` is set, a certificate signing request is emitted for a fleet CA instead
+/// of a self-signed certificate.
+pub fn provision(
+    device_id: &str,
+    device_secret: &[u8],
+    key_label: &[u8],
+    slot: u16,
+    version: u32,
+    csr: bool,
+) -> XtaskResult<Provisioned> {
+    println!("----- Provisioning device {} -----", device_id);
+    let key_pair = KeyPair::generate()?;
+
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, device_id);
+    params.distinguished_name = name;
+
+    let certificate_pem = match csr {
+        true => params.serialize_request(&key_pair)?.pem()?,
+        false => params.self_signed(&key_pair)?.pem(),
+    };
+
+    let key = derive_storage_key(device_secret, key_label);
+    let key_blob = seal_blob(&key, slot, version, &key_pair.serialize_der())?;
+    println!("sealed key blob: {} bytes", key_blob.len());
+
+    Ok(Provisioned {
+        key_blob,
+        certificate_pem,
+    })
+}
+
+/// Derive the secure storage key the same way the device does.
+pub fn derive_storage_key(device_secret: &[u8], label: &[u8]) -> [u8; 32] {
+    let hkdf = Hkdf::<Sha256>::new(Some(STORAGE_KEY_SALT), device_secret);
+    let mut key = [0; 32];
+    // A single digest is always within the HKDF output limit.
+    hkdf.expand(label, &mut key).unwrap();
+    key
+}
+
+/// Seal `data` into a secure storage blob with AES-256-GCM and a random nonce.
+/// The blob header is authenticated as associated data. The blob gets sequence
+/// number 0 and belongs in the first sector of the slot.
+pub fn seal_blob(key: &[u8; 32], slot: u16, version: u32, data: &[u8]) -> XtaskResult<Vec<u8>> {
+    if data.len() > BLOB_MAX_DATA_LEN {
+        return Err(XtaskError::BlobTooLarge(data.len()));
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let mut blob = Vec::with_capacity(BLOB_HEADER_LEN + data.len() + 16);
+    blob.extend(BLOB_MAGIC);
+    blob.extend(0u32.to_le_bytes());
+    blob.extend(version.to_le_bytes());
+    blob.extend(slot.to_le_bytes());
+    blob.extend((data.len() as u16).to_le_bytes());
+    blob.extend(nonce.as_slice());
+
+    let mut ciphertext = data.to_vec();
+    let tag = cipher
+        .encrypt_in_place_detached(&nonce, &blob, &mut ciphertext)
+        .map_err(|e| XtaskError::AesError(e.to_string()))?;
+    blob.extend(ciphertext);
+    blob.extend(tag);
+    Ok(blob)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::{Nonce, Tag};
+
+    #[test]
+    fn seal_blob_layout() {
+        let key = derive_storage_key(b"device secret", b"identity");
+        let blob = seal_blob(&key, 2, 7, b"private key").unwrap();
+
+        assert_eq!(blob.len(), BLOB_HEADER_LEN + 11 + 16);
+        assert_eq!(&blob[0..4], BLOB_MAGIC);
+        assert_eq!(&blob[4..8], &0u32.to_le_bytes());
+        assert_eq!(&blob[8..12], &7u32.to_le_bytes());
+        assert_eq!(&blob[12..14], &2u16.to_le_bytes());
+        assert_eq!(&blob[14..16], &11u16.to_le_bytes());
+
+        let (header, body) = blob.split_at(BLOB_HEADER_LEN);
+        let (ciphertext, tag) = body.split_at(11);
+        let mut plaintext = ciphertext.to_vec();
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&header[16..]),
+                header,
+                &mut plaintext,
+                Tag::from_slice(tag),
+            )
+            .unwrap();
+        assert_eq!(plaintext, b"private key");
+    }
+
+    #[test]
+    fn provision_self_signed() {
+        let provisioned = provision("k230-0001", b"secret", b"identity", 0, 0, false).unwrap();
+        assert!(provisioned
+            .certificate_pem
+            .starts_with("-----BEGIN CERTIFICATE-----"));
+
+        let provisioned = provision("k230-0001", b"secret", b"identity", 0, 0, true).unwrap();
+        assert!(provisioned
+            .certificate_pem
+            .starts_with("-----BEGIN CERTIFICATE REQUEST-----"));
+    }
+}
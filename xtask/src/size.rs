@@ -0,0 +1,401 @@
+//! Post-build size report.
+//!
+//! Reads the allocated sections of a firmware ELF, and optionally the lld
+//! linker map, and reports how much of each memory region they use, the
+//! largest contributing objects and the change since the previous build.
+
+use crate::error::{XtaskError, XtaskResult};
+use object::elf::SHF_ALLOC;
+use object::{Object, ObjectSection, SectionFlags, SectionKind};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A memory region of the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub origin: u64,
+    pub length: u64,
+}
+
+impl MemoryRegion {
+    fn contains(&self, address: u64) -> bool {
+        address >= self.origin && address - self.origin < self.length
+    }
+}
+
+/// Memory regions of the K230, matching the `kendryte-rt` linker script.
+pub fn k230_regions() -> Vec<MemoryRegion> {
+    vec![
+        MemoryRegion {
+            name: "SRAM".into(),
+            origin: 0x8030_0000,
+            length: 0x10_0000,
+        },
+        MemoryRegion {
+            name: "DDR".into(),
+            origin: 0x0000_0000,
+            length: 0x2000_0000,
+        },
+    ]
+}
+
+/// Parse a region as `NAME=ORIGIN+LENGTH`, with decimal or `0x`-prefixed hex numbers.
+pub fn parse_region(value: &str) -> XtaskResult<MemoryRegion> {
+    let invalid = || XtaskError::SizeError(format!("invalid region {}", value));
+    let (name, range) = value.split_once('=').ok_or_else(invalid)?;
+    let (origin, length) = range.split_once('+').ok_or_else(invalid)?;
+    let number = |s: &str| match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+    Ok(MemoryRegion {
+        name: name.to_string(),
+        origin: number(origin).ok_or_else(invalid)?,
+        length: number(length).ok_or_else(invalid)?,
+    })
+}
+
+/// An allocated section of the firmware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    /// Whether the section has contents in the image, as opposed to `.bss`.
+    pub loaded: bool,
+}
+
+/// Read the allocated sections of an ELF file.
+pub fn read_sections(elf: &[u8]) -> XtaskResult<Vec<Section>> {
+    let file = object::File::parse(elf)?;
+    let mut sections = Vec::new();
+    for section in file.sections() {
+        let allocated = matches!(
+            section.flags(),
+            SectionFlags::Elf { sh_flags } if sh_flags & u64::from(SHF_ALLOC) != 0
+        );
+        if !allocated || section.size() == 0 {
+            continue;
+        }
+        sections.push(Section {
+            name: section.name()?.to_string(),
+            address: section.address(),
+            size: section.size(),
+            loaded: !matches!(
+                section.kind(),
+                SectionKind::UninitializedData | SectionKind::UninitializedTls
+            ),
+        });
+    }
+    Ok(sections)
+}
+
+/// Bytes of an input object placed in an output section, from a linker map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapEntry {
+    pub output_section: String,
+    pub object: String,
+    pub size: u64,
+}
+
+/// Parse an lld linker map (`-Map`) into per-object input section sizes.
+///
+/// Objects inside archives are attributed to the archive, so each crate shows
+/// up once.
+pub fn parse_map(map: &str) -> XtaskResult<Vec<MapEntry>> {
+    let mut lines = map.lines();
+    let header = lines
+        .find(|line| line.contains("VMA") && line.contains("Out"))
+        .ok_or_else(|| XtaskError::SizeError("not an lld linker map".into()))?;
+    let column = |name: &str| header.find(name).unwrap_or(usize::MAX);
+    let (input_column, symbol_column) = (column(" In ") + 1, column("Symbol"));
+
+    let mut entries = Vec::new();
+    let mut output_section = String::new();
+    for line in lines {
+        let [_vma, _lma, (_, size), _align, (indent, name), ..] = fields(line)[..] else {
+            continue;
+        };
+        let Ok(size) = u64::from_str_radix(size, 16) else {
+            continue;
+        };
+        if indent < input_column {
+            output_section = name.to_string();
+        } else if indent < symbol_column && size != 0 {
+            entries.push(MapEntry {
+                output_section: output_section.clone(),
+                object: object_name(name),
+                size,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Whitespace separated fields of `line`, with the column each starts at.
+fn fields(line: &str) -> Vec<(usize, &str)> {
+    let mut fields = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                fields.push((s, &line[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+/// Reduce `path/libfoo.rlib(foo.o):(.text.bar)` to `libfoo.rlib`.
+fn object_name(input: &str) -> String {
+    let path = input.rsplit_once(":(").map_or(input, |(path, _)| path);
+    let path = match path.strip_suffix(')') {
+        Some(member) => member.split_once('(').map_or(path, |(archive, _)| archive),
+        None => path,
+    };
+    path.rsplit(['/', '\\']).next().unwrap_or(path).to_string()
+}
+
+/// Usage of a memory region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionUsage {
+    pub region: MemoryRegion,
+    pub used: u64,
+}
+
+/// Sum the sections placed in each region.
+pub fn region_usage(sections: &[Section], regions: &[MemoryRegion]) -> Vec<RegionUsage> {
+    regions
+        .iter()
+        .map(|region| RegionUsage {
+            region: region.clone(),
+            used: sections
+                .iter()
+                .filter(|section| region.contains(section.address))
+                .map(|section| section.size)
+                .sum(),
+        })
+        .collect()
+}
+
+/// Sizes recorded from a previous build, keyed by section or region name.
+pub type Baseline = BTreeMap<String, u64>;
+
+/// Encode the sizes of a build as `NAME SIZE` lines.
+pub fn encode_baseline(sections: &[Section], flash: u64) -> String {
+    let mut out = format!("flash {}\n", flash);
+    for section in sections {
+        let _ = writeln!(out, "{} {}", section.name, section.size);
+    }
+    out
+}
+
+/// Decode a baseline written by [`encode_baseline`], skipping malformed lines.
+pub fn decode_baseline(text: &str) -> Baseline {
+    text.lines()
+        .filter_map(|line| {
+            let (name, size) = line.split_once(' ')?;
+            Some((name.to_string(), size.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Bytes of the firmware stored in the image.
+pub fn flash_size(sections: &[Section]) -> u64 {
+    sections.iter().filter(|s| s.loaded).map(|s| s.size).sum()
+}
+
+/// Render the size report.
+///
+/// `flash_limit` bounds the bytes stored in the image, `top` is the number of
+/// largest objects listed from the map, and `baseline` adds a column with the
+/// change since the previous build.
+pub fn render(
+    sections: &[Section],
+    regions: &[MemoryRegion],
+    flash_limit: Option<u64>,
+    map: &[MapEntry],
+    top: usize,
+    baseline: Option<&Baseline>,
+) -> String {
+    let delta = |name: &str, size: u64| match baseline.and_then(|b| b.get(name)) {
+        Some(&old) if old != size => format!("{:+}", size as i64 - old as i64),
+        Some(_) => String::new(),
+        None if baseline.is_some() => "new".into(),
+        None => String::new(),
+    };
+    let percent = |used: u64, limit: u64| match limit {
+        0 => 0.0,
+        limit => used as f64 * 100.0 / limit as f64,
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<20} {:>18} {:>10} {:>10}",
+        "section", "address", "size", "delta"
+    );
+    for section in sections {
+        let _ = writeln!(
+            out,
+            "{:<20} {:#018x} {:>10} {:>10}",
+            section.name,
+            section.address,
+            section.size,
+            delta(&section.name, section.size)
+        );
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "{:<20} {:>10} {:>10} {:>7}",
+        "region", "used", "size", "usage"
+    );
+    let flash = flash_size(sections);
+    match flash_limit {
+        Some(limit) => {
+            let _ = writeln!(
+                out,
+                "{:<20} {:>10} {:>10} {:>6.1}% {}",
+                "flash",
+                flash,
+                limit,
+                percent(flash, limit),
+                delta("flash", flash)
+            );
+        }
+        None => {
+            let _ = writeln!(
+                out,
+                "{:<20} {:>10} {:>10} {:>7} {}",
+                "flash",
+                flash,
+                "-",
+                "-",
+                delta("flash", flash)
+            );
+        }
+    }
+    for usage in region_usage(sections, regions) {
+        let over = if usage.used > usage.region.length {
+            " OVERFLOW"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "{:<20} {:>10} {:>10} {:>6.1}%{}",
+            usage.region.name,
+            usage.used,
+            usage.region.length,
+            percent(usage.used, usage.region.length),
+            over
+        );
+    }
+
+    if !map.is_empty() && top > 0 {
+        let mut objects: BTreeMap<&str, u64> = BTreeMap::new();
+        for entry in map {
+            if sections.iter().any(|s| s.name == entry.output_section) {
+                *objects.entry(&entry.object).or_default() += entry.size;
+            }
+        }
+        let mut objects: Vec<_> = objects.into_iter().collect();
+        objects.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{:<50} {:>10}", "object", "size");
+        for (object, size) in objects.into_iter().take(top) {
+            let _ = writeln!(out, "{:<50} {:>10}", object, size);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r"
+             VMA              LMA     Size Align Out     In      Symbol
+        80300000         80300000      120     4 .text
+        80300000         80300000       20     2         /tmp/app.o:(.text.entry)
+        80300000         80300000        0     1                 _start
+        80300020         80300020      100     4         /deps/libcore-abc.rlib(core-abc.core.0.rcgu.o):(.text.memcpy)
+        80300120         80300120       10     4 .bss
+        80300120         80300120       10     4         /deps/libapp-1.rlib(app-1.app.0.rcgu.o):(.bss.BUF)
+               0                0       40     1 .debug_info
+               0                0       40     1         /tmp/app.o:(.debug_info)
+";
+
+    fn sections() -> Vec<Section> {
+        vec![
+            Section {
+                name: ".text".into(),
+                address: 0x8030_0000,
+                size: 0x120,
+                loaded: true,
+            },
+            Section {
+                name: ".bss".into(),
+                address: 0x8030_0120,
+                size: 0x10,
+                loaded: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn map_entries() {
+        let entries = parse_map(MAP).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[1],
+            MapEntry {
+                output_section: ".text".into(),
+                object: "libcore-abc.rlib".into(),
+                size: 0x100,
+            }
+        );
+        assert_eq!(entries[2].object, "libapp-1.rlib");
+        assert_eq!(entries[3].output_section, ".debug_info");
+    }
+
+    #[test]
+    fn usage_and_report() {
+        let sections = sections();
+        let usage = region_usage(&sections, &k230_regions());
+        assert_eq!(usage[0].used, 0x130);
+        assert_eq!(usage[1].used, 0);
+        assert_eq!(flash_size(&sections), 0x120);
+
+        let baseline = decode_baseline("flash 256\n.text 256\n");
+        let map = parse_map(MAP).unwrap();
+        let report = render(&sections, &k230_regions(), None, &map, 10, Some(&baseline));
+        assert!(report.contains("+32"));
+        assert!(report.contains("new"));
+        assert!(report.contains("libcore-abc.rlib"));
+        assert!(!report.contains(".debug_info"));
+    }
+
+    #[test]
+    fn baseline_round_trip() {
+        let sections = sections();
+        let baseline = decode_baseline(&encode_baseline(&sections, flash_size(&sections)));
+        assert_eq!(baseline.get("flash"), Some(&0x120));
+        assert_eq!(baseline.get(".bss"), Some(&0x10));
+    }
+
+    #[test]
+    fn region_argument() {
+        let region = parse_region("PSRAM=0x1000+4096").unwrap();
+        assert_eq!(region.origin, 0x1000);
+        assert_eq!(region.length, 4096);
+        assert!(parse_region("PSRAM=0x1000").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Crash dump symbolication.
+//!
+//! Resolves the addresses of a crash dump printed by `kendryte_rt::arch::crash`
+//! to the functions of the firmware ELF:
+//!
+//! ```text
+//! crash: load access fault
+//!   mepc 0x80300124 uart_demo::parse+0x14
+//!     ra 0x80300200 uart_demo::main+0x30
+//!     #0 0x80300200 uart_demo::main+0x30
+//! ```
+
+use crate::error::{XtaskError, XtaskResult};
+use object::{Object, ObjectSymbol, SymbolKind};
+use std::fmt::Write;
+
+/// A function of the firmware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// Demangled name, without the hash.
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+/// An address of the dump, labelled with where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// `mepc`, `ra`, or `#n` for backtrace entries.
+    pub label: String,
+    pub address: u64,
+}
+
+/// Read the function symbols of an ELF file, sorted by address.
+pub fn read_symbols(elf: &[u8]) -> XtaskResult<Vec<Symbol>> {
+    let file = object::File::parse(elf)?;
+    let mut symbols: Vec<Symbol> = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter_map(|symbol| {
+            let name = symbol.name().ok()?;
+            Some(Symbol {
+                name: format!("{:#}", rustc_demangle::demangle(name)),
+                address: symbol.address(),
+                size: symbol.size(),
+            })
+        })
+        .collect();
+    symbols.sort_by_key(|symbol| symbol.address);
+    Ok(symbols)
+}
+
+/// Find the function containing `address`, and the offset into it.
+pub fn lookup(symbols: &[Symbol], address: u64) -> Option<(&Symbol, u64)> {
+    let index = symbols.partition_point(|symbol| symbol.address <= address);
+    let symbol = symbols[..index].last()?;
+    let offset = address - symbol.address;
+    // Symbols without a size only match exactly.
+    (offset < symbol.size.max(1)).then_some((symbol, offset))
+}
+
+/// Extract the program counter, return address and backtrace of a printed dump.
+pub fn parse_dump(text: &str) -> XtaskResult<Vec<Frame>> {
+    let invalid = |token: &str| XtaskError::CrashDumpError(format!("invalid address {}", token));
+    let hex = |token: &str| {
+        token
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| invalid(token))
+    };
+
+    let mut frames = Vec::new();
+    let mut backtrace = Vec::new();
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("backtrace:") => {
+                for token in tokens {
+                    backtrace.push(Frame {
+                        label: format!("#{}", backtrace.len()),
+                        address: hex(token)?,
+                    });
+                }
+            }
+            Some(first) => {
+                for token in std::iter::once(first).chain(tokens) {
+                    if let Some((label @ ("mepc" | "ra"), value)) = token.split_once('=') {
+                        frames.push(Frame {
+                            label: label.to_string(),
+                            address: hex(value)?,
+                        });
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+    if !frames.iter().any(|frame| frame.label == "mepc") {
+        return Err(XtaskError::CrashDumpError("no mepc in the dump".into()));
+    }
+    // The program counter first, then the return address.
+    frames.sort_by_key(|frame| frame.label != "mepc");
+    frames.extend(backtrace);
+    Ok(frames)
+}
+
+/// Render the dump's cause and each frame with its function.
+pub fn render(symbols: &[Symbol], text: &str, frames: &[Frame]) -> String {
+    let mut out = String::new();
+    for line in text.lines().filter(|line| line.starts_with("crash:")) {
+        writeln!(out, "{}", line).unwrap();
+    }
+    for frame in frames {
+        // Return addresses point past the call, which may be the last
+        // instruction of the function.
+        let probe = match frame.label.as_str() {
+            "mepc" => frame.address,
+            _ => frame.address.saturating_sub(1),
+        };
+        write!(out, "{:>6} 0x{:08x} ", frame.label, frame.address).unwrap();
+        match lookup(symbols, probe) {
+            Some((symbol, _)) => writeln!(
+                out,
+                "{}+0x{:x}",
+                symbol.name,
+                frame.address - symbol.address
+            ),
+            None => writeln!(out, "??"),
+        }
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+crash: load access fault
+mcause=0x5 mepc=0x80300124 mtval=0x10 mstatus=0x1800
+ra=0x80300200 sp=0x80310000 gp=0x0
+backtrace: 0x80300200 0x80300400
+";
+
+    fn symbols() -> Vec<Symbol> {
+        vec![
+            Symbol {
+                name: "demo::parse".into(),
+                address: 0x8030_0110,
+                size: 0x40,
+            },
+            Symbol {
+                name: "demo::main".into(),
+                address: 0x8030_01d0,
+                size: 0x30,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_parse_dump() {
+        let frames = parse_dump(DUMP).unwrap();
+        let labels: Vec<_> = frames.iter().map(|f| f.label.as_str()).collect();
+        assert_eq!(labels, ["mepc", "ra", "#0", "#1"]);
+        assert_eq!(frames[0].address, 0x8030_0124);
+        assert_eq!(frames[3].address, 0x8030_0400);
+        assert!(parse_dump("backtrace: 0x1").is_err());
+        assert!(parse_dump("mepc=zz").is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let symbols = symbols();
+        assert_eq!(lookup(&symbols, 0x8030_0124).unwrap().1, 0x14);
+        assert_eq!(lookup(&symbols, 0x8030_0150), None);
+        assert_eq!(lookup(&symbols, 0x8030_0000), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let frames = parse_dump(DUMP).unwrap();
+        let text = render(&symbols(), DUMP, &frames);
+        assert_eq!(
+            text,
+            "crash: load access fault\n  \
+             mepc 0x80300124 demo::parse+0x14\n    \
+             ra 0x80300200 demo::main+0x30\n    \
+             #0 0x80300200 demo::main+0x30\n    \
+             #1 0x80300400 ??\n"
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Trace decoding.
+//!
+//! Decodes the events drained from a `kendryte_hal::trace::Trace` buffer,
+//! each a little endian u64 of kind, ID and 48-bit timer count, and reports
+//! how long each interrupt handler ran and how regularly it was entered,
+//! and how much of the time each task ran:
+//!
+//! ```text
+//!        12.000 us  irq 33 enter
+//!        15.250 us  irq 33 exit
+//!
+//! irq 33: 250 runs, duration 3.1/3.3/4.9 us, period 999.2/1000.0/1012.4 us
+//! task 2: 40 runs, run time 8.0/12.5/30.1 us, total 500.0 us (2.0%)
+//! ```
+//!
+//! A capture is either the raw events, or the stream written by
+//! `Trace::flush`: a `KTRC` header with the timer frequency, then frames of
+//! a sync byte, the event and its XOR. Frames damaged by lost bytes are
+//! skipped.
+
+use crate::error::{XtaskError, XtaskResult};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const TIMESTAMP_BITS: u32 = 48;
+const ID_BITS: u32 = 12;
+/// Magic bytes starting a trace stream.
+const MAGIC: &[u8; 4] = b"KTRC";
+/// Length of the stream header: magic and timer frequency.
+const HEADER_LEN: usize = 8;
+/// Byte starting every frame of a trace stream.
+const SYNC: u8 = 0xA5;
+/// Length of a stream frame: sync byte, event and check byte.
+const FRAME_LEN: usize = 10;
+
+/// What an event records, matching `kendryte_hal::trace::Kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    IrqEnter,
+    IrqExit,
+    DmaDone,
+    Marker,
+    TaskBegin,
+    TaskEnd,
+    Idle,
+    Overflow,
+}
+
+impl Kind {
+    fn from_bits(kind: u64) -> Option<Self> {
+        Some(match kind {
+            1 => Kind::IrqEnter,
+            2 => Kind::IrqExit,
+            3 => Kind::DmaDone,
+            4 => Kind::Marker,
+            5 => Kind::TaskBegin,
+            6 => Kind::TaskEnd,
+            7 => Kind::Idle,
+            8 => Kind::Overflow,
+            _ => return None,
+        })
+    }
+}
+
+/// A decoded event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: Kind,
+    pub id: u16,
+    /// Timer count, extended past the 48-bit wrap.
+    pub timestamp: u64,
+}
+
+/// A decoded capture.
+#[derive(Debug, Default)]
+pub struct Capture {
+    /// Timer frequency from the stream header, if there was one.
+    pub frequency: Option<u32>,
+    pub events: Vec<Event>,
+    /// Bytes of the stream skipped outside a valid frame.
+    pub skipped: usize,
+}
+
+/// Extends 48-bit timestamps across wraps.
+///
+/// Assumes no gap between consecutive events is longer than a wrap period.
+#[derive(Default)]
+struct Clock {
+    base: u64,
+    last: u64,
+}
+
+impl Clock {
+    fn event(&mut self, bits: u64) -> Option<Event> {
+        let kind = Kind::from_bits(bits >> (TIMESTAMP_BITS + ID_BITS))?;
+        let raw = bits & ((1 << TIMESTAMP_BITS) - 1);
+        // A count far below the last one wrapped; one slightly below was
+        // recorded before it but stored after.
+        if raw < self.last && self.last - raw > 1 << (TIMESTAMP_BITS - 1) {
+            self.base += 1 << TIMESTAMP_BITS;
+        }
+        self.last = raw;
+        Some(Event {
+            kind,
+            id: (bits >> TIMESTAMP_BITS) as u16 & 0xFFF,
+            timestamp: self.base + raw,
+        })
+    }
+}
+
+/// Decode a capture of raw events or of a trace stream.
+pub fn decode(capture: &[u8]) -> XtaskResult<Capture> {
+    if capture.starts_with(MAGIC) {
+        return Ok(decode_stream(capture));
+    }
+    if capture.len() % 8 != 0 {
+        return Err(XtaskError::TraceError(format!(
+            "capture of {} bytes is not a whole number of events",
+            capture.len()
+        )));
+    }
+    let mut clock = Clock::default();
+    let mut events = Vec::with_capacity(capture.len() / 8);
+    for (i, word) in capture.chunks_exact(8).enumerate() {
+        let bits = u64::from_le_bytes(word.try_into().unwrap());
+        let event = clock.event(bits).ok_or_else(|| {
+            XtaskError::TraceError(format!(
+                "unknown event kind {} at offset {}",
+                bits >> (TIMESTAMP_BITS + ID_BITS),
+                i * 8
+            ))
+        })?;
+        events.push(event);
+    }
+    Ok(Capture {
+        events,
+        ..Capture::default()
+    })
+}
+
+fn decode_stream(stream: &[u8]) -> Capture {
+    let mut capture = Capture::default();
+    let mut clock = Clock::default();
+    let mut pos = 0;
+    while pos < stream.len() {
+        let rest = &stream[pos..];
+        // The header is sent again when the host may have attached.
+        if rest.len() >= HEADER_LEN && rest.starts_with(MAGIC) {
+            capture.frequency = Some(u32::from_le_bytes(rest[4..8].try_into().unwrap()));
+            pos += HEADER_LEN;
+            continue;
+        }
+        if rest.len() >= FRAME_LEN
+            && rest[0] == SYNC
+            && rest[1..9].iter().fold(0, |check, byte| check ^ byte) == rest[9]
+        {
+            let bits = u64::from_le_bytes(rest[1..9].try_into().unwrap());
+            if let Some(event) = clock.event(bits) {
+                capture.events.push(event);
+                pos += FRAME_LEN;
+                continue;
+            }
+        }
+        capture.skipped += 1;
+        pos += 1;
+    }
+    capture
+}
+
+/// Minimum, mean and maximum of a series of tick counts.
+fn stats(values: &[u64]) -> Option<(u64, f64, u64)> {
+    let min = *values.iter().min()?;
+    let max = *values.iter().max()?;
+    let mean = values.iter().sum::<u64>() as f64 / values.len() as f64;
+    Some((min, mean, max))
+}
+
+/// Render the events and the per-interrupt statistics, with timestamps at `hz`.
+pub fn render(events: &[Event], hz: u32) -> String {
+    let us = |ticks: f64| ticks * 1e6 / hz as f64;
+    let start = events.first().map_or(0, |event| event.timestamp);
+    let mut out = String::new();
+    // Entry times and handler durations per interrupt.
+    let mut irqs: BTreeMap<u16, (Vec<u64>, Vec<u64>)> = BTreeMap::new();
+    let mut open: BTreeMap<u16, u64> = BTreeMap::new();
+    // Run times per task.
+    let mut tasks: BTreeMap<u16, Vec<u64>> = BTreeMap::new();
+    let mut running: BTreeMap<u16, u64> = BTreeMap::new();
+    for event in events {
+        let what = match event.kind {
+            Kind::IrqEnter => {
+                irqs.entry(event.id).or_default().0.push(event.timestamp);
+                open.insert(event.id, event.timestamp);
+                format!("irq {} enter", event.id)
+            }
+            Kind::IrqExit => {
+                if let Some(entered) = open.remove(&event.id) {
+                    let duration = event.timestamp.saturating_sub(entered);
+                    irqs.entry(event.id).or_default().1.push(duration);
+                }
+                format!("irq {} exit", event.id)
+            }
+            Kind::DmaDone => format!("dma {} done", event.id),
+            Kind::Marker => format!("marker {}", event.id),
+            Kind::TaskBegin => {
+                running.insert(event.id, event.timestamp);
+                format!("task {} begin", event.id)
+            }
+            Kind::TaskEnd => {
+                if let Some(began) = running.remove(&event.id) {
+                    let run = event.timestamp.saturating_sub(began);
+                    tasks.entry(event.id).or_default().push(run);
+                }
+                format!("task {} end", event.id)
+            }
+            Kind::Idle => "idle".to_string(),
+            Kind::Overflow => format!("overflow, {} events dropped", event.id),
+        };
+        let time = us(event.timestamp.saturating_sub(start) as f64);
+        writeln!(out, "{:>14.3} us  {}", time, what).unwrap();
+    }
+
+    if !irqs.is_empty() || !tasks.is_empty() {
+        writeln!(out).unwrap();
+    }
+    for (irq, (entries, durations)) in &irqs {
+        write!(out, "irq {}: {} runs", irq, entries.len()).unwrap();
+        if let Some((min, mean, max)) = stats(durations) {
+            let (min, max) = (us(min as f64), us(max as f64));
+            write!(out, ", duration {:.1}/{:.1}/{:.1} us", min, us(mean), max).unwrap();
+        }
+        let periods: Vec<u64> = entries
+            .windows(2)
+            .map(|w| w[1].saturating_sub(w[0]))
+            .collect();
+        if let Some((min, mean, max)) = stats(&periods) {
+            let (min, max) = (us(min as f64), us(max as f64));
+            write!(out, ", period {:.1}/{:.1}/{:.1} us", min, us(mean), max).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    let span = events.last().map_or(0, |event| event.timestamp) - start;
+    for (task, runs) in &tasks {
+        let total = runs.iter().sum::<u64>();
+        write!(out, "task {}: {} runs", task, runs.len()).unwrap();
+        if let Some((min, mean, max)) = stats(runs) {
+            let (min, max) = (us(min as f64), us(max as f64));
+            write!(out, ", run time {:.1}/{:.1}/{:.1} us", min, us(mean), max).unwrap();
+        }
+        write!(out, ", total {:.1} us", us(total as f64)).unwrap();
+        if span > 0 {
+            write!(out, " ({:.1}%)", total as f64 * 100.0 / span as f64).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(kind: u64, id: u64, timestamp: u64) -> [u8; 8] {
+        ((kind << 60) | (id << 48) | timestamp).to_le_bytes()
+    }
+
+    #[test]
+    fn decode_and_report() {
+        // At 1 MHz, irq 7 runs every 1000 us for 2 or 4 us.
+        let mut capture = Vec::new();
+        for (enter, exit) in [(1000, 1002), (2000, 2004), (3000, 3002)] {
+            capture.extend(word(1, 7, enter));
+            capture.extend(word(2, 7, exit));
+        }
+        capture.extend(word(4, 1, 3500));
+        let events = decode(&capture).unwrap().events;
+        assert_eq!(events.len(), 7);
+        assert_eq!(
+            events[6],
+            Event {
+                kind: Kind::Marker,
+                id: 1,
+                timestamp: 3500
+            }
+        );
+
+        let report = render(&events, 1_000_000);
+        assert!(
+            report.contains("      1004.000 us  irq 7 exit"),
+            "{}",
+            report
+        );
+        assert!(report
+            .contains("irq 7: 3 runs, duration 2.0/2.7/4.0 us, period 1000.0/1000.0/1000.0 us"));
+
+        assert!(decode(&capture[..12]).is_err());
+        assert!(decode(&word(9, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn extend_timestamps() {
+        let last = (1 << 48) - 10;
+        let mut capture = Vec::new();
+        capture.extend(word(4, 0, last));
+        capture.extend(word(4, 0, last - 2));
+        capture.extend(word(4, 0, 5));
+        let events = decode(&capture).unwrap().events;
+        assert_eq!(events[1].timestamp, last - 2);
+        assert_eq!(events[2].timestamp, (1 << 48) + 5);
+    }
+
+    fn frame(kind: u64, id: u64, timestamp: u64) -> Vec<u8> {
+        let word = word(kind, id, timestamp);
+        let mut frame = vec![SYNC];
+        frame.extend(word);
+        frame.push(word.iter().fold(0, |check, byte| check ^ byte));
+        frame
+    }
+
+    #[test]
+    fn decode_stream_and_tasks() {
+        // At 1 MHz, task 2 runs for 100 us, then task 3 for 300 us of 1000.
+        let mut stream = b"KTRC\x40\x42\x0F\x00".to_vec();
+        stream.extend(frame(5, 2, 0));
+        stream.extend(frame(6, 2, 100));
+        // A lost byte damages this frame; the next one is found again.
+        stream.extend(&frame(5, 3, 100)[1..]);
+        stream.extend(frame(5, 3, 200));
+        stream.extend(frame(6, 3, 500));
+        stream.extend(frame(8, 4, 600));
+        stream.extend(frame(7, 0, 1000));
+
+        let capture = decode(&stream).unwrap();
+        assert_eq!(capture.frequency, Some(1_000_000));
+        assert_eq!(capture.skipped, 9);
+        assert_eq!(capture.events.len(), 6);
+
+        let report = render(&capture.events, 1_000_000);
+        assert!(report.contains("       600.000 us  overflow, 4 events dropped"));
+        assert!(report.contains("      1000.000 us  idle"));
+        assert!(report
+            .contains("task 2: 1 runs, run time 100.0/100.0/100.0 us, total 100.0 us (10.0%)"));
+        assert!(report
+            .contains("task 3: 1 runs, run time 300.0/300.0/300.0 us, total 300.0 us (30.0%)"));
+    }
+}